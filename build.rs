@@ -0,0 +1,20 @@
+//! Records `git describe` of the source tree in CONAN_CLEANUP_GIT_DESCRIBE, so that files written
+//! by builds from source tell which commit they came from. Builds outside of a git checkout, e.g.
+//! from a source archive, simply don't set it.
+
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/index");
+
+    let output = Command::new("git")
+        .args(["describe", "--always", "--dirty", "--tags"])
+        .output();
+    if let Ok(output) = output {
+        let describe = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+        if output.status.success() && !describe.is_empty() {
+            println!("cargo:rustc-env=CONAN_CLEANUP_GIT_DESCRIBE={}", describe);
+        }
+    }
+}
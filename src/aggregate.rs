@@ -0,0 +1,648 @@
+//! Rolls up the JSON reports of `--output json` of many hosts for `aggregate`, e.g. the nightly
+//! reports of a fleet of build agents: the freed bytes in total and per host, the package names
+//! removed most often, the hosts with failures, and the hosts whose reports are missing or stale.
+
+use crate::json_output::SCHEMA_VERSION;
+
+use serde_json::json;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// What a report tells about the run of a host.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostReport {
+    pub path: PathBuf,
+    pub host: String,
+    /// When the run wrote the report, in seconds since the UNIX epoch.
+    pub created_at: u64,
+    pub schema_version: u64,
+    /// `None` for failed runs and for reports of schema versions before 1, which didn't record it.
+    pub bytes_freed: Option<u64>,
+    /// Recipes of the packages that were removed, once per package.
+    pub removed_packages: Vec<String>,
+    /// Failed removals and the error of a failed run.
+    pub failures: Vec<String>,
+}
+
+/// Reads a report written with `--output json`, or with `--errors json` for a run that failed early.
+pub fn load_report(path: &Path) -> Result<HostReport, String> {
+    let content = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let json: serde_json::Value = serde_json::from_str(&content).map_err(|err| err.to_string())?;
+    parse_report(path, &json)
+}
+
+/// Validates a report and extracts what the roll-up needs. Reports without `schema_version` were
+/// written before it was introduced and count as version 0.
+pub fn parse_report(path: &Path, json: &serde_json::Value) -> Result<HostReport, String> {
+    let run_info = json["run_info"]
+        .as_object()
+        .ok_or("Not a conan_cleanup report, 'run_info' is missing")?;
+    let schema_version = match json.get("schema_version") {
+        None => 0,
+        Some(version) => version.as_u64().ok_or("'schema_version' is no number")?,
+    };
+    if schema_version > SCHEMA_VERSION {
+        return Err(format!(
+            "Schema version {} is newer than the supported version {}",
+            schema_version, SCHEMA_VERSION
+        ));
+    }
+    let host = run_info
+        .get("hostname")
+        .and_then(serde_json::Value::as_str)
+        .ok_or("The report does not name its host")?
+        .to_owned();
+    let created_at = run_info
+        .get("created_at")
+        .and_then(serde_json::Value::as_u64)
+        .ok_or("The report does not tell when it was written")?;
+
+    let mut removed_packages = Vec::new();
+    let mut failures = Vec::new();
+    match json.get("removals") {
+        Some(removals) => {
+            let removals = removals.as_array().ok_or("'removals' is no list")?;
+            for removal in removals {
+                let recipe = removal["recipe"]
+                    .as_str()
+                    .ok_or("A removal is missing its recipe")?;
+                let package = removal["package"].as_str();
+                match (removal["error"].as_str(), package) {
+                    (Some(error), Some(package)) => {
+                        failures.push(format!("{}:{}: {}", recipe, package, error))
+                    }
+                    (Some(error), None) => failures.push(format!("{}: {}", recipe, error)),
+                    (None, Some(_)) => removed_packages.push(recipe.to_owned()),
+                    (None, None) => {}
+                }
+            }
+        }
+        // Runs that failed before the report was made and runs skipped by --cooldown.
+        None if json.get("error").is_some() || json.get("skipped").is_some() => {}
+        None => return Err("'removals' is missing".to_owned()),
+    }
+    if let Some(error) = json.get("error") {
+        failures.push(
+            error["message"]
+                .as_str()
+                .unwrap_or("The run failed")
+                .to_owned(),
+        );
+    }
+    let bytes_freed = match json.get("summary") {
+        Some(summary) if !summary.is_null() => summary["bytes_freed"].as_u64(),
+        _ => None,
+    };
+
+    Ok(HostReport {
+        path: path.to_path_buf(),
+        host,
+        created_at,
+        schema_version,
+        bytes_freed,
+        removed_packages,
+        failures,
+    })
+}
+
+/// The reports of a host combined.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostSummary {
+    pub host: String,
+    pub reports: usize,
+    pub latest_report_at: u64,
+    pub bytes_freed: u64,
+    /// Reports that don't record the freed bytes, so `bytes_freed` lacks theirs.
+    pub reports_without_bytes_freed: usize,
+    pub packages_removed: usize,
+    pub failures: Vec<String>,
+    /// Whether the latest report is older than the stale duration.
+    pub stale: bool,
+}
+
+/// The roll-up of the reports of all hosts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Aggregate {
+    pub reports: usize,
+    pub bytes_freed: u64,
+    /// Sorted by host name.
+    pub hosts: Vec<HostSummary>,
+    /// Package names with the number of removed packages, most removed first.
+    pub top_package_names: Vec<(String, usize)>,
+    /// Expected hosts without any report, empty without a list of expected hosts.
+    pub missing_hosts: Vec<String>,
+    /// Files that are no valid reports, with the reason.
+    pub invalid_reports: Vec<(PathBuf, String)>,
+}
+
+impl Aggregate {
+    pub fn hosts_with_failures(&self) -> impl Iterator<Item = &HostSummary> {
+        self.hosts.iter().filter(|host| !host.failures.is_empty())
+    }
+
+    pub fn stale_hosts(&self) -> impl Iterator<Item = &HostSummary> {
+        self.hosts.iter().filter(|host| host.stale)
+    }
+}
+
+/// Combines the reports. A host is stale if its latest report was written more than `stale_after`
+/// before `now`, in seconds since the UNIX epoch. `top` limits the package names.
+pub fn aggregate(
+    reports: &[HostReport],
+    invalid_reports: Vec<(PathBuf, String)>,
+    expected_hosts: Option<&[String]>,
+    now: u64,
+    stale_after: Duration,
+    top: usize,
+) -> Aggregate {
+    let mut hosts: BTreeMap<&str, HostSummary> = BTreeMap::new();
+    let mut package_names: BTreeMap<&str, usize> = BTreeMap::new();
+    for report in reports {
+        let host = hosts.entry(&report.host).or_insert_with(|| HostSummary {
+            host: report.host.clone(),
+            reports: 0,
+            latest_report_at: 0,
+            bytes_freed: 0,
+            reports_without_bytes_freed: 0,
+            packages_removed: 0,
+            failures: Vec::new(),
+            stale: false,
+        });
+        host.reports += 1;
+        host.latest_report_at = host.latest_report_at.max(report.created_at);
+        match report.bytes_freed {
+            Some(bytes_freed) => host.bytes_freed += bytes_freed,
+            None => host.reports_without_bytes_freed += 1,
+        }
+        host.packages_removed += report.removed_packages.len();
+        host.failures.extend(report.failures.iter().cloned());
+        for recipe in &report.removed_packages {
+            let name = recipe.split('/').next().unwrap_or(recipe);
+            *package_names.entry(name).or_default() += 1;
+        }
+    }
+    for host in hosts.values_mut() {
+        host.stale = now.saturating_sub(host.latest_report_at) > stale_after.as_secs();
+    }
+
+    let mut top_package_names: Vec<_> = package_names
+        .into_iter()
+        .map(|(name, count)| (name.to_owned(), count))
+        .collect();
+    // Stable, so names removed equally often stay sorted by name.
+    top_package_names.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+    top_package_names.truncate(top);
+
+    let missing_hosts = expected_hosts
+        .into_iter()
+        .flatten()
+        .filter(|host| !hosts.contains_key(host.as_str()))
+        .cloned()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+
+    let hosts: Vec<_> = hosts.into_values().collect();
+    Aggregate {
+        reports: reports.len(),
+        bytes_freed: hosts.iter().map(|host| host.bytes_freed).sum(),
+        hosts,
+        top_package_names,
+        missing_hosts,
+        invalid_reports,
+    }
+}
+
+/// Reads a file listing the expected hosts, one per line. Blank lines and lines starting with `#`
+/// are ignored.
+pub fn load_expected_hosts(path: &Path) -> Result<Vec<String>, crate::text_file::TextFileError> {
+    Ok(crate::text_file::read_lines(path)?
+        .iter()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_owned)
+        .collect())
+}
+
+/// Returns the report files given as files, folders of `.json` files or path patterns like
+/// `reports/*.json`, without duplicates.
+pub fn find_reports(inputs: &[&str]) -> Vec<PathBuf> {
+    let mut files = BTreeSet::new();
+    for input in inputs {
+        let path = Path::new(input);
+        if path.is_dir() {
+            let pattern = path.join("*.json");
+            files.extend(crate::wildcard::find_files(&pattern.to_string_lossy()));
+        } else {
+            files.extend(crate::wildcard::find_files(input));
+        }
+    }
+    files.into_iter().collect()
+}
+
+impl Aggregate {
+    pub fn to_json(&self) -> serde_json::Value {
+        let names = |hosts: &mut dyn Iterator<Item = &HostSummary>| {
+            hosts.map(|host| host.host.clone()).collect::<Vec<_>>()
+        };
+        json!({
+            "run_info": crate::run_info::to_json(),
+            "reports": self.reports,
+            "bytes_freed": self.bytes_freed,
+            "hosts": self.hosts.iter().map(|host| json!({
+                "host": host.host,
+                "reports": host.reports,
+                "latest_report_at": host.latest_report_at,
+                "bytes_freed": host.bytes_freed,
+                "reports_without_bytes_freed": host.reports_without_bytes_freed,
+                "packages_removed": host.packages_removed,
+                "failures": host.failures,
+                "stale": host.stale,
+            })).collect::<Vec<_>>(),
+            "top_package_names": self.top_package_names.iter().map(|(name, count)| json!({
+                "name": name,
+                "packages_removed": count,
+            })).collect::<Vec<_>>(),
+            "hosts_with_failures": names(&mut self.hosts_with_failures()),
+            "stale_hosts": names(&mut self.stale_hosts()),
+            "missing_hosts": self.missing_hosts,
+            "invalid_reports": self.invalid_reports.iter().map(|(path, error)| json!({
+                "path": path.display().to_string(),
+                "error": error,
+            })).collect::<Vec<_>>(),
+        })
+    }
+
+    /// Formats the roll-up as tables for the terminal.
+    pub fn to_text(&self) -> String {
+        let mut text = format!(
+            "{} reports of {} hosts, {} freed in total.\n",
+            self.reports,
+            self.hosts.len(),
+            crate::size::format_size(self.bytes_freed)
+        );
+        if !self.hosts.is_empty() {
+            let width = self
+                .hosts
+                .iter()
+                .map(|host| host.host.len())
+                .max()
+                .unwrap_or(0)
+                .max("Host".len());
+            text.push_str(&format!(
+                "\n{:width$}  {:>7}  {:>10}  {:>8}  {:>8}\n",
+                "Host",
+                "Reports",
+                "Freed",
+                "Packages",
+                "Failures",
+                width = width
+            ));
+            for host in &self.hosts {
+                let mut notes = Vec::new();
+                if host.stale {
+                    notes.push("stale".to_owned());
+                }
+                if host.reports_without_bytes_freed > 0 {
+                    notes.push(format!(
+                        "{} reports without freed bytes",
+                        host.reports_without_bytes_freed
+                    ));
+                }
+                text.push_str(&format!(
+                    "{:width$}  {:>7}  {:>10}  {:>8}  {:>8}{}\n",
+                    host.host,
+                    host.reports,
+                    crate::size::format_size(host.bytes_freed),
+                    host.packages_removed,
+                    host.failures.len(),
+                    if notes.is_empty() {
+                        String::new()
+                    } else {
+                        format!("  ({})", notes.join(", "))
+                    },
+                    width = width
+                ));
+            }
+        }
+        if !self.top_package_names.is_empty() {
+            text.push_str("\nPackage names removed most often:\n");
+            for (name, count) in &self.top_package_names {
+                text.push_str(&format!("  {} ({} packages)\n", name, count));
+            }
+        }
+        let mut list = |title: &str, hosts: Vec<&str>| {
+            if !hosts.is_empty() {
+                text.push_str(&format!("\n{}\n", title));
+                for host in hosts {
+                    text.push_str(&format!("  {}\n", host));
+                }
+            }
+        };
+        list(
+            "Hosts with failures:",
+            self.hosts_with_failures()
+                .map(|host| host.host.as_str())
+                .collect(),
+        );
+        list(
+            "Hosts with stale reports:",
+            self.stale_hosts().map(|host| host.host.as_str()).collect(),
+        );
+        list(
+            "Hosts without reports:",
+            self.missing_hosts.iter().map(String::as_str).collect(),
+        );
+        if !self.invalid_reports.is_empty() {
+            text.push_str("\nInvalid reports:\n");
+            for (path, error) in &self.invalid_reports {
+                text.push_str(&format!("  {}: {}\n", path.display(), error));
+            }
+        }
+        text
+    }
+
+    /// Formats the roll-up as a standalone HTML page.
+    pub fn to_html(&self) -> String {
+        let mut html = String::from(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>conan_cleanup fleet report</title>\n<style>\nbody { font-family: sans-serif; }\ntable { border-collapse: collapse; }\nth, td { border: 1px solid #ccc; padding: 4px 8px; text-align: left; }\n.stale, .failed { background: #fdd; }\n</style>\n</head>\n<body>\n",
+        );
+        html.push_str(&format!(
+            "<h1>conan_cleanup fleet report</h1>\n<p>{} reports of {} hosts, {} freed in total.</p>\n",
+            self.reports,
+            self.hosts.len(),
+            escape_html(&crate::size::format_size(self.bytes_freed))
+        ));
+        html.push_str("<h2>Hosts</h2>\n<table>\n<tr><th>Host</th><th>Reports</th><th>Freed</th><th>Packages removed</th><th>Failures</th></tr>\n");
+        for host in &self.hosts {
+            let class = if !host.failures.is_empty() {
+                " class=\"failed\""
+            } else if host.stale {
+                " class=\"stale\""
+            } else {
+                ""
+            };
+            html.push_str(&format!(
+                "<tr{}><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                class,
+                escape_html(&host.host),
+                host.reports,
+                escape_html(&crate::size::format_size(host.bytes_freed)),
+                host.packages_removed,
+                host.failures.len()
+            ));
+        }
+        for host in &self.missing_hosts {
+            html.push_str(&format!(
+                "<tr class=\"stale\"><td>{}</td><td>0</td><td colspan=\"3\">no report</td></tr>\n",
+                escape_html(host)
+            ));
+        }
+        html.push_str("</table>\n");
+        if !self.top_package_names.is_empty() {
+            html.push_str("<h2>Package names removed most often</h2>\n<table>\n<tr><th>Name</th><th>Packages removed</th></tr>\n");
+            for (name, count) in &self.top_package_names {
+                html.push_str(&format!(
+                    "<tr><td>{}</td><td>{}</td></tr>\n",
+                    escape_html(name),
+                    count
+                ));
+            }
+            html.push_str("</table>\n");
+        }
+        let failures: Vec<_> = self
+            .hosts_with_failures()
+            .flat_map(|host| host.failures.iter().map(move |failure| (host, failure)))
+            .collect();
+        if !failures.is_empty() {
+            html.push_str("<h2>Failures</h2>\n<ul>\n");
+            for (host, failure) in failures {
+                html.push_str(&format!(
+                    "<li>{}: {}</li>\n",
+                    escape_html(&host.host),
+                    escape_html(failure)
+                ));
+            }
+            html.push_str("</ul>\n");
+        }
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 24 * 60 * 60;
+    const NOW: u64 = 100 * DAY;
+
+    fn report(host: &str, created_at: u64, removals: serde_json::Value) -> serde_json::Value {
+        json!({
+            "schema_version": 1,
+            "run_info": {"hostname": host, "created_at": created_at},
+            "removals": removals,
+            "summary": {"bytes_freed": 1000},
+        })
+    }
+
+    fn parsed(json: serde_json::Value) -> HostReport {
+        parse_report(Path::new("report.json"), &json).unwrap()
+    }
+
+    fn removal(recipe: &str, package: Option<&str>, error: Option<&str>) -> serde_json::Value {
+        json!({"recipe": recipe, "package": package, "error": error, "succeeded": error.is_none()})
+    }
+
+    fn fleet() -> Vec<HostReport> {
+        vec![
+            parsed(report(
+                "agent-1",
+                NOW - DAY / 2,
+                json!([
+                    removal("boost/1.70.0", Some("aaa"), None),
+                    removal("boost/1.70.0", Some("bbb"), None),
+                    removal("zlib/1.2.11@conan/stable", Some("ccc"), None),
+                ]),
+            )),
+            parsed(report(
+                "agent-2",
+                NOW - 5 * DAY,
+                json!([
+                    removal("boost/1.75.0", Some("aaa"), None),
+                    removal("qt/5.15.2", Some("ddd"), Some("Permission denied")),
+                ]),
+            )),
+            parsed(report(
+                "agent-1",
+                NOW - 2 * DAY,
+                json!([removal("zlib/1.2.11@conan/stable", Some("eee"), None)]),
+            )),
+        ]
+    }
+
+    fn aggregated(reports: &[HostReport], expected: Option<&[String]>) -> Aggregate {
+        aggregate(
+            reports,
+            Vec::new(),
+            expected,
+            NOW,
+            Duration::from_secs(2 * DAY),
+            10,
+        )
+    }
+
+    #[test]
+    fn parses_removals_and_failures() {
+        let report = parsed(report(
+            "agent-1",
+            NOW,
+            json!([
+                removal("boost/1.70.0", Some("aaa"), None),
+                removal("boost/1.70.0", None, None),
+                removal("qt/5.15.2", Some("ddd"), Some("locked")),
+            ]),
+        ));
+
+        assert_eq!(report.host, "agent-1");
+        assert_eq!(report.schema_version, 1);
+        assert_eq!(report.bytes_freed, Some(1000));
+        assert_eq!(report.removed_packages, ["boost/1.70.0"]);
+        assert_eq!(report.failures, ["qt/5.15.2:ddd: locked"]);
+    }
+
+    #[test]
+    fn reports_before_schema_version_1_have_no_freed_bytes() {
+        let report = parsed(json!({
+            "run_info": {"hostname": "agent-1", "created_at": NOW},
+            "removals": [],
+            "summary": {"packages_removed": 0},
+        }));
+
+        assert_eq!(report.schema_version, 0);
+        assert_eq!(report.bytes_freed, None);
+    }
+
+    #[test]
+    fn error_reports_are_failures() {
+        let report = parsed(json!({
+            "run_info": {"hostname": "agent-1", "created_at": NOW},
+            "error": {"code": "conan_not_found", "message": "conan was not found"},
+        }));
+
+        assert!(report.removed_packages.is_empty());
+        assert_eq!(report.failures, ["conan was not found"]);
+    }
+
+    #[test]
+    fn invalid_reports_are_rejected() {
+        let error = |json: serde_json::Value| parse_report(Path::new("x.json"), &json).unwrap_err();
+
+        assert!(error(json!({"recipes": {}})).contains("run_info"));
+        assert!(error(json!({
+            "schema_version": SCHEMA_VERSION + 1,
+            "run_info": {"hostname": "agent-1", "created_at": NOW},
+            "removals": [],
+        }))
+        .contains("newer"));
+        assert!(error(json!({"run_info": {"created_at": NOW}, "removals": []})).contains("host"));
+        assert!(
+            error(json!({"run_info": {"hostname": "a", "created_at": NOW}}))
+                .contains("'removals' is missing")
+        );
+        assert!(error(json!({
+            "run_info": {"hostname": "a", "created_at": NOW},
+            "removals": [{"package": "aaa"}],
+        }))
+        .contains("recipe"));
+    }
+
+    #[test]
+    fn combines_the_reports_per_host() {
+        let aggregate = aggregated(&fleet(), None);
+
+        assert_eq!(aggregate.reports, 3);
+        assert_eq!(aggregate.bytes_freed, 3000);
+        assert_eq!(aggregate.hosts.len(), 2);
+        let agent_1 = &aggregate.hosts[0];
+        assert_eq!(agent_1.host, "agent-1");
+        assert_eq!(agent_1.reports, 2);
+        assert_eq!(agent_1.bytes_freed, 2000);
+        assert_eq!(agent_1.packages_removed, 4);
+        assert_eq!(agent_1.latest_report_at, NOW - DAY / 2);
+        assert!(!agent_1.stale);
+        assert_eq!(
+            aggregate
+                .hosts_with_failures()
+                .map(|host| host.host.as_str())
+                .collect::<Vec<_>>(),
+            ["agent-2"]
+        );
+    }
+
+    #[test]
+    fn ranks_package_names_across_hosts() {
+        let ranked = aggregated(&fleet(), None);
+
+        assert_eq!(
+            ranked.top_package_names,
+            [("boost".to_owned(), 3), ("zlib".to_owned(), 2)]
+        );
+        let top_1 = aggregate(&fleet(), Vec::new(), None, NOW, Duration::from_secs(DAY), 1);
+        assert_eq!(top_1.top_package_names, [("boost".to_owned(), 3)]);
+    }
+
+    #[test]
+    fn matches_the_expected_hosts() {
+        let expected = vec![
+            "agent-1".to_owned(),
+            "agent-2".to_owned(),
+            "agent-3".to_owned(),
+        ];
+        let aggregate = aggregated(&fleet(), Some(&expected));
+
+        assert_eq!(aggregate.missing_hosts, ["agent-3"]);
+        assert_eq!(
+            aggregate
+                .stale_hosts()
+                .map(|host| host.host.as_str())
+                .collect::<Vec<_>>(),
+            ["agent-2"]
+        );
+        assert!(aggregated(&fleet(), None).missing_hosts.is_empty());
+    }
+
+    #[test]
+    fn renders_text_json_and_html() {
+        let expected = vec!["agent-3".to_owned()];
+        let mut reports = fleet();
+        reports[0].host = "<agent-1>".to_owned();
+        let aggregate = aggregated(&reports, Some(&expected));
+
+        let text = aggregate.to_text();
+        assert!(text.contains("3 reports of 3 hosts"));
+        assert!(text.contains("Hosts with failures:\n  agent-2\n"));
+        assert!(text.contains("Hosts without reports:\n  agent-3\n"));
+        assert!(text.contains("  boost (3 packages)\n"));
+
+        let json = aggregate.to_json();
+        assert_eq!(json["bytes_freed"], 3000);
+        assert_eq!(json["missing_hosts"], json!(["agent-3"]));
+        assert_eq!(json["stale_hosts"], json!(["agent-2"]));
+        assert_eq!(json["top_package_names"][0]["name"], "boost");
+
+        let html = aggregate.to_html();
+        assert!(html.contains("<td>&lt;agent-1&gt;</td>"));
+        assert!(html.contains("agent-2: qt/5.15.2:ddd: Permission denied"));
+        assert!(html.ends_with("</html>\n"));
+    }
+}
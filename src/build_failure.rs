@@ -0,0 +1,79 @@
+//! Heuristics to recognize conaninfo.txt files left behind by builds that failed right after conan
+//! installed their dependencies.
+
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Evidence that the build next to a conaninfo.txt failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Marker {
+    /// A `CMakeError.log` (directly or in `CMakeFiles`) newer than the conaninfo.txt.
+    CMakeErrorLog,
+    /// A `.ninja_log` without any successfully built edge.
+    EmptyNinjaLog,
+    /// A file with the given name, e.g. written by the CI after a failed build.
+    File(String),
+}
+
+/// Markers used if none are configured. Both only match when the build clearly didn't succeed.
+pub fn default_markers() -> Vec<Marker> {
+    vec![Marker::CMakeErrorLog, Marker::EmptyNinjaLog]
+}
+
+/// Parses a marker given on the command line: `cmake-error-log`, `empty-ninja-log` or the name of
+/// a marker file.
+pub fn parse_marker(text: &str) -> Marker {
+    match text {
+        "cmake-error-log" => Marker::CMakeErrorLog,
+        "empty-ninja-log" => Marker::EmptyNinjaLog,
+        name => Marker::File(name.to_owned()),
+    }
+}
+
+/// Returns a description of the first marker indicating that the build the given conaninfo.txt
+/// belongs to failed.
+pub fn find_failure_marker(conan_info_path: &Path, markers: &[Marker]) -> Option<String> {
+    let build_dir = conan_info_path.parent()?;
+    markers.iter().find_map(|marker| match marker {
+        Marker::CMakeErrorLog => {
+            let conan_info_modified = modified(conan_info_path)?;
+            ["CMakeError.log", "CMakeFiles/CMakeError.log"]
+                .iter()
+                .map(|name| build_dir.join(name))
+                .find(|log| {
+                    modified(log).is_some_and(|log_modified| log_modified > conan_info_modified)
+                })
+                .map(|log| format!("newer {}", log.display()))
+        }
+        Marker::EmptyNinjaLog => {
+            let ninja_log = build_dir.join(".ninja_log");
+            let content = std::fs::read_to_string(&ninja_log).ok()?;
+            let built_edges = content
+                .lines()
+                .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+                .count();
+            if built_edges == 0 {
+                Some(format!(
+                    "nothing built according to {}",
+                    ninja_log.display()
+                ))
+            } else {
+                None
+            }
+        }
+        Marker::File(name) => {
+            let marker_file = build_dir.join(name);
+            if marker_file.exists() {
+                Some(format!("marker file {}", marker_file.display()))
+            } else {
+                None
+            }
+        }
+    })
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
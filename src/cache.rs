@@ -0,0 +1,661 @@
+use ini::Ini;
+use walkdir::WalkDir;
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Returns the conan home directory (the `.conan` folder) of the current user, honoring
+/// `CONAN_USER_HOME` the same way conan does.
+pub fn conan_home() -> Option<PathBuf> {
+    let user_home = conan_env_var("CONAN_USER_HOME")
+        .or_else(|| std::env::var_os("HOME"))
+        .or_else(|| std::env::var_os("USERPROFILE"))?;
+    let mut conan_home = PathBuf::from(user_home);
+    conan_home.push(".conan");
+    Some(conan_home)
+}
+
+/// Returns the home directory of conan 2 (the `.conan2` folder) of the current user, honoring
+/// `CONAN_HOME`.
+pub fn conan2_home() -> Option<PathBuf> {
+    if let Some(conan_home) = conan_env_var("CONAN_HOME") {
+        return Some(PathBuf::from(conan_home));
+    }
+
+    let user_home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?;
+    let mut conan_home = PathBuf::from(user_home);
+    conan_home.push(".conan2");
+    Some(conan_home)
+}
+
+/// Returns the directory conan stores its recipes and packages in. This is either given by
+/// `CONAN_STORAGE_PATH`, the `[storage]` section of conan.conf or defaults to `<conan home>/data`.
+pub fn storage_path(conan_home: &Path) -> PathBuf {
+    if let Some(path) = std::env::var_os("CONAN_STORAGE_PATH") {
+        return PathBuf::from(path);
+    }
+
+    let configured_path = Ini::load_from_file(conan_home.join("conan.conf"))
+        .ok()
+        .and_then(|conf| conf.get_from(Some("storage"), "path").map(str::to_owned));
+    match configured_path {
+        Some(path) => expand_path(conan_home, &path),
+        None => conan_home.join("data"),
+    }
+}
+
+/// Returns whether the cache stores revisions, as configured by `CONAN_REVISIONS_ENABLED` or by
+/// `revisions_enabled` in the `[general]` section of conan.conf. conan 2 always does.
+pub fn revisions_enabled(conan_home: &Path) -> bool {
+    if crate::reference::is_conan2() {
+        return true;
+    }
+
+    let value = match std::env::var("CONAN_REVISIONS_ENABLED") {
+        Ok(value) => Some(value),
+        Err(_) => Ini::load_from_file(conan_home.join("conan.conf"))
+            .ok()
+            .and_then(|conf| {
+                conf.get_from(Some("general"), "revisions_enabled")
+                    .map(str::to_owned)
+            }),
+    };
+    value.is_some_and(|value| matches!(value.trim().to_lowercase().as_str(), "1" | "true"))
+}
+
+fn expand_path(conan_home: &Path, path: &str) -> PathBuf {
+    if let Some(rest) = path.strip_prefix('~') {
+        if let Some(user_home) = conan_home.parent() {
+            return user_home.join(rest.trim_start_matches(['/', '\\']));
+        }
+    }
+    conan_home.join(path)
+}
+
+/// Returns whether the cache must not be modified, either since `CONAN_READ_ONLY_CACHE` is set or
+/// since the storage folder is not writable.
+pub fn is_read_only(storage_path: &Path) -> bool {
+    let read_only_cache = std::env::var("CONAN_READ_ONLY_CACHE")
+        .is_ok_and(|value| !matches!(value.to_lowercase().as_str(), "" | "0" | "false"));
+    if read_only_cache {
+        return true;
+    }
+
+    match std::fs::metadata(storage_path) {
+        Ok(_) => !is_writable(storage_path),
+        Err(_) => false,
+    }
+}
+
+/// Returns whether the current user may create and remove files in the given folder. On unix this
+/// asks `access`, which also tells about read-only mounts.
+#[cfg(unix)]
+fn is_writable(dir: &Path) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+
+    match std::ffi::CString::new(dir.as_os_str().as_bytes()) {
+        Ok(path) => (unsafe { libc::access(path.as_ptr(), libc::W_OK) }) == 0,
+        Err(_) => false,
+    }
+}
+
+/// Returns whether the given folder is not marked read-only.
+#[cfg(not(unix))]
+fn is_writable(dir: &Path) -> bool {
+    std::fs::metadata(dir).is_ok_and(|metadata| !metadata.permissions().readonly())
+}
+
+/// Returns whether the folder of the given binary package physically resides in the writable
+/// storage folder. A cache overlaying a shared read-only cache links the packages of the read-only
+/// layer into its storage folder, or mounts them read-only, so they cannot be removed through it.
+/// Packages whose folder cannot be found count as writable.
+pub fn is_in_writable_layer(storage_path: &Path, recipe_id: &str, package_id: &str) -> bool {
+    let package_dir = match package_dir(storage_path, recipe_id, package_id)
+        .and_then(|dir| std::fs::canonicalize(dir).ok())
+    {
+        Some(package_dir) => package_dir,
+        None => return true,
+    };
+    let storage_path = match std::fs::canonicalize(packages_root(storage_path)) {
+        Ok(storage_path) => storage_path,
+        Err(_) => return true,
+    };
+    package_dir.starts_with(&storage_path) && package_dir.parent().is_some_and(is_writable)
+}
+
+/// Returns the environment variables that make conan use the given conan home: conan 2 reads it
+/// from `CONAN_HOME`, conan 1 from `CONAN_USER_HOME`, which holds the `.conan` folder.
+pub fn conan_home_env(conan_home: &Path) -> Vec<(&'static str, &Path)> {
+    let mut env = vec![("CONAN_HOME", conan_home)];
+    if conan_home.file_name().is_some_and(|name| name == ".conan") {
+        if let Some(user_home) = conan_home.parent() {
+            env.push(("CONAN_USER_HOME", user_home));
+        }
+    }
+    env
+}
+
+/// Returns the value of an environment variable as the conan commands of the session see it,
+/// which includes the conan home of `Settings::conan_home`.
+fn conan_env_var(name: &str) -> Option<OsString> {
+    let session = crate::session::current();
+    let configured = session
+        .settings()
+        .conan_home
+        .as_deref()
+        .and_then(|conan_home| {
+            conan_home_env(conan_home)
+                .into_iter()
+                .find(|(variable, _)| *variable == name)
+                .map(|(_, value)| value.as_os_str().to_owned())
+        });
+    configured.or_else(|| std::env::var_os(name))
+}
+
+/// Recipe folders resolved by `recipe_dir`, by the folder the layout of conan 1 would put them in.
+static RESOLVED_RECIPE_DIRS: Mutex<BTreeMap<PathBuf, Option<PathBuf>>> =
+    Mutex::new(BTreeMap::new());
+
+/// Returns the cache folder of the given recipe in the layout of conan 1. The folder is derived from
+/// the reference like conan 1 does, but only trusted if it holds an exported recipe. Otherwise conan
+/// is asked for the folder. Returns `None` if the recipe is not in the cache.
+pub fn recipe_dir(storage_path: &Path, recipe_id: &str) -> Option<PathBuf> {
+    let layout_dir = layout_recipe_dir(storage_path, recipe_id)?;
+    if let Some(resolved) = RESOLVED_RECIPE_DIRS.lock().unwrap().get(&layout_dir) {
+        return resolved.clone();
+    }
+
+    let resolved = if is_recipe_dir(&layout_dir) {
+        Some(layout_dir.clone())
+    } else {
+        ask_conan_for_recipe_dir(recipe_id).filter(|dir| is_recipe_dir(dir))
+    };
+    RESOLVED_RECIPE_DIRS
+        .lock()
+        .unwrap()
+        .insert(layout_dir, resolved.clone());
+    resolved
+}
+
+/// Returns the folder conan 1 stores the given recipe in, i.e.
+/// `<storage>/<name>/<version>/<user>/<channel>`, whether it exists or not. References without
+/// user and channel are stored under `_/_`. conan 1 uses the parts of the reference as folder names
+/// as they are, including characters like `+` or `~` and non-ASCII ones.
+fn layout_recipe_dir(storage_path: &Path, recipe_id: &str) -> Option<PathBuf> {
+    let recipe_id = recipe_id.split('#').next().unwrap_or(recipe_id);
+    let (name_version, user_channel) = match recipe_id.find('@') {
+        Some(index) => (&recipe_id[..index], &recipe_id[index + 1..]),
+        None => (recipe_id, ""),
+    };
+
+    let mut name_version = name_version.splitn(2, '/');
+    let name = name_version.next().filter(|name| !name.is_empty())?;
+    let version = name_version.next().filter(|version| !version.is_empty())?;
+
+    let (user, channel) = if user_channel.is_empty() {
+        ("_", "_")
+    } else {
+        let mut user_channel = user_channel.splitn(2, '/');
+        (user_channel.next()?, user_channel.next()?)
+    };
+
+    let mut dir = storage_path.to_path_buf();
+    dir.push(name);
+    dir.push(version);
+    dir.push(user);
+    dir.push(channel);
+    Some(dir)
+}
+
+/// Returns whether the folder holds an exported recipe.
+fn is_recipe_dir(dir: &Path) -> bool {
+    ["conanmanifest.txt", "conanfile.py"]
+        .iter()
+        .any(|file| dir.join("export").join(file).is_file())
+}
+
+/// Returns whether the folder holds a binary package, or is redirected to one with `short_paths`.
+fn is_package_dir(dir: &Path) -> bool {
+    ["conaninfo.txt", "conanmanifest.txt", ".conan_link"]
+        .iter()
+        .any(|file| dir.join(file).is_file())
+}
+
+/// Asks conan 1 for the cache folder of a recipe with `conan info --paths`, which resolves the
+/// recipe's requirements and is only worth it for the rare recipes whose folder cannot be derived
+/// from the reference. conan 2 stores recipes in hashed folders of a different layout, which the
+/// features working on recipe folders don't support, so it is not asked. Its package folders are
+/// resolved by `package_dir`.
+fn ask_conan_for_recipe_dir(recipe_id: &str) -> Option<PathBuf> {
+    if crate::reference::is_conan2() {
+        return None;
+    }
+
+    let reference = crate::reference::argument(recipe_id);
+    let output = crate::subprocess::run_conan(&[
+        "info",
+        &reference,
+        "--paths",
+        "--only",
+        "export_folder",
+        "--json",
+    ])
+    .ok()
+    .filter(|output| output.status.success())?;
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).ok()?;
+    let recipe_id = recipe_id.split('#').next().unwrap_or(recipe_id);
+    let export_folder = json.as_array()?.iter().find_map(|node| {
+        let reference = node["reference"].as_str()?;
+        if reference.split('#').next() == Some(recipe_id) {
+            node["export_folder"].as_str()
+        } else {
+            None
+        }
+    })?;
+    Path::new(export_folder).parent().map(Path::to_path_buf)
+}
+
+/// A recipe found by walking the folder layout of a storage folder rather than asking conan.
+#[derive(Debug)]
+pub struct LayoutRecipe {
+    /// Reference of the recipe, e.g. `zlib/1.2.11@conan/stable` or `boost/1.75.0`.
+    pub recipe_id: String,
+    pub path: PathBuf,
+    /// IDs and folders of the recipe's binary packages.
+    pub packages: Vec<(String, PathBuf)>,
+}
+
+/// Enumerates the recipes and packages of a storage folder from its layout
+/// (`<name>/<version>/<user>/<channel>/package/<id>`), without invoking conan.
+pub fn find_layout_recipes(storage_path: &Path) -> Vec<LayoutRecipe> {
+    let mut recipes = Vec::new();
+    for entry in WalkDir::new(storage_path)
+        .min_depth(4)
+        .max_depth(4)
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_dir())
+    {
+        let components: Vec<_> = match entry.path().strip_prefix(storage_path) {
+            Ok(relative_path) => relative_path
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy().into_owned())
+                .collect(),
+            Err(_) => continue,
+        };
+        let recipe_id = match components.as_slice() {
+            [name, version, user, channel] if user == "_" && channel == "_" => {
+                format!("{}/{}", name, version)
+            }
+            [name, version, user, channel] => format!("{}/{}@{}/{}", name, version, user, channel),
+            _ => continue,
+        };
+
+        let mut packages: Vec<_> = match std::fs::read_dir(entry.path().join("package")) {
+            Ok(entries) => entries
+                .filter_map(|e| e.ok())
+                .filter(|e| e.path().is_dir())
+                .map(|e| (e.file_name().to_string_lossy().into_owned(), e.path()))
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        packages.sort();
+        recipes.push(LayoutRecipe {
+            recipe_id,
+            path: entry.into_path(),
+            packages,
+        });
+    }
+    recipes
+}
+
+/// Returns whether the given path lies inside `storage_path`, so that it may be deleted as part of
+/// cleaning that storage folder.
+pub fn is_inside_storage(storage_path: &Path, path: &Path) -> bool {
+    match path.strip_prefix(storage_path) {
+        Ok(relative_path) => {
+            relative_path.components().next().is_some()
+                && relative_path
+                    .components()
+                    .all(|component| matches!(component, Component::Normal(_)))
+        }
+        Err(_) => false,
+    }
+}
+
+/// Returns the cache folder of the given binary package of a recipe, or `None` if the folder does
+/// not hold a package. conan 2 is asked for the folder, see `ask_conan2_for_package_dir`.
+pub fn package_dir(storage_path: &Path, recipe_id: &str, package_id: &str) -> Option<PathBuf> {
+    if crate::reference::is_conan2() {
+        return ask_conan2_for_package_dir(recipe_id, package_id).filter(|dir| is_package_dir(dir));
+    }
+
+    let mut dir = recipe_dir(storage_path, recipe_id)?;
+    dir.push("package");
+    dir.push(package_id);
+    Some(dir).filter(|dir| is_package_dir(dir))
+}
+
+/// Package folders of conan 2 resolved by `ask_conan2_for_package_dir`, by recipe reference and
+/// package ID.
+static CONAN2_PACKAGE_DIRS: Mutex<BTreeMap<(String, String), Option<PathBuf>>> =
+    Mutex::new(BTreeMap::new());
+
+/// Asks conan 2 for the folder of a binary package with `conan cache path`, since conan 2 stores
+/// packages in hashed folders that cannot be derived from the reference. Unlike `conan info` of
+/// conan 1, this only looks up the cache database. Recipes without revision resolve to their
+/// latest revision.
+fn ask_conan2_for_package_dir(recipe_id: &str, package_id: &str) -> Option<PathBuf> {
+    let key = (recipe_id.to_owned(), package_id.to_owned());
+    if let Some(resolved) = CONAN2_PACKAGE_DIRS.lock().unwrap().get(&key) {
+        return resolved.clone();
+    }
+
+    let reference = format!("{}:{}", recipe_id, package_id);
+    let resolved = crate::subprocess::run_conan(&["cache", "path", &reference])
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_owned())
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from);
+    CONAN2_PACKAGE_DIRS
+        .lock()
+        .unwrap()
+        .insert(key, resolved.clone());
+    resolved
+}
+
+/// Returns the folder the binary packages of the installed conan client are stored in: the given
+/// storage folder for conan 1, the `p` folder of the conan 2 home for conan 2.
+pub fn packages_root(storage_path: &Path) -> PathBuf {
+    if crate::reference::is_conan2() {
+        if let Some(conan_home) = conan2_home() {
+            return conan_home.join("p");
+        }
+    }
+    storage_path.to_path_buf()
+}
+
+/// Returns when the given binary package was written to the cache, based on the modification time
+/// of its package folder.
+pub fn package_timestamp(
+    storage_path: &Path,
+    recipe_id: &str,
+    package_id: &str,
+) -> Option<SystemTime> {
+    let package_dir = package_dir(storage_path, recipe_id, package_id)?;
+    std::fs::metadata(package_dir)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Returns when the given binary package was created, read from the timestamp conan writes into
+/// the first line of the conanmanifest.txt in its package folder.
+pub fn package_manifest_time(
+    storage_path: &Path,
+    recipe_id: &str,
+    package_id: &str,
+) -> Option<SystemTime> {
+    let manifest = std::fs::read_to_string(
+        package_dir(storage_path, recipe_id, package_id)?.join("conanmanifest.txt"),
+    )
+    .ok()?;
+    let seconds: u64 = manifest.lines().next()?.trim().parse().ok()?;
+    Some(UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+/// Returns the remote a recipe was retrieved from according to the metadata.json of its cache
+/// folder. Returns `None` if no remote is recorded, e.g. for recipes created locally with
+/// `conan export` or `conan export-pkg`, or if there is no readable metadata.
+pub fn recipe_remote(storage_path: &Path, recipe_id: &str) -> Option<String> {
+    let metadata =
+        std::fs::read_to_string(recipe_dir(storage_path, recipe_id)?.join("metadata.json")).ok()?;
+    let metadata: serde_json::Value = serde_json::from_str(&metadata).ok()?;
+    metadata["recipe"]["remote"]
+        .as_str()
+        .filter(|remote| !remote.is_empty())
+        .map(str::to_owned)
+}
+
+/// Returns the settings a binary package was built with, read from the conaninfo.txt in its
+/// package folder.
+pub fn package_settings(
+    storage_path: &Path,
+    recipe_id: &str,
+    package_id: &str,
+) -> Option<BTreeMap<String, String>> {
+    let conan_info = Ini::load_from_file(
+        package_dir(storage_path, recipe_id, package_id)?.join("conaninfo.txt"),
+    )
+    .ok()?;
+    Some(match conan_info.section(Some("settings")) {
+        Some(settings) => settings
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect(),
+        None => BTreeMap::new(),
+    })
+}
+
+/// Returns the references of the build requirements a binary package was built with, read from
+/// the conaninfo.txt in its package folder. Recipe revisions are stripped from the references.
+pub fn package_build_requires(
+    storage_path: &Path,
+    recipe_id: &str,
+    package_id: &str,
+) -> Vec<String> {
+    let conan_info = match package_dir(storage_path, recipe_id, package_id)
+        .and_then(|package_dir| Ini::load_from_file(package_dir.join("conaninfo.txt")).ok())
+    {
+        Some(conan_info) => conan_info,
+        None => return Vec::new(),
+    };
+    ["full_build_requires", "build_requires"]
+        .iter()
+        .filter_map(|name| conan_info.section(Some(*name)))
+        .flat_map(|section| section.iter())
+        .map(|(reference, _)| reference.split('#').next().unwrap_or(reference).to_owned())
+        .collect()
+}
+
+/// Returns the total size of all files below the given directory. Files that cannot be read, e.g.
+/// due to missing permissions, are not counted. Counts towards `--io-budget`.
+pub fn directory_size(dir: &Path) -> u64 {
+    let mut entry_count = 0;
+    let size = WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .inspect(|_| entry_count += 1)
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| e.metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum();
+    crate::io_budget::consume(entry_count * crate::io_budget::METADATA_BYTES);
+    size
+}
+
+/// Returns the number of bytes available to unprivileged users on the filesystem containing the
+/// given path.
+#[cfg(unix)]
+pub fn free_space(path: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+/// Returns the number of bytes available on the filesystem containing the given path. Not
+/// supported on this platform.
+#[cfg(not(unix))]
+pub fn free_space(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Returns the folder conan 1 places the contents of packages with `short_paths` in, given by
+/// `CONAN_USER_HOME_SHORT` and defaulting to `C:\.conan` on Windows. Returns `None` if short paths
+/// are not used.
+pub fn short_paths_home() -> Option<PathBuf> {
+    match std::env::var_os("CONAN_USER_HOME_SHORT") {
+        Some(path) if path == "None" => None,
+        Some(path) => Some(PathBuf::from(path)),
+        None if cfg!(windows) => Some(PathBuf::from("C:\\.conan")),
+        None => None,
+    }
+}
+
+/// Returns the folder a cache folder is redirected to with `short_paths`, as recorded in the
+/// `.conan_link` file conan places in the cache folder instead of its contents.
+pub fn short_path_target(dir: &Path) -> Option<PathBuf> {
+    let link = std::fs::read_to_string(dir.join(".conan_link")).ok()?;
+    let target = link.trim();
+    if target.is_empty() {
+        None
+    } else {
+        Some(PathBuf::from(target))
+    }
+}
+
+/// A folder in the short paths home whose cache folder no longer exists.
+#[derive(Debug)]
+pub struct ShortPathsOrphan {
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+/// Finds folders in the short paths home that conan left behind after removing their cache folder.
+/// conan records the cache folder each of them belongs to in its `real_path.txt`.
+pub fn find_short_paths_orphans(short_paths_home: &Path) -> Vec<ShortPathsOrphan> {
+    let entries = match std::fs::read_dir(short_paths_home) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut orphans = Vec::new();
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if !path.is_dir() {
+            continue;
+        }
+
+        let real_path = match std::fs::read_to_string(path.join("real_path.txt")) {
+            Ok(real_path) => real_path,
+            // Folders without back-reference weren't created by conan's short_paths handling.
+            Err(_) => continue,
+        };
+        if !Path::new(real_path.trim()).exists() {
+            orphans.push(ShortPathsOrphan {
+                size: directory_size(&path),
+                path,
+            });
+        }
+    }
+    orphans.sort_by(|a, b| a.path.cmp(&b.path));
+    orphans
+}
+
+/// Cheap indicator of whether the contents of a recipe's cache folder changed, obtained without
+/// invoking conan.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecipeFingerprint {
+    /// Modification time of the recipe's export folder in seconds since the UNIX epoch.
+    pub export_mtime: u64,
+    /// Number of binary packages present in the recipe's package folder.
+    pub package_count: usize,
+}
+
+/// Computes the fingerprint of the given recipe from the cache layout. Returns `None` if the
+/// recipe's folder cannot be derived from its reference, conan is not asked for it since that
+/// would cost more than searching the recipe's packages.
+pub fn recipe_fingerprint(storage_path: &Path, recipe_id: &str) -> Option<RecipeFingerprint> {
+    let recipe_dir = layout_recipe_dir(storage_path, recipe_id)
+        .filter(|recipe_dir| is_recipe_dir(recipe_dir))?;
+    let export_mtime = std::fs::metadata(recipe_dir.join("export"))
+        .and_then(|metadata| metadata.modified())
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()?
+        .as_secs();
+    let package_count = match std::fs::read_dir(recipe_dir.join("package")) {
+        Ok(entries) => entries.filter_map(|e| e.ok()).count(),
+        Err(_) => 0,
+    };
+
+    Some(RecipeFingerprint {
+        export_mtime,
+        package_count,
+    })
+}
+
+/// A lock file left behind in the cache by conan.
+#[derive(Debug)]
+pub struct LockFile {
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+/// Finds lock files (`<folder>.count.lock`, `metadata.json.lock`, ...) that were last modified
+/// longer ago than `older_than`. Only the folders conan places its locks in are searched, exported
+/// sources, builds and package contents are never looked at.
+pub fn find_stale_lock_files(storage_path: &Path, older_than: Duration) -> Vec<LockFile> {
+    let cutoff = match SystemTime::now().checked_sub(older_than) {
+        Some(cutoff) => cutoff,
+        None => return Vec::new(),
+    };
+
+    let mut lock_files = Vec::new();
+    for entry in WalkDir::new(storage_path)
+        .max_depth(6)
+        .into_iter()
+        .filter_entry(|e| {
+            !(e.file_type().is_dir()
+                && e.depth() == 5
+                && e.file_name() != "package"
+                && e.file_name() != "locks")
+        })
+        .filter_map(|e| e.ok())
+    {
+        if !entry.file_type().is_file() || !is_removable_lock_file(storage_path, entry.path()) {
+            continue;
+        }
+
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        match metadata.modified() {
+            Ok(modified) if modified < cutoff => lock_files.push(LockFile {
+                path: entry.into_path(),
+                size: metadata.len(),
+            }),
+            _ => {}
+        }
+    }
+    lock_files
+}
+
+/// Returns whether the given path is a lock file inside the storage folder and thus safe to
+/// delete once no conan process holds it anymore. Lock files are named `*.lock`, and conan 1 keeps
+/// the locks of the packages of a recipe in `<storage>/<name>/<version>/<user>/<channel>/locks`.
+pub fn is_removable_lock_file(storage_path: &Path, path: &Path) -> bool {
+    let relative_path = match path.strip_prefix(storage_path) {
+        Ok(relative_path) => relative_path,
+        Err(_) => return false,
+    };
+
+    let components: Vec<_> = relative_path.components().collect();
+    let in_locks_dir = components.len() == 6 && components[4].as_os_str() == "locks";
+    !components.contains(&Component::ParentDir)
+        && (in_locks_dir
+            || path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.ends_with(".lock")))
+}
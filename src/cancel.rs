@@ -0,0 +1,59 @@
+//! Cancellation of a run by an embedding application or by Ctrl+C. The scan, the search, the plan
+//! and the execution take the token to check before starting conan processes and while walking
+//! folders, so that a cancelled run stops at the next such point instead of being killed halfway
+//! through a removal.
+
+use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use std::sync::Arc;
+
+/// Handle to cancel a run. Clones share the same state.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Cancels `token` on the first Ctrl+C. A second Ctrl+C terminates the process right away.
+#[cfg(unix)]
+pub fn cancel_on_interrupt(token: &CancellationToken) {
+    // Signal handlers may only touch atomics, so the handler gets the flag of the token itself.
+    static FLAG: AtomicPtr<AtomicBool> = AtomicPtr::new(std::ptr::null_mut());
+
+    extern "C" fn cancel(_: libc::c_int) {
+        let flag = FLAG.load(Ordering::SeqCst);
+        if !flag.is_null() {
+            unsafe { (*flag).store(true, Ordering::SeqCst) };
+        }
+        unsafe { libc::signal(libc::SIGINT, libc::SIG_DFL) };
+    }
+
+    // The flag is leaked, since the handler may run until the process exits.
+    let flag = Arc::into_raw(Arc::clone(&token.0)) as *mut AtomicBool;
+    FLAG.store(flag, Ordering::SeqCst);
+    unsafe {
+        let previous = libc::signal(
+            libc::SIGINT,
+            cancel as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+        // Background jobs of shells ignore Ctrl+C, which they should keep doing.
+        if previous == libc::SIG_IGN {
+            libc::signal(libc::SIGINT, libc::SIG_IGN);
+        }
+    }
+}
+
+/// Ctrl+C keeps terminating the process right away, there is no way to handle it without the
+/// Windows API.
+#[cfg(not(unix))]
+pub fn cancel_on_interrupt(_token: &CancellationToken) {}
@@ -0,0 +1,2620 @@
+//! The cleanup of a conan home as a whole: scanning the projects for the packages they use,
+//! searching the cache, planning the removal, removing the planned packages after confirmation,
+//! pruning the recipes left without packages and the optional cleanups of `housekeeping` and
+//! `home_misc`. The run takes its settings, like the conan home, from the current `session`.
+
+use crate::cancel::CancellationToken;
+use crate::error::CleanupError;
+use crate::output::{Output, Prompt};
+use crate::review::ChunkAnswer;
+use crate::scan::{self, PackageKey, UsageFile};
+use crate::size::format_size;
+use crate::usage_pattern::{self, UsagePattern};
+use crate::{
+    build_failure, cache, composition, conan, config, duration, execute, fsutil, home_misc,
+    housekeeping, inventory, io_budget, json_output, keep_file, lockfile, logging, migrate, notify,
+    pattern_usage, plan, profile, ranking, reference, reference_lint, resources, resume, review,
+    risk, run_info, search_results, session, state, subprocess, support_bundle, system_log, timing,
+    transcript, wildcard,
+};
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Age of the lock files removed by `clean_locks` unless configured otherwise.
+pub const DEFAULT_LOCKS_OLDER_THAN: &str = "1d";
+
+/// What a run does besides removing the unused packages of the conan home. The defaults are the
+/// ones of the binary without any option.
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Folders scanned for the usage files of the projects, see `scan`.
+    pub root_paths: Vec<String>,
+    /// Only reports what would be removed.
+    pub dry_run: bool,
+    /// Removes without asking for confirmation.
+    pub force: bool,
+    /// Tries to remove packages from a read-only cache instead of only reporting them.
+    pub force_readonly_attempt: bool,
+    /// Removes packages of risk `confirm_risk` and above without confirmation with `force`, instead
+    /// of keeping them.
+    pub force_risky: bool,
+    /// Asks for every recipe whether to remove its packages.
+    pub interactive: bool,
+    /// Does nothing if the previous run started less than this duration ago.
+    pub cooldown: Option<Duration>,
+    /// Runs despite `cooldown`.
+    pub ignore_cooldown: bool,
+    /// Only fails with `CleanupError::CleanupOverdue` if the last cleanup is older than this
+    /// duration, without cleaning anything.
+    pub nag_after: Option<Duration>,
+    /// Prints the estimated duration of the removal per recipe with `dry_run`.
+    pub simulate_timing: bool,
+    /// Patterns of lockfiles whose packages are the packages in use, instead of the scanned ones.
+    pub strict_lockfiles: Option<Vec<String>>,
+    /// Patterns of the usage files to scan for.
+    pub usage_patterns: Vec<UsagePattern>,
+    /// Names of folders not to descend into while scanning.
+    pub excluded_dirs: Vec<String>,
+    /// Usage files larger than this are skipped.
+    pub max_usage_file_size: u64,
+    /// Markers of failed builds, whose packages are only weakly protected. `None` protects all
+    /// packages in use alike.
+    pub failed_build_markers: Option<Vec<build_failure::Marker>>,
+    /// File to write the provenance of the packages in use to.
+    pub provenance_out: Option<PathBuf>,
+    /// Folder for the temporary files of the run.
+    pub temp_dir: PathBuf,
+    /// Cleans the source, build and download folders with `conan cache clean` after the removals.
+    pub use_conan_cache_clean: bool,
+    /// Folder to keep the results of the conan searches in, see `search_results`.
+    pub keep_search_results: Option<PathBuf>,
+    /// Results kept in `keep_search_results` are deleted after this duration.
+    pub keep_search_results_for: Duration,
+    /// Folder of the support bundle, where the search results are kept as well.
+    pub support_bundle: Option<PathBuf>,
+    /// The rules of the plan. The patterns of `keep_file`, the profile of `profile_compat` and the
+    /// budgets of the configuration file are added to them.
+    pub plan: plan::PlanOptions,
+    /// File listing patterns of recipes whose packages are never removed, see `keep_file`.
+    pub keep_file: Option<PathBuf>,
+    /// Checks the packages against the profile of the given name, or the default profile for
+    /// `Some(None)`.
+    pub profile_compat: Option<Option<String>>,
+    /// Refuses to remove anything if the usage files have revisions but the cache doesn't.
+    pub strict_revisions: bool,
+    /// Prints how many recipes each pattern matches.
+    pub patterns_report: bool,
+    /// Fails if a pattern matches no recipe of the cache.
+    pub fail_on_dead_patterns: bool,
+    /// Only reports the recipe references violating the naming rules of conan.
+    pub lint_references: bool,
+    /// Removes the recipes reported by `lint_references`.
+    pub remove_invalid: bool,
+    /// Only searches the recipes that changed since the previous run.
+    pub incremental: bool,
+    /// Fraction of the packages in use that have to exist in the cache for the usage data to be
+    /// considered to belong to it.
+    pub min_usage_overlap: f64,
+    /// Removes packages with `force` although the usage data seems to belong to another cache.
+    pub ignore_mismatch: bool,
+    /// File to export the anonymized composition of the cache to.
+    pub composition_export: Option<PathBuf>,
+    /// Salt of the anonymized composition, by default next to the conan home.
+    pub salt_file: Option<PathBuf>,
+    /// Lists why each package is kept or removed.
+    pub explain: bool,
+    /// Lists every kept package with `explain`, not just the count per rule.
+    pub explain_full: bool,
+    /// Packages of this risk and above are confirmed one by one.
+    pub confirm_risk: risk::RiskLevel,
+    /// Reviews the removal in chunks of up to this many packages.
+    pub review_chunk: Option<usize>,
+    /// Refuses to remove anything unless the plan hashes to this hash.
+    pub require_hash: Option<String>,
+    /// Packages excepted from the approved plan, as `<reference>:<package ID>`.
+    pub exceptions: Vec<String>,
+    /// File listing more `exceptions`.
+    pub except_file: Option<PathBuf>,
+    /// Fails unless there is an interrupted removal of the plan to resume, see `resume`.
+    pub resume: bool,
+    /// Skips measuring the size of the packages.
+    pub no_sizes: bool,
+    /// Number of recipes whose packages are removed at the same time.
+    pub jobs_remove: usize,
+    /// Checks that removed packages are gone from the disk.
+    pub verify: bool,
+    /// Checks that the recipes left without packages exist on a remote before removing them.
+    pub check_recoverable: bool,
+    /// Removes recipes left without packages although `check_recoverable` finds them on no remote.
+    pub prune_local_only: bool,
+    /// Order of the recipes left without packages.
+    pub prune_sort: Option<ranking::SortKey>,
+    /// Only removes the first this many recipes left without packages.
+    pub prune_top: Option<usize>,
+    /// Removes stale lock files, see `housekeeping::clean_lock_files`.
+    pub clean_locks: bool,
+    /// Age of the stale lock files. Defaults to the one of the configuration file, then to
+    /// `DEFAULT_LOCKS_OLDER_THAN`.
+    pub locks_older_than: Option<Duration>,
+    /// Storage folders conan doesn't know about, whose unused packages are deleted.
+    pub extra_storage: Vec<PathBuf>,
+    /// Removes the short_paths folders no package links to.
+    pub clean_short_paths_orphans: bool,
+    /// Removes the entries of the conan home matching `home_misc_patterns`.
+    pub clean_home_misc: bool,
+    /// Patterns of disposable entries of the conan home, see `home_misc`.
+    pub home_misc_patterns: Vec<String>,
+    /// Prints the resources the run used.
+    pub resource_report: bool,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            root_paths: Vec::new(),
+            dry_run: false,
+            force: false,
+            force_readonly_attempt: false,
+            force_risky: false,
+            interactive: false,
+            cooldown: None,
+            ignore_cooldown: false,
+            nag_after: None,
+            simulate_timing: false,
+            strict_lockfiles: None,
+            usage_patterns: vec![
+                UsagePattern::conaninfo(usage_pattern::DEFAULT_CONANINFO_PATTERN),
+                UsagePattern::conanbuildinfo(usage_pattern::DEFAULT_CONANBUILDINFO_PATTERN),
+                UsagePattern::lockfile(usage_pattern::DEFAULT_LOCKFILE_PATTERN),
+            ],
+            excluded_dirs: scan::DEFAULT_EXCLUDED_DIRS
+                .iter()
+                .map(|dir| dir.to_string())
+                .collect(),
+            max_usage_file_size: 5 * 1024 * 1024,
+            failed_build_markers: None,
+            provenance_out: None,
+            temp_dir: std::env::temp_dir(),
+            use_conan_cache_clean: false,
+            keep_search_results: None,
+            keep_search_results_for: Duration::from_secs(30 * 24 * 60 * 60),
+            support_bundle: None,
+            plan: plan::PlanOptions::default(),
+            keep_file: None,
+            profile_compat: None,
+            strict_revisions: false,
+            patterns_report: false,
+            fail_on_dead_patterns: false,
+            lint_references: false,
+            remove_invalid: false,
+            incremental: false,
+            min_usage_overlap: 0.2,
+            ignore_mismatch: false,
+            composition_export: None,
+            salt_file: None,
+            explain: false,
+            explain_full: false,
+            confirm_risk: risk::RiskLevel::High,
+            review_chunk: None,
+            require_hash: None,
+            exceptions: Vec::new(),
+            except_file: None,
+            resume: false,
+            no_sizes: false,
+            jobs_remove: 1,
+            verify: false,
+            check_recoverable: false,
+            prune_local_only: false,
+            prune_sort: None,
+            prune_top: None,
+            clean_locks: false,
+            locks_older_than: None,
+            extra_storage: Vec::new(),
+            clean_short_paths_orphans: false,
+            clean_home_misc: false,
+            home_misc_patterns: home_misc::DEFAULT_PATTERNS
+                .iter()
+                .map(|pattern| pattern.to_string())
+                .collect(),
+            resource_report: false,
+        }
+    }
+}
+
+/// Runs the cleanup in the current session, asking `prompt` for confirmations and printing to
+/// `out`. With `--output json`, the report of the run is printed as JSON document at the end.
+pub fn run(
+    options: &Options,
+    prompt: &mut dyn Prompt,
+    out: &Output,
+    token: &CancellationToken,
+) -> Result<(), CleanupError> {
+    let started_at = Instant::now();
+    let audit = session::current().settings().read_only;
+    let mut report = json_output::Report::default();
+
+    let root_paths: Vec<&str> = options.root_paths.iter().map(String::as_str).collect();
+    report.root_paths = root_paths
+        .iter()
+        .map(|root_path| root_path.to_string())
+        .collect();
+    // Scanning nothing would consider every package unused.
+    for root_path in &root_paths {
+        if !Path::new(root_path).is_dir() {
+            return Err(CleanupError::InvalidArguments(format!(
+                "Root path '{}' is not an existing directory.",
+                root_path
+            )));
+        }
+    }
+    let (root_paths, root_overlaps) = scan::collapse_roots(&root_paths);
+    for overlap in &root_overlaps {
+        if overlap.duplicate {
+            outln!(
+                out,
+                "Note: root path '{}' is the same directory as '{}', scanning it once.",
+                overlap.root_path,
+                overlap.ancestor
+            );
+        } else {
+            outln!(
+                out,
+                "Note: root path '{}' is inside '{}', scanning it as part of it.",
+                overlap.root_path,
+                overlap.ancestor
+            );
+        }
+    }
+    let conan_home = cache::conan_home();
+    let cache_dirs: Vec<_> = conan_home
+        .iter()
+        .cloned()
+        .chain(cache::conan2_home())
+        .collect();
+
+    let storage_path = conan_home.map(|home| (cache::storage_path(&home), home));
+    let state_path = storage_path
+        .as_ref()
+        .map(|(_, home)| state::state_file_path(home));
+    if audit {
+        for dir in cache_dirs.iter().chain(&cache::short_paths_home()) {
+            fsutil::protect(dir);
+        }
+        if let Some((ref storage_path, _)) = storage_path {
+            // The storage folder may be configured outside of the conan home.
+            fsutil::protect(storage_path);
+        }
+    }
+    migrate::run(state_path.as_deref())?;
+    let mut state = state::load_or_default(state_path.as_deref());
+    let config = match storage_path {
+        Some((_, ref conan_home)) => config::load(conan_home)?,
+        None => config::Config::default(),
+    };
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_secs());
+    let cooldown = options
+        .cooldown
+        .filter(|_| !options.ignore_cooldown && options.nag_after.is_none());
+    if let (Some(cooldown), Some(last_run_at)) = (cooldown, state.last_run_at) {
+        // A start in the future was recorded with a skewed clock and must not block forever.
+        if last_run_at > now {
+            warning!(
+                "The previous run is recorded to have started in the future, ignoring --cooldown."
+            );
+        } else if now - last_run_at < cooldown.as_secs() {
+            let next_eligible_at = last_run_at + cooldown.as_secs();
+            if out.is_json() {
+                json_output::print_cooldown(last_run_at, next_eligible_at);
+            } else {
+                outln!(
+                    out,
+                    "Cleanup already ran {} ago, next eligible in {}.",
+                    duration::format_duration(Duration::from_secs(now - last_run_at)),
+                    duration::format_duration(Duration::from_secs(next_eligible_at - now))
+                );
+            }
+            return Ok(());
+        }
+    }
+
+    let cache_read_only = storage_path
+        .as_ref()
+        .is_some_and(|(storage_path, _)| cache::is_read_only(&cache::packages_root(storage_path)));
+    let read_only = match storage_path {
+        _ if audit => {
+            outln!(
+                out,
+                "=================================================================="
+            );
+            outln!(
+                out,
+                "Read-only run (--read-only), nothing will be modified."
+            );
+            outln!(
+                out,
+                "=================================================================="
+            );
+            true
+        }
+        Some((ref storage_path, _)) if cache_read_only => {
+            if options.force_readonly_attempt {
+                outln!(
+                    out,
+                    "The conan cache is read-only, attempting removals anyway."
+                );
+                false
+            } else {
+                outln!(
+                    out,
+                    "=================================================================="
+                );
+                outln!(
+                    out,
+                    "The conan cache '{}' is read-only.",
+                    storage_path.display()
+                );
+                outln!(
+                    out,
+                    "Only reporting what would be removed, nothing will be removed."
+                );
+                outln!(
+                    out,
+                    "Pass --force-readonly-attempt to try removing packages anyway."
+                );
+                outln!(
+                    out,
+                    "=================================================================="
+                );
+                true
+            }
+        }
+        _ => false,
+    };
+    let dry_run = options.dry_run || audit;
+    // Read-only caches and dry runs only report what would be removed.
+    let report_only = read_only || dry_run;
+
+    let since_last_cleanup = state.last_cleanup().map(|last_cleanup| {
+        let finished_at = UNIX_EPOCH + Duration::from_secs(last_cleanup.finished_at);
+        let since = SystemTime::now()
+            .duration_since(finished_at)
+            .unwrap_or_default();
+        outln!(
+            out,
+            "Last cleanup: {} ago, freed {}",
+            duration::format_duration(since),
+            format_size(last_cleanup.freed_bytes)
+        );
+        since
+    });
+    if let Some(nag_after) = options.nag_after {
+        if since_last_cleanup.is_none_or(|since| since > nag_after) {
+            return Err(CleanupError::CleanupOverdue(nag_after));
+        }
+        return Ok(());
+    }
+    if !report_only {
+        state.last_run_at = Some(now);
+        state::save_unless_protected(&state, state_path.as_deref());
+    }
+
+    let locked_packages = options.strict_lockfiles.as_ref().map(|patterns| {
+        if root_paths.is_empty() {
+            outln!(
+                out,
+                "Strict lockfile mode: conaninfo.txt files are not scanned."
+            );
+        } else {
+            outln!(
+                out,
+                "Strict lockfile mode: conaninfo.txt files below '{}' are ignored.",
+                root_paths.join("', '")
+            );
+        }
+        read_locked_packages(out, patterns)
+    });
+    let locked_packages = locked_packages.transpose()?;
+    let usage_patterns = &options.usage_patterns;
+    let excluded_dirs: Vec<&str> = options.excluded_dirs.iter().map(String::as_str).collect();
+    let phase = logging::Phase::start("scanning for usage files");
+    let mut usage_files = match locked_packages {
+        None => scan::find_usage_files(
+            &root_paths,
+            usage_patterns,
+            &excluded_dirs,
+            &cache_dirs,
+            options.max_usage_file_size,
+            token,
+        ),
+        Some(_) => Vec::new(),
+    };
+    phase.finish();
+    check_cancelled(&mut report, token)?;
+    if let Some(ref markers) = options.failed_build_markers {
+        for usage_file in &mut usage_files {
+            usage_file.failed_build = build_failure::find_failure_marker(&usage_file.path, markers);
+        }
+    }
+    if let Some(ref provenance_path) = options.provenance_out {
+        write_provenance(provenance_path, &root_paths, &usage_files).map_err(|err| {
+            CleanupError::File("write provenance to", provenance_path.clone(), err.into())
+        })?;
+    }
+
+    let usage = plan::Usage::new(&usage_files, &root_paths, locked_packages.as_deref());
+    report.packages_in_use = usage_files
+        .iter()
+        .flat_map(|usage_file| &usage_file.packages)
+        .map(|(recipe, package)| json_output::PackageReference {
+            recipe: recipe.clone(),
+            package: package.clone(),
+        })
+        .collect();
+    report.packages_in_use.sort();
+    report.packages_in_use.dedup();
+
+    let temp_dir = &options.temp_dir;
+    let jobs = session::current().settings().conan_jobs.max(1);
+    let use_conan_cache_clean =
+        options.use_conan_cache_clean && !report_only && conan_cache_clean_available(out);
+    let search_results = match (&options.keep_search_results, &options.support_bundle) {
+        (Some(dir), _) => {
+            for (path, result) in search_results::prune(dir, options.keep_search_results_for) {
+                if let Err(err) = result {
+                    eprintln!(
+                        "Failed to remove old search results '{}': {}",
+                        path.display(),
+                        err
+                    );
+                }
+            }
+            let search_results = search_results::SearchResults::kept(dir).map_err(|err| {
+                CleanupError::File(
+                    "create a folder for the search results in",
+                    dir.clone(),
+                    err.into(),
+                )
+            })?;
+            if let search_results::SearchResults::Kept { ref dir, .. } = search_results {
+                outln!(
+                    out,
+                    "Keeping the conan search results in '{}'.",
+                    dir.display()
+                );
+            }
+            search_results
+        }
+        (None, Some(bundle_dir)) => {
+            let dir = bundle_dir.join(support_bundle::SEARCH_RESULTS_DIR);
+            search_results::SearchResults::kept(&dir).map_err(|err| {
+                CleanupError::File(
+                    "create a folder for the search results in",
+                    dir.clone(),
+                    err.into(),
+                )
+            })?
+        }
+        (None, None) => search_results::SearchResults::temporary(temp_dir).map_err(|err| {
+            CleanupError::File("create a temporary folder in", temp_dir.clone(), err.into())
+        })?,
+    };
+    let (plan_options, profile_path, recipe_patterns) =
+        plan_options(options, storage_path.as_ref(), &config)?;
+    if let Some((_, conan_home)) = &storage_path {
+        let mismatching_files =
+            scan::revision_mismatch(&usage_files, cache::revisions_enabled(conan_home));
+        if let Some(example) = mismatching_files.first() {
+            let message = format!(
+                "{} usage files reference revisions, like '{}', but the cache has revisions disabled. They were probably generated on machines with revisions enabled.",
+                mismatching_files.len(),
+                example.display()
+            );
+            if options.strict_revisions {
+                return Err(CleanupError::Refused(format!(
+                    "{} Not removing anything due to --strict-revisions.",
+                    message
+                )));
+            }
+            warning!(
+                "{} Their revisions are ignored to match them against the cache.",
+                message
+            );
+            report.revision_mismatch = Some(json_output::RevisionMismatch {
+                usage_files: mismatching_files
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect(),
+            });
+        }
+    }
+    let phase = logging::Phase::start("searching recipes");
+    let mut recipe_ids = conan::dedup_recipe_ids(conan::conan_search(
+        &search_results,
+        None,
+        conan::parse_recipe_ids,
+        token,
+    )?);
+    phase.finish();
+    check_cancelled(&mut report, token)?;
+    let cache_is_empty = recipe_ids.is_empty();
+    // In an empty cache every pattern is dead, which tells nothing about the patterns.
+    if !cache_is_empty {
+        check_pattern_usage(
+            out,
+            options,
+            pattern_usage::count_matches(recipe_patterns, &recipe_ids),
+            &mut report,
+        )?;
+    }
+    if options.lint_references {
+        lint_references(
+            out,
+            prompt,
+            &recipe_ids,
+            storage_path
+                .as_ref()
+                .map(|(storage_path, _)| storage_path.as_path()),
+            options.remove_invalid && !report_only,
+            options.force,
+            &mut report,
+        )?;
+        if out.is_json() {
+            report.print();
+        }
+        return Ok(());
+    }
+
+    let excluded_recipe_count = recipe_ids
+        .iter()
+        .filter(|recipe_id| plan_options.is_excluded(recipe_id))
+        .count();
+    // Rules like --keep-latest look at all recipes of the cache, not just the included ones.
+    let all_recipe_ids = recipe_ids.clone();
+    recipe_ids.retain(|recipe_id| plan_options.is_included(recipe_id));
+
+    let mut local_index_recipes = HashSet::new();
+    if reference::is_conan2() {
+        let served_recipes = conan::local_index_recipe_ids(&search_results).unwrap_or_else(|| {
+            warning!("Failed to query the local-recipes-index remotes, their recipes are not told apart.");
+            HashSet::new()
+        });
+        local_index_recipes.extend(
+            recipe_ids
+                .iter()
+                .filter(|recipe_id| served_recipes.contains(*recipe_id))
+                .cloned(),
+        );
+        debug!(
+            "{} recipes are served by local-recipes-index remotes, keeping them.",
+            local_index_recipes.len()
+        );
+    }
+
+    let config_hash = effective_config_hash(
+        &root_paths,
+        usage_patterns,
+        &excluded_dirs,
+        options.strict_lockfiles.as_deref(),
+        &plan_options,
+    );
+
+    // conan 2 stores recipes in hashed folders, so they have no fingerprint that could be taken
+    // without asking conan, which would cost as much as searching them.
+    let incremental = options.incremental && !reference::is_conan2();
+    if options.incremental && !incremental {
+        warning!("--incremental is not supported with conan 2, searching all recipes.");
+    }
+    let previous_snapshot = match state.snapshot.take_if(|_| incremental) {
+        Some(snapshot) if snapshot.config_hash == config_hash => Some(snapshot),
+        Some(_) => {
+            outln!(
+                out,
+                "Configuration changed since the previous run, searching all recipes."
+            );
+            None
+        }
+        None if incremental => {
+            outln!(out, "No previous run recorded, searching all recipes.");
+            None
+        }
+        _ => None,
+    };
+
+    let search = conan::search_cache(
+        &search_results,
+        &recipe_ids,
+        storage_path
+            .as_ref()
+            .map(|(storage_path, _)| storage_path.as_path()),
+        previous_snapshot.as_ref(),
+        incremental.then_some(config_hash),
+        jobs,
+        token,
+    );
+    check_cancelled(&mut report, token)?;
+    let search = search?;
+    for (recipe_id, packages) in &search.searched {
+        state::update_package_labels(&mut state.labels, recipe_id, packages);
+    }
+    let package_settings = search.package_settings();
+    let conan::CacheSearch {
+        packages: recipes_and_packages,
+        reused: cached_recipes,
+        snapshot,
+        ..
+    } = search;
+
+    state.labels.retain(|recipe_id, _| {
+        recipes_and_packages.contains_key(recipe_id) || !plan_options.is_included(recipe_id)
+    });
+    if snapshot.is_some() {
+        state.snapshot = snapshot;
+    }
+    if !report_only {
+        state::save_unless_protected(&state, state_path.as_deref());
+    }
+
+    let force = options.force;
+    if let Some(overlap) = plan::usage_overlap(&usage_files, &recipes_and_packages, &plan_options) {
+        if overlap < options.min_usage_overlap {
+            warning!(
+                "Only {:.0}% of the packages used by the scanned projects exist in the local cache.",
+                overlap * 100.0
+            );
+            warning!("The usage data appears to be for a different cache, so packages that are actually in use may be listed for removal.");
+            if force && !options.ignore_mismatch {
+                return Err(CleanupError::Refused("Refusing to remove packages without confirmation. Pass --ignore-mismatch to proceed anyway.".to_owned()));
+            }
+        }
+    }
+
+    if let Some(ref export_path) = options.composition_export {
+        let (storage_path, conan_home) = match storage_path {
+            Some((ref storage_path, ref conan_home)) => (storage_path, conan_home),
+            None => {
+                return Err(CleanupError::UnknownConanHome(
+                    "export the cache composition",
+                ))
+            }
+        };
+        let salt_path = match options.salt_file {
+            Some(ref path) => path.clone(),
+            None => composition::default_salt_path(conan_home),
+        };
+        let salt = composition::load_or_create_salt(&salt_path)
+            .map_err(|err| CleanupError::File("read salt file", salt_path, err.into()))?;
+        let composition = composition::anonymize(
+            &composition::collect(
+                &inventory::take(storage_path),
+                &usage.packages_in_use,
+                &usage.consumers,
+            ),
+            &salt,
+        );
+        fsutil::atomic_write(
+            export_path,
+            serde_json::to_string_pretty(&composition.to_json())
+                .unwrap()
+                .as_bytes(),
+        )
+        .map_err(|err| {
+            CleanupError::File(
+                "write cache composition to",
+                export_path.clone(),
+                err.into(),
+            )
+        })?;
+        outln!(
+            out,
+            "Wrote the composition of {} package versions to '{}'.",
+            composition.groups.len(),
+            export_path.display()
+        );
+    }
+
+    let removal_plan = plan::compute(
+        &usage,
+        &plan::Cache {
+            storage_path: storage_path
+                .as_ref()
+                .map(|(storage_path, _)| storage_path.as_path()),
+            recipe_ids: &all_recipe_ids,
+            packages: &recipes_and_packages,
+            package_settings: &package_settings,
+            labels: &state.labels,
+            read_only: cache_read_only,
+            local_index_recipes: &local_index_recipes,
+        },
+        &plan_options,
+        token,
+    );
+    check_cancelled(&mut report, token)?;
+    let mut removal_plan = removal_plan?;
+    for (recipe_id, package_id) in &removal_plan.age_fallbacks {
+        outln!(
+            out,
+            "Note: '{}:{}' has no readable conanmanifest.txt, its age is the modification time of its folder.",
+            recipe_id,
+            package_id
+        );
+    }
+    print_kept_packages(
+        out,
+        &removal_plan,
+        &plan_options,
+        &usage_files,
+        &state.labels,
+    );
+
+    if let Some(ref profile_path) = profile_path {
+        outln!(
+            out,
+            "{} of {} unused packages are incompatible with profile '{}'.",
+            removal_plan.incompatible.len(),
+            removal_plan.unused_count,
+            profile_path.display()
+        );
+    }
+
+    if !plan_options.exclude.is_empty() {
+        outln!(
+            out,
+            "Skipped {} recipes matching the --exclude patterns.",
+            excluded_recipe_count
+        );
+    }
+
+    if removal_plan.kept_by_keep_file_count > 0 {
+        outln!(
+            out,
+            "Kept {} unused packages since their recipes are listed in the keep file.",
+            removal_plan.kept_by_keep_file_count
+        );
+    }
+
+    let explain = options.explain;
+    if explain && !removal_plan.kept.is_empty() {
+        let explain_full = options.explain_full;
+        outln!(out, "Kept packages:");
+        for (recipe_id, packages) in &removal_plan.kept {
+            let mut reason_counts: BTreeMap<String, usize> = BTreeMap::new();
+            for (_, reason) in packages {
+                // Consumers are only listed in full mode, the summary just counts the rule.
+                *reason_counts.entry(reason.rule()).or_default() += 1;
+            }
+            let summary: Vec<_> = reason_counts
+                .iter()
+                .map(|(rule, count)| format!("{} {}", count, rule))
+                .collect();
+            outln!(
+                out,
+                "{}: {} kept [{}]",
+                recipe_id,
+                packages.len(),
+                summary.join(", ")
+            );
+            if explain_full {
+                for (package_id, reason) in packages {
+                    outln!(
+                        out,
+                        "  {} [{}]",
+                        describe_package(&state.labels, recipe_id, package_id),
+                        reason
+                    );
+                }
+            }
+        }
+    }
+    let removal_reason = if usage.strict {
+        "not pinned by any lockfile"
+    } else {
+        "not required by any scanned conaninfo.txt"
+    };
+
+    let interactive = options.interactive;
+    let review_chunk = options.review_chunk.filter(|_| !report_only);
+    let mut skipped_package_recipes = Vec::new();
+    let mut skipped_empty_recipes = Vec::new();
+    let mut removed_empty_recipes = Vec::new();
+
+    let plan_hash = removal_plan.hash();
+    report.plan_hash = plan_hash.clone();
+    if let Some(ref required_hash) = options.require_hash {
+        if *required_hash != plan_hash {
+            return Err(CleanupError::Refused(format!(
+                "The plan hash {} differs from the required hash {}, the cache or the usage files changed since the plan was approved. Refusing to remove anything.",
+                plan_hash, required_hash
+            )));
+        }
+    }
+    let mut exceptions = options.exceptions.clone();
+    if let Some(ref path) = options.except_file {
+        exceptions.extend(
+            plan::load_exceptions(path).map_err(|err| {
+                CleanupError::File("read exceptions file", path.clone(), err.into())
+            })?,
+        );
+    }
+    let applied_plan_hash = if exceptions.is_empty() {
+        None
+    } else {
+        let parsed_exceptions = exceptions
+            .iter()
+            .map(|text| {
+                plan::Exception::parse(text).ok_or_else(|| {
+                    CleanupError::InvalidArguments(format!(
+                        "'{}' is no package reference of the form <reference>:<package ID>.",
+                        text
+                    ))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let excepted_count = removal_plan.apply_exceptions(&parsed_exceptions)?;
+        outln!(
+            out,
+            "Excepted {} packages from the approved plan with {} exceptions.",
+            excepted_count,
+            parsed_exceptions.len()
+        );
+        if removal_plan.packages.is_empty() {
+            outln!(out, "The exceptions cover the whole plan.");
+        }
+        let applied_plan_hash = removal_plan.hash();
+        report.applied_plan_hash = Some(applied_plan_hash.clone());
+        report.exceptions = exceptions;
+        Some(applied_plan_hash)
+    };
+    let removal_plan = removal_plan;
+    let mut packages_to_remove: BTreeMap<&String, Vec<&String>> = removal_plan
+        .packages
+        .iter()
+        .map(|(recipe_id, package_ids)| (recipe_id, package_ids.iter().collect()))
+        .collect();
+    let resume_path = storage_path
+        .as_ref()
+        .map(|(_, conan_home)| resume::resume_file_path(conan_home));
+    let resumed = match resume_path {
+        Some(ref resume_path) => load_resume_file(out, resume_path, &removal_plan, report_only),
+        None => None,
+    };
+    match resumed {
+        Some(ref resumed) => {
+            outln!(
+                out,
+                "Resuming the interrupted removal of this plan, which removed {} packages already.",
+                resumed.removed_count()
+            );
+            // Removed packages are skipped even if conan still lists them, no removal is repeated.
+            for (recipe_id, package_ids) in packages_to_remove.iter_mut() {
+                package_ids.retain(|package_id| !resumed.is_removed(recipe_id, package_id));
+            }
+            packages_to_remove.retain(|_, package_ids| !package_ids.is_empty());
+        }
+        None if options.resume => {
+            return Err(CleanupError::InvalidArguments(
+                "There is no interrupted removal of this plan to resume.".to_owned(),
+            ))
+        }
+        None => {}
+    }
+    if let (Some(ref resume_path), true) = (&resume_path, !report_only) {
+        if packages_to_remove.is_empty() {
+            finish_resume_file(resume::remove(resume_path));
+        }
+    }
+
+    let mut editable_recipes = BTreeSet::new();
+    // Recipe, package ID and what conan printed of packages that could not be removed.
+    let mut package_removal_failures: Vec<(String, String, String)> = Vec::new();
+    // A run that finds nothing to remove cleaned the cache as well, unless it only reports.
+    let mut packages_removed = !report_only && packages_to_remove.is_empty();
+    let mut freed_bytes = 0;
+    let mut surviving_short_paths = Vec::new();
+    let mut removal_timings = Vec::new();
+    if !packages_to_remove.is_empty() {
+        // How long measuring the packages of each recipe took, which removing them takes again.
+        let mut measured_sizing: BTreeMap<String, Duration> = BTreeMap::new();
+        // Packages only removed for being incompatible are listed under their own heading.
+        let package_sizes: HashMap<(&str, &str), u64> = if options.no_sizes {
+            HashMap::new()
+        } else {
+            packages_to_remove
+                .iter()
+                .flat_map(|(recipe_id, package_ids)| {
+                    package_ids
+                        .iter()
+                        .map(move |package_id| (recipe_id.as_str(), package_id.as_str()))
+                })
+                .filter_map(|key| {
+                    let sizing_started = Instant::now();
+                    let size = package_size(
+                        storage_path
+                            .as_ref()
+                            .map(|(storage_path, _)| storage_path.as_path()),
+                        key.0,
+                        key.1,
+                    );
+                    *measured_sizing.entry(key.0.to_owned()).or_default() +=
+                        sizing_started.elapsed();
+                    Some((key, size?))
+                })
+                .collect()
+        };
+        let print_listing = |packages: &BTreeMap<&String, Vec<&String>>,
+                             heading: &str,
+                             incompatible_only: bool| {
+            let mut heading = Some(heading);
+            for (recipe_id, package_ids) in packages {
+                let package_ids: Vec<_> = package_ids
+                    .iter()
+                    .filter(|package_id| {
+                        removal_plan
+                            .incompatible_removals
+                            .contains(&(recipe_id.to_string(), package_id.to_string()))
+                            == incompatible_only
+                    })
+                    .collect();
+                if package_ids.is_empty() {
+                    continue;
+                }
+
+                if let Some(heading) = heading.take() {
+                    outln!(out, "{}", heading);
+                }
+                let mut notes = Vec::new();
+                if cached_recipes.contains(*recipe_id) {
+                    notes.push("cached".to_owned());
+                }
+                if local_index_recipes.contains(*recipe_id) {
+                    notes.push("local index".to_owned());
+                }
+                let sizes: Vec<_> = package_ids
+                    .iter()
+                    .filter_map(|package_id| {
+                        package_sizes.get(&(recipe_id.as_str(), package_id.as_str()))
+                    })
+                    .collect();
+                if !sizes.is_empty() {
+                    notes.push(format_size(sizes.into_iter().sum()));
+                }
+                if notes.is_empty() {
+                    outln!(out, "{}", recipe_id);
+                } else {
+                    outln!(out, "{} ({})", recipe_id, notes.join(", "));
+                }
+                for package_id in package_ids {
+                    let key = (recipe_id.as_str(), package_id.as_str());
+                    let plan_key = (recipe_id.to_string(), package_id.to_string());
+                    let mut description = if removal_plan.header_only.contains(&plan_key) {
+                        format!("{} [header-only]", package_id)
+                    } else {
+                        describe_package(&state.labels, recipe_id, package_id)
+                    };
+                    if removal_plan.incompatible.contains(&plan_key) {
+                        description.push_str(" [incompatible]");
+                    }
+                    let risk = risk::risk_level(
+                        removal_plan
+                            .risks
+                            .get(&plan_key)
+                            .map_or(&[][..], Vec::as_slice),
+                    );
+                    if risk > risk::RiskLevel::Low {
+                        description.push_str(&format!(" [{} risk]", risk));
+                    }
+                    let consumer_note = removal_plan.notes.get(&plan_key);
+                    if explain {
+                        let reason = consumer_note.map_or(removal_reason, String::as_str);
+                        description.push_str(&format!(" [{}", reason));
+                        if removal_plan.incompatible_removals.contains(&plan_key) {
+                            description.push_str(", incompatible with the profile so not kept");
+                        }
+                        description.push(']');
+                    } else if let Some(note) = consumer_note {
+                        description.push_str(&format!(" [{}]", note));
+                    }
+                    if let Some(&size) = package_sizes.get(&key) {
+                        description.push_str(&format!(" ({})", format_size(size)));
+                    }
+                    if let Some(&age) = removal_plan.ages.get(&plan_key) {
+                        description.push_str(&format!(" [{} old]", duration::format_duration(age)));
+                    }
+                    outln!(out, "  {}", description);
+                }
+            }
+        };
+        match review_chunk {
+            None => {
+                print_listing(&packages_to_remove, "Packages to remove:", false);
+                print_listing(
+                    &packages_to_remove,
+                    "Incompatible packages to remove:",
+                    true,
+                );
+            }
+            Some(chunk_size) => outln!(out,
+                "{} packages of {} recipes to remove, to be reviewed in chunks of up to {} packages.",
+                packages_to_remove.values().map(Vec::len).sum::<usize>(),
+                packages_to_remove.len(),
+                chunk_size
+            ),
+        }
+        if dry_run {
+            for (recipe_id, package_ids) in &packages_to_remove {
+                for package_id in package_ids {
+                    let plan_key = (recipe_id.to_string(), package_id.to_string());
+                    transcript::record_planned(
+                        conan::remove_package_args(recipe_id, package_id),
+                        removal_plan
+                            .notes
+                            .get(&plan_key)
+                            .map_or(removal_reason, String::as_str),
+                    );
+                }
+            }
+        }
+        if !package_sizes.is_empty() {
+            outln!(
+                out,
+                "Removing these packages would free ~{}.",
+                format_size(package_sizes.values().sum())
+            );
+        }
+        // conan can safely remove packages of different recipes concurrently, but removing
+        // packages of the same recipe at the same time races on the recipe's metadata.
+        let jobs = options.jobs_remove.max(1);
+        let timing_estimate = timing::estimate(
+            packages_to_remove
+                .iter()
+                .map(|(recipe_id, package_ids)| (recipe_id.as_str(), package_ids.len())),
+            &state.removal_timings,
+            &measured_sizing,
+            jobs,
+        );
+        if options.simulate_timing {
+            outln!(out, "Estimated removal duration per recipe:");
+            for recipe in &timing_estimate.recipes {
+                outln!(
+                    out,
+                    "  {}: ~{} removal + {} sizing",
+                    recipe.recipe,
+                    duration::format_duration(recipe.removal),
+                    duration::format_duration(recipe.sizing)
+                );
+            }
+            outln!(out, "Estimated {}.", timing_estimate.summary());
+            report.timing_estimate = Some(timing_estimate);
+        } else if !report_only && timing_estimate.confidence > timing::Confidence::Low {
+            outln!(out, "Estimated {}.", timing_estimate.summary());
+        }
+        outln!(out, "Plan hash: {}", plan_hash);
+        if let Some(ref applied_plan_hash) = applied_plan_hash {
+            outln!(out, "Applied plan hash: {}", applied_plan_hash);
+        }
+        report.candidates = packages_to_remove
+            .iter()
+            .map(|(recipe_id, package_ids)| json_output::RecipeCandidates {
+                recipe: recipe_id.to_string(),
+                packages: package_ids.iter().map(|id| id.to_string()).collect(),
+            })
+            .collect();
+
+        if !force && !report_only && !interactive && review_chunk.is_none() {
+            outln!(
+                out,
+                "Do you want to remove the packages listed above? (yes/no)"
+            );
+        }
+
+        if !report_only
+            && (force || interactive || review_chunk.is_some() || prompt.yes_or_no(out)?)
+        {
+            if interactive {
+                skipped_package_recipes = approve_per_recipe(out, prompt, &mut packages_to_remove)?;
+            }
+            confirm_risky_packages(
+                out,
+                prompt,
+                &mut packages_to_remove,
+                &removal_plan.risks,
+                options.confirm_risk,
+                force && !options.force_risky,
+            )?;
+            let plan: Vec<(&str, Vec<&str>)> = packages_to_remove
+                .iter()
+                .map(|(recipe_id, package_ids)| {
+                    (
+                        recipe_id.as_str(),
+                        package_ids
+                            .iter()
+                            .map(|package_id| package_id.as_str())
+                            .collect(),
+                    )
+                })
+                .collect();
+            let chunks = match review_chunk {
+                Some(chunk_size) => review::chunks(&plan, &package_sizes, chunk_size),
+                None => vec![plan],
+            };
+            // Every removal is recorded, so that an interrupted run can be resumed.
+            let progress = resume_path
+                .clone()
+                .map(|path| resume::Progress::start(path, &removal_plan, resumed));
+            // Recipes whose chunk was rejected or not reviewed with --review-chunk.
+            let mut unapproved_recipes = BTreeSet::new();
+            let mut approved_chunk_count = 0;
+            let mut rejected_chunk_count = 0;
+            let mut deferred_chunk_count = 0;
+            let deferred_plan_path = review::deferred_plan_path(
+                storage_path
+                    .as_ref()
+                    .map_or(temp_dir, |(_, conan_home)| conan_home),
+            );
+
+            for (index, chunk) in chunks.iter().enumerate() {
+                let chunk_to_remove: BTreeMap<_, _> = packages_to_remove
+                    .iter()
+                    .filter(|(recipe_id, _)| {
+                        chunk
+                            .iter()
+                            .any(|(chunk_recipe_id, _)| *chunk_recipe_id == recipe_id.as_str())
+                    })
+                    .map(|(&recipe_id, package_ids)| (recipe_id, package_ids.clone()))
+                    .collect();
+                let chunk_packages: BTreeMap<String, Vec<String>> = chunk_to_remove
+                    .iter()
+                    .map(|(&recipe_id, package_ids)| {
+                        (
+                            recipe_id.clone(),
+                            package_ids
+                                .iter()
+                                .map(|&package_id| package_id.clone())
+                                .collect(),
+                        )
+                    })
+                    .collect();
+                if review_chunk.is_some() {
+                    outln!(out, "Chunk {} of {}:", index + 1, chunks.len());
+                    print_listing(&chunk_to_remove, "Packages to remove:", false);
+                    print_listing(&chunk_to_remove, "Incompatible packages to remove:", true);
+                    let chunk_bytes: u64 = chunk
+                        .iter()
+                        .flat_map(|(recipe_id, package_ids)| {
+                            package_ids
+                                .iter()
+                                .map(move |package_id| (*recipe_id, *package_id))
+                        })
+                        .filter_map(|key| package_sizes.get(&key))
+                        .sum();
+                    if !package_sizes.is_empty() {
+                        outln!(
+                            out,
+                            "Removing the packages of this chunk would free ~{}.",
+                            format_size(chunk_bytes)
+                        );
+                    }
+                    outln!(
+                        out,
+                        "Do you want to remove the packages of this chunk? (yes/no/quit)"
+                    );
+                    match prompt.chunk_answer(out)? {
+                        ChunkAnswer::Yes => approved_chunk_count += 1,
+                        ChunkAnswer::No => {
+                            rejected_chunk_count += 1;
+                            unapproved_recipes
+                                .extend(chunk.iter().map(|(recipe_id, _)| *recipe_id));
+                            continue;
+                        }
+                        ChunkAnswer::Quit => {
+                            let remaining = &chunks[index..];
+                            deferred_chunk_count = remaining.len();
+                            unapproved_recipes.extend(
+                                remaining.iter().flatten().map(|(recipe_id, _)| *recipe_id),
+                            );
+                            review::write_deferred_plan(&deferred_plan_path, remaining).map_err(
+                                |err| {
+                                    CleanupError::File(
+                                        "write the deferred plan to",
+                                        deferred_plan_path.clone(),
+                                        err.into(),
+                                    )
+                                },
+                            )?;
+                            break;
+                        }
+                    }
+                }
+
+                let removal_order = execute::removal_order(
+                    &chunk_packages,
+                    storage_path
+                        .as_ref()
+                        .map(|(storage_path, _)| storage_path.as_path()),
+                );
+                if removal_order.waves.len() > 1 {
+                    let tool_recipes: Vec<_> = removal_order.waves[1..].concat();
+                    outln!(
+                        out,
+                        "Removing {} recipes after the packages built with them: {}",
+                        tool_recipes.len(),
+                        tool_recipes.join(", ")
+                    );
+                }
+                if !removal_order.cycle.is_empty() {
+                    eprintln!(
+                        "The packages of these recipes were built with each other, so they are removed in arbitrary order: {}",
+                        removal_order.cycle.join(", ")
+                    );
+                }
+
+                let phase = logging::Phase::start("removing packages");
+                let execution = execute::execute(
+                    &chunk_packages,
+                    &removal_order,
+                    &execute::ExecuteOptions {
+                        storage_path: storage_path
+                            .as_ref()
+                            .map(|(storage_path, _)| storage_path.as_path()),
+                        jobs,
+                        progress: progress.as_ref(),
+                    },
+                    token,
+                );
+                phase.finish();
+                let execution = match execution {
+                    Ok(execution) => execution,
+                    Err(CleanupError::Cancelled(done)) => {
+                        report.removals.extend(done.removals);
+                        return Err(CleanupError::Cancelled(Box::new(std::mem::take(
+                            &mut report,
+                        ))));
+                    }
+                    Err(err) => return Err(err),
+                };
+                report.removals.extend(execution.removals);
+                freed_bytes += execution.freed_bytes;
+                package_removal_failures.extend(execution.failures);
+                editable_recipes.extend(execution.editable_recipes);
+                surviving_short_paths.extend(execution.surviving_short_paths);
+                removal_timings.extend(execution.timings);
+            }
+            if let Some(progress) = progress {
+                finish_resume_file(progress.finish());
+            }
+            packages_to_remove
+                .retain(|recipe_id, _| !unapproved_recipes.contains(recipe_id.as_str()));
+            if review_chunk.is_some() {
+                outln!(
+                    out,
+                    "Reviewed {} of {} chunks: {} approved, {} rejected.",
+                    approved_chunk_count + rejected_chunk_count,
+                    chunks.len(),
+                    approved_chunk_count,
+                    rejected_chunk_count
+                );
+                if deferred_chunk_count > 0 {
+                    outln!(
+                        out,
+                        "The packages of the {} chunks that were not reviewed are listed in '{}'.",
+                        deferred_chunk_count,
+                        deferred_plan_path.display()
+                    );
+                }
+            }
+            packages_removed = true;
+        }
+    } else if cache_is_empty {
+        outln!(out, "Local cache is empty, nothing to do.");
+    } else if recipe_ids.is_empty() && (options.plan.include.is_some() || excluded_recipe_count > 0)
+    {
+        outln!(
+            out,
+            "No recipes match the --include and --exclude patterns."
+        );
+    } else {
+        outln!(out, "No unused packages found.");
+    }
+
+    if !force && !report_only && !interactive {
+        outln!(
+            out,
+            "Do you want to remove recipes that no longer have any packages? (yes/no)"
+        );
+    }
+
+    if dry_run {
+        let emptied_recipes: Vec<_> = recipe_ids
+            .iter()
+            .filter(|recipe_id| !removal_plan.kept_recipes.contains(*recipe_id))
+            .filter(|recipe_id| {
+                !keeps_packages(
+                    &recipes_and_packages[*recipe_id],
+                    &packages_to_remove.get(recipe_id),
+                )
+            })
+            .collect();
+        if !emptied_recipes.is_empty() {
+            outln!(out, "Recipes that would have no packages left:");
+            for recipe_id in emptied_recipes {
+                outln!(out, "  {}", recipe_id);
+                transcript::record_planned(
+                    execute::remove_recipe_args(recipe_id),
+                    "no packages left",
+                );
+            }
+        }
+    }
+
+    let verify = options.verify;
+    let check_recoverable = options.check_recoverable;
+    let prune_local_only = options.prune_local_only;
+    let mut remote_recipes = HashMap::new();
+    let mut recipe_removal_failures = Vec::new();
+    if !report_only && (force || interactive || prompt.yes_or_no(out)?) {
+        let mut empty_recipes = Vec::new();
+        for recipe_id in &recipe_ids {
+            check_cancelled(&mut report, token)?;
+
+            // Recipes that keep at least one package cannot have become empty, so incremental
+            // runs don't need to search them again.
+            if incremental
+                && keeps_packages(
+                    &recipes_and_packages[recipe_id],
+                    &packages_to_remove.get(&recipe_id),
+                )
+            {
+                continue;
+            }
+
+            // Removing the recipe of an editable package would pull it out from under the
+            // editable layout.
+            if editable_recipes.contains(recipe_id.as_str())
+                || removal_plan.kept_recipes.contains(recipe_id)
+            {
+                continue;
+            }
+
+            // conan may have half-removed a package it failed to remove, which must not take the
+            // recipe with it.
+            if package_removal_failures
+                .iter()
+                .any(|(failed_recipe_id, _, _)| failed_recipe_id == recipe_id)
+            {
+                eprintln!(
+                    "Keeping recipe '{}' since some of its packages could not be removed.",
+                    recipe_id
+                );
+                continue;
+            }
+
+            // A recipe whose packages could not be searched may well have packages left.
+            let packages = match conan::conan_search(
+                &search_results,
+                Some(recipe_id),
+                conan::parse_packages,
+                token,
+            ) {
+                Ok(packages) => packages,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    eprintln!(
+                        "Keeping recipe '{}' since its packages could not be searched.",
+                        recipe_id
+                    );
+                    continue;
+                }
+            };
+
+            if packages.is_empty() {
+                empty_recipes.push(recipe_id);
+            }
+        }
+
+        // The recipes to prune, limited to the first ones of --prune-sort with --prune-top.
+        let now = SystemTime::now();
+        let ranks: HashMap<&String, ranking::Rank> = empty_recipes
+            .iter()
+            .map(|recipe_id| {
+                let recipe_dir = storage_path
+                    .as_ref()
+                    .and_then(|(storage_path, _)| cache::recipe_dir(storage_path, recipe_id));
+                let rank = ranking::Rank {
+                    name: recipe_id.as_str(),
+                    size: recipe_dir
+                        .as_deref()
+                        .filter(|_| !options.no_sizes)
+                        .map(cache::directory_size),
+                    age: recipe_dir
+                        .and_then(|dir| std::fs::metadata(dir.join("export")).ok())
+                        .and_then(|metadata| metadata.modified().ok())
+                        .map(|modified| now.duration_since(modified).unwrap_or_default()),
+                };
+                (*recipe_id, rank)
+            })
+            .collect();
+        let prune_top = options.prune_top;
+        // The biggest offenders are pruned first unless told otherwise.
+        let prune_sort = options
+            .prune_sort
+            .or_else(|| prune_top.map(|_| ranking::SortKey::Size));
+        let (empty_recipes, deferred_recipes) =
+            ranking::sort_and_limit(empty_recipes, prune_sort, prune_top, |recipe_id| {
+                ranks[recipe_id]
+            });
+        if !deferred_recipes.is_empty() {
+            outln!(
+                out,
+                "Deferring {} more recipes without packages due to --prune-top:",
+                deferred_recipes.len()
+            );
+            for recipe_id in &deferred_recipes {
+                outln!(out, "  {}{}", recipe_id, describe_rank(&ranks[recipe_id]));
+            }
+        }
+
+        for recipe_id in empty_recipes {
+            check_cancelled(&mut report, token)?;
+            let rank = ranks[recipe_id];
+            if interactive {
+                outln!(
+                    out,
+                    "Remove recipe '{}'{}, which has no packages left? (yes/no)",
+                    recipe_id,
+                    describe_rank(&rank)
+                );
+                if !prompt.yes_or_no(out)? {
+                    skipped_empty_recipes.push(recipe_id.as_str());
+                    continue;
+                }
+            }
+            if check_recoverable {
+                let recoverable =
+                    is_recipe_recoverable(&search_results, &mut remote_recipes, recipe_id);
+                if recoverable != Some(true) {
+                    match recoverable {
+                        Some(_) => outln!(out,
+                            "Recipe '{}' has no packages left but is LOCAL ONLY - not recoverable",
+                            recipe_id
+                        ),
+                        None => outln!(out,
+                            "Recipe '{}' has no packages left but the remotes could not be queried, it may be the only copy",
+                            recipe_id
+                        ),
+                    }
+                    if !prune_local_only {
+                        if force {
+                            outln!(
+                                out,
+                                "Keeping it. Pass --prune-local-only to remove it anyway."
+                            );
+                            continue;
+                        }
+                        outln!(out, "Do you want to remove it anyway? (yes/no)");
+                        if !prompt.yes_or_no(out)? {
+                            continue;
+                        }
+                    }
+                }
+            }
+
+            outln!(
+                out,
+                "Removing recipe '{}' since it has no packages left",
+                recipe_id
+            );
+
+            let removal = execute::remove_recipe(
+                recipe_id,
+                storage_path
+                    .as_ref()
+                    .map(|(storage_path, _)| storage_path.as_path()),
+                verify,
+                token,
+            )?;
+            if let Some(reason) = removal.failure {
+                eprintln!(
+                    "Failed to remove recipe '{}': '{}' {}",
+                    recipe_id, removal.command_line, reason
+                );
+                if !removal.output.stderr.is_empty() {
+                    eprintln!("{}", String::from_utf8_lossy(&removal.output.stderr));
+                }
+                if !removal.output.stdout.is_empty() {
+                    eprintln!("{}", String::from_utf8_lossy(&removal.output.stdout));
+                }
+                report.removals.push(json_output::Removal {
+                    recipe: recipe_id.clone(),
+                    package: None,
+                    error: Some(reason.clone()),
+                });
+                recipe_removal_failures.push((recipe_id, removal.command_line, reason));
+            } else {
+                report.removals.push(json_output::Removal {
+                    recipe: recipe_id.clone(),
+                    package: None,
+                    error: None,
+                });
+                removed_empty_recipes.push(recipe_id.as_str());
+            }
+        }
+    }
+
+    let mut lock_cleanup = housekeeping::LockCleanup::default();
+    if options.clean_locks || config.clean_locks {
+        // An explicit --locks-older-than overrides the configuration file.
+        let older_than = options
+            .locks_older_than
+            .or(config.locks_older_than)
+            .unwrap_or_else(|| duration::parse_duration(DEFAULT_LOCKS_OLDER_THAN).unwrap());
+        match storage_path {
+            Some((ref storage_path, _)) => {
+                lock_cleanup = housekeeping::clean_lock_files(
+                    out,
+                    prompt,
+                    storage_path,
+                    older_than,
+                    force,
+                    report_only,
+                )?
+            }
+            None => {
+                eprintln!("Cannot clean lock files since the conan home could not be determined.")
+            }
+        }
+    }
+
+    // Runs after the removals, so that conan does not clean folders of packages that are removed
+    // anyway.
+    let cache_clean_output = if use_conan_cache_clean {
+        conan_cache_clean()
+    } else {
+        None
+    };
+
+    if !editable_recipes.is_empty() {
+        eprintln!(
+            "Could not remove packages of {} recipes since they are installed as editable:",
+            editable_recipes.len()
+        );
+        for recipe_id in &editable_recipes {
+            eprintln!("  {}", recipe_id);
+        }
+        eprintln!(
+            "Run 'conan editable remove <reference>' first if they should really be removed."
+        );
+    }
+
+    if !surviving_short_paths.is_empty() {
+        eprintln!(
+            "The short_paths folders of {} removed packages were left behind, e.g. since files in them were still in use:",
+            surviving_short_paths.len()
+        );
+        for path in &surviving_short_paths {
+            eprintln!("  {}", path.display());
+        }
+        if !options.clean_short_paths_orphans {
+            eprintln!("Run with --clean-short-paths-orphans to remove them later.");
+        }
+    }
+
+    let mut extra_storage_deleted_bytes = None;
+    if !options.extra_storage.is_empty() && !report_only {
+        let mut deleted_bytes = 0;
+        for extra_storage_path in &options.extra_storage {
+            deleted_bytes += housekeeping::clean_extra_storage(
+                out,
+                prompt,
+                extra_storage_path,
+                storage_path
+                    .as_ref()
+                    .map(|(storage_path, _)| storage_path.as_path()),
+                &usage.packages_in_use,
+                force,
+            )?;
+        }
+        extra_storage_deleted_bytes = Some(deleted_bytes);
+    }
+
+    if options.clean_short_paths_orphans && !report_only {
+        match cache::short_paths_home() {
+            Some(short_paths_home) => {
+                housekeeping::clean_short_paths_orphans(out, prompt, &short_paths_home, force)?
+            }
+            None => {
+                outln!(
+                    out,
+                    "short_paths are not used, no orphaned short_paths folders to remove."
+                )
+            }
+        }
+    }
+
+    if options.clean_home_misc && !report_only {
+        match storage_path {
+            Some((ref storage_path, ref conan_home)) => home_misc::clean(
+                out,
+                prompt,
+                conan_home,
+                storage_path,
+                &options.home_misc_patterns,
+                force,
+            )?,
+            None => eprintln!(
+                "Cannot clean the conan home since the conan home could not be determined."
+            ),
+        }
+    }
+
+    if let Some(deleted_bytes) = extra_storage_deleted_bytes {
+        outln!(out, "Removed through conan: {}", format_size(freed_bytes));
+        outln!(
+            out,
+            "Deleted as plain folders from extra storage folders: {}",
+            format_size(deleted_bytes)
+        );
+    }
+
+    if let Some((budget, touched_bytes, rate)) = io_budget::summary() {
+        outln!(
+            out,
+            "I/O budget: touched {} at an average of {}/s (budget {}/s).",
+            format_size(touched_bytes),
+            format_size(rate as u64),
+            format_size(budget)
+        );
+    }
+
+    if let Some(output) = cache_clean_output {
+        outln!(out, "Cleaned with 'conan cache clean':");
+        for line in output.lines().filter(|line| !line.trim().is_empty()) {
+            outln!(out, "  {}", line);
+        }
+    }
+
+    if interactive && !report_only {
+        let removed_package_recipes: Vec<_> = packages_to_remove
+            .keys()
+            .map(|recipe_id| recipe_id.as_str())
+            .filter(|recipe_id| !editable_recipes.contains(*recipe_id))
+            .filter(|recipe_id| {
+                !package_removal_failures
+                    .iter()
+                    .any(|(failed_recipe_id, _, _)| failed_recipe_id == recipe_id)
+            })
+            .collect();
+        for (heading, recipes) in [
+            ("Removed unused packages of", &removed_package_recipes),
+            ("Skipped unused packages of", &skipped_package_recipes),
+            ("Removed empty recipes:", &removed_empty_recipes),
+            ("Skipped empty recipes:", &skipped_empty_recipes),
+        ] {
+            if !recipes.is_empty() {
+                outln!(out, "{} {}", heading, recipes.join(", "));
+            }
+        }
+    }
+
+    subprocess::print_warning_summary();
+
+    let removal_failed =
+        !package_removal_failures.is_empty() || !recipe_removal_failures.is_empty();
+    if !package_removal_failures.is_empty() {
+        eprintln!("Packages that could not be removed:");
+        for (recipe_id, package_id, output) in &package_removal_failures {
+            eprintln!("  {}:{}", recipe_id, package_id);
+            for line in output.lines().filter(|line| !line.trim().is_empty()) {
+                eprintln!("    {}", line);
+            }
+        }
+    }
+    if !recipe_removal_failures.is_empty() {
+        eprintln!("Recipes that could not be removed:");
+        for (recipe_id, command_line, reason) in &recipe_removal_failures {
+            eprintln!("  {} ('{}' {})", recipe_id, command_line, reason);
+        }
+    }
+    let failure_count = package_removal_failures.len() + recipe_removal_failures.len();
+
+    if packages_removed && !removal_failed {
+        let finished_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since_epoch| since_epoch.as_secs());
+        state.record_cleanup(state::CleanupRecord {
+            finished_at,
+            freed_bytes,
+        });
+    }
+    // Removal times are recorded also of runs where some removals failed.
+    for (recipe_id, sizing, removal) in &removal_timings {
+        state.record_removal(recipe_id, *sizing, *removal);
+    }
+    if packages_removed {
+        state::save_unless_protected(&state, state_path.as_deref());
+    }
+
+    if options.resource_report {
+        print_resource_report(
+            out,
+            &resources::collect(resources::platform_probe().as_ref()),
+        );
+    }
+
+    let summary = json_output::Summary {
+        usage_files: usage_files.len(),
+        failed_usage_files: scan::failed_file_count(),
+        packages_in_use: usage.packages_in_use.packages.len(),
+        recipes_examined: recipes_and_packages.len(),
+        packages_examined: recipes_and_packages.values().map(Vec::len).sum(),
+        packages_removed: report
+            .removals
+            .iter()
+            .filter(|removal| removal.package.is_some() && removal.error.is_none())
+            .count(),
+        failed_package_removals: package_removal_failures.len(),
+        recipes_removed: report
+            .removals
+            .iter()
+            .filter(|removal| removal.package.is_none() && removal.error.is_none())
+            .count(),
+        bytes_freed: freed_bytes,
+        stale_lock_files: lock_cleanup.found,
+        lock_files_removed: lock_cleanup.removed,
+        lock_file_bytes_freed: lock_cleanup.freed_bytes,
+        wall_time: started_at.elapsed(),
+    };
+    print_summary(out, &summary);
+    report.summary = Some(summary);
+
+    if audit {
+        outln!(out, "Read-only run (--read-only), nothing was modified.");
+    } else if dry_run {
+        outln!(out, "Dry run, nothing removed.");
+    }
+
+    notify::run_finished(freed_bytes, failure_count, dry_run);
+    system_log::run_finished(freed_bytes, failure_count, dry_run);
+
+    let result = if removal_failed {
+        Err(CleanupError::RemovalFailed(
+            package_removal_failures.len(),
+            recipe_removal_failures.len(),
+        ))
+    } else {
+        Ok(())
+    };
+    if out.is_json() {
+        match result {
+            Err(ref err) => report.print_with_error(json_output::error_to_json(err)),
+            Ok(()) => report.print(),
+        }
+    }
+    result
+}
+
+/// Completes the rules of the plan with the keep file, the profile of `profile_compat` and the
+/// budgets of the configuration file. Returns them together with the path of the profile packages
+/// are checked against, if any, and the recipe patterns of the budgets, `include`, `exclude` and the
+/// keep file with their sources.
+fn plan_options(
+    options: &Options,
+    storage_path: Option<&(PathBuf, PathBuf)>,
+    config: &config::Config,
+) -> Result<
+    (
+        plan::PlanOptions,
+        Option<PathBuf>,
+        Vec<pattern_usage::RecipePattern>,
+    ),
+    CleanupError,
+> {
+    let profile_path = match options.profile_compat {
+        Some(ref name) => match storage_path {
+            Some((_, conan_home)) => Some(profile::profile_path(conan_home, name.as_deref())),
+            None => return Err(CleanupError::UnknownConanHome("find the profile")),
+        },
+        None => None,
+    };
+    let profile = match profile_path {
+        Some(ref profile_path) => Some(profile::load_settings(profile_path)?),
+        None => None,
+    };
+    let keep_file_lines = match options.keep_file {
+        Some(ref path) => keep_file::load(path)
+            .map_err(|err| CleanupError::File("read keep file", path.clone(), err.into()))?,
+        None => Vec::new(),
+    };
+    let keep_patterns = keep_file_lines
+        .iter()
+        .map(|(_, pattern)| pattern.clone())
+        .collect();
+    let recipe_patterns = config
+        .budgets
+        .iter()
+        .map(|budget| (budget.pattern.clone(), pattern_usage::PatternSource::Budget))
+        .chain(
+            options
+                .plan
+                .include
+                .iter()
+                .flatten()
+                .map(|pattern| (pattern.clone(), pattern_usage::PatternSource::Include)),
+        )
+        .chain(
+            options
+                .plan
+                .exclude
+                .iter()
+                .map(|pattern| (pattern.clone(), pattern_usage::PatternSource::Exclude)),
+        )
+        .chain(keep_file_lines.into_iter().map(|(line, pattern)| {
+            let path = options.keep_file.clone().unwrap();
+            (pattern, pattern_usage::PatternSource::KeepFile(path, line))
+        }))
+        .collect();
+
+    let plan_options = plan::PlanOptions {
+        keep_patterns,
+        profile,
+        budgets: config.budgets.clone(),
+        ..options.plan.clone()
+    };
+    Ok((plan_options, profile_path, recipe_patterns))
+}
+
+/// Warns about the recipe patterns that match no recipe of the cache, prints the match counts of
+/// all patterns with `--patterns-report` and fails on dead patterns with `--fail-on-dead-patterns`.
+fn check_pattern_usage(
+    out: &Output,
+    options: &Options,
+    usages: Vec<pattern_usage::PatternUsage>,
+    report: &mut json_output::Report,
+) -> Result<(), CleanupError> {
+    if options.patterns_report && !usages.is_empty() {
+        let source_width = usages
+            .iter()
+            .map(|usage| usage.source.to_string().len())
+            .max()
+            .unwrap_or(0)
+            .max("Source".len());
+        outln!(out, "Recipe patterns:");
+        outln!(
+            out,
+            "  {:>7}  {:<width$}  Pattern",
+            "Matches",
+            "Source",
+            width = source_width
+        );
+        for usage in &usages {
+            outln!(
+                out,
+                "  {:>7}  {:<width$}  {}",
+                usage.matches,
+                usage.source.to_string(),
+                usage.pattern,
+                width = source_width
+            );
+        }
+    }
+    let dead_patterns: Vec<_> = usages.into_iter().filter(|usage| usage.is_dead()).collect();
+    if dead_patterns.is_empty() {
+        return Ok(());
+    }
+    if options.fail_on_dead_patterns {
+        return Err(CleanupError::DeadPatterns(dead_patterns));
+    }
+    for usage in &dead_patterns {
+        warning!(
+            "Pattern '{}' of {} matches no recipe in the cache.",
+            usage.pattern,
+            usage.source
+        );
+    }
+    report.dead_patterns = dead_patterns;
+    Ok(())
+}
+
+/// Prints the unused packages kept by the rules of the plan, or how many each rule kept.
+fn print_kept_packages(
+    out: &Output,
+    removal_plan: &plan::RemovalPlan,
+    options: &plan::PlanOptions,
+    usage_files: &[UsageFile],
+    labels: &HashMap<String, HashMap<String, state::PackageLabel>>,
+) {
+    let kept_by = |rule: plan::KeepReason| -> BTreeMap<&String, Vec<&String>> {
+        removal_plan
+            .kept
+            .iter()
+            .filter_map(|(recipe_id, packages)| {
+                let package_ids: Vec<_> = packages
+                    .iter()
+                    .filter(|(_, reason)| *reason == rule)
+                    .map(|(package_id, _)| package_id)
+                    .collect();
+                (!package_ids.is_empty()).then_some((recipe_id, package_ids))
+            })
+            .collect()
+    };
+
+    let preserved_packages = kept_by(plan::KeepReason::NewestOfName);
+    if !preserved_packages.is_empty() {
+        outln!(
+            out,
+            "Unused packages kept as the newest {} of their name:",
+            options.preserve_newest_per_name.unwrap_or_default()
+        );
+        for (recipe_id, package_ids) in &preserved_packages {
+            outln!(out, "{}", recipe_id);
+            for package_id in package_ids {
+                outln!(out, "  {}", describe_package(labels, recipe_id, package_id));
+            }
+        }
+    }
+
+    for (recipe_id, packages) in &removal_plan.kept {
+        let latest_count = packages.iter().find_map(|(_, reason)| match reason {
+            plan::KeepReason::Latest(count) => Some(count),
+            _ => None,
+        });
+        if let Some(count) = latest_count {
+            outln!(out, "{} kept (latest {})", recipe_id, count);
+        }
+    }
+
+    let weakly_protected_packages = kept_by(plan::KeepReason::WeaklyProtected);
+    if !weakly_protected_packages.is_empty() {
+        outln!(
+            out,
+            "Weakly protected packages (only used by builds that apparently failed):"
+        );
+        for (recipe_id, package_ids) in &weakly_protected_packages {
+            outln!(out, "{}", recipe_id);
+            for package_id in package_ids {
+                outln!(out, "  {}", describe_package(labels, recipe_id, package_id));
+                let package_key = PackageKey::new(recipe_id, package_id);
+                for usage_file in usage_files {
+                    let failed_build = match usage_file.failed_build {
+                        Some(ref failed_build) => failed_build,
+                        None => continue,
+                    };
+                    if usage_file.packages.iter().any(|(recipe_id, package_id)| {
+                        PackageKey::new(recipe_id, package_id) == package_key
+                    }) {
+                        outln!(
+                            out,
+                            "    used by {} ({})",
+                            usage_file.path.display(),
+                            failed_build
+                        );
+                    }
+                }
+            }
+        }
+        outln!(
+            out,
+            "Pass --remove-weakly-protected to remove them as well."
+        );
+    }
+
+    let header_only_count =
+        removal_plan.kept_count(|reason| *reason == plan::KeepReason::HeaderOnly);
+    if header_only_count > 0 {
+        outln!(
+            out,
+            "Skipped {} unused header-only packages.",
+            header_only_count
+        );
+    }
+
+    let recent_local_count =
+        removal_plan.kept_count(|reason| *reason == plan::KeepReason::RecentLocalBuild);
+    if recent_local_count > 0 {
+        outln!(
+            out,
+            "Kept {} unused packages that look like recent local builds. Pass --include-recent-local to remove them as well.",
+            recent_local_count
+        );
+    }
+
+    let read_only_layer_count =
+        removal_plan.kept_count(|reason| *reason == plan::KeepReason::ReadOnlyLayer);
+    if read_only_layer_count > 0 {
+        outln!(
+            out,
+            "Kept {} unused packages in a read-only layer of the cache.",
+            read_only_layer_count
+        );
+    }
+
+    let too_young_count =
+        removal_plan.kept_count(|reason| matches!(reason, plan::KeepReason::TooYoung(_)));
+    if let Some(older_than) = options.older_than.filter(|_| too_young_count > 0) {
+        outln!(
+            out,
+            "Kept {} unused packages modified within the last {}.",
+            too_young_count,
+            duration::format_duration(older_than)
+        );
+    }
+}
+
+fn print_resource_report(out: &Output, report: &resources::ResourceReport) {
+    let unknown = || "unknown".to_owned();
+    outln!(out, "Resource usage:");
+    outln!(
+        out,
+        "  Peak memory: {}",
+        report.peak_memory.map_or_else(unknown, format_size)
+    );
+    outln!(
+        out,
+        "  conan processes: {} (CPU time: {})",
+        report.subprocess_count,
+        report
+            .children_cpu_time
+            .map_or_else(unknown, |time| format!("{:.1}s", time.as_secs_f64()))
+    );
+    outln!(
+        out,
+        "  Usage files read: {}",
+        format_size(report.scanned_bytes)
+    );
+    outln!(
+        out,
+        "  JSON parsed: {}",
+        format_size(report.parsed_json_bytes)
+    );
+}
+
+fn print_summary(out: &Output, summary: &json_output::Summary) {
+    outln!(out, "Summary:");
+    outln!(
+        out,
+        "  Usage files parsed: {} ({} failed)",
+        summary.usage_files,
+        summary.failed_usage_files
+    );
+    outln!(out, "  Packages in use: {}", summary.packages_in_use);
+    outln!(
+        out,
+        "  Examined: {} recipes with {} packages",
+        summary.recipes_examined,
+        summary.packages_examined
+    );
+    outln!(
+        out,
+        "  Packages removed: {} ({} failed)",
+        summary.packages_removed,
+        summary.failed_package_removals
+    );
+    outln!(out, "  Recipes removed: {}", summary.recipes_removed);
+    if summary.stale_lock_files > 0 {
+        outln!(
+            out,
+            "  Lock files removed: {} of {} ({})",
+            summary.lock_files_removed,
+            summary.stale_lock_files,
+            format_size(summary.lock_file_bytes_freed)
+        );
+    }
+    outln!(out, "  Wall time: {:.1}s", summary.wall_time.as_secs_f64());
+}
+
+/// Describes the size and age of a recipe to prune, e.g. " (1.2 MB, 3 weeks old)", or nothing if
+/// neither is known.
+fn describe_rank(rank: &ranking::Rank) -> String {
+    let mut notes = Vec::new();
+    if let Some(size) = rank.size {
+        notes.push(format_size(size));
+    }
+    if let Some(age) = rank.age {
+        notes.push(format!("{} old", duration::format_duration(age)));
+    }
+    if notes.is_empty() {
+        String::new()
+    } else {
+        format!(" ({})", notes.join(", "))
+    }
+}
+
+/// Returns whether the recipe exists on any configured remote, or `None` if the remotes could not
+/// be queried. Remotes are searched once per package name, the results are kept in
+/// `remote_recipes`.
+fn is_recipe_recoverable(
+    search_results: &search_results::SearchResults,
+    remote_recipes: &mut HashMap<String, Option<HashSet<String>>>,
+    recipe_id: &str,
+) -> Option<bool> {
+    let recipe_id = recipe_id.split('#').next().unwrap_or(recipe_id);
+    let name = recipe_id.split('/').next().unwrap_or(recipe_id);
+    remote_recipes
+        .entry(name.to_owned())
+        .or_insert_with(|| conan::search_remotes(search_results, name))
+        .as_ref()
+        .map(|recipe_ids| recipe_ids.contains(recipe_id))
+}
+
+/// Formats a package ID for listings, followed by its label if known.
+fn describe_package(
+    labels: &HashMap<String, HashMap<String, state::PackageLabel>>,
+    recipe_id: &str,
+    package_id: &str,
+) -> String {
+    match labels
+        .get(recipe_id)
+        .and_then(|recipe_labels| recipe_labels.get(package_id))
+    {
+        Some(label) => format!("{} ({})", package_id, label.label),
+        None => package_id.to_owned(),
+    }
+}
+
+/// Reads the packages pinned by all lockfiles matching the given patterns. Fails if no lockfile
+/// matches or a lockfile cannot be read.
+fn read_locked_packages(
+    out: &Output,
+    patterns: &[String],
+) -> Result<Vec<lockfile::LockedPackage>, CleanupError> {
+    let mut lockfiles: Vec<_> = patterns
+        .iter()
+        .flat_map(|pattern| wildcard::find_files(pattern))
+        .collect();
+    lockfiles.sort();
+    lockfiles.dedup();
+    if lockfiles.is_empty() {
+        return Err(CleanupError::InvalidArguments(
+            "No lockfiles match the patterns given by --strict-lockfiles.".to_owned(),
+        ));
+    }
+
+    let mut locked_packages = Vec::new();
+    for path in &lockfiles {
+        match lockfile::parse_lockfile(path) {
+            Ok(packages) => locked_packages.extend(packages),
+            Err(err) => {
+                return Err(CleanupError::File(
+                    "read lockfile",
+                    path.clone(),
+                    err.into(),
+                ))
+            }
+        }
+    }
+    outln!(
+        out,
+        "Using {} lockfiles pinning {} packages.",
+        lockfiles.len(),
+        locked_packages.len()
+    );
+    Ok(locked_packages)
+}
+
+/// Writes the provenance document, listing every scanned project directory with its usage files
+/// and the packages they reference.
+fn write_provenance(
+    path: &Path,
+    root_paths: &[&str],
+    usage_files: &[UsageFile],
+) -> std::io::Result<()> {
+    let mut projects: BTreeMap<String, Vec<serde_json::Value>> = BTreeMap::new();
+    for usage_file in usage_files {
+        let project_dir = usage_file
+            .path
+            .parent()
+            .map(|dir| dir.display().to_string())
+            .unwrap_or_default();
+        let packages: Vec<_> = usage_file
+            .packages
+            .iter()
+            .map(|(reference, package_id)| {
+                serde_json::json!({ "reference": reference, "package_id": package_id })
+            })
+            .collect();
+        projects
+            .entry(project_dir)
+            .or_default()
+            .push(serde_json::json!({
+                "path": usage_file.path.display().to_string(),
+                "type": usage_file.pattern.format.name(),
+                "pattern": usage_file.pattern.to_string(),
+                "sha256": usage_file.content_hash,
+                "packages": packages,
+            }));
+    }
+
+    let projects: Vec<_> = projects
+        .into_iter()
+        .map(|(directory, files)| serde_json::json!({ "directory": directory, "files": files }))
+        .collect();
+    let document = serde_json::json!({
+        "version": 1,
+        "run_info": run_info::to_json(),
+        "scan_roots": root_paths,
+        "projects": projects,
+    });
+    fsutil::atomic_write(path, serde_json::to_string_pretty(&document)?.as_bytes())
+}
+
+/// Returns whether `conan cache clean` can be used, which only exists in later releases of conan 2.
+/// Prints a notice if it cannot.
+fn conan_cache_clean_available(out: &Output) -> bool {
+    if !reference::is_conan2() {
+        outln!(
+            out,
+            "'conan cache clean' requires conan 2, ignoring --use-conan-cache-clean."
+        );
+        return false;
+    }
+    let available = subprocess::run_conan(&["cache", "clean", "--help"])
+        .is_ok_and(|output| output.status.success());
+    if !available {
+        outln!(out,
+            "This conan version does not support 'conan cache clean', source, build and download folders are left untouched."
+        );
+    }
+    available
+}
+
+/// Cleans the source, build and download folders of all recipes and packages in the cache with
+/// `conan cache clean`. Returns the output of conan or `None` if it failed.
+fn conan_cache_clean() -> Option<String> {
+    let args = ["cache", "clean", "*", "--source", "--build", "--download"];
+    let command_line = conan::command_line(&args);
+    match subprocess::run_conan(&args) {
+        Ok(output) if output.status.success() => Some(
+            String::from_utf8_lossy(&output.stdout).into_owned()
+                + &String::from_utf8_lossy(&output.stderr),
+        ),
+        Ok(output) => {
+            eprintln!(
+                "'{}' failed: {}",
+                command_line,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            None
+        }
+        Err(err) => {
+            eprintln!("Failed to run '{}': {}", command_line, err);
+            None
+        }
+    }
+}
+
+/// Returns the size of a package in the cache including its short_paths folder, or `None` if its
+/// package folder cannot be found.
+fn package_size(storage_path: Option<&Path>, recipe_id: &str, package_id: &str) -> Option<u64> {
+    let package_dir = storage_path
+        .and_then(|storage_path| cache::package_dir(storage_path, recipe_id, package_id))
+        .filter(|package_dir| package_dir.exists())?;
+    let short_path_size = cache::short_path_target(&package_dir)
+        .map_or(0, |short_path| cache::directory_size(&short_path));
+    Some(cache::directory_size(&package_dir) + short_path_size)
+}
+
+/// Reports cached recipes whose references violate the naming rules of the installed conan
+/// version, grouped by rule. Recipes that exist in the storage folder but are not listed by conan
+/// are checked as well. If `remove` is set, the offending recipes are removed after confirmation.
+fn lint_references(
+    out: &Output,
+    prompt: &mut dyn Prompt,
+    recipe_ids: &[String],
+    storage_path: Option<&Path>,
+    remove: bool,
+    force: bool,
+    report: &mut json_output::Report,
+) -> Result<(), CleanupError> {
+    let conan_major = reference::conan_version().map_or(1, |version| version.major);
+    let mut references: BTreeSet<String> = recipe_ids
+        .iter()
+        .map(|recipe_id| reference::canonical(recipe_id).to_owned())
+        .collect();
+    if let Some(storage_path) = storage_path.filter(|_| conan_major < 2) {
+        references.extend(
+            cache::find_layout_recipes(storage_path)
+                .into_iter()
+                .map(|recipe| recipe.recipe_id),
+        );
+    }
+
+    let mut violations_per_rule: BTreeMap<(&str, &str), Vec<&str>> = BTreeMap::new();
+    let mut invalid_references = Vec::new();
+    for recipe_id in &references {
+        let violations = reference_lint::violations(recipe_id, conan_major);
+        if violations.is_empty() {
+            continue;
+        }
+        for rule in &violations {
+            violations_per_rule
+                .entry((rule.name, rule.description))
+                .or_default()
+                .push(recipe_id);
+        }
+        report
+            .reference_violations
+            .push(json_output::ReferenceViolation {
+                recipe: recipe_id.clone(),
+                rules: violations.iter().map(|rule| rule.name.to_owned()).collect(),
+            });
+        invalid_references.push(recipe_id.as_str());
+    }
+
+    if invalid_references.is_empty() {
+        outln!(
+            out,
+            "All {} cached recipe references follow the naming rules of conan {}.",
+            references.len(),
+            conan_major
+        );
+        return Ok(());
+    }
+    warning!(
+        "{} of {} cached recipe references violate the naming rules of conan {}:",
+        invalid_references.len(),
+        references.len(),
+        conan_major
+    );
+    for ((name, description), recipe_ids) in &violations_per_rule {
+        eprintln!("  {} ({}): {}", name, recipe_ids.len(), description);
+        for recipe_id in recipe_ids {
+            eprintln!("    {}", recipe_id);
+        }
+    }
+
+    if !remove {
+        return Ok(());
+    }
+    if !force {
+        outln!(
+            out,
+            "Do you want to remove these {} recipes? (yes/no)",
+            invalid_references.len()
+        );
+        if !prompt.yes_or_no(out)? {
+            return Ok(());
+        }
+    }
+    for recipe_id in invalid_references {
+        let error = remove_invalid_recipe(out, storage_path, recipe_id).err();
+        if let Some(ref err) = error {
+            eprintln!("Failed to remove recipe '{}': {}", recipe_id, err);
+        }
+        report.removals.push(json_output::Removal {
+            recipe: recipe_id.to_owned(),
+            package: None,
+            error,
+        });
+    }
+    Ok(())
+}
+
+/// Removes a recipe with conan, or deletes its folder in the storage folder if conan cannot remove
+/// it by reference.
+fn remove_invalid_recipe(
+    out: &Output,
+    storage_path: Option<&Path>,
+    recipe_id: &str,
+) -> Result<(), String> {
+    let reference = reference::argument(recipe_id);
+    let confirm = if reference::is_conan2() { "-c" } else { "-f" };
+    let remove_args = ["remove", &reference, confirm];
+    let conan_error = match subprocess::run_conan(&remove_args) {
+        Ok(output)
+            if output.status.success()
+                && storage_path
+                    .and_then(|storage_path| cache::recipe_dir(storage_path, recipe_id))
+                    .is_none_or(|recipe_dir| !recipe_dir.exists()) =>
+        {
+            outln!(out, "Removed recipe '{}'", recipe_id);
+            return Ok(());
+        }
+        Ok(output) if output.status.success() => {
+            "succeeded but the recipe is still in the cache".to_owned()
+        }
+        Ok(output) => format!("exited with {}", output.status),
+        Err(err) => err.to_string(),
+    };
+
+    let recipe_dir = storage_path.and_then(|storage_path| {
+        let recipe_dir = cache::recipe_dir(storage_path, recipe_id)?;
+        let canonical_storage = std::fs::canonicalize(storage_path).ok()?;
+        let canonical_dir = std::fs::canonicalize(&recipe_dir).ok()?;
+        cache::is_inside_storage(&canonical_storage, &canonical_dir).then_some(canonical_dir)
+    });
+    let recipe_dir = match recipe_dir {
+        Some(recipe_dir) => recipe_dir,
+        None => {
+            return Err(format!(
+                "'{}' {}",
+                conan::command_line(&remove_args),
+                conan_error
+            ))
+        }
+    };
+    fsutil::remove_dir_all(&recipe_dir).map_err(|err| {
+        format!(
+            "'{}' {} and deleting '{}' failed: {}",
+            conan::command_line(&remove_args),
+            conan_error,
+            recipe_dir.display(),
+            err
+        )
+    })?;
+    outln!(
+        out,
+        "Deleted folder '{}' of recipe '{}' since '{}' {}",
+        recipe_dir.display(),
+        recipe_id,
+        conan::command_line(&remove_args),
+        conan_error
+    );
+    Ok(())
+}
+
+fn keeps_packages(package_ids: &[String], package_ids_to_remove: &Option<&Vec<&String>>) -> bool {
+    match package_ids_to_remove {
+        Some(to_remove) => package_ids.iter().any(|id| !to_remove.contains(&id)),
+        None => !package_ids.is_empty(),
+    }
+}
+
+/// Hashes all options that influence which packages are considered unused, so that snapshots
+/// taken with a different configuration are not reused.
+fn effective_config_hash(
+    root_paths: &[&str],
+    usage_patterns: &[usage_pattern::UsagePattern],
+    excluded_dirs: &[&str],
+    strict_lockfiles: Option<&[String]>,
+    options: &plan::PlanOptions,
+) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    let mut root_paths: Vec<_> = root_paths
+        .iter()
+        .map(|root_path| std::fs::canonicalize(root_path).unwrap_or_else(|_| root_path.into()))
+        .collect();
+    root_paths.sort();
+    root_paths.hash(&mut hasher);
+    usage_patterns.hash(&mut hasher);
+    excluded_dirs.hash(&mut hasher);
+    strict_lockfiles.hash(&mut hasher);
+    options.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Loads the resume file of an interrupted removal if it belongs to the given plan. Resume files of
+/// other plans and unreadable ones are deleted with a notice, unless the run only reports.
+fn load_resume_file(
+    out: &Output,
+    path: &Path,
+    plan: &plan::RemovalPlan,
+    report_only: bool,
+) -> Option<resume::Progress> {
+    let problem = match resume::Progress::load(path) {
+        Ok(None) => return None,
+        Ok(Some(progress)) if progress.matches(plan) => return Some(progress),
+        Ok(Some(_)) => "belongs to a different plan, the cache or the usage files changed since the removal was interrupted".to_owned(),
+        Err(err) => format!("cannot be read: {}", err),
+    };
+    if report_only {
+        outln!(
+            out,
+            "Note: The resume file '{}' {}.",
+            path.display(),
+            problem
+        );
+    } else {
+        outln!(
+            out,
+            "Note: The resume file '{}' {}, removing it.",
+            path.display(),
+            problem
+        );
+        finish_resume_file(resume::remove(path));
+    }
+    None
+}
+
+/// Warns if the resume file cannot be deleted, which makes the next run of the same plan skip
+/// packages it would remove otherwise.
+fn finish_resume_file(result: std::io::Result<()>) {
+    if let Err(err) = result {
+        warning!("Failed to delete the resume file: {}", err);
+    }
+}
+
+/// Asks for every recipe whether its planned packages should be removed. Recipes that are not
+/// approved are dropped from the plan and returned.
+fn approve_per_recipe<'a>(
+    out: &Output,
+    prompt: &mut dyn Prompt,
+    packages_to_remove: &mut BTreeMap<&'a String, Vec<&String>>,
+) -> Result<Vec<&'a str>, CleanupError> {
+    let mut skipped_recipes = Vec::new();
+    for (recipe_id, package_ids) in packages_to_remove.iter() {
+        outln!(
+            out,
+            "Remove {} unused packages of {}? (yes/no)",
+            package_ids.len(),
+            recipe_id
+        );
+        if !prompt.yes_or_no(out)? {
+            skipped_recipes.push(recipe_id.as_str());
+        }
+    }
+    packages_to_remove.retain(|recipe_id, _| !skipped_recipes.contains(&recipe_id.as_str()));
+    Ok(skipped_recipes)
+}
+
+/// Asks for every planned package at or above the given risk level whether it should really be
+/// removed, after all packages were confirmed at once. Packages that are not confirmed are dropped
+/// from the plan. If `keep_risky` is set, e.g. since packages are removed without confirmation,
+/// such packages are dropped without asking.
+fn confirm_risky_packages(
+    out: &Output,
+    prompt: &mut dyn Prompt,
+    packages_to_remove: &mut BTreeMap<&String, Vec<&String>>,
+    risk_reasons: &HashMap<(String, String), Vec<risk::RiskReason>>,
+    min_level: risk::RiskLevel,
+    keep_risky: bool,
+) -> Result<(), CleanupError> {
+    let mut kept_count = 0;
+    for (recipe_id, package_ids) in packages_to_remove.iter_mut() {
+        let mut confirmed_ids = Vec::new();
+        for package_id in package_ids.iter() {
+            let reasons = risk_reasons
+                .get(&(recipe_id.to_string(), package_id.to_string()))
+                .map_or(&[][..], Vec::as_slice);
+            let level = risk::risk_level(reasons);
+            if level < min_level {
+                confirmed_ids.push(*package_id);
+                continue;
+            }
+            if keep_risky {
+                kept_count += 1;
+                continue;
+            }
+
+            outln!(
+                out,
+                "Package {} of {} is of {} risk:",
+                package_id,
+                recipe_id,
+                level
+            );
+            for reason in reasons {
+                outln!(out, "  {}", reason.description());
+            }
+            outln!(out, "Do you really want to remove it? (yes/no)");
+            if prompt.yes_or_no(out)? {
+                confirmed_ids.push(*package_id);
+            }
+        }
+        *package_ids = confirmed_ids;
+    }
+    packages_to_remove.retain(|_, package_ids| !package_ids.is_empty());
+
+    if kept_count > 0 {
+        outln!(out,
+            "Kept {} packages of at least {} risk. Pass --force-risky to remove them without confirmation.",
+            kept_count, min_level
+        );
+    }
+    Ok(())
+}
+
+/// Aborts the run if it was cancelled, handing over what was done until then.
+fn check_cancelled(
+    report: &mut json_output::Report,
+    token: &CancellationToken,
+) -> Result<(), CleanupError> {
+    if token.is_cancelled() {
+        return Err(CleanupError::Cancelled(Box::new(std::mem::take(report))));
+    }
+    Ok(())
+}
@@ -0,0 +1,227 @@
+//! Anonymized summary of what a cache consists of, grouped by package name and version, for
+//! `--composition-export`. It tells package maintainers how much of the caches their packages
+//! occupy without revealing the projects: consumer paths, user/channel values and the hostname are
+//! replaced by hashes salted with a secret kept on the machine. Exports of the same machine can
+//! thus be correlated, but not exports of different machines.
+
+use crate::inventory::Inventory;
+use crate::scan::{PackageKey, PackagesInUse};
+use crate::sha256::sha256_hex;
+
+use serde_json::json;
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::hash::{BuildHasher, Hasher};
+use std::path::{Path, PathBuf};
+
+pub const COMPOSITION_VERSION: u64 = 1;
+const SALT_FILE_NAME: &str = "conan_cleanup_salt";
+/// Number of hex digits kept of the salted hashes.
+const HASH_LENGTH: usize = 16;
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// Packages of the cache by package name and version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Composition {
+    /// When the composition was collected, in seconds since the UNIX epoch.
+    pub taken_at: u64,
+    pub hostname: Option<String>,
+    pub groups: Vec<Group>,
+}
+
+/// The recipes of one package name and version, e.g. of all user/channel values of `zlib/1.2.11`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Group {
+    pub name: String,
+    pub version: String,
+    /// `<user>/<channel>` of the recipes that have them.
+    pub user_channels: Vec<String>,
+    pub recipe_count: usize,
+    pub package_count: usize,
+    pub packages_in_use: usize,
+    /// Size of the recipe folders, including sources, builds and packages.
+    pub size: u64,
+    pub ages: AgeDistribution,
+    /// Usage files of the scanned projects requiring any of the packages.
+    pub consumers: Vec<String>,
+}
+
+/// Number of packages by the age of their folder.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AgeDistribution {
+    pub up_to_week: usize,
+    pub up_to_month: usize,
+    pub up_to_year: usize,
+    pub older: usize,
+}
+
+impl AgeDistribution {
+    fn add(&mut self, age_seconds: u64) {
+        match age_seconds / DAY {
+            0..=7 => self.up_to_week += 1,
+            8..=30 => self.up_to_month += 1,
+            31..=365 => self.up_to_year += 1,
+            _ => self.older += 1,
+        }
+    }
+}
+
+/// Groups the recipes of an inventory by package name and version. `consumers` holds the usage
+/// files requiring each package.
+pub fn collect(
+    inventory: &Inventory,
+    usage: &PackagesInUse,
+    consumers: &HashMap<PackageKey, Vec<String>>,
+) -> Composition {
+    let mut groups: BTreeMap<(String, String), Group> = BTreeMap::new();
+    let mut group_consumers: BTreeMap<(String, String), BTreeSet<String>> = BTreeMap::new();
+    for (recipe_id, recipe) in &inventory.recipes {
+        let (name_version, user_channel) = match recipe_id.split_once('@') {
+            Some((name_version, user_channel)) => (name_version, Some(user_channel)),
+            None => (recipe_id.as_str(), None),
+        };
+        let (name, version) = name_version.split_once('/').unwrap_or((name_version, ""));
+        let key = (name.to_owned(), version.to_owned());
+        let group = groups.entry(key.clone()).or_insert_with(|| Group {
+            name: name.to_owned(),
+            version: version.to_owned(),
+            user_channels: Vec::new(),
+            recipe_count: 0,
+            package_count: 0,
+            packages_in_use: 0,
+            size: 0,
+            ages: AgeDistribution::default(),
+            consumers: Vec::new(),
+        });
+        group.user_channels.extend(user_channel.map(str::to_owned));
+        group.recipe_count += 1;
+        group.package_count += recipe.packages.len();
+        group.size += recipe.size;
+        for (package_id, package) in &recipe.packages {
+            if usage.contains_recipe(recipe_id) || usage.contains_package(recipe_id, package_id) {
+                group.packages_in_use += 1;
+            }
+            group
+                .ages
+                .add(inventory.taken_at.saturating_sub(package.modified));
+            if let Some(paths) = consumers.get(&PackageKey::new(recipe_id, package_id)) {
+                group_consumers
+                    .entry(key.clone())
+                    .or_default()
+                    .extend(paths.iter().cloned());
+            }
+        }
+    }
+    for (key, paths) in group_consumers {
+        groups.get_mut(&key).unwrap().consumers = paths.into_iter().collect();
+    }
+
+    Composition {
+        taken_at: inventory.taken_at,
+        hostname: crate::run_info::hostname(),
+        groups: groups.into_values().collect(),
+    }
+}
+
+/// Replaces the consumer paths, user/channel values and the hostname by hashes salted with `salt`.
+/// Package names and versions are kept, since they are what the export is about.
+pub fn anonymize(composition: &Composition, salt: &str) -> Composition {
+    let hash = |value: &String| salted_hash(salt, value);
+    let hash_all = |values: &[String]| {
+        let hashes: BTreeSet<_> = values.iter().map(hash).collect();
+        hashes.into_iter().collect()
+    };
+    Composition {
+        taken_at: composition.taken_at,
+        hostname: composition.hostname.as_ref().map(hash),
+        groups: composition
+            .groups
+            .iter()
+            .map(|group| Group {
+                user_channels: hash_all(&group.user_channels),
+                consumers: hash_all(&group.consumers),
+                ..group.clone()
+            })
+            .collect(),
+    }
+}
+
+fn salted_hash(salt: &str, value: &str) -> String {
+    let mut hash = sha256_hex(format!("{}\0{}", salt, value).as_bytes());
+    hash.truncate(HASH_LENGTH);
+    hash
+}
+
+impl Composition {
+    /// Returns the document written by `--composition-export`. Unlike other files of the tool it
+    /// doesn't embed the run info, whose configuration holds paths.
+    pub fn to_json(&self) -> serde_json::Value {
+        let groups: Vec<_> = self
+            .groups
+            .iter()
+            .map(|group| {
+                json!({
+                    "name": group.name,
+                    "version": group.version,
+                    "user_channels": group.user_channels,
+                    "recipes": group.recipe_count,
+                    "packages": group.package_count,
+                    "packages_in_use": group.packages_in_use,
+                    "in_use_ratio": match group.package_count {
+                        0 => None,
+                        count => Some(group.packages_in_use as f64 / count as f64),
+                    },
+                    "size": group.size,
+                    "package_ages": {
+                        "up_to_week": group.ages.up_to_week,
+                        "up_to_month": group.ages.up_to_month,
+                        "up_to_year": group.ages.up_to_year,
+                        "older": group.ages.older,
+                    },
+                    "consumers": group.consumers,
+                })
+            })
+            .collect();
+        json!({
+            "version": COMPOSITION_VERSION,
+            "tool_version": env!("CARGO_PKG_VERSION"),
+            "taken_at": self.taken_at,
+            "host": self.hostname,
+            "total_size": self.groups.iter().map(|group| group.size).sum::<u64>(),
+            "groups": groups,
+        })
+    }
+}
+
+/// Returns the path of the salt file kept in the given conan home.
+pub fn default_salt_path(conan_home: &Path) -> PathBuf {
+    conan_home.join(SALT_FILE_NAME)
+}
+
+/// Reads the salt of the given file, creating the file with a new random salt if it doesn't exist.
+pub fn load_or_create_salt(path: &Path) -> std::io::Result<String> {
+    match std::fs::read_to_string(path) {
+        Ok(salt) if !salt.trim().is_empty() => return Ok(salt.trim().to_owned()),
+        Ok(_) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err),
+    }
+    let salt = new_salt();
+    crate::fsutil::atomic_write(path, format!("{}\n", salt).as_bytes())?;
+    Ok(salt)
+}
+
+/// Returns a new random salt. The hashers of the standard library are randomly seeded per
+/// instance, which is random enough without depending on a random number generator.
+fn new_salt() -> String {
+    let random_state = std::collections::hash_map::RandomState::new();
+    let seeds: Vec<_> = (0..4u8)
+        .map(|index| {
+            let mut hasher = random_state.build_hasher();
+            hasher.write_u8(index);
+            hasher.finish().to_string()
+        })
+        .collect();
+    sha256_hex(seeds.join(":").as_bytes())
+}
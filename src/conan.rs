@@ -0,0 +1,996 @@
+//! Invocations of conan that search and remove recipes and packages, and the parsing of their
+//! results. Results are read in the layout of `conan search` of conan 1, the output of conan 2 is
+//! converted by `conan2`.
+
+use crate::cancel::CancellationToken;
+use crate::error::CleanupError;
+use crate::size::format_size;
+use crate::{
+    cache, conan2, fsutil, io_budget, logging, reference, resources, scheduler, search_results,
+    state, subprocess,
+};
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+
+/// Searches the packages of the given recipes with up to `jobs` concurrent searches. Prints the
+/// progress to stderr if it is a terminal.
+pub fn search_packages<'a>(
+    search_results: &search_results::SearchResults,
+    recipe_ids: &[&'a String],
+    jobs: usize,
+    token: &CancellationToken,
+) -> HashMap<&'a String, Result<Vec<PackageInfo>, CleanupError>> {
+    use std::io::IsTerminal;
+
+    let total = recipe_ids.len();
+    let show_progress = total > 1 && std::io::stderr().is_terminal();
+    let finished = std::sync::atomic::AtomicUsize::new(0);
+    let results = scheduler::run_grouped(
+        recipe_ids
+            .iter()
+            .map(|recipe_id| (*recipe_id, vec![()]))
+            .collect(),
+        jobs,
+        |recipe_id, _| {
+            let packages = conan_search(search_results, Some(recipe_id), parse_packages, token);
+            let finished = finished.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+            if show_progress {
+                eprint!("\rSearched {}/{} recipes", finished, total);
+            }
+            packages
+        },
+    );
+    if show_progress {
+        eprintln!();
+    }
+    results
+        .into_iter()
+        .map(|(recipe_id, mut packages)| (recipe_id, packages.remove(0)))
+        .collect()
+}
+
+/// The packages of the cache, as found by `search_cache`.
+pub struct CacheSearch {
+    /// Package IDs per recipe.
+    pub packages: BTreeMap<String, Vec<String>>,
+    /// Packages of the recipes that were actually searched.
+    pub searched: BTreeMap<String, Vec<PackageInfo>>,
+    /// Recipes whose package IDs were taken from the previous run.
+    pub reused: BTreeSet<String>,
+    /// Fingerprints and package IDs of the recipes for the next incremental run, only taken for
+    /// incremental runs.
+    pub snapshot: Option<state::Snapshot>,
+}
+
+impl CacheSearch {
+    /// Returns the settings of the searched packages by recipe and package ID.
+    pub fn package_settings(&self) -> HashMap<(String, String), BTreeMap<String, String>> {
+        self.searched
+            .iter()
+            .flat_map(|(recipe_id, packages)| {
+                packages.iter().filter_map(move |package| {
+                    let settings = package.settings.clone()?;
+                    Some(((recipe_id.clone(), package.id.clone()), settings))
+                })
+            })
+            .collect()
+    }
+}
+
+/// Searches the packages of the given recipes with up to `jobs` concurrent searches. Recipes whose
+/// fingerprint did not change since `previous_snapshot` was taken reuse its package IDs instead.
+/// Fingerprints are only computed, and a snapshot taken, if the hash of the configuration to
+/// record in the snapshot is given.
+/// Fails if the packages of any recipe could not be searched, since packages in use could not be
+/// told apart from unused ones without them, and with `CleanupError::Cancelled` once `token` is
+/// cancelled.
+pub fn search_cache(
+    search_results: &search_results::SearchResults,
+    recipe_ids: &[String],
+    storage_path: Option<&std::path::Path>,
+    previous_snapshot: Option<&state::Snapshot>,
+    config_hash: Option<u64>,
+    jobs: usize,
+    token: &CancellationToken,
+) -> Result<CacheSearch, CleanupError> {
+    let mut fingerprints = HashMap::new();
+    let mut previous_package_ids = HashMap::new();
+    for recipe_id in recipe_ids.iter().filter(|_| config_hash.is_some()) {
+        let fingerprint = storage_path
+            .and_then(|storage_path| cache::recipe_fingerprint(storage_path, recipe_id));
+        let package_ids = match (previous_snapshot, &fingerprint) {
+            (Some(previous), Some(fingerprint)) => previous
+                .recipes
+                .get(recipe_id)
+                .filter(|recipe| recipe.fingerprint == *fingerprint)
+                .map(|recipe| recipe.package_ids.clone()),
+            _ => None,
+        };
+        if let Some(package_ids) = package_ids {
+            previous_package_ids.insert(recipe_id, package_ids);
+        }
+        if let Some(fingerprint) = fingerprint {
+            fingerprints.insert(recipe_id, fingerprint);
+        }
+    }
+    let recipes_to_search: Vec<_> = recipe_ids
+        .iter()
+        .filter(|recipe_id| !previous_package_ids.contains_key(recipe_id))
+        .collect();
+    let phase = logging::Phase::start("searching packages");
+    let mut searched_packages = search_packages(search_results, &recipes_to_search, jobs, token);
+    phase.finish();
+
+    let mut search = CacheSearch {
+        packages: BTreeMap::new(),
+        searched: BTreeMap::new(),
+        reused: BTreeSet::new(),
+        snapshot: config_hash.map(|config_hash| state::Snapshot {
+            config_hash,
+            recipes: HashMap::new(),
+        }),
+    };
+    let mut search_failures = Vec::new();
+    for recipe_id in recipe_ids {
+        let package_ids = match previous_package_ids.remove(recipe_id) {
+            Some(package_ids) => {
+                search.reused.insert(recipe_id.clone());
+                package_ids
+            }
+            None => match searched_packages.remove(recipe_id).unwrap() {
+                Ok(packages) => {
+                    let package_ids = packages.iter().map(|package| package.id.clone()).collect();
+                    search.searched.insert(recipe_id.clone(), packages);
+                    package_ids
+                }
+                // Searches that were not started any more after cancelling are no failures.
+                Err(_) if token.is_cancelled() => continue,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    search_failures.push(err);
+                    continue;
+                }
+            },
+        };
+        if let (Some(snapshot), Some(fingerprint)) =
+            (&mut search.snapshot, fingerprints.remove(recipe_id))
+        {
+            snapshot.recipes.insert(
+                recipe_id.clone(),
+                state::RecipeSnapshot {
+                    fingerprint,
+                    package_ids: package_ids.clone(),
+                },
+            );
+        }
+        search.packages.insert(recipe_id.clone(), package_ids);
+    }
+    if token.is_cancelled() {
+        return Err(CleanupError::Cancelled(Box::default()));
+    }
+    if !search_failures.is_empty() {
+        return Err(CleanupError::SearchFailed(search_failures));
+    }
+    Ok(search)
+}
+
+/// Searches the local cache for all recipes or for the packages of a single recipe and parses the
+/// result with `parse`.
+pub fn conan_search<T>(
+    search_results: &search_results::SearchResults,
+    recipe_id: Option<&str>,
+    parse: fn(&serde_json::Value) -> Result<T, ConanJsonError>,
+    token: &CancellationToken,
+) -> Result<T, CleanupError> {
+    let json_path = search_results.path_for(recipe_id.unwrap_or("all_recipes"));
+    let json_path_arg = json_path.to_string_lossy();
+    let pattern;
+    let recipe_reference;
+    let search_args = if reference::is_conan2() {
+        pattern = conan2::list_pattern(recipe_id);
+        vec!["list", &pattern, "--format=json"]
+    } else {
+        recipe_reference = recipe_id.map(reference::argument);
+        let mut search_args = vec!["search", "-j", &json_path_arg];
+        search_args.extend(recipe_reference.as_deref());
+        search_args
+    };
+
+    // A result file left behind by an earlier search must never be taken for this one's.
+    match std::fs::remove_file(&json_path) {
+        Ok(()) => {}
+        Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => {
+            return Err(CleanupError::File(
+                "remove the previous search result",
+                json_path,
+                err.into(),
+            ))
+        }
+    }
+
+    let result = match subprocess::run_conan_unless_cancelled(&search_args, token) {
+        // Running out of space is reported with more helpful advice when reading the result.
+        Ok(output) if !output.status.success() && !is_no_space_error(&output) => {
+            Err(CleanupError::ConanFailed(
+                command_line(&search_args),
+                output.status,
+                printed_output(&output),
+            ))
+        }
+        Ok(output) => if reference::is_conan2() {
+            read_conan2_list(&json_path, &output)
+        } else {
+            read_conan1_search(&json_path, &output)
+        }
+        .and_then(|json| parse(&json))
+        .map_err(|err| match err {
+            // conan prints the reason of the error rather than writing it to the JSON output.
+            ConanJsonError::ConanReportedError(message) if message.is_empty() => {
+                ConanJsonError::ConanReportedError(error_lines(&output))
+            }
+            err => err,
+        })
+        .map_err(|err| CleanupError::ConanOutput(command_line(&search_args), err)),
+        Err(err) => Err(CleanupError::ConanCommand(command_line(&search_args), err)),
+    };
+    search_results.discard(&json_path);
+    result
+}
+
+/// Returns what a failed conan command printed to stderr and stdout.
+fn printed_output(output: &std::process::Output) -> String {
+    [&output.stderr, &output.stdout]
+        .iter()
+        .map(|stream| String::from_utf8_lossy(stream).trim().to_owned())
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Returns the lines of the output of conan that report errors, joined by spaces.
+fn error_lines(output: &std::process::Output) -> String {
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    stdout
+        .lines()
+        .chain(stderr.lines())
+        .filter(|line| line.starts_with("ERROR"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn read_conan1_search(
+    json_path: &std::path::Path,
+    output: &std::process::Output,
+) -> Result<serde_json::Value, ConanJsonError> {
+    if !output.status.success() && is_no_space_error(output) {
+        return Err(output_write_failed(json_path));
+    }
+
+    read_search_result(json_path, &output.stdout)
+}
+
+/// Reads the output of `conan list` of conan 2 and returns it in the layout of conan 1 search
+/// results.
+fn read_conan2_list(
+    json_path: &std::path::Path,
+    output: &std::process::Output,
+) -> Result<serde_json::Value, ConanJsonError> {
+    // conan 2 reports on stdout only. Writing the result to the search result file keeps it
+    // available like the results of conan 1.
+    fsutil::atomic_write(json_path, &output.stdout).map_err(ConanJsonError::Io)?;
+    let json = read_search_result(json_path, &output.stdout)?;
+    conan2::to_search_result(&json).map_err(ConanJsonError::FormatError)
+}
+
+/// Returns whether conan reported running out of space, e.g. while writing its JSON output.
+fn is_no_space_error(output: &std::process::Output) -> bool {
+    [&output.stderr, &output.stdout].iter().any(|stream| {
+        let text = String::from_utf8_lossy(stream);
+        text.contains("No space left on device")
+            || text.contains("Errno 28")
+            || text.contains("Disk quota exceeded")
+    })
+}
+
+fn output_write_failed(json_path: &std::path::Path) -> ConanJsonError {
+    let free_space = json_path.parent().and_then(cache::free_space);
+    ConanJsonError::OutputWriteFailed(json_path.to_path_buf(), free_space)
+}
+
+/// Reads the JSON document conan wrote to `json_path`. Some conan wrapper scripts (and conan 2 when
+/// given conan 1 style arguments) print the document to stdout instead and leave the file missing
+/// or empty, so the captured stdout is used in that case.
+fn read_search_result(
+    json_path: &std::path::Path,
+    stdout: &[u8],
+) -> Result<serde_json::Value, ConanJsonError> {
+    match std::fs::read_to_string(json_path) {
+        Ok(ref content) if !content.trim().is_empty() => {
+            // A document that ends prematurely was most likely cut off when the filesystem ran
+            // full.
+            return parse_contaminated_json(content, &json_path.display().to_string()).map_err(
+                |err| {
+                    if err.is_eof() {
+                        output_write_failed(json_path)
+                    } else {
+                        ConanJsonError::InvalidOutput(json_path.to_path_buf(), err)
+                    }
+                },
+            );
+        }
+        Ok(_) => {}
+        Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => return Err(ConanJsonError::Io(err)),
+    }
+
+    parse_contaminated_json(&String::from_utf8_lossy(stdout), "the output of conan").map_err(|_| {
+        let stdout_start = &stdout[..stdout.len().min(200)];
+        ConanJsonError::MissingOutput(
+            json_path.to_path_buf(),
+            String::from_utf8_lossy(stdout_start).into_owned(),
+        )
+    })
+}
+
+/// Parses a JSON document that conan wrapper scripts may have surrounded with a byte order mark,
+/// log lines or other text. A warning naming `source` and showing the surrounding text is printed
+/// the first time any had to be skipped. Returns the error of parsing the unmodified text if no document can
+/// be found.
+fn parse_contaminated_json(
+    text: &str,
+    source: &str,
+) -> Result<serde_json::Value, serde_json::Error> {
+    resources::count_parsed_json_bytes(text.len());
+    trace!(
+        "Parsing {} of JSON from {}",
+        format_size(text.len() as u64),
+        source
+    );
+    let error = match serde_json::from_str(text) {
+        Ok(json) => return Ok(json),
+        Err(err) => err,
+    };
+
+    let start = match text.find(['{', '[']) {
+        Some(start) => start,
+        None => return Err(error),
+    };
+    let mut documents =
+        serde_json::Deserializer::from_str(&text[start..]).into_iter::<serde_json::Value>();
+    let json = match documents.next() {
+        Some(Ok(json)) => json,
+        _ => return Err(error),
+    };
+    let end = start + documents.byte_offset();
+
+    // Wrappers contaminate every output the same way, so warning once is enough.
+    static WARNED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+    if WARNED.swap(true, std::sync::atomic::Ordering::SeqCst) {
+        return Ok(json);
+    }
+    let escape = |junk: &str| {
+        let junk: String = junk.trim_end().chars().take(80).collect();
+        format!("{:?}", junk)
+    };
+    warning!(
+        "{} contains text around its JSON document, probably written by a conan wrapper script. It was ignored.",
+        source
+    );
+    warning!(
+        "Text before the document: {}, text after the document: {}",
+        escape(&text[..start]),
+        escape(&text[end..])
+    );
+    Ok(json)
+}
+
+pub fn parse_recipe_ids(json: &serde_json::Value) -> Result<Vec<String>, ConanJsonError> {
+    let mut recipe_ids = Vec::new();
+    for item in result_items(json)? {
+        if item.get("recipe").is_none() && item.get("packages").is_none() {
+            debug!("Ignoring search result without recipe: {}", item);
+            continue;
+        }
+        let recipe_object = item["recipe"].as_object().ok_or_else(|| {
+            ConanJsonError::FormatError("'items' array is missing the 'recipe' object".to_owned())
+        })?;
+        let id = recipe_object
+            .get("id")
+            .and_then(serde_json::Value::as_str)
+            .ok_or_else(|| {
+                ConanJsonError::FormatError("'recipe' object is missing the 'id' string".to_owned())
+            })?;
+        recipe_ids.push(id.to_owned());
+    }
+    Ok(recipe_ids)
+}
+
+/// Removes recipes that conan reported more than once, e.g. when a misconfigured remote shadows
+/// the local cache, comparing their canonical references. The first occurrence is kept.
+pub fn dedup_recipe_ids(recipe_ids: Vec<String>) -> Vec<String> {
+    let mut occurrences: BTreeMap<String, usize> = BTreeMap::new();
+    let mut unique_ids = Vec::new();
+    for recipe_id in recipe_ids {
+        let count = occurrences
+            .entry(reference::canonical(&recipe_id).to_owned())
+            .or_insert(0);
+        *count += 1;
+        if *count == 1 {
+            unique_ids.push(recipe_id);
+        }
+    }
+
+    let duplicates: Vec<_> = occurrences
+        .iter()
+        .filter(|(_, count)| **count > 1)
+        .map(|(recipe_id, count)| format!("{} ({} times)", recipe_id, count))
+        .collect();
+    if !duplicates.is_empty() {
+        warning!(
+            "conan reported {} recipes more than once, they are only processed once: {}",
+            duplicates.len(),
+            duplicates.join(", ")
+        );
+    }
+    unique_ids
+}
+
+/// A binary package of a recipe as reported by `conan search`.
+pub struct PackageInfo {
+    pub id: String,
+    /// Settings the package was built with, `None` if conan did not report any.
+    pub settings: Option<BTreeMap<String, String>>,
+    pub options: BTreeMap<String, String>,
+}
+
+pub fn parse_packages(json: &serde_json::Value) -> Result<Vec<PackageInfo>, ConanJsonError> {
+    let mut package_infos = Vec::new();
+    // A recipe without packages may have no item at all.
+    let items_object = match result_items(json)?.first() {
+        Some(item) => item.as_object().ok_or_else(|| {
+            ConanJsonError::FormatError("'items' array has no objects".to_owned())
+        })?,
+        None => return Ok(package_infos),
+    };
+
+    if items_object.contains_key("packages") {
+        let packages = items_object["packages"].as_array().ok_or_else(|| {
+            ConanJsonError::FormatError("First 'items' object has no 'packages' array".to_owned())
+        })?;
+
+        for package in packages {
+            let id = package["id"].as_str().ok_or_else(|| {
+                ConanJsonError::FormatError("'package' is missing an 'id' string".to_owned())
+            })?;
+            package_infos.push(PackageInfo {
+                id: id.to_owned(),
+                settings: package.get("settings").and_then(string_map),
+                options: package
+                    .get("options")
+                    .and_then(string_map)
+                    .unwrap_or_default(),
+            });
+        }
+    }
+
+    Ok(package_infos)
+}
+
+/// Returns the `items` of the search result of the local cache, which conan leaves out if the cache
+/// is empty, i.e. writes `{"error": false, "results": []}`. Some configurations also add the
+/// results of the remotes, whose `remote` names the remote instead of being `null`, in any order.
+fn result_items(json: &serde_json::Value) -> Result<&[serde_json::Value], ConanJsonError> {
+    // The results of a failed search are incomplete, a recipe could seem to have no packages.
+    if json["error"].as_bool() == Some(true) {
+        let message = ["error_message", "message"]
+            .iter()
+            .find_map(|key| json[*key].as_str())
+            .unwrap_or_default();
+        return Err(ConanJsonError::ConanReportedError(message.to_owned()));
+    }
+    let results = json["results"].as_array().ok_or_else(|| {
+        ConanJsonError::FormatError("Missing top-level 'results' array".to_owned())
+    })?;
+    if results.is_empty() {
+        return Ok(&[]);
+    }
+    let is_local = |result: &&serde_json::Value| match result.get("remote") {
+        None | Some(serde_json::Value::Null) => true,
+        Some(remote) => remote.as_str() == Some("local"),
+    };
+    let remotes: Vec<_> = results
+        .iter()
+        .filter(|result| !is_local(result))
+        .map(|result| result["remote"].as_str().unwrap_or("?"))
+        .collect();
+    if !remotes.is_empty() {
+        debug!(
+            "Ignoring the search results of the remotes '{}'",
+            remotes.join("', '")
+        );
+    }
+    let result_object = match results.iter().find(is_local) {
+        Some(result) => result.as_object().ok_or_else(|| {
+            ConanJsonError::FormatError("'results' array is missing its root object".to_owned())
+        })?,
+        None => {
+            let remotes = remotes.into_iter().map(str::to_owned).collect();
+            return Err(ConanJsonError::MissingLocalResults(remotes));
+        }
+    };
+    let items = result_object
+        .get("items")
+        .and_then(serde_json::Value::as_array)
+        .ok_or_else(|| {
+            ConanJsonError::FormatError(
+                "Root object of 'results' array is missing the 'items' array".to_owned(),
+            )
+        })?;
+    Ok(items)
+}
+
+/// Returns the IDs of the packages in the result of searching the packages of a single recipe.
+pub fn parse_package_ids(json: &serde_json::Value) -> Result<Vec<String>, ConanJsonError> {
+    Ok(parse_packages(json)?
+        .into_iter()
+        .map(|package| package.id)
+        .collect())
+}
+
+fn string_map(value: &serde_json::Value) -> Option<BTreeMap<String, String>> {
+    let object = value.as_object()?;
+    Some(
+        object
+            .iter()
+            .filter_map(|(key, value)| value.as_str().map(|value| (key.clone(), value.to_owned())))
+            .collect(),
+    )
+}
+
+/// Searches all configured remotes for recipes of the given package name. Returns `None` if any
+/// remote could not be queried.
+pub fn search_remotes(
+    search_results: &search_results::SearchResults,
+    name: &str,
+) -> Option<HashSet<String>> {
+    let json_path = search_results.path_for(&format!("remotes_{}", name));
+    let recipe_ids = search_remotes_into(&json_path, name);
+    search_results.discard(&json_path);
+    recipe_ids
+}
+
+fn search_remotes_into(json_path: &std::path::Path, name: &str) -> Option<HashSet<String>> {
+    let json_path_arg = json_path.to_string_lossy();
+    let pattern = format!("{}/*", name);
+    let args: &[&str] = if reference::is_conan2() {
+        &["list", &pattern, "-r", "*", "--format=json"]
+    } else {
+        &["search", &pattern, "-r", "all", "-j", &json_path_arg]
+    };
+    let output = match subprocess::run_conan(args) {
+        Ok(output) if output.status.success() => output,
+        Ok(_) => return None,
+        Err(err) => {
+            warning!("Failed to run '{}': {}", command_line(args), err);
+            return None;
+        }
+    };
+
+    if reference::is_conan2() {
+        fsutil::atomic_write(json_path, &output.stdout).ok()?;
+        let json = read_search_result(json_path, &output.stdout).ok()?;
+        return conan2::remote_recipe_ids(&json);
+    }
+    let json = read_search_result(json_path, &output.stdout).ok()?;
+    let mut recipe_ids = HashSet::new();
+    for result in json["results"].as_array()? {
+        for item in result["items"].as_array()? {
+            let id = item["recipe"]["id"].as_str()?;
+            recipe_ids.insert(id.split('#').next().unwrap_or(id).to_owned());
+        }
+    }
+    Some(recipe_ids)
+}
+
+/// Returns the references of the recipes served by the local-recipes-index remotes of conan 2,
+/// without revisions, or `None` if the remotes could not be listed or queried. Such remotes serve
+/// the recipes of a local folder, e.g. a checkout of conan-center-index.
+pub fn local_index_recipe_ids(
+    search_results: &search_results::SearchResults,
+) -> Option<HashSet<String>> {
+    let args = ["remote", "list", "--format=json"];
+    let remotes = match subprocess::run_conan(&args) {
+        Ok(output) if output.status.success() => output,
+        Ok(_) => return None,
+        Err(err) => {
+            warning!("Failed to run '{}': {}", command_line(&args), err);
+            return None;
+        }
+    };
+    let remotes: serde_json::Value = serde_json::from_slice(&remotes.stdout).ok()?;
+
+    let mut recipe_ids = HashSet::new();
+    for remote in conan2::local_recipes_index_remotes(&remotes)? {
+        let json_path = search_results.path_for(&format!("local_index_{}", remote));
+        let args = ["list", "*", "-r", &remote, "--format=json"];
+        let output = match subprocess::run_conan(&args) {
+            Ok(output) if output.status.success() => output,
+            Ok(_) => return None,
+            Err(err) => {
+                warning!("Failed to run '{}': {}", command_line(&args), err);
+                return None;
+            }
+        };
+        fsutil::atomic_write(&json_path, &output.stdout).ok()?;
+        let json = read_search_result(&json_path, &output.stdout);
+        search_results.discard(&json_path);
+        recipe_ids.extend(conan2::remote_recipe_ids(&json.ok()?)?);
+    }
+    Some(recipe_ids)
+}
+
+/// Result of removing a single binary package.
+pub struct PackageRemoval {
+    pub package_id: String,
+    pub output: std::process::Output,
+    /// Size of the package before its removal, including its short_paths folder.
+    pub size: u64,
+    /// The package's short_paths folder if it still exists after a successful removal.
+    pub surviving_short_path: Option<std::path::PathBuf>,
+    /// How long measuring the package took.
+    pub sizing_time: std::time::Duration,
+    /// How long conan took to remove the package.
+    pub removal_time: std::time::Duration,
+}
+
+pub fn remove_package(
+    storage_path: Option<&std::path::Path>,
+    recipe_id: &str,
+    package_id: &str,
+    token: &CancellationToken,
+) -> Result<PackageRemoval, CleanupError> {
+    let package_dir = storage_path
+        .and_then(|storage_path| cache::package_dir(storage_path, recipe_id, package_id));
+    let sizing_started = std::time::Instant::now();
+    let short_path = package_dir.as_deref().and_then(cache::short_path_target);
+    let size = package_dir
+        .iter()
+        .chain(short_path.iter())
+        .map(|dir| cache::directory_size(dir))
+        .sum();
+    let sizing_time = sizing_started.elapsed();
+
+    let remove_args = remove_package_args(recipe_id, package_id);
+    let remove_args: Vec<&str> = remove_args.iter().map(String::as_str).collect();
+    let removal_started = std::time::Instant::now();
+    let output = subprocess::run_conan_unless_cancelled(&remove_args, token)
+        .map_err(|err| CleanupError::ConanCommand(command_line(&remove_args), err))?;
+    let removal_time = removal_started.elapsed();
+    // Spaces out the removals, so that conan deletes at about the rate of --io-budget.
+    io_budget::consume(size);
+    let surviving_short_path =
+        short_path.filter(|short_path| output.status.success() && short_path.exists());
+
+    Ok(PackageRemoval {
+        package_id: package_id.to_owned(),
+        output,
+        size,
+        surviving_short_path,
+        sizing_time,
+        removal_time,
+    })
+}
+
+/// Returns the arguments of the conan command removing a package without asking.
+pub fn remove_package_args(recipe_id: &str, package_id: &str) -> Vec<String> {
+    let reference = reference::argument(recipe_id);
+    if reference::is_conan2() {
+        let package_reference = conan2::package_reference(&reference, package_id);
+        vec!["remove".to_owned(), package_reference, "-c".to_owned()]
+    } else {
+        vec![
+            "remove".to_owned(),
+            reference,
+            "-p".to_owned(),
+            package_id.to_owned(),
+            "-f".to_owned(),
+        ]
+    }
+}
+
+/// Returns the command line of a conan invocation for use in messages.
+pub fn command_line(args: &[&str]) -> String {
+    let mut command_line = String::from("conan");
+    for arg in args {
+        command_line.push(' ');
+        command_line.push_str(arg);
+    }
+    command_line
+}
+
+/// Returns whether a failed `conan remove` refused to remove a package because it is installed as
+/// editable. Conan 1.22 and later report "... is installed as editable, remove it first ...",
+/// earlier versions "... is in editable mode".
+pub fn is_editable_error(output: &std::process::Output) -> bool {
+    [&output.stderr, &output.stdout].iter().any(|stream| {
+        let text = String::from_utf8_lossy(stream);
+        text.contains("installed as editable") || text.contains("in editable mode")
+    })
+}
+
+#[derive(Debug)]
+pub enum ConanJsonError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    FormatError(String),
+    /// conan flagged its output with `"error": true`. Holds the message conan gave, if any.
+    ConanReportedError(String),
+    /// The search results only hold the results of the named remotes, not of the local cache.
+    MissingLocalResults(Vec<String>),
+    /// Neither the output file nor stdout contained a JSON document. Holds the path of the output
+    /// file and the beginning of stdout.
+    MissingOutput(std::path::PathBuf, String),
+    /// The output file holds no valid JSON document.
+    InvalidOutput(std::path::PathBuf, serde_json::Error),
+    /// conan could not write its complete output file, most likely since the filesystem is full.
+    /// Holds the path of the output file and the free space of its filesystem, if known.
+    OutputWriteFailed(std::path::PathBuf, Option<u64>),
+}
+
+impl fmt::Display for ConanJsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConanJsonError::Io(ref err) => err.fmt(f),
+            ConanJsonError::Json(ref err) => err.fmt(f),
+            ConanJsonError::MissingOutput(ref path, ref stdout_start) => write!(
+                f,
+                "conan wrote no JSON output to '{}' and its output is no JSON document either (output started with {:?})",
+                path.display(),
+                stdout_start
+            ),
+            ConanJsonError::InvalidOutput(ref path, ref err) => write!(
+                f,
+                "conan wrote no valid JSON document to '{}': {}",
+                path.display(),
+                err
+            ),
+            ConanJsonError::OutputWriteFailed(ref path, free_space) => {
+                write!(
+                    f,
+                    "conan could not write its complete output to '{}', the filesystem is probably full",
+                    path.display()
+                )?;
+                if let Some(free_space) = free_space {
+                    write!(f, " ({} free)", format_size(free_space))?;
+                }
+                write!(
+                    f,
+                    ". Use --temp-dir to write the output to a location with more space."
+                )
+            }
+            ConanJsonError::FormatError(ref err) => write!(
+                f,
+                "Unexpected JSON format (conan might have changed its output format): {}",
+                err
+            ),
+            ConanJsonError::ConanReportedError(ref message) if message.is_empty() => {
+                write!(f, "conan reported an error without message")
+            }
+            ConanJsonError::ConanReportedError(ref message) => {
+                write!(f, "conan reported an error: {}", message)
+            }
+            ConanJsonError::MissingLocalResults(ref remotes) => write!(
+                f,
+                "conan reported results of the remotes '{}', but none of the local cache. Refusing to remove packages based on remote listings.",
+                remotes.join("', '")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConanJsonError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            ConanJsonError::Io(ref err) => Some(err),
+            ConanJsonError::Json(ref err) | ConanJsonError::InvalidOutput(_, ref err) => Some(err),
+            ConanJsonError::FormatError(_)
+            | ConanJsonError::ConanReportedError(_)
+            | ConanJsonError::MissingLocalResults(_)
+            | ConanJsonError::MissingOutput(..)
+            | ConanJsonError::OutputWriteFailed(..) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ConanJsonError {
+    fn from(err: std::io::Error) -> ConanJsonError {
+        ConanJsonError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for ConanJsonError {
+    fn from(err: serde_json::Error) -> ConanJsonError {
+        ConanJsonError::Json(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn empty_cache_has_no_recipes() {
+        let json = json!({"error": false, "results": []});
+
+        assert!(parse_recipe_ids(&json).unwrap().is_empty());
+        assert!(parse_package_ids(&json).unwrap().is_empty());
+    }
+
+    #[test]
+    fn recipe_without_items_has_no_packages() {
+        let json = json!({"error": false, "results": [{"remote": null, "items": []}]});
+
+        assert!(parse_recipe_ids(&json).unwrap().is_empty());
+        assert!(parse_package_ids(&json).unwrap().is_empty());
+    }
+
+    #[test]
+    fn recipe_without_packages_array_has_no_packages() {
+        let json = json!({
+            "error": false,
+            "results": [{"remote": null, "items": [{"recipe": {"id": "zlib/1.2.11"}}]}],
+        });
+
+        assert!(parse_package_ids(&json).unwrap().is_empty());
+    }
+
+    #[test]
+    fn items_without_recipe_and_packages_are_ignored() {
+        let json = json!({
+            "error": false,
+            "results": [{
+                "remote": null,
+                "items": [{}, {"recipe": {"id": "zlib/1.2.11"}}, {"unknown": 1}],
+            }],
+        });
+
+        assert_eq!(parse_recipe_ids(&json).unwrap(), ["zlib/1.2.11"]);
+    }
+
+    #[test]
+    fn packages_with_settings_and_options() {
+        let json = json!({
+            "error": false,
+            "results": [{
+                "remote": null,
+                "items": [{
+                    "recipe": {"id": "zlib/1.2.11"},
+                    "packages": [
+                        {"id": "aaa", "settings": {"os": "Linux"}, "options": {"shared": "True"}},
+                        {"id": "bbb"},
+                    ],
+                }],
+            }],
+        });
+
+        let packages = parse_packages(&json).unwrap();
+        assert_eq!(packages.len(), 2);
+        assert_eq!(packages[0].id, "aaa");
+        assert_eq!(packages[0].settings.as_ref().unwrap()["os"], "Linux");
+        assert_eq!(packages[0].options["shared"], "True");
+        assert_eq!(packages[1].settings, None);
+        assert!(packages[1].options.is_empty());
+        assert_eq!(parse_package_ids(&json).unwrap(), ["aaa", "bbb"]);
+    }
+
+    #[test]
+    fn results_of_remotes_are_ignored() {
+        let json = json!({
+            "error": false,
+            "results": [
+                {"remote": "conancenter", "items": [{"recipe": {"id": "boost/1.75.0"}}]},
+                {"remote": null, "items": [{"recipe": {"id": "zlib/1.2.11"}}]},
+            ],
+        });
+
+        assert_eq!(parse_recipe_ids(&json).unwrap(), ["zlib/1.2.11"]);
+    }
+
+    #[test]
+    fn results_of_remotes_only_are_an_error() {
+        let json = json!({
+            "error": false,
+            "results": [{"remote": "conancenter", "items": []}],
+        });
+
+        match parse_recipe_ids(&json) {
+            Err(ConanJsonError::MissingLocalResults(remotes)) => {
+                assert_eq!(remotes, ["conancenter"])
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn error_reported_by_conan() {
+        let json = json!({"error": true, "results": [], "error_message": "Lock on recipe"});
+
+        match parse_package_ids(&json) {
+            Err(ConanJsonError::ConanReportedError(message)) => {
+                assert_eq!(message, "Lock on recipe")
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn malformed_results() {
+        assert!(matches!(
+            parse_recipe_ids(&json!({"error": false})),
+            Err(ConanJsonError::FormatError(_))
+        ));
+        assert!(matches!(
+            parse_recipe_ids(&json!({"error": false, "results": [{"remote": null}]})),
+            Err(ConanJsonError::FormatError(_))
+        ));
+        assert!(matches!(
+            parse_recipe_ids(&json!({
+                "error": false,
+                "results": [{"remote": null, "items": [{"recipe": {"name": "zlib"}}]}],
+            })),
+            Err(ConanJsonError::FormatError(_))
+        ));
+    }
+
+    #[test]
+    fn duplicate_recipes_are_processed_once() {
+        let recipe_ids = vec![
+            "zlib/1.2.11".to_owned(),
+            "boost/1.75.0".to_owned(),
+            "zlib/1.2.11@_/_".to_owned(),
+            "zlib/1.2.11@".to_owned(),
+        ];
+
+        assert_eq!(
+            dedup_recipe_ids(recipe_ids),
+            ["zlib/1.2.11", "boost/1.75.0"]
+        );
+    }
+
+    #[test]
+    fn json_surrounded_by_wrapper_output() {
+        let text = "\u{feff}Activating environment\n{\"error\": false, \"results\": []}\nDone\n";
+
+        let json = parse_contaminated_json(text, "test").unwrap();
+        assert_eq!(json["results"], json!([]));
+        assert!(parse_contaminated_json("no document", "test").is_err());
+    }
+
+    #[test]
+    fn search_result_falls_back_to_stdout() {
+        let missing = std::env::temp_dir().join(format!(
+            "conan_cleanup_missing_search_result_{}.json",
+            std::process::id()
+        ));
+
+        let json = read_search_result(&missing, br#"{"error": false, "results": []}"#).unwrap();
+        assert_eq!(json["error"], false);
+        assert!(matches!(
+            read_search_result(&missing, b"ERROR: no json"),
+            Err(ConanJsonError::MissingOutput(..))
+        ));
+    }
+
+    #[test]
+    fn command_line_for_messages() {
+        assert_eq!(
+            command_line(&["remove", "zlib/1.2.11@", "-f"]),
+            "conan remove zlib/1.2.11@ -f"
+        );
+    }
+}
@@ -0,0 +1,175 @@
+//! Support for conan 2, whose `conan list` reports the cache contents in a different JSON layout
+//! than `conan search` of conan 1, and whose `conan remove` takes package references instead of
+//! `-p`.
+
+use serde_json::json;
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+/// Returns the `conan list` pattern of all recipes, or of all packages of all revisions of the
+/// given recipe.
+pub fn list_pattern(recipe_id: Option<&str>) -> String {
+    match recipe_id {
+        Some(recipe_id) if recipe_id.contains('#') => format!("{}:*", recipe_id),
+        Some(recipe_id) => format!("{}#*:*", recipe_id),
+        None => "*".to_owned(),
+    }
+}
+
+/// Returns the pattern of `conan remove` matching the given package in all revisions of the
+/// recipe, e.g. `zlib/1.2.13#*:<package ID>`.
+pub fn package_reference(recipe_id: &str, package_id: &str) -> String {
+    if recipe_id.contains('#') {
+        format!("{}:{}", recipe_id, package_id)
+    } else {
+        format!("{}#*:{}", recipe_id, package_id)
+    }
+}
+
+/// Converts the result of `conan list --format=json` for the local cache into the layout of the
+/// results of `conan search -j` of conan 1, with one item per recipe. The packages of all recipe
+/// revisions are merged.
+pub fn to_search_result(list: &serde_json::Value) -> Result<serde_json::Value, String> {
+    let cache = list["Local Cache"]
+        .as_object()
+        .ok_or_else(|| "Missing top-level 'Local Cache' object".to_owned())?;
+    if let Some(error) = cache.get("error") {
+        return Err(format!("conan list reported an error: {}", error));
+    }
+
+    let mut items = Vec::new();
+    for (recipe_id, recipe) in cache {
+        let mut packages = BTreeMap::new();
+        for revision in recipe["revisions"]
+            .as_object()
+            .into_iter()
+            .flat_map(|revisions| revisions.values())
+        {
+            for (package_id, package) in revision["packages"]
+                .as_object()
+                .into_iter()
+                .flat_map(|packages| packages.iter())
+            {
+                packages.entry(package_id.clone()).or_insert_with(|| {
+                    json!({
+                        "id": package_id,
+                        "settings": package["info"]["settings"],
+                        "options": package["info"]["options"],
+                    })
+                });
+            }
+        }
+        items.push(json!({
+            "recipe": {"id": recipe_id},
+            "packages": packages.into_values().collect::<Vec<_>>(),
+        }));
+    }
+
+    Ok(json!({
+        "error": false,
+        "results": [{"remote": null, "items": items}],
+    }))
+}
+
+/// Returns the references of the recipes reported by `conan list --format=json` for remotes, or
+/// `None` if any remote reported an error.
+pub fn remote_recipe_ids(list: &serde_json::Value) -> Option<HashSet<String>> {
+    let mut recipe_ids = HashSet::new();
+    for remote in list.as_object()?.values() {
+        let recipes = remote.as_object()?;
+        if recipes.contains_key("error") {
+            return None;
+        }
+        for recipe_id in recipes.keys() {
+            recipe_ids.insert(recipe_id.split('#').next().unwrap_or(recipe_id).to_owned());
+        }
+    }
+    Some(recipe_ids)
+}
+
+/// Type of the remotes serving recipes from a local folder, e.g. a checkout of conan-center-index.
+const LOCAL_RECIPES_INDEX: &str = "local-recipes-index";
+
+/// Returns the names of the enabled local-recipes-index remotes reported by
+/// `conan remote list --format=json`, or `None` if the listing cannot be read. conan versions that
+/// don't report the `remote_type` of the remotes tell them by their URL, a folder holding a
+/// `recipes` folder.
+pub fn local_recipes_index_remotes(remotes: &serde_json::Value) -> Option<Vec<String>> {
+    let mut names = Vec::new();
+    for remote in remotes.as_array()? {
+        let name = remote["name"].as_str()?;
+        if remote["enabled"].as_bool() == Some(false) {
+            continue;
+        }
+        let is_local_index = match remote["remote_type"].as_str() {
+            Some(remote_type) => remote_type == LOCAL_RECIPES_INDEX,
+            None => remote["url"]
+                .as_str()
+                .is_some_and(|url| !url.contains("://") && Path::new(url).join("recipes").is_dir()),
+        };
+        if is_local_index {
+            names.push(name.to_owned());
+        }
+    }
+    Some(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_recipes_index_remotes_by_type() {
+        let remotes =
+            serde_json::from_str(include_str!("../tests/fixtures/conan2_remote_list.json"))
+                .unwrap();
+
+        assert_eq!(
+            local_recipes_index_remotes(&remotes),
+            Some(vec!["cci-fork".to_owned()])
+        );
+    }
+
+    #[test]
+    fn local_recipes_index_remotes_by_folder_without_type() {
+        let index = std::env::temp_dir().join(format!(
+            "conan_cleanup_conan2_test_{}_index",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(index.join("recipes")).unwrap();
+        let remotes = json!([
+            {"name": "conancenter", "url": "https://center2.conan.io", "verify_ssl": true, "enabled": true},
+            {"name": "artifactory", "url": "/no/such/folder", "verify_ssl": true, "enabled": true},
+            {"name": "cci-fork", "url": index, "verify_ssl": true, "enabled": true},
+        ]);
+
+        let names = local_recipes_index_remotes(&remotes);
+        std::fs::remove_dir_all(&index).unwrap();
+        assert_eq!(names, Some(vec!["cci-fork".to_owned()]));
+    }
+
+    #[test]
+    fn local_recipes_index_remotes_of_invalid_listing() {
+        assert_eq!(local_recipes_index_remotes(&json!({"error": "x"})), None);
+        assert_eq!(local_recipes_index_remotes(&json!([])), Some(Vec::new()));
+    }
+
+    #[test]
+    fn recipes_of_local_recipes_index() {
+        let list = serde_json::from_str(include_str!(
+            "../tests/fixtures/conan2_list_local_index.json"
+        ))
+        .unwrap();
+
+        let mut recipe_ids: Vec<_> = remote_recipe_ids(&list).unwrap().into_iter().collect();
+        recipe_ids.sort();
+        assert_eq!(recipe_ids, ["fmt/10.1.1", "zlib/1.2.13", "zlib/1.3"]);
+    }
+    #[test]
+    fn recipes_of_failed_remote() {
+        let list = json!({"cci-fork": {"error": "Remote not found"}});
+
+        assert_eq!(remote_recipe_ids(&list), None);
+    }
+}
@@ -0,0 +1,423 @@
+//! The optional configuration file `conan_cleanup.conf` in the conan home, which enables parts of
+//! the cleanup for every run instead of passing their flags each time. Flags given on the command
+//! line take precedence. Named profiles bundle options of the command line, selected with
+//! `--profile-name`. Example:
+//!
+//! ```ini
+//! [locks]
+//! clean = true
+//! older_than = 7d
+//!
+//! [profile.aggressive]
+//! older-than = 7d
+//! keep-latest = 1
+//! jobs-remove = 4
+//!
+//! [profile.audit]
+//! read-only = true
+//! include = boost/*, qt/*
+//!
+//! [budget.qt/*]
+//! max_binaries = 10
+//! max_bytes = 5G
+//! ```
+
+use crate::error::CleanupError;
+use crate::plan::Budget;
+use crate::{duration, size, text_file};
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub const FILE_NAME: &str = "conan_cleanup.conf";
+/// Prefix of the sections holding profiles, `[profile.<name>]`.
+const PROFILE_SECTION_PREFIX: &str = "profile.";
+/// Prefix of the sections holding budgets, `[budget.<pattern>]`.
+const BUDGET_SECTION_PREFIX: &str = "budget.";
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Config {
+    /// Whether stale lock files are removed as with `--clean-locks`.
+    pub clean_locks: bool,
+    /// Minimum age of the lock files to remove, as with `--locks-older-than`.
+    pub locks_older_than: Option<Duration>,
+    /// The profiles by name.
+    pub profiles: BTreeMap<String, Profile>,
+    /// The budgets of the package names, ordered by pattern.
+    pub budgets: Vec<Budget>,
+}
+
+impl Config {
+    /// Returns the profile of the given name, or an error listing the defined profiles.
+    pub fn profile(&self, name: &str, path: &Path) -> Result<&Profile, CleanupError> {
+        self.profiles.get(name).ok_or_else(|| {
+            CleanupError::InvalidArguments(match self.profiles.len() {
+                0 => format!(
+                    "Unknown profile '{}', '{}' defines no profiles.",
+                    name,
+                    path.display()
+                ),
+                _ => format!(
+                    "Unknown profile '{}', '{}' defines: {}.",
+                    name,
+                    path.display(),
+                    self.profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+                ),
+            })
+        })
+    }
+}
+
+/// A named bundle of options of the command line, `[profile.<name>]` in the configuration file.
+/// The keys are the long names of the options, the values are given as on the command line, flags
+/// take `true` or `false`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profile {
+    pub name: String,
+    pub options: BTreeMap<String, String>,
+}
+
+impl Profile {
+    /// Returns the arguments adding the options of the profile that are not given explicitly,
+    /// i.e. for which `is_explicit` is false, so that explicit flags of the command line win.
+    /// Flags set to `false` add nothing, values separated by commas are passed as several values.
+    pub fn args(&self, is_explicit: impl Fn(&str) -> bool) -> Vec<String> {
+        let mut args = Vec::new();
+        for (name, value) in self.contributed_options(is_explicit) {
+            match value.as_str() {
+                "true" => args.push(format!("--{}", name)),
+                "false" => {}
+                _ => args.extend(
+                    value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|value| !value.is_empty())
+                        .map(|value| format!("--{}={}", name, value)),
+                ),
+            }
+        }
+        args
+    }
+
+    /// Returns the options of the profile that are not given explicitly.
+    pub fn contributed_options<'a>(
+        &'a self,
+        is_explicit: impl Fn(&str) -> bool + 'a,
+    ) -> impl Iterator<Item = (&'a String, &'a String)> + 'a {
+        self.options
+            .iter()
+            .filter(move |(name, _)| !is_explicit(name))
+    }
+}
+
+impl fmt::Display for Profile {
+    /// Formats the options like on the command line.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.options.len() {
+            0 => write!(f, "changes nothing"),
+            _ => write!(f, "{}", self.args(|_| false).join(" ")),
+        }
+    }
+}
+
+/// Returns the path of the configuration file of the given conan home.
+pub fn config_path(conan_home: &Path) -> PathBuf {
+    conan_home.join(FILE_NAME)
+}
+
+/// Loads the configuration file of the given conan home. A missing file configures nothing, an
+/// unreadable file or invalid values fail.
+pub fn load(conan_home: &Path) -> Result<Config, CleanupError> {
+    let path = config_path(conan_home);
+    if !path.is_file() {
+        return Ok(Config::default());
+    }
+    // Read like every other file users edit, which drops a byte order mark and CRLF line endings
+    // or rejects them with `--strict-input-files`.
+    let lines = text_file::read_lines(&path)
+        .map_err(|err| CleanupError::File("read configuration", path.clone(), err.into()))?;
+    // Without escapes, which would garble Windows paths.
+    let ini = ini::Ini::load_from_str_noescape(&lines.join("\n"))
+        .map_err(|err| CleanupError::File("read configuration", path.clone(), err.into()))?;
+    let invalid = |key: &str, value: &str| {
+        CleanupError::InvalidArguments(format!(
+            "Invalid value '{}' of '{}' in '{}'.",
+            value,
+            key,
+            path.display()
+        ))
+    };
+
+    let mut config = Config::default();
+    if let Some(value) = ini.get_from(Some("locks"), "clean") {
+        config.clean_locks = match value.trim().to_lowercase().as_str() {
+            "true" | "1" | "yes" => true,
+            "false" | "0" | "no" => false,
+            _ => return Err(invalid("locks.clean", value)),
+        };
+    }
+    if let Some(value) = ini.get_from(Some("locks"), "older_than") {
+        config.locks_older_than = Some(
+            duration::parse_duration(value).ok_or_else(|| invalid("locks.older_than", value))?,
+        );
+    }
+    for (section, properties) in ini.iter() {
+        if let Some(pattern) = section
+            .as_deref()
+            .and_then(|section| section.strip_prefix(BUDGET_SECTION_PREFIX))
+        {
+            let key = |name: &str| format!("{}{}.{}", BUDGET_SECTION_PREFIX, pattern, name);
+            let budget = Budget {
+                pattern: pattern.trim().to_owned(),
+                max_binaries: match properties.get("max_binaries") {
+                    Some(value) => Some(
+                        value
+                            .trim()
+                            .parse()
+                            .map_err(|_| invalid(&key("max_binaries"), value))?,
+                    ),
+                    None => None,
+                },
+                max_bytes: match properties.get("max_bytes") {
+                    Some(value) => Some(
+                        size::parse_size(value).ok_or_else(|| invalid(&key("max_bytes"), value))?,
+                    ),
+                    None => None,
+                },
+            };
+            if budget.pattern.is_empty()
+                || (budget.max_binaries.is_none() && budget.max_bytes.is_none())
+            {
+                return Err(CleanupError::InvalidArguments(format!(
+                    "Budget '{}' in '{}' needs a pattern and max_binaries or max_bytes.",
+                    pattern,
+                    path.display()
+                )));
+            }
+            config.budgets.push(budget);
+            continue;
+        }
+        let name = match section
+            .as_deref()
+            .and_then(|section| section.strip_prefix(PROFILE_SECTION_PREFIX))
+        {
+            Some(name) => name.trim(),
+            None => continue,
+        };
+        if name.is_empty() {
+            return Err(invalid("a profile section", section.as_deref().unwrap()));
+        }
+        let mut options = BTreeMap::new();
+        for (key, value) in properties {
+            // Underscores are accepted like in the other sections.
+            let option = key.trim().replace('_', "-");
+            if options.insert(option, value.trim().to_owned()).is_some() {
+                return Err(CleanupError::InvalidArguments(format!(
+                    "Profile '{}' in '{}' sets '{}' twice.",
+                    name,
+                    path.display(),
+                    key.trim()
+                )));
+            }
+        }
+        config.profiles.insert(
+            name.to_owned(),
+            Profile {
+                name: name.to_owned(),
+                options,
+            },
+        );
+    }
+    config.budgets.sort_by(|a, b| a.pattern.cmp(&b.pattern));
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A conan home in a temporary folder, removed when dropped.
+    struct TestHome(PathBuf);
+
+    impl TestHome {
+        fn with_config(name: &str, content: &str) -> TestHome {
+            let dir = std::env::temp_dir().join(format!(
+                "conan_cleanup_config_test_{}_{}",
+                std::process::id(),
+                name
+            ));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(config_path(&dir), content).unwrap();
+            TestHome(dir)
+        }
+    }
+
+    impl Drop for TestHome {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn missing_file_configures_nothing() {
+        let home = TestHome::with_config("missing", "");
+        std::fs::remove_file(config_path(&home.0)).unwrap();
+
+        assert_eq!(load(&home.0).unwrap(), Config::default());
+    }
+
+    #[test]
+    fn locks_section() {
+        let home = TestHome::with_config("locks", "[locks]\nclean = yes\nolder_than = 7d\n");
+
+        let config = load(&home.0).unwrap();
+        assert!(config.clean_locks);
+        assert_eq!(
+            config.locks_older_than,
+            Some(Duration::from_secs(7 * 24 * 60 * 60))
+        );
+    }
+
+    #[test]
+    fn byte_order_mark_and_crlf_are_normalized() {
+        let home = TestHome::with_config(
+            "notepad",
+            "\u{feff}[locks]\r\nclean = true \r\nolder_than = 2h\n",
+        );
+
+        let config = load(&home.0).unwrap();
+        assert!(config.clean_locks);
+        assert_eq!(
+            config.locks_older_than,
+            Some(Duration::from_secs(2 * 60 * 60))
+        );
+    }
+
+    #[test]
+    fn profiles() {
+        let home = TestHome::with_config(
+            "profiles",
+            "[profile.audit]\nread-only = true\n\n[profile.aggressive]\nolder_than = 7d\nkeep-latest = 1\n",
+        );
+
+        let config = load(&home.0).unwrap();
+        assert_eq!(
+            config.profiles.keys().collect::<Vec<_>>(),
+            ["aggressive", "audit"]
+        );
+        let profile = config.profile("aggressive", &home.0).unwrap();
+        assert_eq!(profile.options["older-than"], "7d");
+        assert_eq!(profile.to_string(), "--keep-latest=1 --older-than=7d");
+    }
+
+    #[test]
+    fn unknown_profile_lists_the_defined_ones() {
+        let home = TestHome::with_config("unknown_profile", "[profile.b]\n[profile.a]\n");
+        let config = load(&home.0).unwrap();
+
+        let err = config
+            .profile("c", Path::new("conan_cleanup.conf"))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unknown profile 'c', 'conan_cleanup.conf' defines: a, b."
+        );
+        let err = Config::default()
+            .profile("c", Path::new("conan_cleanup.conf"))
+            .unwrap_err();
+        assert!(err.to_string().contains("defines no profiles"));
+    }
+
+    #[test]
+    fn explicit_options_take_precedence_over_the_profile() {
+        let profile = Profile {
+            name: "ci".to_owned(),
+            options: [
+                ("dry-run", "true"),
+                ("force", "false"),
+                ("include", "boost/*, qt/*"),
+                ("older-than", "30d"),
+            ]
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect(),
+        };
+
+        assert_eq!(
+            profile.args(|_| false),
+            [
+                "--dry-run",
+                "--include=boost/*",
+                "--include=qt/*",
+                "--older-than=30d"
+            ]
+        );
+        assert_eq!(
+            profile.args(|name| name == "older-than" || name == "include"),
+            ["--dry-run"]
+        );
+        assert_eq!(
+            profile
+                .contributed_options(|name| name == "dry-run")
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>(),
+            ["force", "include", "older-than"]
+        );
+    }
+
+    #[test]
+    fn profile_setting_an_option_twice_fails() {
+        let home = TestHome::with_config(
+            "duplicate_option",
+            "[profile.ci]\nprune-top = 1\nprune_top = 2\n",
+        );
+
+        assert!(matches!(
+            load(&home.0),
+            Err(CleanupError::InvalidArguments(ref message)) if message.contains("twice")
+        ));
+    }
+
+    #[test]
+    fn invalid_values_fail() {
+        let home = TestHome::with_config("invalid", "[locks]\nclean = maybe\n");
+
+        assert!(matches!(
+            load(&home.0),
+            Err(CleanupError::InvalidArguments(ref message)) if message.contains("locks.clean")
+        ));
+    }
+
+    #[test]
+    fn budgets() {
+        let home = TestHome::with_config(
+            "budgets",
+            "[budget.qt/*]\nmax_binaries = 10\nmax_bytes = 5G\n\n[budget.boost/*]\nmax_bytes = 512M\n",
+        );
+
+        let config = load(&home.0).unwrap();
+        assert_eq!(
+            config.budgets,
+            vec![
+                Budget {
+                    pattern: "boost/*".to_owned(),
+                    max_binaries: None,
+                    max_bytes: Some(512 << 20),
+                },
+                Budget {
+                    pattern: "qt/*".to_owned(),
+                    max_binaries: Some(10),
+                    max_bytes: Some(5 << 30),
+                },
+            ]
+        );
+
+        let home = TestHome::with_config("budget_without_limit", "[budget.qt/*]\n");
+        assert!(load(&home.0).is_err());
+        let home = TestHome::with_config("invalid_budget", "[budget.qt/*]\nmax_bytes = 5X\n");
+        assert!(load(&home.0).is_err());
+    }
+}
@@ -0,0 +1,59 @@
+use std::time::Duration;
+
+const MINUTE: u64 = 60;
+const HOUR: u64 = 60 * MINUTE;
+const DAY: u64 = 24 * HOUR;
+const WEEK: u64 = 7 * DAY;
+
+/// Parses durations like `90s`, `30d`, `6w` or `90days`. A number without unit is interpreted
+/// as days.
+pub fn parse_duration(text: &str) -> Option<Duration> {
+    let text = text.trim();
+    let unit_start = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(unit_start);
+    let number: u64 = number.parse().ok()?;
+
+    let unit_seconds = match unit.trim() {
+        "s" | "sec" | "secs" | "second" | "seconds" => 1,
+        "m" | "min" | "mins" | "minute" | "minutes" => MINUTE,
+        "h" | "hour" | "hours" => HOUR,
+        "" | "d" | "day" | "days" => DAY,
+        "w" | "week" | "weeks" => WEEK,
+        _ => return None,
+    };
+    number.checked_mul(unit_seconds).map(Duration::from_secs)
+}
+
+/// Validator for clap arguments taking a duration.
+pub fn validate_duration(text: String) -> Result<(), String> {
+    match parse_duration(&text) {
+        Some(_) => Ok(()),
+        None => Err(format!(
+            "'{}' is not a valid duration (expected e.g. '12h', '30d' or '6w')",
+            text
+        )),
+    }
+}
+
+/// Formats a duration for humans using its largest fitting unit (up to days), e.g. `47 days` or
+/// `3 hours`.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (count, unit) = if seconds >= DAY {
+        (seconds / DAY, "day")
+    } else if seconds >= HOUR {
+        (seconds / HOUR, "hour")
+    } else if seconds >= MINUTE {
+        (seconds / MINUTE, "minute")
+    } else {
+        (seconds, "second")
+    };
+
+    if count == 1 {
+        format!("{} {}", count, unit)
+    } else {
+        format!("{} {}s", count, unit)
+    }
+}
@@ -0,0 +1,329 @@
+//! The error that aborts a run.
+
+use crate::conan::ConanJsonError;
+use crate::pattern_usage::PatternUsage;
+use crate::text_file::TextFileError;
+use crate::{duration, json_output, migrate, profile};
+
+use serde_json::json;
+
+use std::fmt;
+use std::time::Duration;
+
+/// Exit codes of conan_cleanup. Codes of 10 and above report a finding rather than a failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitCode {
+    Success = 0,
+    /// conan could not be found or run, or failed to search the cache.
+    ConanFailed = 1,
+    /// Output of conan or a file like a lockfile, profile or snapshot could not be parsed.
+    ParseError = 2,
+    /// Some of the packages or recipes could not be removed.
+    RemovalFailed = 3,
+    /// Any other failure, e.g. invalid arguments or a file that cannot be written.
+    Failure = 4,
+    /// Packages to remove were found. Reserved for a mode that only checks for them.
+    CandidatesFound = 10,
+    /// The cache was not cleaned up within the duration given by `--nag-after`.
+    CleanupOverdue = 11,
+    /// The run was cancelled, e.g. by Ctrl+C. The value is the one shells use for `SIGINT`.
+    Cancelled = 130,
+}
+
+/// Describes the exit codes for the help of the command line.
+pub const EXIT_CODES_HELP: &str = "EXIT CODES:
+    0     Success
+    1     conan could not be found or run, or failed to search the cache
+    2     Output of conan or a file like a lockfile, profile or snapshot could not be parsed
+    3     Some of the packages or recipes could not be removed
+    4     Any other failure, e.g. invalid arguments or a file that cannot be written
+    11    --nag-after: the cache was not cleaned up within the given duration
+    130   The run was cancelled, e.g. by Ctrl+C
+
+ERROR CODES (--errors json):
+    invalid_arguments       The arguments cannot be combined or refer to something that does not exist
+    unknown_conan_home      The conan home could not be determined
+    file_access_failed      A file could not be read or written
+    invalid_file            A file like a lockfile, profile or snapshot could not be parsed
+    conan_not_found         conan is not installed or not on the PATH
+    conan_not_started       conan could not be started for another reason
+    conan_failed            A conan command failed
+    cache_locked            Another conan process holds a lock on the cache
+    invalid_conan_output    The output of a conan command could not be read
+    search_failed           The packages of some recipes could not be searched
+    removal_failed          Some of the packages or recipes could not be removed
+    refused                 Removing packages was refused, e.g. without confirmation
+    cleanup_overdue         --nag-after: the cache was not cleaned up within the given duration
+    dead_patterns           --fail-on-dead-patterns: recipe patterns match no recipe of the cache
+    migration_failed        Files of an older version could not be migrated
+    invalid_profile         The profile could not be read
+    stdin_failed            The answer to a prompt could not be read
+    cancelled               The run was cancelled";
+
+/// Error that aborts a run. It is reported once by `main`, which also chooses the exit code.
+#[derive(Debug)]
+pub enum CleanupError {
+    /// The given arguments cannot be combined or refer to something that does not exist.
+    InvalidArguments(String),
+    /// The conan home could not be determined, but is needed for the described purpose.
+    UnknownConanHome(&'static str),
+    /// Reading or writing a file failed. Holds what was done with the file, e.g. `read keep file`,
+    /// and the file.
+    File(
+        &'static str,
+        std::path::PathBuf,
+        Box<dyn std::error::Error + Send + Sync>,
+    ),
+    /// A conan command could not be started. Holds the command line.
+    ConanCommand(String, std::io::Error),
+    /// A conan command exited with a failure. Holds the command line, the exit status and what the
+    /// command printed.
+    ConanFailed(String, std::process::ExitStatus, String),
+    /// The result of a conan command could not be read. Holds the command line.
+    ConanOutput(String, ConanJsonError),
+    /// The packages of some recipes could not be searched. Holds why, the failures were already
+    /// reported.
+    SearchFailed(Vec<CleanupError>),
+    /// The given numbers of packages and recipes could not be removed. The failures were already
+    /// reported.
+    RemovalFailed(usize, usize),
+    /// Removing packages was refused for the described reason.
+    Refused(String),
+    /// The cache was not cleaned up within the duration given by `--nag-after`.
+    CleanupOverdue(Duration),
+    /// Recipe patterns match no recipe of the cache with `--fail-on-dead-patterns`.
+    DeadPatterns(Vec<PatternUsage>),
+    Migrate(migrate::MigrateError),
+    Profile(profile::ProfileError),
+    Stdin(std::io::Error),
+    /// The run was cancelled. Holds the report of what was done until then.
+    Cancelled(Box<json_output::Report>),
+}
+
+impl CleanupError {
+    pub fn exit_code(&self) -> ExitCode {
+        match *self {
+            CleanupError::ConanCommand(..)
+            | CleanupError::ConanFailed(..)
+            | CleanupError::SearchFailed(_) => ExitCode::ConanFailed,
+            CleanupError::ConanOutput(_, ConanJsonError::ConanReportedError(_)) => {
+                ExitCode::ConanFailed
+            }
+            CleanupError::ConanOutput(..) => ExitCode::ParseError,
+            // Reading files fails with I/O errors, parsing them with any other error.
+            CleanupError::File(_, _, ref err) if !err.is::<std::io::Error>() => {
+                ExitCode::ParseError
+            }
+            CleanupError::Profile(profile::ProfileError::Read(
+                _,
+                TextFileError::NotNormalized(_),
+            )) => ExitCode::ParseError,
+            CleanupError::RemovalFailed(..) => ExitCode::RemovalFailed,
+            CleanupError::CleanupOverdue(_) => ExitCode::CleanupOverdue,
+            CleanupError::Cancelled(_) => ExitCode::Cancelled,
+            CleanupError::InvalidArguments(_)
+            | CleanupError::UnknownConanHome(_)
+            | CleanupError::File(..)
+            | CleanupError::Refused(_)
+            | CleanupError::DeadPatterns(_)
+            | CleanupError::Migrate(_)
+            | CleanupError::Profile(_)
+            | CleanupError::Stdin(_) => ExitCode::Failure,
+        }
+    }
+}
+
+impl CleanupError {
+    /// Returns the stable code of the kind of the error, reported with `--errors json`. The codes
+    /// are part of the command line interface and listed in `ERROR_CODES_HELP`.
+    pub fn code(&self) -> &'static str {
+        match *self {
+            CleanupError::InvalidArguments(_) => "invalid_arguments",
+            CleanupError::UnknownConanHome(_) => "unknown_conan_home",
+            CleanupError::File(..) if self.exit_code() == ExitCode::ParseError => "invalid_file",
+            CleanupError::File(..) => "file_access_failed",
+            CleanupError::ConanCommand(_, ref err)
+                if err.kind() == std::io::ErrorKind::NotFound =>
+            {
+                "conan_not_found"
+            }
+            CleanupError::ConanCommand(..) => "conan_not_started",
+            CleanupError::ConanFailed(_, _, ref output) if is_lock_error(output) => "cache_locked",
+            CleanupError::ConanOutput(_, ConanJsonError::ConanReportedError(ref message))
+                if is_lock_error(message) =>
+            {
+                "cache_locked"
+            }
+            CleanupError::ConanFailed(..)
+            | CleanupError::ConanOutput(_, ConanJsonError::ConanReportedError(_)) => "conan_failed",
+            CleanupError::ConanOutput(..) => "invalid_conan_output",
+            // A cache locked by another conan process fails all searches alike.
+            CleanupError::SearchFailed(ref failures)
+                if !failures.is_empty()
+                    && failures.iter().all(|err| err.code() == "cache_locked") =>
+            {
+                "cache_locked"
+            }
+            CleanupError::SearchFailed(_) => "search_failed",
+            CleanupError::RemovalFailed(..) => "removal_failed",
+            CleanupError::Refused(_) => "refused",
+            CleanupError::CleanupOverdue(_) => "cleanup_overdue",
+            CleanupError::DeadPatterns(_) => "dead_patterns",
+            CleanupError::Migrate(_) => "migration_failed",
+            CleanupError::Profile(_) => "invalid_profile",
+            CleanupError::Stdin(_) => "stdin_failed",
+            CleanupError::Cancelled(_) => "cancelled",
+        }
+    }
+
+    /// Returns what the error refers to, e.g. the command line of a failed conan command, as the
+    /// `context` of the JSON error of `--errors json`.
+    pub fn context(&self) -> serde_json::Value {
+        match *self {
+            CleanupError::UnknownConanHome(purpose) => json!({ "purpose": purpose }),
+            CleanupError::File(action, ref path, _) => json!({
+                "action": action,
+                "path": path.to_string_lossy(),
+            }),
+            CleanupError::ConanCommand(ref command_line, _)
+            | CleanupError::ConanOutput(ref command_line, _) => {
+                json!({ "command_line": command_line })
+            }
+            CleanupError::ConanFailed(ref command_line, status, ref output) => json!({
+                "command_line": command_line,
+                "exit_code": status.code(),
+                "output": output,
+            }),
+            CleanupError::SearchFailed(ref failures) => json!({
+                "failures": failures
+                    .iter()
+                    .map(|err| json!({
+                        "code": err.code(),
+                        "message": err.to_string(),
+                        "context": err.context(),
+                    }))
+                    .collect::<Vec<_>>(),
+            }),
+            CleanupError::RemovalFailed(packages, recipes) => json!({
+                "failed_packages": packages,
+                "failed_recipes": recipes,
+            }),
+            CleanupError::CleanupOverdue(nag_after) => {
+                json!({ "nag_after_seconds": nag_after.as_secs() })
+            }
+            CleanupError::DeadPatterns(ref patterns) => json!({ "patterns": patterns }),
+            CleanupError::Cancelled(ref report) => json!({ "removals": report.removals }),
+            CleanupError::InvalidArguments(_)
+            | CleanupError::Refused(_)
+            | CleanupError::Migrate(_)
+            | CleanupError::Profile(_)
+            | CleanupError::Stdin(_) => json!({}),
+        }
+    }
+}
+
+/// Returns whether conan failed since another conan process holds a lock on the cache, as told by
+/// the messages of conan 1 and of the database of conan 2.
+fn is_lock_error(message: &str) -> bool {
+    let message = message.to_lowercase();
+    message.contains("could not be acquired") || message.contains("is locked")
+}
+
+impl fmt::Display for CleanupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CleanupError::InvalidArguments(ref message) | CleanupError::Refused(ref message) => {
+                message.fmt(f)
+            }
+            CleanupError::UnknownConanHome(purpose) => write!(
+                f,
+                "Cannot {} since the conan home could not be determined.",
+                purpose
+            ),
+            CleanupError::File(action, ref path, ref err) => {
+                write!(f, "Failed to {} '{}': {}", action, path.display(), err)
+            }
+            CleanupError::ConanCommand(ref command_line, ref err) => {
+                write!(f, "Failed to run '{}': {}", command_line, err)
+            }
+            CleanupError::ConanFailed(ref command_line, status, ref output) => {
+                write!(f, "'{}' exited with {}", command_line, status)?;
+                if !output.is_empty() {
+                    write!(f, ":\n{}", output)?;
+                }
+                Ok(())
+            }
+            CleanupError::ConanOutput(ref command_line, ref err) => {
+                write!(f, "Failed to read the result of '{}': {}", command_line, err)
+            }
+            CleanupError::SearchFailed(ref failures) => write!(
+                f,
+                "Failed to search {} recipes, not removing anything.",
+                failures.len()
+            ),
+            CleanupError::RemovalFailed(0, recipes) => {
+                write!(f, "Failed to remove {} recipes.", recipes)
+            }
+            CleanupError::RemovalFailed(packages, 0) => {
+                write!(f, "Failed to remove {} packages.", packages)
+            }
+            CleanupError::RemovalFailed(packages, recipes) => write!(
+                f,
+                "Failed to remove {} packages and {} recipes.",
+                packages, recipes
+            ),
+            CleanupError::CleanupOverdue(nag_after) => write!(
+                f,
+                "The conan cache has not been cleaned up within the last {}. Consider running conan_cleanup.",
+                duration::format_duration(nag_after)
+            ),
+            CleanupError::DeadPatterns(ref patterns) => {
+                write!(f, "Patterns match no recipe in the cache:")?;
+                for usage in patterns {
+                    write!(f, "\n  '{}' of {}", usage.pattern, usage.source)?;
+                }
+                write!(f, "\nNot removing anything due to --fail-on-dead-patterns.")
+            }
+            CleanupError::Migrate(ref err) => err.fmt(f),
+            CleanupError::Profile(ref err) => err.fmt(f),
+            CleanupError::Stdin(ref err) => write!(f, "Failed to read answer from stdin: {}", err),
+            CleanupError::Cancelled(ref report) => match report.removals.len() {
+                0 => write!(f, "Cancelled."),
+                count => write!(f, "Cancelled after {} removals.", count),
+            },
+        }
+    }
+}
+
+impl std::error::Error for CleanupError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            CleanupError::File(_, _, ref err) => Some(err.as_ref()),
+            CleanupError::ConanCommand(_, ref err) | CleanupError::Stdin(ref err) => Some(err),
+            CleanupError::ConanOutput(_, ref err) => Some(err),
+            CleanupError::Migrate(ref err) => Some(err),
+            CleanupError::Profile(ref err) => Some(err),
+            CleanupError::InvalidArguments(_)
+            | CleanupError::UnknownConanHome(_)
+            | CleanupError::ConanFailed(..)
+            | CleanupError::SearchFailed(_)
+            | CleanupError::RemovalFailed(..)
+            | CleanupError::Refused(_)
+            | CleanupError::CleanupOverdue(_)
+            | CleanupError::DeadPatterns(_)
+            | CleanupError::Cancelled(_) => None,
+        }
+    }
+}
+
+impl From<migrate::MigrateError> for CleanupError {
+    fn from(err: migrate::MigrateError) -> CleanupError {
+        CleanupError::Migrate(err)
+    }
+}
+
+impl From<profile::ProfileError> for CleanupError {
+    fn from(err: profile::ProfileError) -> CleanupError {
+        CleanupError::Profile(err)
+    }
+}
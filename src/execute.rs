@@ -0,0 +1,238 @@
+//! Execution of a removal plan through conan: the packages of the plan, the packages built with a
+//! tool before the tool itself, and the recipes that have no packages left afterwards.
+
+use crate::cancel::CancellationToken;
+use crate::error::CleanupError;
+use crate::json_output::{Removal, Report};
+use crate::removal_order::RemovalOrder;
+use crate::resume::Progress;
+use crate::{cache, conan, reference, removal_order, scheduler, subprocess};
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub struct ExecuteOptions<'a> {
+    /// The storage folder of the cache, if known, to measure the packages before removing them.
+    pub storage_path: Option<&'a Path>,
+    /// Number of packages removed concurrently. conan can safely remove packages of different
+    /// recipes concurrently, but removing packages of the same recipe at the same time races on
+    /// the recipe's metadata, so those are always removed one after another.
+    pub jobs: usize,
+    /// Where every removed package is recorded as soon as it is gone, see `resume`.
+    pub progress: Option<&'a Progress>,
+}
+
+/// What removing the packages of a plan did.
+#[derive(Debug, Default)]
+pub struct Execution {
+    /// Removals that were attempted.
+    pub removals: Vec<Removal>,
+    /// Total size of the removed packages.
+    pub freed_bytes: u64,
+    /// Recipe, package ID and what conan printed of packages that could not be removed.
+    pub failures: Vec<(String, String, String)>,
+    /// Recipes whose packages conan refused to remove since they are installed as editable.
+    pub editable_recipes: BTreeSet<String>,
+    /// short_paths folders of removed packages that were left behind, e.g. since files in them
+    /// were still in use.
+    pub surviving_short_paths: Vec<PathBuf>,
+    /// Recipe, sizing time and removal time of the packages that were removed.
+    pub timings: Vec<(String, Duration, Duration)>,
+}
+
+/// Orders the removal of the recipes of the packages by the build requirements of the packages,
+/// see `removal_order`.
+pub fn removal_order(
+    packages: &BTreeMap<String, Vec<String>>,
+    storage_path: Option<&Path>,
+) -> RemovalOrder {
+    removal_order::removal_waves(
+        &packages.keys().cloned().collect(),
+        &removal_build_requires(storage_path, packages),
+    )
+}
+
+/// Collects the build requirements of the packages to remove per recipe, which determine the order
+/// of their removal.
+fn removal_build_requires(
+    storage_path: Option<&Path>,
+    packages: &BTreeMap<String, Vec<String>>,
+) -> BTreeMap<String, BTreeSet<String>> {
+    let storage_path = match storage_path {
+        Some(storage_path) => storage_path,
+        None => return BTreeMap::new(),
+    };
+    packages
+        .iter()
+        .map(|(recipe_id, package_ids)| {
+            let build_requires = package_ids
+                .iter()
+                .flat_map(|package_id| {
+                    cache::package_build_requires(storage_path, recipe_id, package_id)
+                })
+                .collect();
+            (recipe_id.clone(), build_requires)
+        })
+        .collect()
+}
+
+/// Removes the packages, given as package IDs per recipe reference, wave by wave in the given
+/// order. Failures are reported to stderr and collected, they don't stop the other removals. Once
+/// `token` is cancelled, no further removals are started and the run fails with
+/// `CleanupError::Cancelled`, carrying the removals that were done.
+pub fn execute(
+    packages: &BTreeMap<String, Vec<String>>,
+    order: &RemovalOrder,
+    options: &ExecuteOptions,
+    token: &CancellationToken,
+) -> Result<Execution, CleanupError> {
+    let mut results = Vec::new();
+    for wave in &order.waves {
+        let removals: Vec<_> = packages
+            .iter()
+            .filter(|(recipe_id, _)| wave.binary_search(recipe_id).is_ok())
+            .map(|(recipe_id, package_ids)| (recipe_id.clone(), package_ids.clone()))
+            .collect();
+        results.extend(scheduler::run_grouped(
+            removals,
+            options.jobs,
+            |recipe_id, package_id| {
+                let removal =
+                    conan::remove_package(options.storage_path, recipe_id, package_id, token);
+                if let (Some(progress), Ok(removal)) = (options.progress, &removal) {
+                    if removal.output.status.success() {
+                        if let Err(err) = progress.record(recipe_id, package_id) {
+                            warning!(
+                                "Failed to record the removal of '{}:{}' in the resume file: {}",
+                                recipe_id,
+                                package_id,
+                                err
+                            );
+                        }
+                    }
+                }
+                removal
+            },
+        ));
+    }
+
+    let mut execution = Execution::default();
+    for (recipe_id, removals) in results {
+        // The removals are in the order of the package IDs they were started for.
+        for (package_id, removal) in packages[&recipe_id].iter().zip(removals) {
+            let removal = match removal {
+                Ok(removal) => removal,
+                // Removals that were not started any more after cancelling are no failures.
+                Err(_) if token.is_cancelled() => continue,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    execution.removals.push(Removal {
+                        recipe: recipe_id.clone(),
+                        package: Some(package_id.clone()),
+                        error: Some(err.to_string()),
+                    });
+                    execution.failures.push((
+                        recipe_id.clone(),
+                        package_id.clone(),
+                        err.to_string(),
+                    ));
+                    continue;
+                }
+            };
+            if removal.output.status.success() {
+                execution.freed_bytes += removal.size;
+                execution.timings.push((
+                    recipe_id.clone(),
+                    removal.sizing_time,
+                    removal.removal_time,
+                ));
+            } else {
+                if conan::is_editable_error(&removal.output) {
+                    execution.editable_recipes.insert(recipe_id.clone());
+                }
+                eprintln!(
+                    "Failed to remove package '{}' of recipe '{}': conan exited with {}",
+                    removal.package_id, recipe_id, removal.output.status
+                );
+                let stderr = String::from_utf8_lossy(&removal.output.stderr)
+                    .trim()
+                    .to_owned();
+                if !stderr.is_empty() {
+                    eprintln!("{}", stderr);
+                }
+                execution
+                    .failures
+                    .push((recipe_id.clone(), removal.package_id.clone(), stderr));
+            }
+            execution.removals.push(Removal {
+                recipe: recipe_id.clone(),
+                error: (!removal.output.status.success())
+                    .then(|| format!("exited with {}", removal.output.status)),
+                package: Some(removal.package_id),
+            });
+            execution
+                .surviving_short_paths
+                .extend(removal.surviving_short_path);
+        }
+    }
+    if token.is_cancelled() {
+        return Err(CleanupError::Cancelled(Box::new(Report {
+            removals: execution.removals,
+            ..Report::default()
+        })));
+    }
+    Ok(execution)
+}
+
+/// Result of removing a recipe that has no packages left.
+pub struct RecipeRemoval {
+    pub command_line: String,
+    pub output: std::process::Output,
+    /// Why the removal failed, `None` if it succeeded.
+    pub failure: Option<String>,
+}
+
+/// Removes a recipe that has no packages left. With `verify`, the removal only succeeds if the
+/// recipe is gone from the cache afterwards. Fails without starting conan once `token` is
+/// cancelled.
+pub fn remove_recipe(
+    recipe_id: &str,
+    storage_path: Option<&Path>,
+    verify: bool,
+    token: &CancellationToken,
+) -> Result<RecipeRemoval, CleanupError> {
+    let remove_args = remove_recipe_args(recipe_id);
+    let remove_args: Vec<&str> = remove_args.iter().map(String::as_str).collect();
+    let output = subprocess::run_conan_unless_cancelled(&remove_args, token)
+        .map_err(|err| CleanupError::ConanCommand(conan::command_line(&remove_args), err))?;
+
+    let failure = if !output.status.success() {
+        Some(format!("exited with {}", output.status))
+    } else if verify && !is_recipe_gone(storage_path, recipe_id) {
+        Some("succeeded but the recipe is still in the cache".to_owned())
+    } else {
+        None
+    };
+    Ok(RecipeRemoval {
+        command_line: conan::command_line(&remove_args),
+        output,
+        failure,
+    })
+}
+
+/// Returns the arguments of the conan command removing a recipe without asking.
+pub fn remove_recipe_args(recipe_id: &str) -> Vec<String> {
+    let confirm = if reference::is_conan2() { "-c" } else { "-f" };
+    vec![
+        "remove".to_owned(),
+        reference::argument(recipe_id),
+        confirm.to_owned(),
+    ]
+}
+
+fn is_recipe_gone(storage_path: Option<&Path>, recipe_id: &str) -> bool {
+    storage_path
+        .and_then(|storage_path| cache::recipe_dir(storage_path, recipe_id))
+        .is_none_or(|recipe_dir| !recipe_dir.exists())
+}
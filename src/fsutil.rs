@@ -0,0 +1,134 @@
+//! Crash-safe writing of the files the tool produces, and setting aside files that turn out to be
+//! corrupt. With `--read-only`, all writes and removals below the protected folders fail. Also
+//! tells whether two paths lead to the same file.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Makes all writes and removals below `dir` fail for the rest of the session.
+pub fn protect(dir: &Path) {
+    crate::session::current()
+        .protected_dirs
+        .lock()
+        .unwrap()
+        .push(dir.to_path_buf());
+}
+
+/// Returns whether `path` is below a folder protected by `protect`.
+pub fn is_protected(path: &Path) -> bool {
+    crate::session::current()
+        .protected_dirs
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|dir| path.starts_with(dir))
+}
+
+/// Fails if `path` must not be modified, see `protect`.
+pub fn check_writable(path: &Path) -> std::io::Result<()> {
+    if is_protected(path) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            "--read-only forbids modifying the conan home",
+        ));
+    }
+    Ok(())
+}
+
+/// `std::fs::remove_file`, unless the file is protected, see `protect`.
+pub fn remove_file(path: &Path) -> std::io::Result<()> {
+    check_writable(path)?;
+    std::fs::remove_file(path)
+}
+
+/// `std::fs::remove_dir_all`, unless the folder is protected, see `protect`.
+pub fn remove_dir_all(path: &Path) -> std::io::Result<()> {
+    check_writable(path)?;
+    std::fs::remove_dir_all(path)
+}
+
+/// Writes `content` to a temporary file next to `path` and renames it over `path` afterwards, so
+/// a crash or a full disk never leaves a partially written file behind.
+pub fn atomic_write(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    check_writable(path)?;
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(format!(".tmp-{}", std::process::id()));
+    let temp_path = PathBuf::from(temp_path);
+
+    let result =
+        write_and_sync(&temp_path, content).and_then(|()| std::fs::rename(&temp_path, path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result?;
+
+    sync_parent_dir(path);
+    Ok(())
+}
+
+fn write_and_sync(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let mut file = std::fs::File::create(path)?;
+    file.write_all(content)?;
+    file.sync_all()
+}
+
+/// Makes the rename durable. Only possible on unix, where directories can be opened like files.
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        if let Ok(dir) = std::fs::File::open(dir) {
+            let _ = dir.sync_all();
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) {}
+
+/// Renames a corrupt file to `<name>.corrupt-<timestamp>`, so that it is out of the way but still
+/// available for inspection. Returns the new path.
+pub fn quarantine(path: &Path) -> std::io::Result<PathBuf> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_secs());
+    let mut quarantine_path = path.as_os_str().to_owned();
+    quarantine_path.push(format!(".corrupt-{}", timestamp));
+    let quarantine_path = PathBuf::from(quarantine_path);
+
+    check_writable(path)?;
+    std::fs::rename(path, &quarantine_path)?;
+    Ok(quarantine_path)
+}
+
+/// Identifies a file or folder regardless of the path it is reached by, e.g. through symlinks or
+/// `..`: its device and inode on unix, its canonical path elsewhere.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FileIdentity {
+    #[cfg(unix)]
+    device: u64,
+    #[cfg(unix)]
+    inode: u64,
+    #[cfg(not(unix))]
+    canonical_path: PathBuf,
+}
+
+/// Returns the identity of the file or folder at `path`, following symlinks.
+#[cfg(unix)]
+pub fn file_identity(path: &Path) -> std::io::Result<FileIdentity> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = std::fs::metadata(path)?;
+    Ok(FileIdentity {
+        device: metadata.dev(),
+        inode: metadata.ino(),
+    })
+}
+
+/// Returns the identity of the file or folder at `path`, following symlinks.
+#[cfg(not(unix))]
+pub fn file_identity(path: &Path) -> std::io::Result<FileIdentity> {
+    Ok(FileIdentity {
+        canonical_path: std::fs::canonicalize(path)?,
+    })
+}
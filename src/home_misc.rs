@@ -0,0 +1,179 @@
+//! Finds files in the conan home that conan or its users leave behind and that are safe to
+//! delete, like backups of settings.yml or profiles and leftover temporary folders.
+
+use crate::error::CleanupError;
+use crate::output::{Output, Prompt};
+use crate::size::format_size;
+use crate::{fsutil, io_budget};
+
+use walkdir::WalkDir;
+
+use std::path::{Component, Path, PathBuf};
+
+/// Patterns of disposable entries. Patterns starting with `/` are matched against the path relative
+/// to the conan home, all others against the name of files and folders at any depth. `*` matches
+/// any number of characters and `?` matches a single one.
+pub const DEFAULT_PATTERNS: &[&str] = &[
+    "*.backup",
+    "*.backup.*",
+    "*.bak",
+    "*.dirty",
+    "*.orig",
+    "/tmp",
+];
+
+/// A disposable file or folder in the conan home. Folders are removed as a whole.
+#[derive(Debug)]
+pub struct MiscEntry {
+    pub path: PathBuf,
+    pub is_dir: bool,
+    pub size: u64,
+}
+
+/// Searches the conan home for entries matching any of the given patterns. The storage folder is
+/// never searched, even if it is located inside the conan home.
+pub fn find_disposable_entries(
+    conan_home: &Path,
+    storage_path: &Path,
+    patterns: &[String],
+) -> Vec<MiscEntry> {
+    let mut entries = Vec::new();
+    let mut walker = WalkDir::new(conan_home).min_depth(1).into_iter();
+    while let Some(entry) = walker.next() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        if entry.path().starts_with(storage_path) {
+            if entry.file_type().is_dir() {
+                walker.skip_current_dir();
+            }
+            continue;
+        }
+
+        let relative_path = match entry.path().strip_prefix(conan_home) {
+            Ok(relative_path) => relative_path,
+            Err(_) => continue,
+        };
+        if !patterns
+            .iter()
+            .any(|pattern| matches_entry(pattern, relative_path))
+            || !is_removable(conan_home, storage_path, entry.path())
+        {
+            continue;
+        }
+
+        let is_dir = entry.file_type().is_dir();
+        let size = if is_dir {
+            walker.skip_current_dir();
+            crate::cache::directory_size(entry.path())
+        } else {
+            entry.metadata().map(|metadata| metadata.len()).unwrap_or(0)
+        };
+        entries.push(MiscEntry {
+            path: entry.into_path(),
+            is_dir,
+            size,
+        });
+    }
+    entries
+}
+
+/// Returns whether the given path is inside the conan home but outside the storage folder and thus
+/// may be deleted.
+pub fn is_removable(conan_home: &Path, storage_path: &Path, path: &Path) -> bool {
+    let relative_path = match path.strip_prefix(conan_home) {
+        Ok(relative_path) => relative_path,
+        Err(_) => return false,
+    };
+
+    relative_path.components().next().is_some()
+        && !relative_path
+            .components()
+            .any(|component| component == Component::ParentDir)
+        && !path.starts_with(storage_path)
+        && !storage_path.starts_with(path)
+}
+
+fn matches_entry(pattern: &str, relative_path: &Path) -> bool {
+    if let Some(pattern) = pattern.strip_prefix('/') {
+        let relative_path = relative_path
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        crate::wildcard::matches(pattern, &relative_path)
+    } else {
+        relative_path
+            .file_name()
+            .map(|name| crate::wildcard::matches(pattern, &name.to_string_lossy()))
+            .unwrap_or(false)
+    }
+}
+
+/// Removes the entries of the conan home matching any of the given patterns, see
+/// `find_disposable_entries`, after confirmation unless `force` is set.
+pub fn clean(
+    out: &Output,
+    prompt: &mut dyn Prompt,
+    conan_home: &Path,
+    storage_path: &Path,
+    patterns: &[String],
+    force: bool,
+) -> Result<(), CleanupError> {
+    let entries = find_disposable_entries(conan_home, storage_path, patterns);
+    if entries.is_empty() {
+        outln!(out, "No disposable files found in the conan home.");
+        return Ok(());
+    }
+
+    outln!(out, "Disposable files in the conan home:");
+    for entry in &entries {
+        outln!(
+            out,
+            "  {} ({})",
+            entry.path.display(),
+            format_size(entry.size)
+        );
+    }
+    let total_size: u64 = entries.iter().map(|entry| entry.size).sum();
+    outln!(
+        out,
+        "Found {} disposable entries ({}).",
+        entries.len(),
+        format_size(total_size)
+    );
+
+    if !force {
+        outln!(out, "Do you want to remove these files? (yes/no)");
+    }
+
+    if force || prompt.yes_or_no(out)? {
+        let mut removed_count = 0;
+        for entry in &entries {
+            if !is_removable(conan_home, storage_path, &entry.path) {
+                continue;
+            }
+
+            let result = if entry.is_dir {
+                fsutil::remove_dir_all(&entry.path)
+            } else {
+                fsutil::remove_file(&entry.path)
+            };
+            match result {
+                Ok(()) => {
+                    removed_count += 1;
+                    io_budget::consume(entry.size);
+                }
+                Err(err) => eprintln!("Failed to remove '{}': {}", entry.path.display(), err),
+            }
+        }
+        outln!(
+            out,
+            "Removed {} of {} disposable entries.",
+            removed_count,
+            entries.len()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,245 @@
+//! Cleanups of the conan home besides the removal of unused packages: stale lock files, orphaned
+//! short_paths folders and the unused packages of storage folders conan doesn't know about.
+
+use crate::error::CleanupError;
+use crate::output::{Output, Prompt};
+use crate::scan::PackagesInUse;
+use crate::size::format_size;
+use crate::{cache, duration, fsutil, io_budget};
+
+use std::path::Path;
+use std::time::Duration;
+
+/// Stale lock files found and removed by `clean_lock_files`.
+#[derive(Debug, Default)]
+pub struct LockCleanup {
+    pub found: usize,
+    pub removed: usize,
+    pub freed_bytes: u64,
+}
+
+/// Removes the lock files in the storage folder that were not modified within `older_than`, or
+/// only reports them with `report_only`.
+pub fn clean_lock_files(
+    out: &Output,
+    prompt: &mut dyn Prompt,
+    storage_path: &Path,
+    older_than: Duration,
+    force: bool,
+    report_only: bool,
+) -> Result<LockCleanup, CleanupError> {
+    let older_than_text = duration::format_duration(older_than);
+    let lock_files = cache::find_stale_lock_files(storage_path, older_than);
+    let mut cleanup = LockCleanup {
+        found: lock_files.len(),
+        ..LockCleanup::default()
+    };
+    if lock_files.is_empty() {
+        outln!(out, "No lock files older than {} found.", older_than_text);
+        return Ok(cleanup);
+    }
+
+    let total_size: u64 = lock_files.iter().map(|lock_file| lock_file.size).sum();
+    outln!(
+        out,
+        "Found {} lock files older than {} ({}).",
+        lock_files.len(),
+        older_than_text,
+        format_size(total_size)
+    );
+    if report_only {
+        return Ok(cleanup);
+    }
+
+    if !force {
+        outln!(out, "Do you want to remove these lock files? (yes/no)");
+    }
+
+    if force || prompt.yes_or_no(out)? {
+        for lock_file in &lock_files {
+            if !cache::is_removable_lock_file(storage_path, &lock_file.path) {
+                continue;
+            }
+
+            match fsutil::remove_file(&lock_file.path) {
+                Ok(()) => {
+                    cleanup.removed += 1;
+                    cleanup.freed_bytes += lock_file.size;
+                }
+                Err(err) => eprintln!(
+                    "Failed to remove lock file '{}': {}",
+                    lock_file.path.display(),
+                    err
+                ),
+            }
+        }
+        outln!(
+            out,
+            "Removed {} of {} lock files.",
+            cleanup.removed,
+            lock_files.len()
+        );
+    }
+    Ok(cleanup)
+}
+
+/// Deletes the unused packages of a storage folder conan doesn't know about by deleting their
+/// folders. Recipes without any package in use are deleted as a whole. Returns the number of bytes
+/// deleted.
+pub fn clean_extra_storage(
+    out: &Output,
+    prompt: &mut dyn Prompt,
+    extra_storage_path: &Path,
+    storage_path: Option<&Path>,
+    packages_in_use: &PackagesInUse,
+    force: bool,
+) -> Result<u64, CleanupError> {
+    let canonical_path = match std::fs::canonicalize(extra_storage_path) {
+        Ok(path) => path,
+        Err(err) => {
+            eprintln!(
+                "Cannot read extra storage folder '{}': {}",
+                extra_storage_path.display(),
+                err
+            );
+            return Ok(0);
+        }
+    };
+    let overlaps_storage = storage_path
+        .and_then(|storage_path| std::fs::canonicalize(storage_path).ok())
+        .is_some_and(|storage_path| {
+            storage_path.starts_with(&canonical_path) || canonical_path.starts_with(&storage_path)
+        });
+    if overlaps_storage {
+        eprintln!(
+            "Skipping extra storage folder '{}' since it overlaps the storage folder used by conan.",
+            extra_storage_path.display()
+        );
+        return Ok(0);
+    }
+
+    let mut deletions = Vec::new();
+    for recipe in cache::find_layout_recipes(&canonical_path) {
+        let unused_packages: Vec<_> = recipe
+            .packages
+            .iter()
+            .filter(|(package_id, _)| {
+                !packages_in_use.contains_package(&recipe.recipe_id, package_id)
+            })
+            .collect();
+        if unused_packages.len() == recipe.packages.len() {
+            deletions.push((recipe.recipe_id, "whole recipe".to_owned(), recipe.path));
+        } else {
+            for (package_id, path) in unused_packages {
+                deletions.push((recipe.recipe_id.clone(), package_id.clone(), path.clone()));
+            }
+        }
+    }
+
+    if deletions.is_empty() {
+        outln!(
+            out,
+            "No unused packages found in extra storage folder '{}'.",
+            extra_storage_path.display()
+        );
+        return Ok(0);
+    }
+
+    outln!(
+        out,
+        "Unused contents of extra storage folder '{}' (unreachable by conan):",
+        extra_storage_path.display()
+    );
+    let sizes: Vec<_> = deletions
+        .iter()
+        .map(|(_, _, path)| cache::directory_size(path))
+        .collect();
+    for ((recipe_id, what, _), size) in deletions.iter().zip(&sizes) {
+        outln!(out, "  {} {} ({})", recipe_id, what, format_size(*size));
+    }
+    outln!(
+        out,
+        "Deleting them frees {}.",
+        format_size(sizes.iter().sum::<u64>())
+    );
+
+    if !force {
+        outln!(out, "Do you want to delete these folders? (yes/no)");
+    }
+    if !force && !prompt.yes_or_no(out)? {
+        return Ok(0);
+    }
+
+    let mut deleted_bytes = 0;
+    for ((_, _, path), size) in deletions.iter().zip(&sizes) {
+        if !cache::is_inside_storage(&canonical_path, path) {
+            continue;
+        }
+        match fsutil::remove_dir_all(path) {
+            Ok(()) => {
+                deleted_bytes += size;
+                io_budget::consume(*size);
+            }
+            Err(err) => eprintln!("Failed to delete folder '{}': {}", path.display(), err),
+        }
+    }
+    Ok(deleted_bytes)
+}
+
+/// Removes the folders below the short_paths home that no package of the cache links to anymore.
+pub fn clean_short_paths_orphans(
+    out: &Output,
+    prompt: &mut dyn Prompt,
+    short_paths_home: &Path,
+    force: bool,
+) -> Result<(), CleanupError> {
+    let orphans = cache::find_short_paths_orphans(short_paths_home);
+    if orphans.is_empty() {
+        outln!(out, "No orphaned short_paths folders found.");
+        return Ok(());
+    }
+
+    outln!(out, "Orphaned short_paths folders:");
+    for orphan in &orphans {
+        outln!(
+            out,
+            "  {} ({})",
+            orphan.path.display(),
+            format_size(orphan.size)
+        );
+    }
+    let total_size: u64 = orphans.iter().map(|orphan| orphan.size).sum();
+    outln!(
+        out,
+        "Found {} orphaned short_paths folders ({}).",
+        orphans.len(),
+        format_size(total_size)
+    );
+
+    if !force {
+        outln!(out, "Do you want to remove these folders? (yes/no)");
+    }
+
+    if force || prompt.yes_or_no(out)? {
+        let mut removed_size = 0;
+        for orphan in &orphans {
+            match fsutil::remove_dir_all(&orphan.path) {
+                Ok(()) => {
+                    removed_size += orphan.size;
+                    io_budget::consume(orphan.size);
+                }
+                Err(err) => eprintln!(
+                    "Failed to remove folder '{}': {}",
+                    orphan.path.display(),
+                    err
+                ),
+            }
+        }
+        outln!(
+            out,
+            "Reclaimed {} from orphaned short_paths folders.",
+            format_size(removed_size)
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,366 @@
+//! Records the contents of a storage folder in an inventory file and compares two inventories, to
+//! find out what makes a cache grow. Inventories are taken from the folder layout alone, without
+//! invoking conan and without modifying the cache.
+
+use serde_json::json;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const INVENTORY_VERSION: u64 = 1;
+
+/// Recipes and binary packages of a storage folder at a point in time.
+#[derive(Debug)]
+pub struct Inventory {
+    /// When the inventory was taken, in seconds since the UNIX epoch.
+    pub taken_at: u64,
+    pub storage_path: String,
+    pub recipes: BTreeMap<String, RecipeInventory>,
+}
+
+#[derive(Debug)]
+pub struct RecipeInventory {
+    /// Size of the whole recipe folder, including sources, builds and packages.
+    pub size: u64,
+    /// Modification time of the recipe folder in seconds since the UNIX epoch.
+    pub modified: u64,
+    pub packages: BTreeMap<String, PackageInventory>,
+}
+
+#[derive(Debug)]
+pub struct PackageInventory {
+    /// Size of the package, including its short_paths folder.
+    pub size: u64,
+    /// Modification time of the package folder in seconds since the UNIX epoch.
+    pub modified: u64,
+}
+
+/// Takes an inventory of the given storage folder.
+pub fn take(storage_path: &Path) -> Inventory {
+    let recipes = crate::cache::find_layout_recipes(storage_path)
+        .into_iter()
+        .map(|recipe| {
+            let packages = recipe
+                .packages
+                .iter()
+                .map(|(package_id, package_dir)| {
+                    let size = crate::cache::directory_size(package_dir)
+                        + crate::cache::short_path_target(package_dir)
+                            .map_or(0, |short_path| crate::cache::directory_size(&short_path));
+                    (
+                        package_id.clone(),
+                        PackageInventory {
+                            size,
+                            modified: modification_time(package_dir),
+                        },
+                    )
+                })
+                .collect();
+            let inventory = RecipeInventory {
+                size: crate::cache::directory_size(&recipe.path),
+                modified: modification_time(&recipe.path),
+                packages,
+            };
+            (recipe.recipe_id, inventory)
+        })
+        .collect();
+
+    Inventory {
+        taken_at: seconds_since_epoch(SystemTime::now()),
+        storage_path: storage_path.to_string_lossy().into_owned(),
+        recipes,
+    }
+}
+
+fn modification_time(path: &Path) -> u64 {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .map_or(0, seconds_since_epoch)
+}
+
+fn seconds_since_epoch(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_secs())
+}
+
+impl Inventory {
+    /// Returns the combined size of all recipe folders.
+    pub fn total_size(&self) -> u64 {
+        self.recipes.values().map(|recipe| recipe.size).sum()
+    }
+
+    pub fn load(path: &Path) -> Result<Inventory, InventoryError> {
+        let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        crate::run_info::print_embedded(path, &json);
+        let version = json["version"]
+            .as_u64()
+            .ok_or_else(|| InventoryError::FormatError("Missing 'version' number".to_owned()))?;
+        if version != INVENTORY_VERSION {
+            return Err(InventoryError::FormatError(format!(
+                "Unsupported version {}",
+                version
+            )));
+        }
+
+        let recipe_objects = json["recipes"]
+            .as_object()
+            .ok_or_else(|| InventoryError::FormatError("Missing 'recipes' object".to_owned()))?;
+        let mut recipes = BTreeMap::new();
+        for (recipe_id, recipe) in recipe_objects {
+            let incomplete =
+                || InventoryError::FormatError(format!("Recipe '{}' is incomplete", recipe_id));
+            let package_objects = recipe["packages"].as_object().ok_or_else(incomplete)?;
+            let mut packages = BTreeMap::new();
+            for (package_id, package) in package_objects {
+                match (package["size"].as_u64(), package["modified"].as_u64()) {
+                    (Some(size), Some(modified)) => {
+                        packages.insert(package_id.clone(), PackageInventory { size, modified });
+                    }
+                    _ => return Err(incomplete()),
+                }
+            }
+            match (recipe["size"].as_u64(), recipe["modified"].as_u64()) {
+                (Some(size), Some(modified)) => {
+                    recipes.insert(
+                        recipe_id.clone(),
+                        RecipeInventory {
+                            size,
+                            modified,
+                            packages,
+                        },
+                    );
+                }
+                _ => return Err(incomplete()),
+            }
+        }
+
+        Ok(Inventory {
+            taken_at: json["taken_at"].as_u64().unwrap_or(0),
+            storage_path: json["storage_path"].as_str().unwrap_or("").to_owned(),
+            recipes,
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), InventoryError> {
+        let recipes: serde_json::Map<String, serde_json::Value> = self
+            .recipes
+            .iter()
+            .map(|(recipe_id, recipe)| {
+                let packages: serde_json::Map<String, serde_json::Value> = recipe
+                    .packages
+                    .iter()
+                    .map(|(package_id, package)| {
+                        (
+                            package_id.clone(),
+                            json!({"size": package.size, "modified": package.modified}),
+                        )
+                    })
+                    .collect();
+                (
+                    recipe_id.clone(),
+                    json!({
+                        "size": recipe.size,
+                        "modified": recipe.modified,
+                        "packages": packages,
+                    }),
+                )
+            })
+            .collect();
+        let json = json!({
+            "version": INVENTORY_VERSION,
+            "run_info": crate::run_info::to_json(),
+            "taken_at": self.taken_at,
+            "storage_path": self.storage_path,
+            "recipes": recipes,
+        });
+
+        crate::fsutil::atomic_write(path, serde_json::to_string_pretty(&json)?.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Differences between two inventories of the same storage folder.
+#[derive(Debug, Default)]
+pub struct InventoryDiff {
+    /// Recipes that only exist in the newer inventory, with their size.
+    pub added_recipes: Vec<(String, u64)>,
+    /// Recipes that only exist in the older inventory, with their size.
+    pub removed_recipes: Vec<(String, u64)>,
+    /// Packages of recipes in both inventories that only exist in the newer one, with their size.
+    pub added_packages: Vec<(String, String, u64)>,
+    /// Packages of recipes in both inventories that only exist in the older one, with their size.
+    pub removed_packages: Vec<(String, String, u64)>,
+    /// Combined size of all recipes of a package name in the older and the newer inventory.
+    pub size_per_name: BTreeMap<String, (u64, u64)>,
+    /// Removed and added recipes with the same name, version and at least one common package ID,
+    /// which were most likely moved to another user or channel.
+    pub probably_moved: Vec<(String, String)>,
+}
+
+/// Compares an older inventory with a newer one.
+pub fn diff(old: &Inventory, new: &Inventory) -> InventoryDiff {
+    let mut diff = InventoryDiff::default();
+    for (recipe_id, recipe) in &new.recipes {
+        match old.recipes.get(recipe_id) {
+            Some(old_recipe) => {
+                for (package_id, package) in &recipe.packages {
+                    if !old_recipe.packages.contains_key(package_id) {
+                        diff.added_packages.push((
+                            recipe_id.clone(),
+                            package_id.clone(),
+                            package.size,
+                        ));
+                    }
+                }
+                for (package_id, package) in &old_recipe.packages {
+                    if !recipe.packages.contains_key(package_id) {
+                        diff.removed_packages.push((
+                            recipe_id.clone(),
+                            package_id.clone(),
+                            package.size,
+                        ));
+                    }
+                }
+            }
+            None => diff.added_recipes.push((recipe_id.clone(), recipe.size)),
+        }
+    }
+    for (recipe_id, recipe) in &old.recipes {
+        if !new.recipes.contains_key(recipe_id) {
+            diff.removed_recipes.push((recipe_id.clone(), recipe.size));
+        }
+    }
+
+    for (recipe_id, recipe) in &old.recipes {
+        diff.size_per_name
+            .entry(package_name(recipe_id).to_owned())
+            .or_insert((0, 0))
+            .0 += recipe.size;
+    }
+    for (recipe_id, recipe) in &new.recipes {
+        diff.size_per_name
+            .entry(package_name(recipe_id).to_owned())
+            .or_insert((0, 0))
+            .1 += recipe.size;
+    }
+
+    for (removed_id, _) in &diff.removed_recipes {
+        for (added_id, _) in &diff.added_recipes {
+            let old_packages: BTreeSet<_> = old.recipes[removed_id].packages.keys().collect();
+            let shares_packages = new.recipes[added_id]
+                .packages
+                .keys()
+                .any(|package_id| old_packages.contains(package_id));
+            if name_and_version(removed_id) == name_and_version(added_id) && shares_packages {
+                diff.probably_moved
+                    .push((removed_id.clone(), added_id.clone()));
+            }
+        }
+    }
+    diff
+}
+
+/// Returns the name of a recipe reference, e.g. `zlib` of `zlib/1.2.11@conan/stable`.
+fn package_name(recipe_id: &str) -> &str {
+    recipe_id.split('/').next().unwrap_or(recipe_id)
+}
+
+/// Returns the reference without user and channel, e.g. `zlib/1.2.11` of
+/// `zlib/1.2.11@conan/stable`.
+fn name_and_version(recipe_id: &str) -> &str {
+    recipe_id.split('@').next().unwrap_or(recipe_id)
+}
+
+impl InventoryDiff {
+    pub fn to_json(&self) -> serde_json::Value {
+        let recipes = |recipes: &[(String, u64)]| -> Vec<serde_json::Value> {
+            recipes
+                .iter()
+                .map(|(recipe_id, size)| json!({"recipe": recipe_id, "size": size}))
+                .collect()
+        };
+        let packages = |packages: &[(String, String, u64)]| -> Vec<serde_json::Value> {
+            packages
+                .iter()
+                .map(|(recipe_id, package_id, size)| {
+                    json!({"recipe": recipe_id, "package": package_id, "size": size})
+                })
+                .collect()
+        };
+        let size_per_name: serde_json::Map<String, serde_json::Value> = self
+            .size_per_name
+            .iter()
+            .map(|(name, (old_size, new_size))| {
+                (name.clone(), json!({"old": old_size, "new": new_size}))
+            })
+            .collect();
+        let probably_moved: Vec<_> = self
+            .probably_moved
+            .iter()
+            .map(|(from, to)| json!({"from": from, "to": to}))
+            .collect();
+        let (old_total, new_total) = self.total_sizes();
+
+        json!({
+            "added_recipes": recipes(&self.added_recipes),
+            "removed_recipes": recipes(&self.removed_recipes),
+            "added_packages": packages(&self.added_packages),
+            "removed_packages": packages(&self.removed_packages),
+            "size_per_name": size_per_name,
+            "probably_moved": probably_moved,
+            "total": {"old": old_total, "new": new_total},
+        })
+    }
+
+    /// Returns the combined size of all recipes in the older and the newer inventory.
+    pub fn total_sizes(&self) -> (u64, u64) {
+        self.size_per_name
+            .values()
+            .fold((0, 0), |(old_total, new_total), (old_size, new_size)| {
+                (old_total + old_size, new_total + new_size)
+            })
+    }
+}
+
+#[derive(Debug)]
+pub enum InventoryError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    FormatError(String),
+}
+
+impl fmt::Display for InventoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InventoryError::Io(ref err) => err.fmt(f),
+            InventoryError::Json(ref err) => err.fmt(f),
+            InventoryError::FormatError(ref err) => {
+                write!(f, "Unexpected snapshot file format: {}", err)
+            }
+        }
+    }
+}
+
+impl std::error::Error for InventoryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            InventoryError::Io(ref err) => Some(err),
+            InventoryError::Json(ref err) => Some(err),
+            InventoryError::FormatError(_) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for InventoryError {
+    fn from(err: std::io::Error) -> InventoryError {
+        InventoryError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for InventoryError {
+    fn from(err: serde_json::Error) -> InventoryError {
+        InventoryError::Json(err)
+    }
+}
@@ -0,0 +1,121 @@
+//! Throttling of the filesystem operations of the tool for `--io-budget`, so that cleaning a cache
+//! on a network share doesn't saturate the filer. The helpers that size and delete folders report
+//! the bytes they touched, and the removal of each package reports its size, which stands in for
+//! the work conan does. Whenever more bytes were touched within the current window than the budget
+//! allows, the reporting thread sleeps until the rate is back within the budget.
+
+use std::time::{Duration, Instant};
+
+/// Bytes charged for every file or folder visited while sizing a folder, which costs a metadata
+/// request on network filesystems even if the file is empty.
+pub const METADATA_BYTES: u64 = 4096;
+/// Length of the windows the rate is measured in. Bursts within a window are fine, the rate only
+/// has to hold across it.
+const WINDOW: Duration = Duration::from_secs(1);
+
+/// Source of the time that the throttle measures and sleeps with.
+pub trait Clock {
+    fn now(&self) -> Instant;
+    fn sleep(&self, duration: Duration);
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+/// Limits the rate of touched bytes to a budget in bytes per second.
+pub struct Throttle<C: Clock> {
+    clock: C,
+    bytes_per_second: u64,
+    started_at: Instant,
+    window_start: Instant,
+    bytes_in_window: u64,
+    total_bytes: u64,
+}
+
+impl<C: Clock> Throttle<C> {
+    pub fn new(clock: C, bytes_per_second: u64) -> Throttle<C> {
+        let now = clock.now();
+        Throttle {
+            clock,
+            bytes_per_second: bytes_per_second.max(1),
+            started_at: now,
+            window_start: now,
+            bytes_in_window: 0,
+            total_bytes: 0,
+        }
+    }
+
+    /// Records that `bytes` were touched and sleeps as long as needed to stay within the budget.
+    /// Returns how long it slept.
+    pub fn consume(&mut self, bytes: u64) -> Duration {
+        let now = self.clock.now();
+        if now.duration_since(self.window_start) >= WINDOW {
+            self.window_start = now;
+            self.bytes_in_window = 0;
+        }
+        self.bytes_in_window += bytes;
+        self.total_bytes += bytes;
+
+        let due = self.window_start
+            + Duration::from_secs_f64(self.bytes_in_window as f64 / self.bytes_per_second as f64);
+        let delay = due.saturating_duration_since(now);
+        if !delay.is_zero() {
+            self.clock.sleep(delay);
+        }
+        // Whatever exceeded the window was paid for by sleeping.
+        if due.duration_since(self.window_start) >= WINDOW {
+            self.window_start = due;
+            self.bytes_in_window = 0;
+        }
+        delay
+    }
+
+    /// Returns the bytes touched so far and the average rate in bytes per second.
+    pub fn summary(&self) -> (u64, f64) {
+        let elapsed = self
+            .clock
+            .now()
+            .duration_since(self.started_at)
+            .as_secs_f64();
+        let rate = match elapsed {
+            elapsed if elapsed > 0.0 => self.total_bytes as f64 / elapsed,
+            _ => 0.0,
+        };
+        (self.total_bytes, rate)
+    }
+
+    pub fn bytes_per_second(&self) -> u64 {
+        self.bytes_per_second
+    }
+}
+
+/// Records that `bytes` were touched, sleeping if that exceeds the budget of the session, see
+/// `Settings::io_budget`. Does nothing without a budget. Concurrent removals wait for each other
+/// here, so the budget holds for all of them together.
+pub fn consume(bytes: u64) {
+    if let Some(ref throttle) = crate::session::current().throttle {
+        let delay = throttle.lock().unwrap().consume(bytes);
+        if !delay.is_zero() {
+            trace!("Slept {:.2?} to stay within --io-budget", delay);
+        }
+    }
+}
+
+/// Returns the budget, the bytes touched so far and the average rate in bytes per second, if there
+/// is a budget.
+pub fn summary() -> Option<(u64, u64, f64)> {
+    crate::session::current().throttle.as_ref().map(|throttle| {
+        let throttle = throttle.lock().unwrap();
+        let (total_bytes, rate) = throttle.summary();
+        (throttle.bytes_per_second(), total_bytes, rate)
+    })
+}
@@ -0,0 +1,281 @@
+//! Machine-readable result of a run for `--output json`. Human-readable output goes to stderr in
+//! this mode, see `output`, so that stdout only carries the JSON document printed at the end of the
+//! run.
+
+use crate::error::CleanupError;
+use crate::pattern_usage::{PatternSource, PatternUsage};
+use crate::timing::{Estimate, RecipeEstimate};
+
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+/// Version of the report format, raised when fields are added, removed or change their meaning.
+/// Reports before version 1 have no `schema_version` and no `bytes_freed` in their summary.
+pub const SCHEMA_VERSION: u64 = 1;
+
+/// Result of a run.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub root_paths: Vec<String>,
+    /// Hash of the planned package removals, see `plan_hash`.
+    pub plan_hash: String,
+    /// Hash of the plan without the packages excepted by `--except`, if any were.
+    pub applied_plan_hash: Option<String>,
+    pub exceptions: Vec<String>,
+    /// Packages required by the scanned usage files.
+    pub packages_in_use: Vec<PackageReference>,
+    /// Packages planned for removal, grouped by recipe.
+    pub candidates: Vec<RecipeCandidates>,
+    /// Removals that were attempted, empty for dry runs.
+    pub removals: Vec<Removal>,
+    /// Cached references violating the naming rules of conan, only checked with
+    /// `--lint-references`.
+    pub reference_violations: Vec<ReferenceViolation>,
+    /// Patterns of `--include`, `--exclude` and the keep file that match no recipe of the cache.
+    pub dead_patterns: Vec<PatternUsage>,
+    /// Usage files referencing revisions although the cache has them disabled.
+    pub revision_mismatch: Option<RevisionMismatch>,
+    /// How long removing the candidates is estimated to take, only with `--simulate-timing`.
+    pub timing_estimate: Option<Estimate>,
+    /// Counts of what the run did, set when it finishes.
+    pub summary: Option<Summary>,
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PackageReference {
+    pub recipe: String,
+    pub package: String,
+}
+
+#[derive(Debug)]
+pub struct RecipeCandidates {
+    pub recipe: String,
+    pub packages: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct ReferenceViolation {
+    pub recipe: String,
+    /// Names of the violated rules.
+    pub rules: Vec<String>,
+}
+
+/// Removal of a single package, or of a whole recipe if `package` is `None`.
+#[derive(Debug)]
+pub struct Removal {
+    pub recipe: String,
+    pub package: Option<String>,
+    /// Why the removal failed, `None` if it succeeded.
+    pub error: Option<String>,
+}
+
+/// Usage files whose revisions were ignored to match them against a cache without revisions.
+#[derive(Debug)]
+pub struct RevisionMismatch {
+    pub usage_files: Vec<String>,
+}
+
+/// What a run did, printed at its end.
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub usage_files: usize,
+    /// Usage files that could not be read or parsed.
+    pub failed_usage_files: usize,
+    pub packages_in_use: usize,
+    pub recipes_examined: usize,
+    pub packages_examined: usize,
+    pub packages_removed: usize,
+    pub failed_package_removals: usize,
+    pub recipes_removed: usize,
+    /// Size of the removed packages.
+    pub bytes_freed: u64,
+    /// Lock files older than `--locks-older-than` found with lock file cleaning enabled.
+    pub stale_lock_files: usize,
+    pub lock_files_removed: usize,
+    /// Total size of the removed lock files.
+    pub lock_file_bytes_freed: u64,
+    pub wall_time: std::time::Duration,
+}
+
+impl Report {
+    /// Prints the report to stdout.
+    pub fn print(&self) {
+        match serde_json::to_string_pretty(self) {
+            Ok(json) => println!("{}", json),
+            Err(err) => eprintln!("Failed to write JSON output: {}", err),
+        }
+    }
+
+    /// Prints the report of a failed run to stdout, with the error as made by `error_to_json`.
+    pub fn print_with_error(&self, error: serde_json::Value) {
+        let mut report = match serde_json::to_value(self) {
+            Ok(report) => report,
+            Err(err) => return eprintln!("Failed to write JSON output: {}", err),
+        };
+        report["error"] = error;
+        match serde_json::to_string_pretty(&report) {
+            Ok(json) => println!("{}", json),
+            Err(err) => eprintln!("Failed to write JSON output: {}", err),
+        }
+    }
+}
+
+/// Returns the error of a failed run as JSON object with its stable code, its message, its exit
+/// code and what it refers to.
+pub fn error_to_json(err: &CleanupError) -> serde_json::Value {
+    serde_json::json!({
+        "code": err.code(),
+        "message": err.to_string(),
+        "exit_code": err.exit_code() as i32,
+        "context": err.context(),
+    })
+}
+
+/// Prints the error of a failed run for `--errors json` as a single line to stdout.
+pub fn print_error(err: &CleanupError) {
+    let json = serde_json::json!({
+        "run_info": crate::run_info::to_json(),
+        "error": error_to_json(err),
+    });
+    println!("{}", json);
+}
+
+/// Prints the result of a run skipped by `--cooldown` as a single line to stdout. Times are in
+/// seconds since the UNIX epoch.
+pub fn print_cooldown(last_run_at: u64, next_eligible_at: u64) {
+    let json = serde_json::json!({
+        "run_info": crate::run_info::to_json(),
+        "skipped": "cooldown",
+        "last_run_at": last_run_at,
+        "next_eligible_at": next_eligible_at,
+    });
+    println!("{}", json);
+}
+
+impl Serialize for Report {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut report = serializer.serialize_struct("Report", 14)?;
+        report.serialize_field("schema_version", &SCHEMA_VERSION)?;
+        report.serialize_field("run_info", &crate::run_info::to_json())?;
+        report.serialize_field("root_paths", &self.root_paths)?;
+        report.serialize_field("plan_hash", &self.plan_hash)?;
+        report.serialize_field("applied_plan_hash", &self.applied_plan_hash)?;
+        report.serialize_field("exceptions", &self.exceptions)?;
+        report.serialize_field("packages_in_use", &self.packages_in_use)?;
+        report.serialize_field("candidates", &self.candidates)?;
+        report.serialize_field("removals", &self.removals)?;
+        report.serialize_field("reference_violations", &self.reference_violations)?;
+        report.serialize_field("dead_patterns", &self.dead_patterns)?;
+        report.serialize_field("revision_mismatch", &self.revision_mismatch)?;
+        report.serialize_field("timing_estimate", &self.timing_estimate)?;
+        report.serialize_field("summary", &self.summary)?;
+        report.end()
+    }
+}
+
+impl Serialize for PackageReference {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut package = serializer.serialize_struct("PackageReference", 2)?;
+        package.serialize_field("recipe", &self.recipe)?;
+        package.serialize_field("package", &self.package)?;
+        package.end()
+    }
+}
+
+impl Serialize for RecipeCandidates {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut candidates = serializer.serialize_struct("RecipeCandidates", 2)?;
+        candidates.serialize_field("recipe", &self.recipe)?;
+        candidates.serialize_field("packages", &self.packages)?;
+        candidates.end()
+    }
+}
+
+impl Serialize for Removal {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut removal = serializer.serialize_struct("Removal", 4)?;
+        removal.serialize_field("recipe", &self.recipe)?;
+        removal.serialize_field("package", &self.package)?;
+        removal.serialize_field("succeeded", &self.error.is_none())?;
+        removal.serialize_field("error", &self.error)?;
+        removal.end()
+    }
+}
+
+impl Serialize for RevisionMismatch {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut mismatch = serializer.serialize_struct("RevisionMismatch", 3)?;
+        mismatch.serialize_field("cache_revisions_enabled", &false)?;
+        mismatch.serialize_field("usage_files", &self.usage_files)?;
+        mismatch.serialize_field("normalization", "revisions_ignored")?;
+        mismatch.end()
+    }
+}
+
+impl Serialize for Summary {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut summary = serializer.serialize_struct("Summary", 13)?;
+        summary.serialize_field("usage_files", &self.usage_files)?;
+        summary.serialize_field("failed_usage_files", &self.failed_usage_files)?;
+        summary.serialize_field("packages_in_use", &self.packages_in_use)?;
+        summary.serialize_field("recipes_examined", &self.recipes_examined)?;
+        summary.serialize_field("packages_examined", &self.packages_examined)?;
+        summary.serialize_field("packages_removed", &self.packages_removed)?;
+        summary.serialize_field("failed_package_removals", &self.failed_package_removals)?;
+        summary.serialize_field("recipes_removed", &self.recipes_removed)?;
+        summary.serialize_field("bytes_freed", &self.bytes_freed)?;
+        summary.serialize_field("stale_lock_files", &self.stale_lock_files)?;
+        summary.serialize_field("lock_files_removed", &self.lock_files_removed)?;
+        summary.serialize_field("lock_file_bytes_freed", &self.lock_file_bytes_freed)?;
+        summary.serialize_field("wall_time_seconds", &self.wall_time.as_secs_f64())?;
+        summary.end()
+    }
+}
+
+impl Serialize for ReferenceViolation {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut violation = serializer.serialize_struct("ReferenceViolation", 2)?;
+        violation.serialize_field("recipe", &self.recipe)?;
+        violation.serialize_field("rules", &self.rules)?;
+        violation.end()
+    }
+}
+
+impl Serialize for Estimate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut estimate = serializer.serialize_struct("Estimate", 6)?;
+        estimate.serialize_field("removal_seconds", &self.removal.as_secs_f64())?;
+        estimate.serialize_field("sizing_seconds", &self.sizing.as_secs_f64())?;
+        estimate.serialize_field("total_seconds", &self.total().as_secs_f64())?;
+        estimate.serialize_field("confidence", self.confidence.as_str())?;
+        estimate.serialize_field("unrecorded_packages", &self.unrecorded_packages)?;
+        estimate.serialize_field("recipes", &self.recipes)?;
+        estimate.end()
+    }
+}
+
+impl Serialize for RecipeEstimate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut estimate = serializer.serialize_struct("RecipeEstimate", 4)?;
+        estimate.serialize_field("recipe", &self.recipe)?;
+        estimate.serialize_field("packages", &self.packages)?;
+        estimate.serialize_field("removal_seconds", &self.removal.as_secs_f64())?;
+        estimate.serialize_field("sizing_seconds", &self.sizing.as_secs_f64())?;
+        estimate.end()
+    }
+}
+
+impl Serialize for PatternUsage {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (file, line) = match self.source {
+            PatternSource::KeepFile(ref path, line) => (Some(path.to_string_lossy()), Some(line)),
+            _ => (None, None),
+        };
+        let mut usage = serializer.serialize_struct("PatternUsage", 5)?;
+        usage.serialize_field("pattern", &self.pattern)?;
+        usage.serialize_field("source", self.source.kind())?;
+        usage.serialize_field("file", &file)?;
+        usage.serialize_field("line", &line)?;
+        usage.serialize_field("matches", &self.matches)?;
+        usage.end()
+    }
+}
@@ -0,0 +1,84 @@
+//! Reads keep files, listing recipes whose packages are never removed, e.g. since they are only
+//! consumed by scripts that leave no conaninfo.txt behind.
+
+use crate::text_file::TextFileError;
+
+use std::path::Path;
+
+/// Reads the recipe patterns of a keep file, each with its line number counting from 1. Every line
+/// holds a reference like `openssl/1.1.1k@corp/stable` or a pattern like `toolchain/*`, matched like
+/// the patterns of `--include` and `--exclude`, see `reference::matches_pattern`. Blank lines and
+/// lines starting with `#` are ignored.
+pub fn load(path: &Path) -> Result<Vec<(usize, String)>, TextFileError> {
+    Ok(parse(&crate::text_file::read_lines(path)?))
+}
+
+fn parse(lines: &[String]) -> Vec<(usize, String)> {
+    lines
+        .iter()
+        .map(|line| line.trim())
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(index, line)| (index + 1, line.to_owned()))
+        .collect()
+}
+
+/// Returns whether the recipe matches any of the patterns of a keep file.
+pub fn matches(patterns: &[String], recipe_id: &str) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| crate::reference::matches_pattern(pattern, recipe_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(content: &str) -> Vec<String> {
+        crate::text_file::normalize(content).0
+    }
+
+    #[test]
+    fn patterns_keep_their_line_numbers() {
+        let patterns = parse(&lines(
+            "# toolchains\ntoolchain/*\n\n  openssl/1.1.1k@corp/stable  \n#zlib/*\nqt/*\n",
+        ));
+
+        assert_eq!(
+            patterns,
+            [
+                (2, "toolchain/*".to_owned()),
+                (4, "openssl/1.1.1k@corp/stable".to_owned()),
+                (6, "qt/*".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn windows_line_endings_and_byte_order_mark_are_ignored() {
+        let patterns = parse(&lines("\u{feff}toolchain/*\r\nqt/*\r\n"));
+
+        assert_eq!(
+            patterns,
+            [(1, "toolchain/*".to_owned()), (2, "qt/*".to_owned())]
+        );
+    }
+
+    #[test]
+    fn empty_file_has_no_patterns() {
+        assert!(parse(&lines("")).is_empty());
+        assert!(parse(&lines("# nothing to keep\n\n")).is_empty());
+    }
+
+    #[test]
+    fn recipes_match_like_include_patterns() {
+        let patterns = vec!["toolchain/*".to_owned(), "boost/*@*/stable".to_owned()];
+
+        assert!(matches(&patterns, "toolchain/1.0"));
+        assert!(matches(&patterns, "toolchain/2.0@corp/testing"));
+        assert!(matches(&patterns, "boost/1.75.0@user/stable"));
+        assert!(!matches(&patterns, "boost/1.75.0"));
+        assert!(!matches(&patterns, "toolchains/1.0"));
+        assert!(!matches(&[], "toolchain/1.0"));
+    }
+}
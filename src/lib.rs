@@ -0,0 +1,111 @@
+//! Finds unused packages in the local conan cache and removes them. `scan` finds the packages in
+//! use by the projects, `conan` searches and removes the packages of the cache and `plan` decides
+//! which of them to remove. `cleanup` runs the whole cleanup with the settings of a `session`. The
+//! conan_cleanup binary adds the arguments, the prompts and the output.
+
+extern crate clap;
+extern crate ini;
+extern crate serde;
+extern crate serde_json;
+extern crate walkdir;
+
+/// Prints a line of the human-readable output of the run, see `output::Output`.
+#[macro_export]
+macro_rules! outln {
+    ($out:expr) => {
+        $out.line(format_args!(""))
+    };
+    ($out:expr, $($arg:tt)*) => {
+        $out.line(format_args!($($arg)*))
+    };
+}
+
+/// Prints a diagnostic of `-v` to stderr, see `logging`.
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        $crate::log_at!($crate::logging::Level::Debug, $($arg)*)
+    };
+}
+
+/// Prints a diagnostic of `-vv` to stderr, see `logging`.
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        $crate::log_at!($crate::logging::Level::Trace, $($arg)*)
+    };
+}
+
+/// Prints a warning to stderr and copies it to the system log, see `system_log`.
+#[macro_export]
+macro_rules! warning {
+    ($($arg:tt)*) => {{
+        let message = format!($($arg)*);
+        eprintln!("WARNING: {}", message);
+        $crate::system_log::warning(&message);
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! log_at {
+    ($level:expr, $($arg:tt)*) => {
+        if $crate::logging::is_enabled($level) {
+            eprintln!("[{}] {}", $level.label(), format_args!($($arg)*))
+        }
+    };
+}
+
+pub mod aggregate;
+pub mod build_failure;
+pub mod cache;
+pub mod cancel;
+pub mod cleanup;
+pub mod composition;
+pub mod conan;
+pub mod conan2;
+pub mod config;
+pub mod duration;
+pub mod error;
+pub mod execute;
+pub mod fsutil;
+pub mod home_misc;
+pub mod housekeeping;
+pub mod inventory;
+pub mod io_budget;
+pub mod json_output;
+pub mod keep_file;
+pub mod lockfile;
+pub mod logging;
+pub mod migrate;
+pub mod notify;
+pub mod output;
+pub mod pattern_usage;
+pub mod plan;
+pub mod plan_hash;
+pub mod profile;
+pub mod ranking;
+pub mod reference;
+pub mod reference_lint;
+pub mod removal_order;
+pub mod resources;
+pub mod resume;
+pub mod review;
+pub mod risk;
+pub mod run_info;
+pub mod scan;
+pub mod scheduler;
+pub mod search_results;
+pub mod serve;
+pub mod session;
+pub mod sha256;
+pub mod size;
+pub mod state;
+pub mod subprocess;
+pub mod support_bundle;
+pub mod system_log;
+pub mod text_file;
+pub mod timing;
+pub mod transcript;
+pub mod usage_pattern;
+pub mod wildcard;
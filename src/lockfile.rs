@@ -0,0 +1,105 @@
+//! Reads the packages pinned by conan 1 and conan 2 lockfiles.
+
+use std::fmt;
+use std::path::Path;
+
+/// A package pinned by a lockfile. Conan 2 lockfiles only pin recipes, so their entries don't have
+/// a package ID.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LockedPackage {
+    /// Reference of the recipe without revision, e.g. `zlib/1.2.11@conan/stable`.
+    pub reference: String,
+    pub package_id: Option<String>,
+}
+
+/// Parses the given lockfile. Both the conan 1 format (`graph_lock` nodes with `ref` and
+/// `package_id` or a combined `pref`) and the conan 2 format (`requires` lists) are supported.
+pub fn parse_lockfile(path: &Path) -> Result<Vec<LockedPackage>, LockfileError> {
+    let content = std::fs::read_to_string(path)?;
+    let json: serde_json::Value = serde_json::from_str(&content)?;
+
+    let mut packages = Vec::new();
+    if let Some(nodes) = json["graph_lock"]["nodes"].as_object() {
+        for node in nodes.values() {
+            if let Some(pref) = node["pref"].as_str() {
+                let mut pref = pref.splitn(2, ':');
+                let reference = pref.next().unwrap_or_default();
+                let package_id = pref.next().map(strip_revision);
+                packages.push(LockedPackage {
+                    reference: strip_revision(reference),
+                    package_id,
+                });
+            } else if let Some(reference) = node["ref"].as_str() {
+                packages.push(LockedPackage {
+                    reference: strip_revision(reference),
+                    package_id: node["package_id"].as_str().map(str::to_owned),
+                });
+            }
+        }
+    } else if json.get("requires").is_some() {
+        for list in &["requires", "build_requires", "python_requires"] {
+            for reference in json[list].as_array().into_iter().flatten() {
+                let reference = reference.as_str().ok_or_else(|| {
+                    LockfileError::FormatError(format!("'{}' contains a non-string entry", list))
+                })?;
+                packages.push(LockedPackage {
+                    reference: strip_revision(reference),
+                    package_id: None,
+                });
+            }
+        }
+    } else {
+        return Err(LockfileError::FormatError(
+            "Neither 'graph_lock' nor 'requires' found".to_owned(),
+        ));
+    }
+    Ok(packages)
+}
+
+/// Removes revisions (`#...`) and conan 2 timestamps (`%...`) from a reference or package ID.
+fn strip_revision(reference: &str) -> String {
+    reference
+        .split(['#', '%'])
+        .next()
+        .unwrap_or(reference)
+        .to_owned()
+}
+
+#[derive(Debug)]
+pub enum LockfileError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    FormatError(String),
+}
+
+impl fmt::Display for LockfileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LockfileError::Io(ref err) => err.fmt(f),
+            LockfileError::Json(ref err) => err.fmt(f),
+            LockfileError::FormatError(ref err) => write!(f, "Unexpected lockfile format: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for LockfileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            LockfileError::Io(ref err) => Some(err),
+            LockfileError::Json(ref err) => Some(err),
+            LockfileError::FormatError(_) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for LockfileError {
+    fn from(err: std::io::Error) -> LockfileError {
+        LockfileError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for LockfileError {
+    fn from(err: serde_json::Error) -> LockfileError {
+        LockfileError::Json(err)
+    }
+}
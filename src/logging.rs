@@ -0,0 +1,85 @@
+//! Diagnostics for `-v` and `-vv`, printed to stderr so that stdout stays parseable. `-v` reports
+//! the conan invocations and the usage files found, `-vv` also timings, the sizes of parsed JSON
+//! documents and the packages each usage file requires. Without `-v`, the level is taken from
+//! `RUST_LOG` (`debug` or `trace`, also as `conan_cleanup=debug`).
+
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// `-v`
+    Debug = 1,
+    /// `-vv`
+    Trace = 2,
+}
+
+impl Level {
+    pub fn label(self) -> &'static str {
+        match self {
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        }
+    }
+}
+
+/// Returns the level of the number of `-v` given, or the one of `RUST_LOG` if there was none.
+pub fn level(verbose_count: u64) -> Option<Level> {
+    let level = match verbose_count {
+        0 => std::env::var("RUST_LOG").map_or(0, |value| level_of_env(&value)),
+        count => count,
+    };
+    match level {
+        0 => None,
+        1 => Some(Level::Debug),
+        _ => Some(Level::Trace),
+    }
+}
+
+/// Returns the level of a `RUST_LOG` value. Directives for other crates are ignored.
+fn level_of_env(value: &str) -> u64 {
+    value
+        .split(',')
+        .filter_map(|directive| match directive.split_once('=') {
+            Some((target, level)) if target.trim() == "conan_cleanup" => Some(level),
+            Some(_) => None,
+            None => Some(directive),
+        })
+        .map(|level| match level.trim().to_lowercase().as_str() {
+            "trace" => Level::Trace as u64,
+            "debug" => Level::Debug as u64,
+            _ => 0,
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Returns whether diagnostics of the given level are printed in the session, see
+/// `Settings::log_level`.
+pub fn is_enabled(level: Level) -> bool {
+    crate::session::current()
+        .settings()
+        .log_level
+        .is_some_and(|enabled| enabled >= level)
+}
+
+/// Times a phase of the run and reports its duration at trace level when it is finished.
+pub struct Phase {
+    name: &'static str,
+    start: Instant,
+}
+
+impl Phase {
+    pub fn start(name: &'static str) -> Phase {
+        trace!("Started {}", name);
+        Phase {
+            name,
+            start: Instant::now(),
+        }
+    }
+
+    pub fn finish(self) -> Duration {
+        let duration = self.start.elapsed();
+        trace!("Finished {} after {:.2?}", self.name, duration);
+        duration
+    }
+}
@@ -1,379 +1,1459 @@
 extern crate clap;
-extern crate ini;
 extern crate serde_json;
-extern crate walkdir;
 
-use ini::Ini;
-use walkdir::WalkDir;
+use conan_cleanup::error::{CleanupError, EXIT_CODES_HELP};
+use conan_cleanup::review::ChunkAnswer;
+use conan_cleanup::scan;
+use conan_cleanup::size::{format_size, parse_size};
+use conan_cleanup::usage_pattern::{self, UsagePattern};
+use conan_cleanup::{
+    aggregate, build_failure, cache, cancel, cleanup, config, duration, inventory, json_output,
+    logging, notify, output, plan, ranking, risk, run_info, serve, session, subprocess,
+    support_bundle, system_log, transcript,
+};
+use conan_cleanup::{outln, warning};
 
-use std::collections::HashMap;
-use std::fmt;
-use std::process::Command;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 fn main() {
-    let args = clap::App::new("conan_cleanup")
-        .version("0.1")
-        .about("Aids in removing unused conan packages from the local cache")
-        .arg(clap::Arg::with_name("root_path")
-            .help("Path to the directory containing all projects that use conan. It is recursively parsed for conaninfo.txt files to know which packages are actively used.")
-            .required(true))
-        .arg(clap::Arg::with_name("force")
-            .short("f")
-            .help("Force complete removal of unused packages without requiring manual approval.")
-            .takes_value(false))
-        .get_matches();
-
-    let root_path = args.value_of("root_path").unwrap();
-    let packages_in_use = find_packages_in_use(root_path);
-
-    let json_path = temp_json_file_path();
-    Command::new("conan")
-        .args(&["search", "-j", &json_path.to_string_lossy()])
-        .output()
-        .unwrap_or_else(|err| {
-            eprintln!("'conan search' failed: {}", err);
-            std::process::exit(-1)
-        });
+    let token = cancel::CancellationToken::new();
+    cancel::cancel_on_interrupt(&token);
 
-    let recipe_ids = match parse_recipe_ids(&json_path) {
-        Ok(ids) => ids,
+    let args = match parse_args(std::env::args_os().collect()) {
+        Ok(args) => args,
+        Err(err) if err.use_stderr() => exit_with_error(
+            CleanupError::InvalidArguments(err.message),
+            &output::Output::new(false),
+            errors_as_json_requested(std::env::args()),
+            &token,
+        ),
+        // The help and the version are no errors.
         Err(err) => {
-            eprintln!("Failed to parse used recipe IDs: {}", err);
-            std::process::exit(-1)
+            println!("{}", err.message);
+            return;
         }
     };
-    let mut recipes_and_packages = HashMap::new();
-    for recipe_id in &recipe_ids {
-        Command::new("conan")
-            .args(&["search", "-j", &json_path.to_string_lossy(), recipe_id])
-            .output()
-            .unwrap_or_else(|err| {
-                eprintln!("'conan search' failed: {}", err);
-                std::process::exit(-1)
-            });
-
-        let package_ids = match parse_package_ids(&json_path) {
-            Ok(ids) => ids,
-            Err(err) => {
-                eprintln!(
-                    "Failed to parse packages IDs available in local cache: {}",
-                    err
-                );
-                std::process::exit(-1)
-            }
-        };
-        recipes_and_packages.insert(recipe_id, package_ids);
-    }
-
-    let mut packages_to_remove = HashMap::new();
-    for (recipe_id, package_ids) in &recipes_and_packages {
-        let mut package_ids_to_remove = Vec::new();
-        for package_id in package_ids {
-            if !packages_in_use.contains(package_id) {
-                package_ids_to_remove.push(package_id);
+    let (args, sources) = match apply_profile(args) {
+        Ok(applied) => applied,
+        Err(err) => exit_with_error(
+            err,
+            &output::Output::new(false),
+            errors_as_json_requested(std::env::args()),
+            &token,
+        ),
+    };
+    let json = args.value_of("output") == Some("json");
+    let errors_as_json = json || args.value_of("errors") == Some("json");
+    // Only the JSON error goes to stdout then.
+    let out = if errors_as_json && !json {
+        output::Output::to_writer(Box::new(std::io::stderr()), false)
+    } else {
+        output::Output::new(json)
+    };
+    let settings = match settings(&args) {
+        Ok(settings) => settings,
+        Err(err) => exit_with_error(err, &out, errors_as_json, &token),
+    };
+    session::Session::new(settings).run(|| {
+        if args.is_present("print-config") {
+            if let Err(err) = print_config(&args, &sources, &out) {
+                exit_with_error(err, &out, errors_as_json, &token);
             }
+            return;
+        }
+        let result = run(&args, &out, &token);
+        let written = write_transcript(&args);
+        if let Err(err) = result.and(written) {
+            exit_with_error(err, &out, errors_as_json, &token);
         }
+    })
+}
 
-        if !package_ids_to_remove.is_empty() {
-            packages_to_remove.insert(recipe_id, package_ids_to_remove);
+/// Parses the command line. clap takes a root path resembling a subcommand, like 'projects' for
+/// 'profiles', for a mistyped subcommand, so the command line is parsed without subcommands then.
+fn parse_args(raw_args: Vec<std::ffi::OsString>) -> clap::Result<clap::ArgMatches<'static>> {
+    match app().get_matches_from_safe(&raw_args) {
+        Err(err)
+            if err.kind == clap::ErrorKind::InvalidSubcommand
+                && err
+                    .info
+                    .as_ref()
+                    .and_then(|info| info.first())
+                    .is_some_and(|path| std::path::Path::new(path).exists()) =>
+        {
+            app_without_subcommands().get_matches_from_safe(raw_args)
         }
+        result => result,
     }
+}
 
-    let force = args.is_present("force");
-    if !packages_to_remove.is_empty() {
-        println!("Packages to remove:");
-        for (recipe_id, package_ids) in &packages_to_remove {
-            println!("{}", recipe_id);
-            for package_id in package_ids {
-                println!("  {}", package_id);
-            }
-        }
+/// Where the options of a run came from, see `--print-config`.
+struct OptionSources {
+    /// Options given on the command line.
+    explicit: BTreeSet<String>,
+    /// The profile of `--profile-name` and the configuration file defining it.
+    profile: Option<(config::Profile, std::path::PathBuf)>,
+}
 
-        if !force {
-            println!("Do you want to remove the packages listed above? (yes/no)");
-        }
+/// Returns the conan home whose configuration file configures the run.
+fn config_home(args: &clap::ArgMatches) -> Option<std::path::PathBuf> {
+    args.value_of("conan-home")
+        .map(std::path::PathBuf::from)
+        .or_else(cache::conan_home)
+}
 
-        if force || get_yes_or_no() {
-            for (recipe_id, package_ids) in &packages_to_remove {
-                for package_id in package_ids {
-                    Command::new("conan")
-                        .args(&["remove", recipe_id, "-p", package_id, "-f"])
-                        .output()
-                        .unwrap_or_else(|err| {
-                            eprintln!("'conan remove' failed: {}", err);
-                            std::process::exit(-1)
-                        });
-                }
-            }
-        }
-    } else {
-        println!("No unused packages found.");
-    }
-
-    if !force {
-        println!("Do you want to remove recipes that no longer have any packages? (yes/no)");
-    }
-
-    if force || get_yes_or_no() {
-        for recipe_id in &recipe_ids {
-            Command::new("conan")
-                .args(&["search", "-j", &json_path.to_string_lossy(), recipe_id])
-                .output()
-                .unwrap_or_else(|err| {
-                    eprintln!("'conan search' failed: {}", err);
-                    std::process::exit(-1)
-                });
-
-            let package_ids = match parse_package_ids(&json_path) {
-                Ok(ids) => ids,
-                Err(err) => {
-                    eprintln!(
-                        "Failed to parse package IDs of recipe '{}': {}",
-                        recipe_id, err
-                    );
-                    std::process::exit(-1)
-                }
+/// Adds the options of the profile of `--profile-name` that are not given on the command line to
+/// the arguments, by parsing the command line again with them.
+fn apply_profile(
+    args: clap::ArgMatches<'static>,
+) -> Result<(clap::ArgMatches<'static>, OptionSources), CleanupError> {
+    let explicit: BTreeSet<String> = args
+        .args
+        .iter()
+        .filter(|(_, arg)| arg.occurs > 0)
+        .map(|(name, _)| name.to_string())
+        .collect();
+    let name = match args.value_of("profile-name") {
+        Some(name) => name.to_owned(),
+        None => {
+            let sources = OptionSources {
+                explicit,
+                profile: None,
             };
+            return Ok((args, sources));
+        }
+    };
+    let conan_home =
+        config_home(&args).ok_or(CleanupError::UnknownConanHome("find the profiles"))?;
+    let path = config::config_path(&conan_home);
+    let profile = config::load(&conan_home)?.profile(&name, &path)?.clone();
 
-            if package_ids.is_empty() {
-                println!(
-                    "Removing recipe '{}' since it has no packages left",
-                    recipe_id
-                );
+    // The options of the profile go first, so that they cannot end up as arguments of subcommands.
+    let mut raw_args: Vec<std::ffi::OsString> = std::env::args_os().collect();
+    let profile_args = profile.args(|option| explicit.contains(option));
+    raw_args.splice(1..1, profile_args.into_iter().map(Into::into));
+    let args = parse_args(raw_args).map_err(|err| {
+        CleanupError::InvalidArguments(format!(
+            "Invalid profile '{}' in '{}': {}",
+            name,
+            path.display(),
+            err.message
+        ))
+    })?;
+    let sources = OptionSources {
+        explicit,
+        profile: Some((profile, path)),
+    };
+    Ok((args, sources))
+}
 
-                let remove_command = Command::new("conan")
-                    .args(&["remove", recipe_id, "-f"])
-                    .output()
-                    .unwrap_or_else(|err| {
-                        eprintln!("'conan remove' failed: {}", err);
-                        std::process::exit(-1)
-                    });
-
-                if !remove_command.status.success() {
-                    if !remove_command.stderr.is_empty() {
-                        eprintln!(
-                            "{}",
-                            String::from_utf8_lossy(remove_command.stderr.as_slice())
-                        );
-                    }
-                    if !remove_command.stdout.is_empty() {
-                        eprintln!(
-                            "{}",
-                            String::from_utf8_lossy(remove_command.stdout.as_slice())
-                        );
-                    }
-                }
+/// Prints the effective options and where each value came from: the command line, the profile of
+/// `--profile-name`, the configuration file or the defaults.
+fn print_config(
+    args: &clap::ArgMatches,
+    sources: &OptionSources,
+    out: &output::Output,
+) -> Result<(), CleanupError> {
+    let profile = sources.profile.as_ref().map(|(profile, _)| profile);
+    let mut options: Vec<(String, serde_json::Value, String)> = run_info::configuration(args)
+        .into_iter()
+        .filter(|(name, _)| name != "print-config")
+        .map(|(name, value)| {
+            let source = if sources.explicit.contains(&name) {
+                "command line".to_owned()
+            } else if let Some(profile) =
+                profile.filter(|profile| profile.options.contains_key(&name))
+            {
+                format!("profile '{}'", profile.name)
+            } else {
+                "default".to_owned()
+            };
+            (name, value, source)
+        })
+        .collect();
+    if let Some(conan_home) = config_home(args) {
+        let config = config::load(&conan_home)?;
+        let source = format!("{}", config::config_path(&conan_home).display());
+        if config.clean_locks {
+            options.push((
+                "[locks] clean".to_owned(),
+                serde_json::json!(true),
+                source.clone(),
+            ));
+        }
+        if let Some(older_than) = config.locks_older_than {
+            options.push((
+                "[locks] older_than".to_owned(),
+                serde_json::json!(duration::format_duration(older_than)),
+                source.clone(),
+            ));
+        }
+        for budget in &config.budgets {
+            let section = format!("[budget.{}]", budget.pattern);
+            if let Some(max_binaries) = budget.max_binaries {
+                options.push((
+                    format!("{} max_binaries", section),
+                    serde_json::json!(max_binaries),
+                    source.clone(),
+                ));
+            }
+            if let Some(max_bytes) = budget.max_bytes {
+                options.push((
+                    format!("{} max_bytes", section),
+                    serde_json::json!(format_size(max_bytes)),
+                    source.clone(),
+                ));
             }
         }
     }
 
-    if let Err(err) = std::fs::remove_file(&json_path) {
-        eprintln!(
-            "Failed to remove temporary file '{}': {}",
-            json_path.display(),
-            err
+    if out.is_json() {
+        let options: Vec<_> = options
+            .iter()
+            .map(|(name, value, source)| {
+                serde_json::json!({ "name": name, "value": value, "source": source })
+            })
+            .collect();
+        let json = serde_json::json!({
+            "profile": profile.map(|profile| &profile.name),
+            "options": options,
+        });
+        println!("{}", serde_json::to_string_pretty(&json).unwrap());
+        return Ok(());
+    }
+    let format_value = |value: &serde_json::Value| match value {
+        serde_json::Value::String(value) => value.clone(),
+        serde_json::Value::Array(values) => values
+            .iter()
+            .map(|value| {
+                value
+                    .as_str()
+                    .map_or_else(|| value.to_string(), str::to_owned)
+            })
+            .collect::<Vec<_>>()
+            .join(", "),
+        value => value.to_string(),
+    };
+    let rows: Vec<(String, String, &str)> = options
+        .iter()
+        .map(|(name, value, source)| {
+            let name = match name.starts_with('[') {
+                true => name.clone(),
+                false => format!("--{}", name),
+            };
+            (name, format_value(value), source.as_str())
+        })
+        .collect();
+    let name_width = rows
+        .iter()
+        .map(|(name, _, _)| name.len())
+        .max()
+        .unwrap_or(0);
+    let value_width = rows
+        .iter()
+        .map(|(_, value, _)| value.len())
+        .max()
+        .unwrap_or(0);
+    for (name, value, source) in &rows {
+        outln!(
+            out,
+            "{:name_width$}  {:value_width$}  ({})",
+            name,
+            value,
+            source,
+            name_width = name_width,
+            value_width = value_width
         );
-        eprintln!("Please remove the file manually.");
     }
+    Ok(())
 }
 
-fn parse_recipe_ids(result_file_path: &std::path::Path) -> Result<Vec<String>, ConanJsonError> {
-    let file_content = std::fs::read_to_string(result_file_path)?;
-    let json: serde_json::Value = serde_json::from_str(&file_content)?;
-    let results = json["results"].as_array().ok_or_else(|| {
-        ConanJsonError::FormatError("Missing top-level 'results' array".to_owned())
-    })?;
-    let result_object = &results[0].as_object().ok_or_else(|| {
-        ConanJsonError::FormatError("'results' array is missing its root object".to_owned())
-    })?;
-    let items = result_object["items"].as_array().ok_or_else(|| {
-        ConanJsonError::FormatError(
-            "Root object of 'results' array is missing the 'items' array".to_owned(),
-        )
-    })?;
-    let mut recipe_ids = Vec::new();
-    for item in items {
-        let recipe_object = item["recipe"].as_object().ok_or_else(|| {
-            ConanJsonError::FormatError("'items' array is missing the 'recipe' object".to_owned())
-        })?;
-        let id = recipe_object["id"].as_str().ok_or_else(|| {
-            ConanJsonError::FormatError("'recipe' object is missing the 'id' string".to_owned())
+/// Lists the profiles of the configuration file with the options each changes relative to the
+/// defaults.
+fn print_profiles(out: &output::Output, args: &clap::ArgMatches) -> Result<(), CleanupError> {
+    let conan_home =
+        config_home(args).ok_or(CleanupError::UnknownConanHome("find the profiles"))?;
+    let path = config::config_path(&conan_home);
+    let config = config::load(&conan_home)?;
+    if config.profiles.is_empty() {
+        outln!(out, "'{}' defines no profiles.", path.display());
+        return Ok(());
+    }
+    // The values of the options without any given.
+    let defaults = app().get_matches_from(["conan_cleanup", "--print-config"]);
+    outln!(out, "Profiles of '{}':", path.display());
+    let width = config.profiles.keys().map(String::len).max().unwrap_or(0);
+    for profile in config.profiles.values() {
+        let changes: Vec<String> = profile
+            .options
+            .iter()
+            .filter_map(
+                |(name, value)| match (value.as_str(), defaults.value_of(name)) {
+                    (value, Some(default)) if value == default => None,
+                    ("false", None) => None,
+                    ("true", None) => Some(format!("--{}", name)),
+                    (value, Some(default)) => {
+                        Some(format!("--{} {} (default {})", name, value, default))
+                    }
+                    (value, None) => Some(format!("--{} {}", name, value)),
+                },
+            )
+            .collect();
+        let summary = match changes.len() {
+            0 => "same as the defaults".to_owned(),
+            _ => changes.join("; "),
+        };
+        outln!(out, "  {:width$}  {}", profile.name, summary, width = width);
+    }
+    Ok(())
+}
+
+/// Writes the transcript of `--transcript` and the bundle of `--support-bundle` of a dry run, also
+/// if the run failed, since that is when they are needed most.
+fn write_transcript(args: &clap::ArgMatches) -> Result<(), CleanupError> {
+    if !args.is_present("dry-run") && !args.is_present("read-only") {
+        return Ok(());
+    }
+    let entries = transcript::entries();
+    if let Some(path) = args.value_of("transcript") {
+        let path = std::path::Path::new(path);
+        transcript::write(path, &entries).map_err(|err| {
+            CleanupError::File("write the transcript to", path.to_path_buf(), err.into())
         })?;
-        recipe_ids.push(id.to_owned());
     }
-    Ok(recipe_ids)
+    if let Some(dir) = args.value_of("support-bundle") {
+        let dir = std::path::Path::new(dir);
+        let redaction = if args.is_present("no-redact") {
+            support_bundle::Redaction::none()
+        } else {
+            support_bundle::Redaction::of_current_user()
+        };
+        support_bundle::write(dir, &entries, &support_bundle::environment(), &redaction).map_err(
+            |err| CleanupError::File("write the support bundle to", dir.to_path_buf(), err.into()),
+        )?;
+    }
+    Ok(())
 }
 
-fn parse_package_ids(result_file_path: &std::path::Path) -> Result<Vec<String>, ConanJsonError> {
-    let file_content = std::fs::read_to_string(result_file_path)?;
-    let json: serde_json::Value = serde_json::from_str(&file_content)?;
-    let results = json["results"].as_array().ok_or_else(|| {
-        ConanJsonError::FormatError("Missing top-level 'results' array".to_owned())
-    })?;
-    let result_object = &results[0].as_object().ok_or_else(|| {
-        ConanJsonError::FormatError("'results' array is missing its root object".to_owned())
-    })?;
-    let items = result_object["items"].as_array().ok_or_else(|| {
-        ConanJsonError::FormatError(
-            "Root object of 'results' array is missing the 'items' array".to_owned(),
-        )
-    })?;
-    let items_object = &items[0]
-        .as_object()
-        .ok_or_else(|| ConanJsonError::FormatError("'items' array has no objects".to_owned()))?;
+/// Returns whether the raw arguments ask for errors as JSON, for arguments that clap rejected.
+fn errors_as_json_requested(args: impl Iterator<Item = String>) -> bool {
+    let args: Vec<String> = args.collect();
+    args.windows(2)
+        .any(|pair| (pair[0] == "--output" || pair[0] == "--errors") && pair[1] == "json")
+        || args
+            .iter()
+            .any(|arg| arg == "--output=json" || arg == "--errors=json")
+}
 
-    let mut package_ids: Vec<String> = Vec::new();
+/// Reports the error of a run and exits with its exit code.
+fn exit_with_error(
+    mut err: CleanupError,
+    out: &output::Output,
+    errors_as_json: bool,
+    token: &cancel::CancellationToken,
+) -> ! {
+    // Whatever failed after cancelling, e.g. a conan command that was no longer started, failed
+    // because of it.
+    if token.is_cancelled() && !matches!(err, CleanupError::Cancelled(_)) {
+        err = CleanupError::Cancelled(Box::default());
+    }
+    match err {
+        // The reminder of --nag-after is no failure of the tool.
+        CleanupError::CleanupOverdue(_) => outln!(out, "{}", err),
+        _ => eprintln!("{}", err),
+    }
+    match err {
+        // The report of the removals done so far carries the error.
+        CleanupError::Cancelled(ref report) if out.is_json() => {
+            report.print_with_error(json_output::error_to_json(&err))
+        }
+        // Printed with the report of the removals already.
+        CleanupError::RemovalFailed(..) if out.is_json() => (),
+        _ if errors_as_json => json_output::print_error(&err),
+        _ => (),
+    }
+    system_log::run_failed(err.exit_code(), &err.to_string());
+    std::process::exit(err.exit_code() as i32);
+}
 
-    if items_object.contains_key("packages") {
-        let packages = items_object["packages"].as_array().ok_or_else(|| {
-            ConanJsonError::FormatError("First 'items' object has no 'packages' array".to_owned())
-        })?;
+fn app() -> clap::App<'static, 'static> {
+    app_without_subcommands()
+        .setting(clap::AppSettings::SubcommandsNegateReqs)
+        .subcommand(clap::SubCommand::with_name("snapshot")
+            .about("Records the recipes and packages of the conan cache with their sizes in a file, without modifying the cache.")
+            .arg(clap::Arg::with_name("file")
+                .help("File to write the snapshot to.")
+                .required(true)))
+        .subcommand(clap::SubCommand::with_name("snapshot-diff")
+            .about("Reports the recipes and packages added and removed between two snapshots and the growth per package name.")
+            .arg(clap::Arg::with_name("old")
+                .help("The older snapshot.")
+                .required(true))
+            .arg(clap::Arg::with_name("new")
+                .help("The newer snapshot.")
+                .required(true))
+            .arg(clap::Arg::with_name("json")
+                .long("json")
+                .help("Print the differences as JSON document.")))
+        .subcommand(clap::SubCommand::with_name("aggregate")
+            .about("Combines the reports of --output json of many hosts, e.g. the nightly reports of build agents, into one summary: the bytes freed in total and per host, the package names removed most often, the hosts with failures and the hosts whose reports are missing or stale.")
+            .arg(clap::Arg::with_name("reports")
+                .help("Report files, folders of report files ending in '.json', or patterns of report files like 'reports/*.json'.")
+                .required(true)
+                .multiple(true))
+            .arg(clap::Arg::with_name("expect-hosts")
+                .long("expect-hosts")
+                .help("File listing the hosts expected to report, one per line. Lines starting with '#' are ignored. Expected hosts without reports are listed as missing.")
+                .takes_value(true)
+                .value_name("FILE"))
+            .arg(clap::Arg::with_name("stale-after")
+                .long("stale-after")
+                .help("Duration after which the latest report of a host is stale, e.g. '36h'.")
+                .takes_value(true)
+                .value_name("DURATION")
+                .default_value("2d")
+                .validator(duration::validate_duration))
+            .arg(clap::Arg::with_name("top")
+                .long("top")
+                .help("Number of package names removed most often to list.")
+                .takes_value(true)
+                .value_name("N")
+                .default_value("10")
+                .validator(validate_count))
+            .arg(clap::Arg::with_name("format")
+                .long("format")
+                .help("Format of the summary.")
+                .takes_value(true)
+                .possible_values(&["table", "json", "html"])
+                .default_value("table")))
+        .subcommand(clap::SubCommand::with_name("profiles")
+            .about("Lists the profiles of the conan_cleanup.conf of the conan home, selectable with --profile-name, with the options each changes relative to the defaults."))
+        .subcommand(clap::SubCommand::with_name("serve")
+            .about("Answers line-delimited JSON-RPC 2.0 requests to scan, plan, execute and explain removals, holding the scan of the projects between requests. See the documentation of the serve module for the protocol.")
+            .arg(clap::Arg::with_name("stdio")
+                .long("stdio")
+                .help("Read the requests from stdin and write the responses to stdout.")
+                .required(true)))
+}
 
-        for package in packages {
-            let id = package["id"].as_str().ok_or_else(|| {
-                ConanJsonError::FormatError("'package' is missing an 'id' string".to_owned())
-            })?;
-            package_ids.push(id.to_owned());
+/// Returns the app with the arguments of the cleanup only.
+fn app_without_subcommands() -> clap::App<'static, 'static> {
+    clap::App::new("conan_cleanup")
+        .version("0.1")
+        .after_help(EXIT_CODES_HELP)
+        .about("Aids in removing unused conan packages from the local cache")
+        .arg(clap::Arg::with_name("root_path")
+            .help("Paths to the directories containing all projects that use conan. They are recursively parsed for conaninfo.txt files to know which packages are actively used.")
+            .multiple(true)
+            .required_unless_one(&["strict-lockfiles", "nag-after", "lint-references", "print-config"]))
+        .arg(clap::Arg::with_name("usage-filename")
+            .long("usage-filename")
+            .help("Pattern of the names of files in the conaninfo.txt format that tell which packages are in use. Supports '*' and '?'. Replaces the default of 'conaninfo.txt'.")
+            .takes_value(true)
+            .value_name("PATTERN")
+            .multiple(true)
+            .number_of_values(1))
+        .arg(clap::Arg::with_name("usage-file")
+            .long("usage-file")
+            .help("Pattern of the names of usage files with a format hint, e.g. 'lock:*.conan.lock' for lockfiles or 'conaninfo:deps.conaninfo'. If a file matches several patterns, the first one given wins, patterns of --usage-filename first. Replaces the defaults of 'conanbuildinfo:conanbuildinfo.txt' and 'lock:conan.lock'.")
+            .takes_value(true)
+            .value_name("FORMAT:PATTERN")
+            .multiple(true)
+            .number_of_values(1)
+            .validator(validate_usage_pattern))
+        .arg(clap::Arg::with_name("sources")
+            .long("sources")
+            .help("Comma-separated kinds of usage files to trust, ignoring usage files of other kinds. Defaults to all of them.")
+            .takes_value(true)
+            .value_name("KINDS")
+            .use_delimiter(true)
+            .possible_values(&["conaninfo", "conanbuildinfo", "lockfile"]))
+        .arg(clap::Arg::with_name("exclude-dir")
+            .long("exclude-dir")
+            .help("Pattern of directories not to descend into while searching for usage files, matched against directory names and paths relative to the root path. Supports '*' and '?', e.g. 'node_modules' or '**/build/artifacts'. '.git', '.svn' and '.hg' are excluded by default.")
+            .takes_value(true)
+            .value_name("PATTERN")
+            .multiple(true)
+            .number_of_values(1))
+        .arg(clap::Arg::with_name("no-default-excludes")
+            .long("no-default-excludes")
+            .help("Also search '.git', '.svn' and '.hg' directories for usage files."))
+        .arg(clap::Arg::with_name("resource-report")
+            .long("resource-report")
+            .help("Print the resources used by the run at its end: peak memory, number and CPU time of conan processes, bytes of usage files read and bytes of JSON parsed. Memory and CPU time are only measured on unix."))
+        .arg(clap::Arg::with_name("keep-file")
+            .long("keep-file")
+            .help("File listing recipes whose packages are never removed, one reference or pattern of the form 'name/version@user/channel' like 'toolchain/*' per line, matched like the patterns of --include. Blank lines and lines starting with '#' are ignored.")
+            .takes_value(true)
+            .value_name("PATH"))
+        .arg(clap::Arg::with_name("include")
+            .long("include")
+            .help("Only clean up recipes matching the given pattern of the form 'name/version@user/channel', e.g. 'qt/*' or 'boost/*@*/stable'. Other recipes are neither searched nor removed. May be given multiple times.")
+            .takes_value(true)
+            .value_name("PATTERN")
+            .multiple(true)
+            .number_of_values(1))
+        .arg(clap::Arg::with_name("exclude")
+            .long("exclude")
+            .help("Never clean up recipes matching the given pattern of the form 'name/version@user/channel', e.g. '*/*@corp/unstable'. Takes precedence over --include. May be given multiple times.")
+            .takes_value(true)
+            .value_name("PATTERN")
+            .multiple(true)
+            .number_of_values(1))
+        .arg(clap::Arg::with_name("patterns-report")
+            .long("patterns-report")
+            .help("Print every recipe pattern of the budgets of the configuration file, of --include, of --exclude and of the keep file with the number of recipes of the cache it matches."))
+        .arg(clap::Arg::with_name("fail-on-dead-patterns")
+            .long("fail-on-dead-patterns")
+            .help("Fail without removing anything if a recipe pattern of the budgets of the configuration file, of --include, of --exclude or of the keep file matches no recipe of the cache, e.g. due to a typo. By default such patterns are only warned about."))
+        .arg(clap::Arg::with_name("interactive")
+            .long("interactive")
+            .help("Confirm the removal of unused packages and of recipes without packages recipe by recipe instead of all at once.")
+            .conflicts_with("force"))
+        .arg(clap::Arg::with_name("review-chunk")
+            .long("review-chunk")
+            .help("Review the packages to remove in chunks of up to N packages instead of all at once. The packages of a recipe always share a chunk. Each approved chunk is removed before the next one is shown. Quitting writes the chunks that were not reviewed yet to a file.")
+            .takes_value(true)
+            .value_name("N")
+            .validator(validate_chunk_size)
+            .conflicts_with_all(&["force", "interactive", "dry-run", "read-only"]))
+        .arg(clap::Arg::with_name("confirm-risk")
+            .long("confirm-risk")
+            .help("Confirm the removal of every package of at least this risk individually. Packages in use by the projects selected with --only-consumed-by and recent local builds are of high risk, packages only used by failed builds and incompatible packages that would otherwise be kept of medium risk. With -f, such packages are kept unless --force-risky is given.")
+            .takes_value(true)
+            .value_name("LEVEL")
+            .default_value("high")
+            .possible_values(risk::RiskLevel::NAMES))
+        .arg(clap::Arg::with_name("force-risky")
+            .long("force-risky")
+            .help("With -f, also remove packages at or above the --confirm-risk level without confirmation.")
+            .requires("force"))
+        .arg(clap::Arg::with_name("force")
+            .short("f")
+            .help("Force complete removal of unused packages without requiring manual approval.")
+            .takes_value(false))
+        .arg(clap::Arg::with_name("incremental")
+            .long("incremental")
+            .help("Only search recipes whose cache folders changed since the previous run and reuse the previous results for all others.")
+            .overrides_with("no-incremental"))
+        .arg(clap::Arg::with_name("no-incremental")
+            .long("no-incremental")
+            .help("Force a full run that searches every recipe (default).")
+            .overrides_with("incremental"))
+        .arg(clap::Arg::with_name("clean-locks")
+            .long("clean-locks")
+            .help("Also remove lock files conan left behind in the cache. Enabled for every run by 'clean = true' in the [locks] section of conan_cleanup.conf in the conan home."))
+        .arg(clap::Arg::with_name("use-conan-cache-clean")
+            .long("use-conan-cache-clean")
+            .help("With conan 2, also clean source, build and download folders with 'conan cache clean' after removing packages."))
+        .arg(clap::Arg::with_name("locks-older-than")
+            .long("locks-older-than")
+            .help("Only remove lock files that were not modified within the given duration (e.g. '12h', '30d'). Overrides 'older_than' in the [locks] section of conan_cleanup.conf.")
+            .takes_value(true)
+            .default_value(cleanup::DEFAULT_LOCKS_OLDER_THAN)
+            .validator(duration::validate_duration))
+        .arg(clap::Arg::with_name("preserve-newest-per-name")
+            .long("preserve-newest-per-name")
+            .help("Always keep the N most recently written binaries of every package name, regardless of whether they are in use. With --keep-latest, the binaries are chosen among the versions --keep-latest does not keep, so that both protect packages.")
+            .takes_value(true)
+            .value_name("N")
+            .validator(validate_count))
+        .arg(clap::Arg::with_name("keep-latest")
+            .long("keep-latest")
+            .help("Always keep the N highest versions of every package name and user/channel, regardless of whether they are in use. Only the packages of older versions are removed. Applies before --preserve-newest-per-name.")
+            .takes_value(true)
+            .value_name("N")
+            .validator(validate_count))
+        .arg(clap::Arg::with_name("provenance-out")
+            .long("provenance-out")
+            .help("Write which packages each scanned project uses, as JSON document, to the given file.")
+            .takes_value(true)
+            .value_name("PATH"))
+        .arg(clap::Arg::with_name("composition-export")
+            .long("composition-export")
+            .help("Write an anonymized summary of the cache by package name and version to the given file, to share with package maintainers: counts, sizes, ages and how many packages are in use. Consumer paths, user/channel values and the hostname are replaced by salted hashes.")
+            .takes_value(true)
+            .value_name("PATH"))
+        .arg(clap::Arg::with_name("salt-file")
+            .long("salt-file")
+            .help("File holding the salt of the hashes of --composition-export, created if missing. Exports with the same salt can be correlated. Defaults to a file in the conan home, which correlates the exports of a machine.")
+            .takes_value(true)
+            .value_name("PATH")
+            .requires("composition-export"))
+        .arg(clap::Arg::with_name("verify")
+            .long("verify")
+            .help("Check the cache after each removal to make sure the removed recipe is actually gone."))
+        .arg(clap::Arg::with_name("io-budget")
+            .long("io-budget")
+            .help("Limit the filesystem operations to about the given MB per second, e.g. for caches on network shares: sizing folders, deleting folders directly and, by spacing them out according to the sizes of the packages, conan removals.")
+            .takes_value(true)
+            .value_name("MB/s")
+            .validator(validate_io_budget))
+        .arg(clap::Arg::with_name("jobs-remove")
+            .long("jobs-remove")
+            .help("Number of packages to remove concurrently. Packages of the same recipe are always removed one after another.")
+            .takes_value(true)
+            .value_name("N")
+            .default_value("1")
+            .validator(validate_job_count))
+        .arg(clap::Arg::with_name("skip-header-only")
+            .long("skip-header-only")
+            .help("Never remove header-only packages. Removing them frees next to no space but forces a re-download."))
+        .arg(clap::Arg::with_name("recent-local-age")
+            .long("recent-local-age")
+            .help("Packages created within the given duration (e.g. '12h', '7d') whose recipe was not retrieved from any remote are considered recent local builds, e.g. from 'conan export-pkg', and are kept.")
+            .takes_value(true)
+            .value_name("DURATION")
+            .default_value("7d")
+            .validator(duration::validate_duration))
+        .arg(clap::Arg::with_name("older-than")
+            .long("older-than")
+            .help("Only remove unused packages that were not written to the cache within the given duration (e.g. '30d', '6w', '90days'), see --age-source. The listing shows the age of every package.")
+            .takes_value(true)
+            .value_name("DURATION")
+            .validator(duration::validate_duration))
+        .arg(clap::Arg::with_name("age-source")
+            .long("age-source")
+            .help("Where --older-than, --preserve-newest-per-name and the ages in the listing take the time a package was written from: the timestamp conan records in its conanmanifest.txt when packaging it, which survives copying or restoring the cache, the modification time of its package folder, or the newer of both. Packages without a readable manifest fall back to the modification time.")
+            .takes_value(true)
+            .value_name("SOURCE")
+            .possible_values(&["manifest", "mtime", "newest"])
+            .default_value("mtime"))
+        .arg(clap::Arg::with_name("older-than-unknown")
+            .long("older-than-unknown")
+            .help("Whether to keep or remove unused packages whose package folder cannot be found to tell their age with --older-than. Defaults to keeping them.")
+            .takes_value(true)
+            .possible_values(&["keep", "remove"])
+            .requires("older-than"))
+        .arg(clap::Arg::with_name("include-recent-local")
+            .long("include-recent-local")
+            .help("Also remove unused recent local builds."))
+        .arg(clap::Arg::with_name("header-only-max-size")
+            .long("header-only-max-size")
+            .help("Packages without settings are only considered header-only if their package folder is not larger than this (e.g. '512K', '1M').")
+            .takes_value(true)
+            .value_name("SIZE")
+            .default_value("1M")
+            .validator(validate_size))
+        .arg(clap::Arg::with_name("lint-references")
+            .long("lint-references")
+            .help("Only check the references of all cached recipes against the naming rules of the installed conan version and report the violations grouped by rule."))
+        .arg(clap::Arg::with_name("remove-invalid")
+            .long("remove-invalid")
+            .help("Offer recipes found by --lint-references for removal. Recipes conan cannot remove by reference are deleted from the cache folder directly.")
+            .requires("lint-references"))
+        .arg(clap::Arg::with_name("no-sizes")
+            .long("no-sizes")
+            .help("Don't determine the size of the packages to remove, which takes long on slow network-mounted caches."))
+        .arg(clap::Arg::with_name("max-usage-file-size")
+            .long("max-usage-file-size")
+            .help("Skip usage files larger than this (e.g. '512K', '5M'), which are most likely no usage files at all.")
+            .takes_value(true)
+            .value_name("SIZE")
+            .default_value("5M")
+            .validator(validate_size))
+        .arg(clap::Arg::with_name("min-usage-overlap")
+            .long("min-usage-overlap")
+            .help("Warn if less than this fraction of the packages used by the scanned projects exist in the local cache, which indicates the usage data belongs to a different cache. Forced runs are aborted in this case.")
+            .takes_value(true)
+            .value_name("FRACTION")
+            .default_value("0.2")
+            .validator(validate_fraction))
+        .arg(clap::Arg::with_name("ignore-mismatch")
+            .long("ignore-mismatch")
+            .help("Proceed with a forced run even if the usage data appears to belong to a different cache."))
+        .arg(clap::Arg::with_name("clean-home-misc")
+            .long("clean-home-misc")
+            .help("Also remove disposable files from the conan home outside of the package storage, like settings.yml and profile backups or leftover temporary folders."))
+        .arg(clap::Arg::with_name("home-misc-pattern")
+            .long("home-misc-pattern")
+            .help("Additional pattern of disposable entries for --clean-home-misc. Patterns starting with '/' are matched against the path relative to the conan home, all others against file and folder names. Supports '*' and '?'.")
+            .takes_value(true)
+            .value_name("PATTERN")
+            .multiple(true)
+            .number_of_values(1))
+        .arg(clap::Arg::with_name("temp-dir")
+            .long("temp-dir")
+            .help("Directory conan writes its search results to. Defaults to the system's temporary directory. The results of large caches can get bigger than a small temporary filesystem.")
+            .takes_value(true)
+            .value_name("DIR"))
+        .arg(clap::Arg::with_name("conan-version")
+            .long("conan-version")
+            .help("Major version of the installed conan client. Detected with 'conan --version' by default.")
+            .takes_value(true)
+            .value_name("VERSION")
+            .possible_values(&["1", "2"]))
+        .arg(clap::Arg::with_name("keep-search-results")
+            .long("keep-search-results")
+            .help("Keep the result of every conan search in a file of its own, in a subfolder of the given directory named after the start of the run. Helps to find out later why packages were removed.")
+            .takes_value(true)
+            .value_name("DIR"))
+        .arg(clap::Arg::with_name("keep-search-results-days")
+            .long("keep-search-results-days")
+            .help("Remove the search results of runs started more than the given number of days ago from the --keep-search-results directory. Defaults to 30.")
+            .takes_value(true)
+            .value_name("DAYS")
+            .validator(validate_count)
+            .requires("keep-search-results"))
+        .arg(clap::Arg::with_name("jobs")
+            .long("jobs")
+            .help("Maximum number of conan processes running at the same time, across all phases. Defaults to the number of CPUs. The running conan commands are printed to stderr on SIGQUIT or when the file 'conan_cleanup.status' is created in the temporary directory.")
+            .takes_value(true)
+            .value_name("N")
+            .validator(validate_job_count))
+        .arg(clap::Arg::with_name("require-hash")
+            .long("require-hash")
+            .help("Refuse to remove anything unless the hash of the planned package removals, printed below the listing, equals the given one.")
+            .takes_value(true)
+            .value_name("HASH"))
+        .arg(clap::Arg::with_name("resume")
+            .long("resume")
+            .help("Continue the removal a previous run was interrupted in, skipping the packages it removed. Fails unless the resume file next to the state file belongs to the plan of this run. A matching resume file is picked up without this flag as well."))
+        .arg(clap::Arg::with_name("except")
+            .long("except")
+            .help("Leave the given package out of the plan approved by --require-hash, e.g. 'zlib/1.2.11@conan/stable:<package ID>'. '*' and '?' are supported in both parts. Fails if it matches no package of the plan.")
+            .takes_value(true)
+            .value_name("REFERENCE:PACKAGE_ID")
+            .multiple(true)
+            .number_of_values(1)
+            .validator(validate_exception)
+            .requires("require-hash"))
+        .arg(clap::Arg::with_name("except-file")
+            .long("except-file")
+            .help("File with one exception per line, see --except. Blank lines and lines starting with '#' are ignored.")
+            .takes_value(true)
+            .value_name("FILE")
+            .requires("require-hash"))
+        .arg(clap::Arg::with_name("output")
+            .long("output")
+            .help("Print the result as JSON document to stdout instead of human-readable output, which then goes to stderr. Requires --dry-run or -f since there is no way to confirm removals.")
+            .takes_value(true)
+            .value_name("FORMAT")
+            .possible_values(&["human", "json"])
+            .conflicts_with("interactive"))
+        .arg(clap::Arg::with_name("errors")
+            .long("errors")
+            .help("Print a failure of the run as JSON object with a stable error code to stdout, see ERROR CODES below. Implied by --output json. Human-readable output goes to stderr then.")
+            .takes_value(true)
+            .value_name("FORMAT")
+            .possible_values(&["human", "json"]))
+        .arg(clap::Arg::with_name("notify")
+            .long("notify")
+            .help("Show a desktop notification when the run finished and when it waits for confirmation after working unattended for a while."))
+        .arg(clap::Arg::with_name("syslog")
+            .long("syslog")
+            .help("Also write warnings, errors and the summary of the run to syslog (Unix only)."))
+        .arg(clap::Arg::with_name("eventlog")
+            .long("eventlog")
+            .help("Also write warnings, errors and the summary of the run to the Application log of the Windows Event Log (Windows only)."))
+        .arg(clap::Arg::with_name("verbose")
+            .short("v")
+            .long("verbose")
+            .multiple(true)
+            .help("Print every conan command with its complete output and every usage file found. By default the output of successful conan commands is hidden and only lines matching the patterns of --conan-warning-pattern are reported once at the end. Given twice, also print timings, the sizes of the JSON documents parsed and the packages required by each usage file. RUST_LOG=debug and RUST_LOG=trace do the same."))
+        .arg(clap::Arg::with_name("conan-warning-pattern")
+            .long("conan-warning-pattern")
+            .help("Additional pattern of conan output lines to report even if the conan command succeeded. Matched case-insensitively against whole lines, supports '*' and '?'. Lines about deprecations, things not found, migrations and disabled remotes are always reported.")
+            .takes_value(true)
+            .value_name("PATTERN")
+            .multiple(true)
+            .number_of_values(1))
+        .arg(clap::Arg::with_name("profile-compat")
+            .long("profile-compat")
+            .help("Compare the settings of unused packages with the given conan profile (the default profile if no name is given) and mark packages built for other settings as incompatible.")
+            .takes_value(true)
+            .value_name("PROFILE")
+            .min_values(0)
+            .require_equals(true))
+        .arg(clap::Arg::with_name("remove-incompatible")
+            .long("remove-incompatible")
+            .help("Also remove unused packages that are incompatible with the profile given by --profile-compat if they would otherwise be kept, e.g. as the newest of their name. Packages in use are never removed.")
+            .requires("profile-compat"))
+        .arg(clap::Arg::with_name("check-recoverable")
+            .long("check-recoverable")
+            .help("Before removing a recipe that has no packages left, check whether it can be retrieved again from any configured remote. Recipes found on no remote are only removed after confirmation."))
+        .arg(clap::Arg::with_name("prune-sort")
+            .long("prune-sort")
+            .help("Order in which recipes without packages left are pruned: largest recipe folder first, by name, or oldest export first. Recipes of unknown size or age come last. Defaults to the order of the cache, or to size with --prune-top.")
+            .takes_value(true)
+            .value_name("KEY")
+            .possible_values(&["size", "name", "age"]))
+        .arg(clap::Arg::with_name("prune-top")
+            .long("prune-top")
+            .help("Only prune the first N recipes without packages left in the order of --prune-sort. The others are listed as deferred to a later run.")
+            .takes_value(true)
+            .value_name("N")
+            .validator(validate_count))
+        .arg(clap::Arg::with_name("prune-local-only")
+            .long("prune-local-only")
+            .help("Remove recipes that exist on no remote without asking, destroying the only copy of their exported sources.")
+            .requires("check-recoverable"))
+        .arg(clap::Arg::with_name("strict-revisions")
+            .long("strict-revisions")
+            .help("Fail instead of ignoring the revisions of usage files that reference revisions although the cache has them disabled."))
+        .arg(clap::Arg::with_name("strict-lockfiles")
+            .long("strict-lockfiles")
+            .help("Treat exactly the packages pinned by the lockfiles matching the given pattern as in use and remove everything else. conaninfo.txt files are not scanned in this mode. '*' and '?' are supported, '*' also matches across folders.")
+            .takes_value(true)
+            .value_name("PATTERN")
+            .multiple(true)
+            .number_of_values(1))
+        .arg(clap::Arg::with_name("nag-after")
+            .long("nag-after")
+            .help("Only check when the cache was cleaned last. Prints a reminder and exits with code 11 if it was not cleaned within the given duration (e.g. '30d'), exits with 0 otherwise.")
+            .takes_value(true)
+            .value_name("DURATION")
+            .validator(duration::validate_duration))
+        .arg(clap::Arg::with_name("cooldown")
+            .long("cooldown")
+            .help("Exit right away if a run that could remove packages started within the given duration (e.g. '8h'), so that running from hooks or build wrappers asks at most once per duration. Recorded per conan home in its state file.")
+            .takes_value(true)
+            .value_name("DURATION")
+            .validator(duration::validate_duration))
+        .arg(clap::Arg::with_name("ignore-cooldown")
+            .long("ignore-cooldown")
+            .help("Run even if the duration given by --cooldown has not passed yet."))
+        .arg(clap::Arg::with_name("strict-input-files")
+            .long("strict-input-files")
+            .help("Fail if a keep file, profile, conan_cleanup.conf or list of expected hosts has a byte order mark, mixed line endings or trailing whitespace, instead of ignoring them with a warning."))
+        .arg(clap::Arg::with_name("read-only")
+            .long("read-only")
+            .help("Audit the cache without modifying it: only run conan commands that read the cache and refuse to write or delete anything in the conan home, e.g. when running as an unprivileged user. Implies --dry-run.")
+            .conflicts_with_all(&["force", "force-readonly-attempt"]))
+        .arg(clap::Arg::with_name("conan-home")
+            .long("conan-home")
+            .value_name("DIR")
+            .takes_value(true)
+            .help("The conan home to clean instead of the one of the environment: the '.conan' folder of conan 1 or the home of conan 2. In a cache overlaying a shared read-only cache, only the packages of the writable layer are removed."))
+        .arg(clap::Arg::with_name("force-readonly-attempt")
+            .long("force-readonly-attempt")
+            .help("Try to remove packages even if the cache is read-only, either since CONAN_READ_ONLY_CACHE is set or the storage folder is not writable."))
+        .arg(clap::Arg::with_name("explain")
+            .long("explain")
+            .help("Annotate every package to remove with the reason for its removal and summarize the kept packages per recipe together with the rules that keep them. '--explain=full' lists every kept package.")
+            .takes_value(true)
+            .value_name("full")
+            .min_values(0)
+            .require_equals(true)
+            .possible_values(&["full"]))
+        .arg(clap::Arg::with_name("clean-short-paths-orphans")
+            .long("clean-short-paths-orphans")
+            .help("Also remove folders in the short_paths home (CONAN_USER_HOME_SHORT, C:\\.conan by default on Windows) whose package was already removed from the cache."))
+        .arg(clap::Arg::with_name("only-consumed-by")
+            .long("only-consumed-by")
+            .help("Only remove packages that are used exclusively by projects matching the given pattern, as if those projects were gone. The pattern is matched against the paths of the conaninfo.txt files relative to the root path, e.g. 'old-product/*'. '*' also matches across folders.")
+            .takes_value(true)
+            .value_name("PATTERN")
+            .multiple(true)
+            .number_of_values(1)
+            .conflicts_with("strict-lockfiles"))
+        .arg(clap::Arg::with_name("not-consumed-by")
+            .long("not-consumed-by")
+            .help("Keep packages selected by --only-consumed-by if any project matching the given pattern uses them.")
+            .takes_value(true)
+            .value_name("PATTERN")
+            .multiple(true)
+            .number_of_values(1)
+            .requires("only-consumed-by"))
+        .arg(clap::Arg::with_name("extra-storage")
+            .long("extra-storage")
+            .help("Additional storage folder conan no longer uses, e.g. after storage.path was changed. Its unused packages are deleted as plain folders after a separate confirmation, since conan cannot manage them.")
+            .takes_value(true)
+            .value_name("PATH")
+            .multiple(true)
+            .number_of_values(1))
+        .arg(clap::Arg::with_name("downgrade-failed-builds")
+            .long("downgrade-failed-builds")
+            .help("Recognize conaninfo.txt files of builds that apparently failed and list packages only used by such builds separately as weakly protected."))
+        .arg(clap::Arg::with_name("failed-build-marker")
+            .long("failed-build-marker")
+            .help("Evidence of a failed build next to a conaninfo.txt: 'cmake-error-log' (a newer CMakeError.log), 'empty-ninja-log' (a .ninja_log without built edges) or the name of a marker file. Replaces the default of 'cmake-error-log' and 'empty-ninja-log'.")
+            .takes_value(true)
+            .value_name("MARKER")
+            .multiple(true)
+            .number_of_values(1)
+            .requires("downgrade-failed-builds"))
+        .arg(clap::Arg::with_name("remove-weakly-protected")
+            .long("remove-weakly-protected")
+            .help("Also remove packages that are only used by builds that apparently failed.")
+            .requires("downgrade-failed-builds"))
+        .arg(clap::Arg::with_name("dry-run")
+            .long("dry-run")
+            .help("Only list the packages and recipes that would be removed, without asking and without removing anything.")
+            .conflicts_with("force"))
+        .arg(clap::Arg::with_name("simulate-timing")
+            .long("simulate-timing")
+            .help("With --dry-run, estimate how long removing the listed packages would take, per recipe and in total, from the removal times recorded by previous runs and the time measuring the packages took. Without recorded removals, every package is assumed to take 2 seconds and the estimate is labeled low confidence."))
+        .arg(clap::Arg::with_name("transcript")
+            .long("transcript")
+            .help("With --dry-run, write every conan command of the run in order to the given file: the searches it executed and the removals it would have executed, each with its phase, target reference and the reason it was planned. The same is written as JSON to the file with '.json' appended.")
+            .takes_value(true)
+            .value_name("PATH"))
+        .arg(clap::Arg::with_name("support-bundle")
+            .long("support-bundle")
+            .help("With --dry-run, write everything needed to reproduce the run for a bug report to the given directory: the transcript of --transcript, the conan search results, the effective configuration and the environment the tool found. The name of the current user is replaced by '<user>' in paths.")
+            .takes_value(true)
+            .value_name("DIR")
+            .conflicts_with("keep-search-results"))
+        .arg(clap::Arg::with_name("no-redact")
+            .long("no-redact")
+            .help("Keep the name of the current user in the paths of the --support-bundle.")
+            .requires("support-bundle"))
+        .arg(clap::Arg::with_name("profile-name")
+            .long("profile-name")
+            .help("Apply the options of the section '[profile.<NAME>]' of the conan_cleanup.conf of the conan home, e.g. 'older-than = 30d' or 'dry-run = true'. Options given on the command line take precedence. See the profiles subcommand.")
+            .takes_value(true)
+            .value_name("NAME"))
+        .arg(clap::Arg::with_name("print-config")
+            .long("print-config")
+            .help("Print the effective options and where each value came from, the command line, the profile of --profile-name, the configuration file or the defaults, and exit."))
+}
+
+/// Returns the settings of the session the run runs in.
+fn settings(args: &clap::ArgMatches) -> Result<session::Settings, CleanupError> {
+    if args.is_present("syslog") && cfg!(not(unix)) {
+        return Err(CleanupError::InvalidArguments(
+            "--syslog is only supported on Unix, use --eventlog.".to_owned(),
+        ));
+    }
+    if args.is_present("eventlog") && cfg!(not(windows)) {
+        return Err(CleanupError::InvalidArguments(
+            "--eventlog is only supported on Windows, use --syslog.".to_owned(),
+        ));
+    }
+    Ok(session::Settings {
+        conan_home: args.value_of("conan-home").map(PathBuf::from),
+        conan_major_version: args
+            .value_of("conan-version")
+            .map(|major| major.parse().unwrap()),
+        read_only: args.is_present("read-only"),
+        conan_jobs: match args.value_of("jobs") {
+            Some(jobs) => jobs.parse().unwrap(),
+            None => std::thread::available_parallelism().map_or(1, |jobs| jobs.get()),
+        },
+        conan_warning_patterns: subprocess::DEFAULT_IMPORTANT_PATTERNS
+            .iter()
+            .map(|pattern| pattern.to_string())
+            .chain(values(args, "conan-warning-pattern"))
+            .collect(),
+        strict_input_files: args.is_present("strict-input-files"),
+        io_budget: args.value_of("io-budget").map(|budget| {
+            let megabytes_per_second: f64 = budget.parse().unwrap();
+            (megabytes_per_second * 1024.0 * 1024.0) as u64
+        }),
+        transcript: args.is_present("transcript") || args.is_present("support-bundle"),
+        notify: args.is_present("notify"),
+        system_log: args.is_present("syslog") || args.is_present("eventlog"),
+        log_level: logging::level(args.occurrences_of("verbose")),
+        configuration: Some(run_info::effective_configuration(args)),
+    })
+}
+
+fn run(
+    args: &clap::ArgMatches,
+    out: &output::Output,
+    token: &cancel::CancellationToken,
+) -> Result<(), CleanupError> {
+    if let Some(conan_home) = args.value_of("conan-home") {
+        if !Path::new(conan_home).is_dir() {
+            return Err(CleanupError::InvalidArguments(format!(
+                "Conan home '{}' is not an existing directory.",
+                conan_home
+            )));
+        }
+    }
+
+    match args.subcommand() {
+        ("snapshot", Some(snapshot_args)) => {
+            return take_snapshot(out, snapshot_args.value_of("file").unwrap());
+        }
+        ("snapshot-diff", Some(diff_args)) => {
+            return print_snapshot_diff(
+                out,
+                diff_args.value_of("old").unwrap(),
+                diff_args.value_of("new").unwrap(),
+                diff_args.is_present("json"),
+            );
+        }
+        ("aggregate", Some(aggregate_args)) => {
+            return print_aggregate(out, aggregate_args);
+        }
+        ("profiles", Some(_)) => {
+            return print_profiles(out, args);
+        }
+        ("serve", Some(_)) => {
+            let stdout = std::io::stdout();
+            return serve::serve(std::io::stdin().lock(), stdout.lock(), token)
+                .map_err(CleanupError::Stdin);
+        }
+        _ => {}
+    }
+
+    let dry_run = args.is_present("dry-run") || args.is_present("read-only");
+    if out.is_json() && !dry_run && !args.is_present("force") {
+        return Err(CleanupError::InvalidArguments(
+            "--output json cannot ask for confirmation, pass --dry-run or -f.".to_owned(),
+        ));
+    }
+    for name in &["simulate-timing", "transcript", "support-bundle"] {
+        if args.is_present(name) && !dry_run {
+            return Err(CleanupError::InvalidArguments(format!(
+                "--{} requires --dry-run.",
+                name
+            )));
         }
     }
 
-    Ok(package_ids)
+    let options = cleanup_options(args);
+    subprocess::watch_status_requests(options.temp_dir.join("conan_cleanup.status"));
+    cleanup::run(&options, &mut StdinPrompt, out, token)
 }
 
-fn find_packages_in_use(root_path: &str) -> Vec<String> {
-    let mut packages_in_use = Vec::new();
-    for entry in WalkDir::new(root_path)
-        .follow_links(true)
+/// Returns the values of an option given any number of times.
+fn values(args: &clap::ArgMatches, name: &str) -> Vec<String> {
+    args.values_of(name)
         .into_iter()
-        .filter_map(|e| e.ok())
-    {
-        if entry.file_name() == "conaninfo.txt" {
-            let packages = match parse_required_packages(entry.path()) {
-                Ok(packages) => packages,
-                Err(ref err) => {
-                    eprintln!("Failed to parse '{}': {}", entry.path().display(), err);
-                    continue;
-                }
-            };
+        .flatten()
+        .map(str::to_owned)
+        .collect()
+}
 
-            packages_in_use.extend(packages);
-        }
+/// Returns the options of the cleanup given by the arguments.
+fn cleanup_options(args: &clap::ArgMatches) -> cleanup::Options {
+    let path = |name| args.value_of(name).map(PathBuf::from);
+    let duration = |name| {
+        args.value_of(name)
+            .map(|value| duration::parse_duration(value).unwrap())
+    };
+    let count = |name| args.value_of(name).map(|value| value.parse().unwrap());
+    let defaults = cleanup::Options::default();
+
+    let usage_patterns = match args.values_of("usage-filename") {
+        Some(patterns) => patterns.map(UsagePattern::conaninfo).collect(),
+        None => vec![UsagePattern::conaninfo(
+            usage_pattern::DEFAULT_CONANINFO_PATTERN,
+        )],
     }
+    .into_iter()
+    .chain(match args.values_of("usage-file") {
+        Some(values) => values
+            .map(|value| UsagePattern::parse(value).unwrap())
+            .collect(),
+        None => vec![
+            UsagePattern::conanbuildinfo(usage_pattern::DEFAULT_CONANBUILDINFO_PATTERN),
+            UsagePattern::lockfile(usage_pattern::DEFAULT_LOCKFILE_PATTERN),
+        ],
+    })
+    .filter(|pattern| match args.values_of("sources") {
+        Some(mut sources) => sources
+            .any(|source| usage_pattern::UsageFormat::from_name(source) == Some(pattern.format)),
+        None => true,
+    })
+    .collect();
+    let excluded_dirs = scan::DEFAULT_EXCLUDED_DIRS
+        .iter()
+        .map(|dir| dir.to_string())
+        .filter(|_| !args.is_present("no-default-excludes"))
+        .chain(values(args, "exclude-dir"))
+        .collect();
+    let failed_build_markers = match args.values_of("failed-build-marker") {
+        _ if !args.is_present("downgrade-failed-builds") => None,
+        Some(markers) => Some(markers.map(build_failure::parse_marker).collect()),
+        None => Some(build_failure::default_markers()),
+    };
+    let plan = plan::PlanOptions {
+        include: args.values_of("include").map(|_| values(args, "include")),
+        exclude: values(args, "exclude"),
+        keep_latest: count("keep-latest"),
+        preserve_newest_per_name: count("preserve-newest-per-name"),
+        older_than: duration("older-than"),
+        age_source: plan::AgeSource::parse(args.value_of("age-source").unwrap()).unwrap(),
+        remove_unknown_age: args.value_of("older-than-unknown") == Some("remove"),
+        only_consumed_by: args
+            .values_of("only-consumed-by")
+            .map(|_| values(args, "only-consumed-by")),
+        not_consumed_by: values(args, "not-consumed-by"),
+        remove_weakly_protected: args.is_present("remove-weakly-protected"),
+        remove_incompatible: args.is_present("remove-incompatible"),
+        skip_header_only: args.is_present("skip-header-only"),
+        header_only_max_size: parse_size(args.value_of("header-only-max-size").unwrap()).unwrap(),
+        include_recent_local: args.is_present("include-recent-local"),
+        recent_local_age: duration("recent-local-age").unwrap(),
+        ..plan::PlanOptions::default()
+    };
 
-    packages_in_use.sort();
-    packages_in_use.dedup();
-    packages_in_use
+    cleanup::Options {
+        root_paths: values(args, "root_path"),
+        dry_run: args.is_present("dry-run"),
+        force: args.is_present("force"),
+        force_readonly_attempt: args.is_present("force-readonly-attempt"),
+        force_risky: args.is_present("force-risky"),
+        interactive: args.is_present("interactive"),
+        cooldown: duration("cooldown"),
+        ignore_cooldown: args.is_present("ignore-cooldown"),
+        nag_after: duration("nag-after"),
+        simulate_timing: args.is_present("simulate-timing"),
+        strict_lockfiles: args
+            .values_of("strict-lockfiles")
+            .map(|_| values(args, "strict-lockfiles")),
+        usage_patterns,
+        excluded_dirs,
+        max_usage_file_size: parse_size(args.value_of("max-usage-file-size").unwrap()).unwrap(),
+        failed_build_markers,
+        provenance_out: path("provenance-out"),
+        temp_dir: path("temp-dir").unwrap_or(defaults.temp_dir),
+        use_conan_cache_clean: args.is_present("use-conan-cache-clean"),
+        keep_search_results: path("keep-search-results"),
+        keep_search_results_for: args
+            .value_of("keep-search-results-days")
+            .map_or(defaults.keep_search_results_for, |days| {
+                Duration::from_secs(days.parse::<u64>().unwrap() * 24 * 60 * 60)
+            }),
+        support_bundle: path("support-bundle"),
+        plan,
+        keep_file: path("keep-file"),
+        profile_compat: args
+            .is_present("profile-compat")
+            .then(|| args.value_of("profile-compat").map(str::to_owned)),
+        strict_revisions: args.is_present("strict-revisions"),
+        patterns_report: args.is_present("patterns-report"),
+        fail_on_dead_patterns: args.is_present("fail-on-dead-patterns"),
+        lint_references: args.is_present("lint-references"),
+        remove_invalid: args.is_present("remove-invalid"),
+        incremental: args.is_present("incremental"),
+        min_usage_overlap: args.value_of("min-usage-overlap").unwrap().parse().unwrap(),
+        ignore_mismatch: args.is_present("ignore-mismatch"),
+        composition_export: path("composition-export"),
+        salt_file: path("salt-file"),
+        explain: args.is_present("explain"),
+        explain_full: args.value_of("explain") == Some("full"),
+        confirm_risk: risk::RiskLevel::from_name(args.value_of("confirm-risk").unwrap()).unwrap(),
+        review_chunk: count("review-chunk"),
+        require_hash: args.value_of("require-hash").map(str::to_owned),
+        exceptions: values(args, "except"),
+        except_file: path("except-file"),
+        resume: args.is_present("resume"),
+        no_sizes: args.is_present("no-sizes"),
+        jobs_remove: count("jobs-remove").unwrap(),
+        verify: args.is_present("verify"),
+        check_recoverable: args.is_present("check-recoverable"),
+        prune_local_only: args.is_present("prune-local-only"),
+        prune_sort: args
+            .value_of("prune-sort")
+            .and_then(ranking::SortKey::parse),
+        prune_top: count("prune-top"),
+        clean_locks: args.is_present("clean-locks"),
+        // Only an explicit --locks-older-than overrides the configuration file.
+        locks_older_than: duration("locks-older-than")
+            .filter(|_| args.occurrences_of("locks-older-than") > 0),
+        extra_storage: args
+            .values_of("extra-storage")
+            .into_iter()
+            .flatten()
+            .map(PathBuf::from)
+            .collect(),
+        clean_short_paths_orphans: args.is_present("clean-short-paths-orphans"),
+        clean_home_misc: args.is_present("clean-home-misc"),
+        home_misc_patterns: defaults
+            .home_misc_patterns
+            .into_iter()
+            .chain(values(args, "home-misc-pattern"))
+            .collect(),
+        resource_report: args.is_present("resource-report"),
+    }
 }
 
-#[derive(Debug)]
-enum ConanIniError {
-    Ini(ini::ini::Error),
-    MissingSection(String),
-}
+/// Asks the questions of the run on stdin.
+struct StdinPrompt;
+
+impl StdinPrompt {
+    /// Reads lines from stdin until `parse` accepts one, printing `retry` after each other line.
+    fn read<T>(
+        out: &output::Output,
+        retry: &str,
+        parse: impl Fn(&str) -> Option<T>,
+    ) -> Result<T, CleanupError> {
+        notify::waiting_for_input();
+        loop {
+            let mut answer = String::new();
+            std::io::stdin()
+                .read_line(&mut answer)
+                .map_err(CleanupError::Stdin)?;
 
-impl fmt::Display for ConanIniError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            ConanIniError::Ini(ref err) => err.fmt(f),
-            ConanIniError::MissingSection(ref section) => {
-                write!(f, "Section '{}' is missing", section)
+            notify::input_received();
+            match parse(answer.trim()) {
+                Some(answer) => return Ok(answer),
+                None => outln!(out, "{}", retry),
             }
         }
     }
 }
 
-impl std::error::Error for ConanIniError {
-    fn description(&self) -> &str {
-        match *self {
-            ConanIniError::Ini(ref err) => err.description(),
-            ConanIniError::MissingSection(_) => "missing section",
-        }
+impl output::Prompt for StdinPrompt {
+    fn yes_or_no(&mut self, out: &output::Output) -> Result<bool, CleanupError> {
+        StdinPrompt::read(out, "yes/no?", |answer| match answer {
+            "Yes" | "yes" | "y" | "Y" => Some(true),
+            "No" | "no" | "n" | "N" => Some(false),
+            _ => None,
+        })
     }
-}
 
-impl From<ini::ini::Error> for ConanIniError {
-    fn from(err: ini::ini::Error) -> ConanIniError {
-        ConanIniError::Ini(err)
+    fn chunk_answer(&mut self, out: &output::Output) -> Result<ChunkAnswer, CleanupError> {
+        StdinPrompt::read(out, "yes/no/quit?", |answer| match answer {
+            "Yes" | "yes" | "y" | "Y" => Some(ChunkAnswer::Yes),
+            "No" | "no" | "n" | "N" => Some(ChunkAnswer::No),
+            "Quit" | "quit" | "q" | "Q" => Some(ChunkAnswer::Quit),
+            _ => None,
+        })
     }
 }
 
-fn parse_required_packages<P: AsRef<std::path::Path>>(
-    file_path: P,
-) -> Result<Vec<String>, ConanIniError> {
-    let conan_info = Ini::load_from_file(file_path)?;
-    let full_requires = conan_info
-        .section(Some("full_requires".to_owned()))
-        .ok_or_else(|| ConanIniError::MissingSection("full_requires".to_owned()))?;
+fn take_snapshot(out: &output::Output, path: &str) -> Result<(), CleanupError> {
+    let storage_path = match cache::conan_home() {
+        Some(conan_home) => cache::storage_path(&conan_home),
+        None => return Err(CleanupError::UnknownConanHome("take a snapshot")),
+    };
 
-    let mut required_packages = Vec::new();
-    for (_, value) in full_requires {
-        required_packages.push(value.to_owned());
-    }
-    Ok(required_packages)
+    let snapshot = inventory::take(&storage_path);
+    snapshot
+        .save(std::path::Path::new(path))
+        .map_err(|err| CleanupError::File("write snapshot", path.into(), err.into()))?;
+    let package_count: usize = snapshot
+        .recipes
+        .values()
+        .map(|recipe| recipe.packages.len())
+        .sum();
+    outln!(
+        out,
+        "Recorded {} recipes with {} packages ({}) in '{}'.",
+        snapshot.recipes.len(),
+        package_count,
+        format_size(snapshot.total_size()),
+        path
+    );
+    Ok(())
 }
 
-#[derive(Debug)]
-enum ConanJsonError {
-    Io(std::io::Error),
-    Json(serde_json::Error),
-    FormatError(String),
-}
+fn print_aggregate(out: &output::Output, args: &clap::ArgMatches) -> Result<(), CleanupError> {
+    let inputs: Vec<&str> = args.values_of("reports").unwrap().collect();
+    let paths = aggregate::find_reports(&inputs);
+    if paths.is_empty() {
+        return Err(CleanupError::InvalidArguments(format!(
+            "No reports found at '{}'.",
+            inputs.join("', '")
+        )));
+    }
+    let expected_hosts = match args.value_of("expect-hosts") {
+        Some(path) => Some(
+            aggregate::load_expected_hosts(path.as_ref()).map_err(|err| {
+                CleanupError::File("read the expected hosts from", path.into(), err.into())
+            })?,
+        ),
+        None => None,
+    };
 
-impl fmt::Display for ConanJsonError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            ConanJsonError::Io(ref err) => err.fmt(f),
-            ConanJsonError::Json(ref err) => err.fmt(f),
-            ConanJsonError::FormatError(ref err) => write!(
-                f,
-                "Unexpected JSON format (conan might have changed its output format): {}",
-                err
-            ),
+    let mut reports = Vec::new();
+    let mut invalid_reports = Vec::new();
+    for path in paths {
+        match aggregate::load_report(&path) {
+            Ok(report) => reports.push(report),
+            Err(err) => {
+                warning!("Ignoring '{}': {}", path.display(), err);
+                invalid_reports.push((path, err));
+            }
         }
     }
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_secs());
+    let aggregate = aggregate::aggregate(
+        &reports,
+        invalid_reports,
+        expected_hosts.as_deref(),
+        now,
+        duration::parse_duration(args.value_of("stale-after").unwrap()).unwrap(),
+        args.value_of("top").unwrap().parse().unwrap(),
+    );
+    match args.value_of("format") {
+        Some("json") => outln!(
+            out,
+            "{}",
+            serde_json::to_string_pretty(&aggregate.to_json()).unwrap()
+        ),
+        Some("html") => outln!(out, "{}", aggregate.to_html().trim_end()),
+        _ => outln!(out, "{}", aggregate.to_text().trim_end()),
+    }
+    Ok(())
 }
 
-impl std::error::Error for ConanJsonError {
-    fn description(&self) -> &str {
-        match *self {
-            ConanJsonError::Io(ref err) => err.description(),
-            ConanJsonError::Json(ref err) => err.description(),
-            ConanJsonError::FormatError(_) => "unexpected JSON format",
+fn print_snapshot_diff(
+    out: &output::Output,
+    old_path: &str,
+    new_path: &str,
+    json: bool,
+) -> Result<(), CleanupError> {
+    let load = |path: &str| {
+        inventory::Inventory::load(std::path::Path::new(path))
+            .map_err(|err| CleanupError::File("read snapshot", path.into(), err.into()))
+    };
+    let (old, new) = (load(old_path)?, load(new_path)?);
+    if old.storage_path != new.storage_path {
+        eprintln!(
+            "The snapshots were taken of different storage folders ('{}' and '{}').",
+            old.storage_path, new.storage_path
+        );
+    }
+
+    let diff = inventory::diff(&old, &new);
+    if json {
+        outln!(
+            out,
+            "{}",
+            serde_json::to_string_pretty(&diff.to_json()).unwrap()
+        );
+        return Ok(());
+    }
+
+    let print_recipes = |title: &str, recipes: &[(String, u64)]| {
+        if !recipes.is_empty() {
+            outln!(out, "{}", title);
+            for (recipe_id, size) in recipes {
+                outln!(out, "  {} ({})", recipe_id, format_size(*size));
+            }
+        }
+    };
+    let print_packages = |title: &str, packages: &[(String, String, u64)]| {
+        if !packages.is_empty() {
+            outln!(out, "{}", title);
+            for (recipe_id, package_id, size) in packages {
+                outln!(
+                    out,
+                    "  {}:{} ({})",
+                    recipe_id,
+                    package_id,
+                    format_size(*size)
+                );
+            }
+        }
+    };
+    print_recipes("Added recipes:", &diff.added_recipes);
+    print_recipes("Removed recipes:", &diff.removed_recipes);
+    print_packages("Added packages:", &diff.added_packages);
+    print_packages("Removed packages:", &diff.removed_packages);
+
+    let changed_names: Vec<_> = diff
+        .size_per_name
+        .iter()
+        .filter(|(_, (old_size, new_size))| old_size != new_size)
+        .collect();
+    if !changed_names.is_empty() {
+        outln!(out, "Size per package name:");
+        let name_width = changed_names
+            .iter()
+            .map(|(name, _)| name.len())
+            .max()
+            .unwrap_or(0);
+        for (name, (old_size, new_size)) in changed_names {
+            outln!(
+                out,
+                "  {:width$}  {:>10} -> {:>10}  ({})",
+                name,
+                format_size(*old_size),
+                format_size(*new_size),
+                format_size_change(*old_size, *new_size),
+                width = name_width
+            );
         }
     }
+
+    for (from, to) in &diff.probably_moved {
+        outln!(
+            out,
+            "Note: '{}' was probably moved to '{}', they share the name, version and package IDs.",
+            from,
+            to
+        );
+    }
+
+    let (old_total, new_total) = diff.total_sizes();
+    outln!(
+        out,
+        "Total: {} -> {} ({})",
+        format_size(old_total),
+        format_size(new_total),
+        format_size_change(old_total, new_total)
+    );
+    Ok(())
 }
 
-impl From<std::io::Error> for ConanJsonError {
-    fn from(err: std::io::Error) -> ConanJsonError {
-        ConanJsonError::Io(err)
+fn format_size_change(old_size: u64, new_size: u64) -> String {
+    if new_size >= old_size {
+        format!("+{}", format_size(new_size - old_size))
+    } else {
+        format!("-{}", format_size(old_size - new_size))
     }
 }
 
-impl From<serde_json::Error> for ConanJsonError {
-    fn from(err: serde_json::Error) -> ConanJsonError {
-        ConanJsonError::Json(err)
+fn validate_size(value: String) -> Result<(), String> {
+    match parse_size(&value) {
+        Some(_) => Ok(()),
+        None => Err(format!(
+            "'{}' is not a valid size (expected e.g. '512K', '1M' or '5G')",
+            value
+        )),
     }
 }
 
-fn temp_json_file_path() -> std::path::PathBuf {
-    let mut temp_dir = std::env::temp_dir();
-    temp_dir.push("conan_search_result");
-    temp_dir.set_extension("json");
-    temp_dir
+fn validate_exception(value: String) -> Result<(), String> {
+    match plan::Exception::parse(&value) {
+        Some(_) => Ok(()),
+        None => Err(format!(
+            "'{}' is no package reference of the form <reference>:<package ID>",
+            value
+        )),
+    }
 }
 
-fn get_yes_or_no() -> bool {
-    loop {
-        let mut answer = String::new();
-        if let Err(err) = std::io::stdin().read_line(&mut answer) {
-            eprintln!("Failed to read answer from stdin: {}", err);
-            std::process::exit(-1)
-        }
+fn validate_usage_pattern(value: String) -> Result<(), String> {
+    usage_pattern::UsagePattern::parse(&value).map(|_| ())
+}
 
-        match answer.trim() {
-            "Yes" | "yes" | "y" | "Y" => return true,
-            "No" | "no" | "n" | "N" => return false,
-            _ => println!("yes/no?"),
-        }
+fn validate_count(value: String) -> Result<(), String> {
+    value
+        .parse::<usize>()
+        .map(|_| ())
+        .map_err(|_| format!("'{}' is not a valid count", value))
+}
+
+fn validate_io_budget(value: String) -> Result<(), String> {
+    match value.parse::<f64>() {
+        Ok(budget) if budget > 0.0 && budget.is_finite() => Ok(()),
+        _ => Err(format!(
+            "'{}' is not a positive number of MB per second",
+            value
+        )),
+    }
+}
+
+fn validate_fraction(value: String) -> Result<(), String> {
+    match value.parse::<f64>() {
+        Ok(fraction) if (0.0..=1.0).contains(&fraction) => Ok(()),
+        _ => Err(format!("'{}' is not a fraction between 0 and 1", value)),
+    }
+}
+
+fn validate_chunk_size(value: String) -> Result<(), String> {
+    match value.parse::<usize>() {
+        Ok(chunk_size) if chunk_size > 0 => Ok(()),
+        _ => Err(format!("'{}' is not a valid number of packages", value)),
+    }
+}
+
+fn validate_job_count(value: String) -> Result<(), String> {
+    match value.parse::<usize>() {
+        Ok(jobs) if jobs > 0 => Ok(()),
+        _ => Err(format!("'{}' is not a valid number of jobs", value)),
     }
 }
@@ -0,0 +1,68 @@
+//! Cleans up after and checks files written by other versions of the tool before they are used.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Name of the search result file in the temporary directory used by version 0.1, which was
+/// never removed if a run was aborted.
+const LEGACY_TEMP_FILE_NAME: &str = "conan_search_result.json";
+
+/// Runs all migrations. Fails only if a file was written by a newer version of the tool, since
+/// using or overwriting it could lose information.
+pub fn run(state_path: Option<&Path>) -> Result<(), MigrateError> {
+    remove_legacy_temp_file();
+    if let Some(state_path) = state_path {
+        check_state_file(state_path)?;
+    }
+    Ok(())
+}
+
+fn remove_legacy_temp_file() {
+    let path = std::env::temp_dir().join(LEGACY_TEMP_FILE_NAME);
+    if crate::fsutil::remove_file(&path).is_ok() {
+        debug!(
+            "Removed temporary file '{}' left behind by a previous version.",
+            path.display()
+        );
+    }
+}
+
+/// Checks that the state file, if any, is in a format this version understands.
+fn check_state_file(path: &Path) -> Result<(), MigrateError> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        // Unreadable files are reported when the state is loaded.
+        Err(_) => return Ok(()),
+    };
+    let version = serde_json::from_str::<serde_json::Value>(&content)
+        .ok()
+        .and_then(|json| json["version"].as_u64());
+
+    match version {
+        Some(version) if version > crate::state::STATE_VERSION => {
+            Err(MigrateError::NewerVersion(path.to_path_buf(), version))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[derive(Debug)]
+pub enum MigrateError {
+    /// The file at the given path has a version newer than this tool supports.
+    NewerVersion(PathBuf, u64),
+}
+
+impl fmt::Display for MigrateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MigrateError::NewerVersion(ref path, version) => write!(
+                f,
+                "'{}' was written by a newer version of conan_cleanup (format version {}). Please update conan_cleanup.",
+                path.display(),
+                version
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MigrateError {}
@@ -0,0 +1,132 @@
+//! Desktop notifications for runs that are left unattended. Notifications are shown with the
+//! tools of the platform (`notify-send`, `osascript` or PowerShell) if the `notifications` feature
+//! is enabled. Failing to show a notification never affects the run, it is only reported with
+//! `--verbose`.
+
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "notifications")]
+const TITLE: &str = "conan_cleanup";
+/// Time without interaction after which a prompt is worth a notification.
+const PROMPT_NOTIFICATION_DELAY: Duration = Duration::from_secs(30);
+
+/// Notifies that the run waits for an answer, unless the user interacted with the tool recently
+/// and is probably still watching. Notifications are only shown with `Settings::notify`.
+pub fn waiting_for_input() {
+    let session = crate::session::current();
+    if !session.settings().notify {
+        return;
+    }
+    let idle = session
+        .last_interaction
+        .lock()
+        .unwrap()
+        .is_none_or(|last_interaction| last_interaction.elapsed() > PROMPT_NOTIFICATION_DELAY);
+    if idle {
+        send(&waiting_message());
+    }
+}
+
+/// Records that the user just answered a prompt.
+pub fn input_received() {
+    *crate::session::current().last_interaction.lock().unwrap() = Some(Instant::now());
+}
+
+/// Notifies that the run finished.
+pub fn run_finished(freed_bytes: u64, failure_count: usize, dry_run: bool) {
+    if crate::session::current().settings().notify {
+        send(&finished_message(freed_bytes, failure_count, dry_run));
+    }
+}
+
+fn waiting_message() -> String {
+    "Waiting for confirmation to remove packages.".to_owned()
+}
+
+/// Returns the body of the notification sent when the run finished, e.g. `Freed 1.4 GB.` or
+/// `Freed 12.0 MB, failed to remove 2 recipes.`.
+pub fn finished_message(freed_bytes: u64, failure_count: usize, dry_run: bool) -> String {
+    if dry_run {
+        return "Dry run finished, nothing removed.".to_owned();
+    }
+    let freed = format!("Freed {}", crate::size::format_size(freed_bytes));
+    match failure_count {
+        0 => format!("{}.", freed),
+        1 => format!("{}, failed to remove 1 recipe.", freed),
+        count => format!("{}, failed to remove {} recipes.", freed, count),
+    }
+}
+
+#[cfg(feature = "notifications")]
+fn send(body: &str) {
+    if let Err(err) = deliver(body) {
+        if crate::subprocess::is_verbose() {
+            eprintln!("Failed to show desktop notification: {}", err);
+        }
+    }
+}
+
+#[cfg(not(feature = "notifications"))]
+fn send(_body: &str) {
+    if crate::subprocess::is_verbose() {
+        eprintln!("Desktop notifications are not supported by this build.");
+    }
+}
+
+#[cfg(all(feature = "notifications", target_os = "macos"))]
+fn deliver(body: &str) -> Result<(), String> {
+    let quote = |text: &str| format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""));
+    let script = format!(
+        "display notification {} with title {}",
+        quote(body),
+        quote(TITLE)
+    );
+    wait_for(quiet_command("osascript").args(["-e", &script]))
+}
+
+#[cfg(all(feature = "notifications", windows))]
+fn deliver(body: &str) -> Result<(), String> {
+    let quote = |text: &str| format!("'{}'", text.replace('\'', "''"));
+    // A balloon tip works without any modules that would have to be installed first.
+    let script = format!(
+        "Add-Type -AssemblyName System.Windows.Forms; \
+         $icon = New-Object System.Windows.Forms.NotifyIcon; \
+         $icon.Icon = [System.Drawing.SystemIcons]::Information; \
+         $icon.Visible = $true; \
+         $icon.ShowBalloonTip(10000, {}, {}, 'Info'); \
+         Start-Sleep -Seconds 10; \
+         $icon.Dispose()",
+        quote(TITLE),
+        quote(body)
+    );
+    // The balloon tip disappears with the PowerShell process, so it is not waited for.
+    quiet_command("powershell")
+        .args(["-NoProfile", "-NonInteractive", "-Command", &script])
+        .spawn()
+        .map(|_| ())
+        .map_err(|err| err.to_string())
+}
+
+#[cfg(all(feature = "notifications", unix, not(target_os = "macos")))]
+fn deliver(body: &str) -> Result<(), String> {
+    wait_for(quiet_command("notify-send").args([TITLE, body]))
+}
+
+#[cfg(feature = "notifications")]
+fn quiet_command(program: &str) -> std::process::Command {
+    let mut command = std::process::Command::new(program);
+    command
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null());
+    command
+}
+
+#[cfg(all(feature = "notifications", not(windows)))]
+fn wait_for(command: &mut std::process::Command) -> Result<(), String> {
+    match command.status() {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(status.to_string()),
+        Err(err) => Err(err.to_string()),
+    }
+}
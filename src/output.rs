@@ -0,0 +1,61 @@
+//! Destination of the human-readable output of a run. With `--output json`, stdout only carries the
+//! JSON document printed at the end of the run, so the messages go to stderr instead.
+
+use crate::error::CleanupError;
+use crate::review::ChunkAnswer;
+
+use std::cell::RefCell;
+use std::fmt;
+use std::io::Write;
+
+/// Writes the lines of a run to stdout, stderr or any other writer.
+pub struct Output {
+    writer: RefCell<Box<dyn Write>>,
+    json: bool,
+}
+
+impl Output {
+    /// Returns the output of a run that prints its result as JSON document if `json` is set.
+    pub fn new(json: bool) -> Output {
+        let writer: Box<dyn Write> = if json {
+            Box::new(std::io::stderr())
+        } else {
+            Box::new(std::io::stdout())
+        };
+        Output {
+            writer: RefCell::new(writer),
+            json,
+        }
+    }
+
+    /// Returns an output that writes to `writer`, e.g. to collect the lines in memory.
+    pub fn to_writer(writer: Box<dyn Write>, json: bool) -> Output {
+        Output {
+            writer: RefCell::new(writer),
+            json,
+        }
+    }
+
+    /// Returns whether the result of the run is printed as JSON document.
+    pub fn is_json(&self) -> bool {
+        self.json
+    }
+
+    /// Writes a line and flushes it, so that prompts show before the answer is read. Failures to
+    /// write, e.g. to a closed pipe, are ignored rather than aborting the run.
+    pub fn line(&self, args: fmt::Arguments) {
+        let mut writer = self.writer.borrow_mut();
+        let _ = writeln!(writer, "{}", args);
+        let _ = writer.flush();
+    }
+}
+
+/// Answers the questions of a run, like whether to remove the packages listed. The question is
+/// printed to the output before asking for the answer.
+pub trait Prompt {
+    /// Asks a question that is answered with yes or no.
+    fn yes_or_no(&mut self, out: &Output) -> Result<bool, CleanupError>;
+
+    /// Asks whether to remove the packages of a chunk of `--review-chunk`.
+    fn chunk_answer(&mut self, out: &Output) -> Result<ChunkAnswer, CleanupError>;
+}
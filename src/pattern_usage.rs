@@ -0,0 +1,167 @@
+//! Match counts of the recipe patterns of the budgets, `--include`, `--exclude` and the keep file
+//! against the recipes of the cache. A pattern that matches no recipe is dead: it was made for
+//! recipes that are gone or it has a typo, which silently drops the rule it was meant to give.
+
+use crate::reference;
+
+use std::fmt;
+use std::path::PathBuf;
+
+/// Where a recipe pattern was given.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatternSource {
+    /// A `[budget.<pattern>]` section of the configuration file.
+    Budget,
+    Include,
+    Exclude,
+    /// A keep file and the line number of the pattern in it, counting from 1.
+    KeepFile(PathBuf, usize),
+}
+
+impl PatternSource {
+    /// Returns the kind of the source for JSON output.
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            PatternSource::Budget => "budget",
+            PatternSource::Include => "include",
+            PatternSource::Exclude => "exclude",
+            PatternSource::KeepFile(..) => "keep_file",
+        }
+    }
+}
+
+impl fmt::Display for PatternSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PatternSource::Budget => write!(f, "the budgets of conan_cleanup.conf"),
+            PatternSource::Include => write!(f, "--include"),
+            PatternSource::Exclude => write!(f, "--exclude"),
+            PatternSource::KeepFile(ref path, line) => write!(f, "{}:{}", path.display(), line),
+        }
+    }
+}
+
+/// A recipe pattern and where it was given.
+pub type RecipePattern = (String, PatternSource);
+
+/// A recipe pattern and the number of recipes it matches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatternUsage {
+    pub pattern: String,
+    pub source: PatternSource,
+    pub matches: usize,
+}
+
+impl PatternUsage {
+    pub fn is_dead(&self) -> bool {
+        self.matches == 0
+    }
+}
+
+/// Counts the recipes each pattern matches, see `reference::matches_pattern`. The patterns keep
+/// their order, a pattern given twice is counted twice.
+pub fn count_matches(patterns: Vec<RecipePattern>, recipe_ids: &[String]) -> Vec<PatternUsage> {
+    patterns
+        .into_iter()
+        .map(|(pattern, source)| {
+            let matches = recipe_ids
+                .iter()
+                .filter(|recipe_id| reference::matches_pattern(&pattern, recipe_id))
+                .count();
+            PatternUsage {
+                pattern,
+                source,
+                matches,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recipe_ids() -> Vec<String> {
+        [
+            "boost/1.70.0",
+            "boost/1.75.0",
+            "qt/5.15.2@corp/stable",
+            "zlib/1.2.11@conan/stable",
+        ]
+        .iter()
+        .map(|recipe_id| recipe_id.to_string())
+        .collect()
+    }
+
+    fn count(pattern: &str) -> usize {
+        count_matches(
+            vec![(pattern.to_owned(), PatternSource::Include)],
+            &recipe_ids(),
+        )[0]
+        .matches
+    }
+
+    #[test]
+    fn pattern_matching_no_recipe_is_dead() {
+        let usages = count_matches(
+            vec![(
+                "toolchian/*".to_owned(),
+                PatternSource::KeepFile("keep.txt".into(), 3),
+            )],
+            &recipe_ids(),
+        );
+
+        assert_eq!(usages[0].matches, 0);
+        assert!(usages[0].is_dead());
+        assert_eq!(usages[0].source.to_string(), "keep.txt:3");
+        assert_eq!(count("*/*@corp/unstable"), 0);
+    }
+
+    #[test]
+    fn pattern_matching_one_recipe() {
+        assert_eq!(count("qt/*"), 1);
+        assert_eq!(count("zlib/1.2.11@conan/stable"), 1);
+        assert_eq!(count("boost/1.70.0"), 1);
+    }
+
+    #[test]
+    fn pattern_matching_many_recipes() {
+        assert_eq!(count("boost/*"), 2);
+        assert_eq!(count("*"), 4);
+        assert_eq!(count("*/*@*/stable"), 2);
+    }
+
+    #[test]
+    fn patterns_keep_their_order_and_sources() {
+        let usages = count_matches(
+            vec![
+                ("boost/*".to_owned(), PatternSource::Exclude),
+                ("fmt/*".to_owned(), PatternSource::Include),
+            ],
+            &recipe_ids(),
+        );
+
+        assert_eq!(
+            usages
+                .iter()
+                .map(|usage| (usage.pattern.as_str(), usage.source.kind(), usage.matches))
+                .collect::<Vec<_>>(),
+            [("boost/*", "exclude", 2), ("fmt/*", "include", 0)]
+        );
+    }
+
+    #[test]
+    fn budget_patterns_are_counted() {
+        let usages = count_matches(
+            vec![("qt/*".to_owned(), PatternSource::Budget)],
+            &recipe_ids(),
+        );
+
+        assert_eq!(usages[0].matches, 1);
+        assert_eq!(usages[0].source.kind(), "budget");
+        assert_eq!(
+            usages[0].source.to_string(),
+            "the budgets of conan_cleanup.conf"
+        );
+    }
+}
@@ -0,0 +1,1675 @@
+//! The plan of which packages to remove. `compute` applies the rules of `PlanOptions` to the
+//! packages of the cache and the usage found by `scan`, and records for every package it keeps
+//! which rule kept it. The binary only prints and executes the plan.
+
+use crate::cancel::CancellationToken;
+use crate::error::CleanupError;
+use crate::lockfile::LockedPackage;
+use crate::plan_hash::plan_hash;
+use crate::risk::RiskReason;
+use crate::scan::{PackageKey, PackagesInUse, UsageFile};
+use crate::state::{PackageLabel, HEADER_ONLY_LABEL};
+use crate::text_file::TextFileError;
+use crate::{cache, duration, keep_file, profile, reference, reference_lint, wildcard};
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// The rules that keep packages although they are not in use, or remove packages although they
+/// are. The defaults are the ones of the binary without any option.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PlanOptions {
+    /// Patterns of the recipes to consider, `None` considers all recipes.
+    pub include: Option<Vec<String>>,
+    /// Patterns of the recipes to leave alone, which win over `include`.
+    pub exclude: Vec<String>,
+    /// Patterns of the recipes whose packages are never removed, see `keep_file`.
+    pub keep_patterns: Vec<String>,
+    /// Keeps all packages of the given number of highest versions of every name and user/channel.
+    pub keep_latest: Option<usize>,
+    /// Keeps the given number of most recently written unused packages of every name, among the
+    /// versions not kept by `keep_latest`.
+    pub preserve_newest_per_name: Option<usize>,
+    /// Only removes unused packages not written to the cache within this duration.
+    pub older_than: Option<Duration>,
+    /// Where `older_than` and `preserve_newest_per_name` take the time a package was written from.
+    pub age_source: AgeSource,
+    /// Removes unused packages whose age cannot be told with `older_than` instead of keeping them.
+    pub remove_unknown_age: bool,
+    /// Patterns of consumer paths. If set, only packages all of whose consumers match are removed,
+    /// as if those projects were gone.
+    pub only_consumed_by: Option<Vec<String>>,
+    /// Patterns of consumer paths whose packages are kept despite `only_consumed_by`.
+    pub not_consumed_by: Vec<String>,
+    /// Removes packages only used by failed builds instead of keeping them.
+    pub remove_weakly_protected: bool,
+    /// Settings of the profile packages have to be compatible with to be kept by the rules for
+    /// header-only and newest packages.
+    pub profile: Option<BTreeMap<String, String>>,
+    /// Removes incompatible packages although a rule would keep them.
+    pub remove_incompatible: bool,
+    /// Keeps unused header-only packages.
+    pub skip_header_only: bool,
+    /// Packages without settings are only header-only if their folder is not larger than this.
+    pub header_only_max_size: u64,
+    /// Removes unused recent local builds instead of keeping them.
+    pub include_recent_local: bool,
+    /// Packages created within this duration whose recipe was not retrieved from any remote are
+    /// recent local builds.
+    pub recent_local_age: Duration,
+    /// Caps on the binaries of every package name, applied after all other rules.
+    pub budgets: Vec<Budget>,
+}
+
+impl Default for PlanOptions {
+    fn default() -> PlanOptions {
+        PlanOptions {
+            include: None,
+            exclude: Vec::new(),
+            keep_patterns: Vec::new(),
+            keep_latest: None,
+            preserve_newest_per_name: None,
+            older_than: None,
+            age_source: AgeSource::Mtime,
+            remove_unknown_age: false,
+            only_consumed_by: None,
+            not_consumed_by: Vec::new(),
+            remove_weakly_protected: false,
+            profile: None,
+            remove_incompatible: false,
+            skip_header_only: false,
+            header_only_max_size: 1024 * 1024,
+            include_recent_local: false,
+            recent_local_age: Duration::from_secs(7 * 24 * 60 * 60),
+            budgets: Vec::new(),
+        }
+    }
+}
+
+impl PlanOptions {
+    /// Returns whether the recipe matches any of the `exclude` patterns.
+    pub fn is_excluded(&self, recipe_id: &str) -> bool {
+        matches_any_reference(&self.exclude, recipe_id)
+    }
+
+    /// Returns whether the recipe is considered at all. Exclusion wins over inclusion.
+    pub fn is_included(&self, recipe_id: &str) -> bool {
+        !self.is_excluded(recipe_id)
+            && self
+                .include
+                .as_ref()
+                .is_none_or(|patterns| matches_any_reference(patterns, recipe_id))
+    }
+}
+
+fn matches_any_reference(patterns: &[String], recipe_id: &str) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| reference::matches_pattern(pattern, recipe_id))
+}
+
+/// A cap on the binaries of every package name of the recipes matching a pattern. When a name
+/// exceeds it, its oldest unused binaries are removed until it no longer does, even if another rule
+/// would keep them. Packages in use are never removed for a budget.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Budget {
+    /// Pattern of the recipes, like `qt/*`.
+    pub pattern: String,
+    /// The maximum number of binaries of a name.
+    pub max_binaries: Option<usize>,
+    /// The maximum total size of the binaries of a name.
+    pub max_bytes: Option<u64>,
+}
+
+/// What the scanned projects use.
+#[derive(Debug, Default)]
+pub struct Usage {
+    pub packages_in_use: PackagesInUse,
+    /// Paths of the usage files requiring each package, relative to their scan root.
+    pub consumers: HashMap<PackageKey, Vec<String>>,
+    /// Packages only required by usage files of failed builds.
+    pub weakly_protected: HashSet<PackageKey>,
+    /// Whether the packages in use are the ones pinned by lockfiles rather than the ones required
+    /// by usage files.
+    pub strict: bool,
+}
+
+impl Usage {
+    /// Collects the packages used by the given usage files, found below the given root paths, and
+    /// pinned by the given lockfiles, see `--strict-lockfiles`.
+    pub fn new(
+        usage_files: &[UsageFile],
+        root_paths: &[&str],
+        locked_packages: Option<&[LockedPackage]>,
+    ) -> Usage {
+        let mut consumers: HashMap<PackageKey, Vec<String>> = HashMap::new();
+        for usage_file in usage_files {
+            let relative_path = root_paths
+                .iter()
+                .find_map(|root_path| usage_file.path.strip_prefix(root_path).ok())
+                .unwrap_or(&usage_file.path)
+                .to_string_lossy()
+                .replace('\\', "/");
+            for (recipe_id, package_id) in &usage_file.packages {
+                consumers
+                    .entry(PackageKey::new(recipe_id, package_id))
+                    .or_default()
+                    .push(relative_path.clone());
+            }
+        }
+
+        let strongly_used_packages: HashSet<PackageKey> = usage_files
+            .iter()
+            .filter(|usage_file| usage_file.failed_build.is_none())
+            .flat_map(|usage_file| &usage_file.packages)
+            .map(|(recipe_id, package_id)| PackageKey::new(recipe_id, package_id))
+            .collect();
+        let weakly_protected = usage_files
+            .iter()
+            .flat_map(|usage_file| &usage_file.packages)
+            .map(|(recipe_id, package_id)| PackageKey::new(recipe_id, package_id))
+            .filter(|package| !strongly_used_packages.contains(package))
+            .collect();
+
+        Usage {
+            packages_in_use: crate::scan::find_packages_in_use(
+                usage_files,
+                locked_packages.unwrap_or_default(),
+            ),
+            consumers,
+            weakly_protected,
+            strict: locked_packages.is_some(),
+        }
+    }
+}
+
+/// Where the time a package was written to the cache is taken from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AgeSource {
+    /// The timestamp conan records in the conanmanifest.txt of the package when packaging it, which
+    /// survives copying the cache.
+    Manifest,
+    /// The modification time of the package folder.
+    Mtime,
+    /// The later of both.
+    Newest,
+}
+
+impl AgeSource {
+    /// Parses the value of `--age-source`.
+    pub fn parse(value: &str) -> Option<AgeSource> {
+        match value {
+            "manifest" => Some(AgeSource::Manifest),
+            "mtime" => Some(AgeSource::Mtime),
+            "newest" => Some(AgeSource::Newest),
+            _ => None,
+        }
+    }
+}
+
+/// The contents of the cache to plan the removal for.
+pub struct Cache<'a> {
+    /// The storage folder of the cache, if known. Rules that need the package folders, like
+    /// `older_than`, treat the packages as if their folders could not be found without it.
+    pub storage_path: Option<&'a Path>,
+    /// References of all recipes of the cache.
+    pub recipe_ids: &'a [String],
+    /// Package IDs of the included recipes.
+    pub packages: &'a BTreeMap<String, Vec<String>>,
+    /// Settings of the packages known from searching them. Settings of other packages are read
+    /// from the cache.
+    pub package_settings: &'a HashMap<(String, String), BTreeMap<String, String>>,
+    /// Labels of the packages by recipe and package ID, which tell header-only packages.
+    pub labels: &'a HashMap<String, HashMap<String, PackageLabel>>,
+    /// Whether the whole cache is read-only. Otherwise packages that physically reside in a
+    /// read-only layer of the cache are kept.
+    pub read_only: bool,
+    /// Recipes served by a local-recipes-index remote of conan 2, which are exported again whenever
+    /// they are used, so they are never removed. Their packages are.
+    pub local_index_recipes: &'a HashSet<String>,
+}
+
+/// The rule that keeps a package.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeepReason {
+    /// Its recipe is pinned without package ID by a lockfile, so all its packages are in use.
+    PinnedRecipe,
+    /// Its recipe matches a pattern of the keep file.
+    KeepFile,
+    /// Its recipe is among the given number of highest versions of its name.
+    Latest(usize),
+    /// It is required by the usage files at the given paths, if known.
+    InUse(Vec<String>),
+    /// It is pinned by a lockfile of `--strict-lockfiles`.
+    PinnedByLockfile,
+    /// It is in use by projects other than the ones of `only_consumed_by`.
+    NotConsumedBySelected,
+    /// It is only used by failed builds.
+    WeaklyProtected,
+    RecentLocalBuild,
+    /// It was modified the given time ago, within `older_than`.
+    TooYoung(Duration),
+    /// Its age cannot be told for `older_than`.
+    UnknownAge,
+    HeaderOnly,
+    NewestOfName,
+    /// Its folder resides in a read-only layer of the cache, see `cache::is_in_writable_layer`.
+    ReadOnlyLayer,
+}
+
+impl KeepReason {
+    /// Returns whether the package is kept since projects use it, in which case no budget removes
+    /// it. Neither does a budget remove packages of the keep file or of a read-only layer.
+    fn protects_from_budget(&self) -> bool {
+        match self {
+            KeepReason::PinnedRecipe
+            | KeepReason::KeepFile
+            | KeepReason::InUse(_)
+            | KeepReason::PinnedByLockfile
+            | KeepReason::NotConsumedBySelected
+            | KeepReason::WeaklyProtected
+            | KeepReason::ReadOnlyLayer => true,
+            KeepReason::Latest(_)
+            | KeepReason::RecentLocalBuild
+            | KeepReason::TooYoung(_)
+            | KeepReason::UnknownAge
+            | KeepReason::HeaderOnly
+            | KeepReason::NewestOfName => false,
+        }
+    }
+
+    /// Returns the rule without the details of the package, to count the packages per rule.
+    pub fn rule(&self) -> String {
+        match self {
+            KeepReason::InUse(_) => "in use".to_owned(),
+            reason => reason.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for KeepReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeepReason::PinnedRecipe => write!(f, "recipe pinned by a lockfile"),
+            KeepReason::KeepFile => write!(f, "listed in the keep file"),
+            KeepReason::Latest(count) => write!(f, "kept (latest {})", count),
+            KeepReason::InUse(paths) if paths.is_empty() => write!(f, "in use"),
+            KeepReason::InUse(paths) => write!(f, "in use by {}", paths.join(", ")),
+            KeepReason::PinnedByLockfile => write!(f, "pinned by a lockfile"),
+            KeepReason::NotConsumedBySelected => {
+                write!(f, "not consumed by the selected projects")
+            }
+            KeepReason::WeaklyProtected => write!(f, "weakly protected"),
+            KeepReason::RecentLocalBuild => write!(f, "recent local build"),
+            KeepReason::TooYoung(age) => {
+                write!(f, "modified {} ago", duration::format_duration(*age))
+            }
+            KeepReason::UnknownAge => write!(f, "age unknown"),
+            KeepReason::HeaderOnly => write!(f, "header-only"),
+            KeepReason::NewestOfName => write!(f, "newest of its name"),
+            KeepReason::ReadOnlyLayer => write!(f, "in a read-only layer of the cache"),
+        }
+    }
+}
+
+/// Packages to remove and packages kept, as package IDs per recipe reference, together with what
+/// the rules found out about them. Packages are identified by recipe reference and package ID.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct RemovalPlan {
+    pub packages: BTreeMap<String, Vec<String>>,
+    /// Packages kept although they exist in the cache, together with the rule keeping them.
+    pub kept: BTreeMap<String, Vec<(String, KeepReason)>>,
+    /// Recipes kept as a whole by the keep file, `keep_latest` or since they are served by a
+    /// local-recipes-index remote, which must not be removed even if they have no packages left.
+    pub kept_recipes: BTreeSet<String>,
+    /// Why packages are removed although a rule would keep them, e.g. since they are only used by
+    /// failed builds or exceed a budget.
+    pub notes: HashMap<(String, String), String>,
+    /// Rules that put a package into the plan although something speaks for keeping it.
+    pub risks: HashMap<(String, String), Vec<RiskReason>>,
+    /// Unused packages incompatible with the profile.
+    pub incompatible: HashSet<(String, String)>,
+    /// Packages only removed since they are incompatible with the profile, which a rule would
+    /// keep otherwise.
+    pub incompatible_removals: HashSet<(String, String)>,
+    /// Header-only packages to remove.
+    pub header_only: HashSet<(String, String)>,
+    /// Ages of the packages to remove, only determined with `older_than`.
+    pub ages: HashMap<(String, String), Duration>,
+    /// Packages whose age was taken from the modification time of their folder instead since
+    /// their conanmanifest.txt could not be read, see `AgeSource`.
+    pub age_fallbacks: BTreeSet<(String, String)>,
+    /// Number of packages not in use, whether they are removed or kept by a rule.
+    pub unused_count: usize,
+    /// Number of unused packages kept by the keep file.
+    pub kept_by_keep_file_count: usize,
+    /// Budgets that cannot be met without removing packages in use, by pattern and package name.
+    pub unmet_budgets: BTreeSet<(String, String)>,
+}
+
+impl RemovalPlan {
+    /// Returns the hash of the packages to remove, see `plan_hash`.
+    pub fn hash(&self) -> String {
+        plan_hash(self.packages.iter().map(|(recipe_id, package_ids)| {
+            (
+                recipe_id.as_str(),
+                package_ids.iter().map(String::as_str).collect(),
+            )
+        }))
+    }
+
+    /// Returns the number of packages kept by the rules `is_rule` holds for.
+    pub fn kept_count(&self, is_rule: impl Fn(&KeepReason) -> bool) -> usize {
+        self.kept
+            .values()
+            .flatten()
+            .filter(|(_, reason)| is_rule(reason))
+            .count()
+    }
+
+    /// Removes the packages matching any of the exceptions from the plan and returns how many
+    /// were removed. Fails without changing the plan if an exception matches no package of the
+    /// plan, which is most likely a typo.
+    pub fn apply_exceptions(&mut self, exceptions: &[Exception]) -> Result<usize, CleanupError> {
+        let unmatched: Vec<_> = exceptions
+            .iter()
+            .filter(|exception| {
+                !self.packages.iter().any(|(recipe_id, package_ids)| {
+                    package_ids
+                        .iter()
+                        .any(|package_id| exception.matches(recipe_id, package_id))
+                })
+            })
+            .map(|exception| exception.text.as_str())
+            .collect();
+        if !unmatched.is_empty() {
+            return Err(CleanupError::InvalidArguments(format!(
+                "These exceptions match no package of the plan: {}",
+                unmatched.join(", ")
+            )));
+        }
+
+        let mut excepted_count = 0;
+        for (recipe_id, package_ids) in self.packages.iter_mut() {
+            package_ids.retain(|package_id| {
+                let excepted = exceptions
+                    .iter()
+                    .any(|exception| exception.matches(recipe_id, package_id));
+                if excepted {
+                    excepted_count += 1;
+                }
+                !excepted
+            });
+        }
+        self.packages
+            .retain(|_, package_ids| !package_ids.is_empty());
+        Ok(excepted_count)
+    }
+}
+
+/// Plans the removal of the packages of the cache that are not in use, applying the rules of the
+/// options. Packages of recipes that are not included are neither removed nor kept. Fails with
+/// `CleanupError::Cancelled` once `token` is cancelled.
+pub fn compute(
+    usage: &Usage,
+    cache: &Cache,
+    options: &PlanOptions,
+    token: &CancellationToken,
+) -> Result<RemovalPlan, CleanupError> {
+    let mut plan = RemovalPlan::default();
+    let packages_in_use = &usage.packages_in_use;
+    // The latest versions of a name are determined among all recipes of the cache, so that
+    // excluding the newest versions does not make older ones the latest.
+    let latest_recipes = match options.keep_latest {
+        Some(count) => latest_recipes_per_name(cache.recipe_ids, count),
+        None => HashSet::new(),
+    };
+    // `keep_latest` applies first: the newest packages of a name are preserved among the packages
+    // of the versions it does not keep anyway.
+    let preserved_packages = match (options.preserve_newest_per_name, cache.storage_path) {
+        (Some(count), Some(storage_path)) => newest_packages_per_name(
+            storage_path,
+            cache.packages,
+            &latest_recipes,
+            count,
+            options.age_source,
+            &mut plan.age_fallbacks,
+        ),
+        _ => HashSet::new(),
+    };
+    // Without the storage folder the ages of the packages are unknown, so none of them can be told
+    // apart from the newest ones and all are kept instead.
+    let preserve_all = options.preserve_newest_per_name.is_some() && cache.storage_path.is_none();
+    if preserve_all {
+        warning!(
+            "Cannot find the storage folder of the cache to tell the newest packages of every name, keeping all unused packages due to --preserve-newest-per-name."
+        );
+    }
+    let now = SystemTime::now();
+
+    for (recipe_id, package_ids) in cache.packages {
+        if token.is_cancelled() {
+            return Err(CleanupError::Cancelled(Box::default()));
+        }
+        let kept = |plan: &mut RemovalPlan, package_id: &String, reason: KeepReason| {
+            plan.kept
+                .entry(recipe_id.clone())
+                .or_default()
+                .push((package_id.clone(), reason));
+        };
+        let is_latest = latest_recipes.contains(recipe_id.as_str());
+        let in_keep_file = keep_file::matches(&options.keep_patterns, recipe_id);
+        if is_latest || in_keep_file || cache.local_index_recipes.contains(recipe_id) {
+            plan.kept_recipes.insert(recipe_id.clone());
+        }
+        let mut package_ids_to_remove = Vec::new();
+        if packages_in_use.contains_recipe(recipe_id) {
+            for package_id in package_ids {
+                kept(&mut plan, package_id, KeepReason::PinnedRecipe);
+            }
+            continue;
+        }
+        if in_keep_file {
+            plan.kept_by_keep_file_count += package_ids
+                .iter()
+                .filter(|package_id| !packages_in_use.contains_package(recipe_id, package_id))
+                .count();
+            for package_id in package_ids {
+                let reason = if packages_in_use.contains_package(recipe_id, package_id) {
+                    in_use_reason(usage, recipe_id, package_id)
+                } else {
+                    KeepReason::KeepFile
+                };
+                kept(&mut plan, package_id, reason);
+            }
+            continue;
+        }
+        if let Some(count) = options.keep_latest.filter(|_| is_latest) {
+            // Being in use is the stronger reason to keep a package.
+            for package_id in package_ids {
+                let reason = if packages_in_use.contains_package(recipe_id, package_id) {
+                    in_use_reason(usage, recipe_id, package_id)
+                } else {
+                    KeepReason::Latest(count)
+                };
+                kept(&mut plan, package_id, reason);
+            }
+            continue;
+        }
+        for package_id in package_ids {
+            let key = (recipe_id.clone(), package_id.clone());
+            let package_key = PackageKey::new(recipe_id, package_id);
+            let in_use_reason = || in_use_reason(usage, recipe_id, package_id);
+            if let Some(ref only_consumed_by) = options.only_consumed_by {
+                // Only packages whose consumers all match are candidates, as if those projects
+                // were gone.
+                let paths = usage
+                    .consumers
+                    .get(&package_key)
+                    .map_or(&[][..], Vec::as_slice);
+                let matches_any = |patterns: &[String], path: &String| {
+                    patterns
+                        .iter()
+                        .any(|pattern| wildcard::matches(pattern, path))
+                };
+                if paths.is_empty()
+                    || !paths.iter().all(|path| matches_any(only_consumed_by, path))
+                    || paths
+                        .iter()
+                        .any(|path| matches_any(&options.not_consumed_by, path))
+                {
+                    let reason = if packages_in_use.contains_package(recipe_id, package_id) {
+                        in_use_reason()
+                    } else {
+                        KeepReason::NotConsumedBySelected
+                    };
+                    kept(&mut plan, package_id, reason);
+                    continue;
+                }
+                plan.notes.insert(
+                    key.clone(),
+                    format!("only consumed by {}", paths.join(", ")),
+                );
+                plan.risks
+                    .entry(key.clone())
+                    .or_default()
+                    .push(RiskReason::InUseBySelectedProjects);
+            } else if packages_in_use.contains_package(recipe_id, package_id) {
+                if !usage.weakly_protected.contains(&package_key) {
+                    kept(&mut plan, package_id, in_use_reason());
+                    continue;
+                }
+
+                if options.remove_weakly_protected {
+                    plan.notes
+                        .insert(key.clone(), "only used by failed builds".to_owned());
+                    plan.risks
+                        .entry(key.clone())
+                        .or_default()
+                        .push(RiskReason::WeaklyProtected);
+                } else {
+                    kept(&mut plan, package_id, KeepReason::WeaklyProtected);
+                    continue;
+                }
+            }
+            plan.unused_count += 1;
+
+            if let Some(storage_path) = cache.storage_path.filter(|_| !cache.read_only) {
+                if !cache::is_in_writable_layer(storage_path, recipe_id, package_id) {
+                    kept(&mut plan, package_id, KeepReason::ReadOnlyLayer);
+                    continue;
+                }
+            }
+
+            let incompatible = options.profile.as_ref().is_some_and(|profile| {
+                is_incompatible_package(
+                    profile,
+                    cache.package_settings,
+                    cache.storage_path,
+                    recipe_id,
+                    package_id,
+                )
+            });
+            if incompatible {
+                plan.incompatible.insert(key.clone());
+            }
+            let remove_anyway = incompatible && options.remove_incompatible;
+
+            let recent_local_build = cache.storage_path.is_some_and(|storage_path| {
+                is_recent_local_build(
+                    storage_path,
+                    recipe_id,
+                    package_id,
+                    options.recent_local_age,
+                    now,
+                )
+            });
+            if recent_local_build {
+                if !options.include_recent_local && !remove_anyway {
+                    kept(&mut plan, package_id, KeepReason::RecentLocalBuild);
+                    continue;
+                }
+                plan.risks
+                    .entry(key.clone())
+                    .or_default()
+                    .push(RiskReason::RecentLocalBuild);
+            }
+
+            if let Some(older_than) = options.older_than {
+                let age = cache
+                    .storage_path
+                    .and_then(|storage_path| {
+                        package_time(
+                            storage_path,
+                            recipe_id,
+                            package_id,
+                            options.age_source,
+                            &mut plan.age_fallbacks,
+                        )
+                    })
+                    .map(|modified| now.duration_since(modified).unwrap_or_default());
+                match age {
+                    Some(age) if age < older_than => {
+                        kept(&mut plan, package_id, KeepReason::TooYoung(age));
+                        continue;
+                    }
+                    Some(age) => {
+                        plan.ages.insert(key.clone(), age);
+                    }
+                    None if options.remove_unknown_age => warning!(
+                        "Cannot find the package folder of '{}:{}' to tell its age, removing it due to --older-than-unknown remove.",
+                        recipe_id,
+                        package_id
+                    ),
+                    None => {
+                        warning!(
+                            "Cannot find the package folder of '{}:{}' to tell its age, keeping it.",
+                            recipe_id,
+                            package_id
+                        );
+                        kept(&mut plan, package_id, KeepReason::UnknownAge);
+                        continue;
+                    }
+                }
+            }
+
+            if is_header_only_package(
+                cache.labels,
+                cache.storage_path,
+                recipe_id,
+                package_id,
+                options.header_only_max_size,
+            ) {
+                if options.skip_header_only && !remove_anyway {
+                    kept(&mut plan, package_id, KeepReason::HeaderOnly);
+                    continue;
+                }
+                plan.header_only.insert(key.clone());
+                if options.skip_header_only {
+                    plan.incompatible_removals.insert(key.clone());
+                    plan.risks
+                        .entry(key.clone())
+                        .or_default()
+                        .push(RiskReason::IncompatibleDespiteKeepRule);
+                }
+            }
+
+            if preserve_all
+                || preserved_packages.contains(&(recipe_id.as_str(), package_id.as_str()))
+            {
+                if remove_anyway {
+                    plan.incompatible_removals.insert(key.clone());
+                    plan.risks
+                        .entry(key)
+                        .or_default()
+                        .push(RiskReason::IncompatibleDespiteKeepRule);
+                    package_ids_to_remove.push(package_id.clone());
+                    continue;
+                }
+                let reason = if preserve_all {
+                    KeepReason::UnknownAge
+                } else {
+                    KeepReason::NewestOfName
+                };
+                kept(&mut plan, package_id, reason);
+            } else {
+                package_ids_to_remove.push(package_id.clone());
+            }
+        }
+
+        if !package_ids_to_remove.is_empty() {
+            debug_assert!(
+                package_ids_to_remove.iter().collect::<HashSet<_>>().len()
+                    == package_ids_to_remove.len(),
+                "a package of '{}' is planned for removal more than once",
+                recipe_id
+            );
+            plan.packages
+                .insert(recipe_id.clone(), package_ids_to_remove);
+        }
+    }
+
+    if !options.budgets.is_empty() {
+        match cache.storage_path {
+            Some(storage_path) => {
+                apply_budgets(&mut plan, storage_path, cache.packages, options, now, token)?
+            }
+            None => warning!(
+                "Cannot find the storage folder of the cache to tell the sizes and ages of the packages, ignoring the budgets."
+            ),
+        }
+    }
+    Ok(plan)
+}
+
+/// Adds the oldest unused packages of every name exceeding a budget to the plan until the name
+/// meets the budget, see `Budget`. Packages whose age cannot be told are considered the oldest, ties
+/// are removed in the order of their references. Budgets that cannot be met without removing
+/// packages in use are reported and recorded in `unmet_budgets`.
+fn apply_budgets(
+    plan: &mut RemovalPlan,
+    storage_path: &Path,
+    packages: &BTreeMap<String, Vec<String>>,
+    options: &PlanOptions,
+    now: SystemTime,
+    token: &CancellationToken,
+) -> Result<(), CleanupError> {
+    let mut sizes = HashMap::new();
+    for budget in &options.budgets {
+        let mut packages_by_name: BTreeMap<&str, Vec<(&String, &String)>> = BTreeMap::new();
+        for (recipe_id, package_ids) in packages
+            .iter()
+            .filter(|(recipe_id, _)| reference::matches_pattern(&budget.pattern, recipe_id))
+        {
+            let name = recipe_id.split('/').next().unwrap_or(recipe_id);
+            let entry = packages_by_name.entry(name).or_default();
+            entry.extend(package_ids.iter().map(|package_id| (recipe_id, package_id)));
+        }
+
+        for (name, name_packages) in packages_by_name {
+            if token.is_cancelled() {
+                return Err(CleanupError::Cancelled(Box::default()));
+            }
+            let is_removed = |plan: &RemovalPlan, recipe_id: &String, package_id: &String| {
+                plan.packages
+                    .get(recipe_id)
+                    .is_some_and(|package_ids| package_ids.contains(package_id))
+            };
+            let mut remaining: Vec<_> = name_packages
+                .into_iter()
+                .filter(|(recipe_id, package_id)| !is_removed(plan, recipe_id, package_id))
+                .collect();
+            let mut size = |recipe_id: &String, package_id: &String| -> u64 {
+                if budget.max_bytes.is_none() {
+                    return 0;
+                }
+                *sizes
+                    .entry((recipe_id.clone(), package_id.clone()))
+                    .or_insert_with(|| {
+                        cache::package_dir(storage_path, recipe_id, package_id)
+                            .map_or(0, |dir| cache::directory_size(&dir))
+                    })
+            };
+            let mut binaries = remaining.len();
+            let mut bytes: u64 = remaining
+                .iter()
+                .map(|(recipe_id, package_id)| size(recipe_id, package_id))
+                .sum();
+            let is_over = |binaries: usize, bytes: u64| {
+                budget.max_binaries.is_some_and(|max| binaries > max)
+                    || budget.max_bytes.is_some_and(|max| bytes > max)
+            };
+            if !is_over(binaries, bytes) {
+                continue;
+            }
+
+            remaining.retain(|(recipe_id, package_id)| {
+                plan.kept.get(*recipe_id).is_some_and(|kept| {
+                    kept.iter()
+                        .any(|(id, reason)| id == *package_id && !reason.protects_from_budget())
+                })
+            });
+            let mut candidates: Vec<_> = remaining
+                .into_iter()
+                .map(|(recipe_id, package_id)| {
+                    let time = package_time(
+                        storage_path,
+                        recipe_id,
+                        package_id,
+                        options.age_source,
+                        &mut plan.age_fallbacks,
+                    );
+                    (time, recipe_id, package_id)
+                })
+                .collect();
+            candidates.sort();
+
+            for (time, recipe_id, package_id) in candidates {
+                if !is_over(binaries, bytes) {
+                    break;
+                }
+                binaries -= 1;
+                bytes -= size(recipe_id, package_id);
+                let key = (recipe_id.clone(), package_id.clone());
+                if let Some(kept) = plan.kept.get_mut(recipe_id) {
+                    kept.retain(|(id, _)| id != package_id);
+                    if kept.is_empty() {
+                        plan.kept.remove(recipe_id);
+                    }
+                }
+                // In the order of the cache like the other packages of the plan.
+                let planned = plan.packages.entry(recipe_id.clone()).or_default();
+                planned.push(package_id.clone());
+                planned.sort_by_key(|id| packages[recipe_id].iter().position(|other| other == id));
+                if let Some(time) = time {
+                    plan.ages
+                        .insert(key.clone(), now.duration_since(time).unwrap_or_default());
+                }
+                plan.notes
+                    .insert(key, format!("over budget ({})", budget.pattern));
+            }
+            if is_over(binaries, bytes) {
+                warning!(
+                    "The binaries of '{}' exceed the budget of '{}' without removing packages in use, keeping those.",
+                    name,
+                    budget.pattern
+                );
+                plan.unmet_budgets
+                    .insert((budget.pattern.clone(), name.to_owned()));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns when the given package was written to the cache according to `source`. Packages without
+/// a readable conanmanifest.txt fall back to the modification time of their folder and are added
+/// to `fallbacks`.
+fn package_time(
+    storage_path: &Path,
+    recipe_id: &str,
+    package_id: &str,
+    source: AgeSource,
+    fallbacks: &mut BTreeSet<(String, String)>,
+) -> Option<SystemTime> {
+    let modified = || cache::package_timestamp(storage_path, recipe_id, package_id);
+    if source == AgeSource::Mtime {
+        return modified();
+    }
+    match cache::package_manifest_time(storage_path, recipe_id, package_id) {
+        Some(created) if source == AgeSource::Newest => {
+            Some(modified().map_or(created, |modified| modified.max(created)))
+        }
+        Some(created) => Some(created),
+        None => {
+            let modified = modified();
+            if modified.is_some() {
+                fallbacks.insert((recipe_id.to_owned(), package_id.to_owned()));
+            }
+            modified
+        }
+    }
+}
+
+/// Returns the `count` most recently written binaries of every package name, across all versions,
+/// users and channels of that name except the `skipped_recipes`. Packages whose timestamp cannot be
+/// determined are considered the oldest.
+fn newest_packages_per_name<'a>(
+    storage_path: &Path,
+    packages: &'a BTreeMap<String, Vec<String>>,
+    skipped_recipes: &HashSet<&str>,
+    count: usize,
+    age_source: AgeSource,
+    age_fallbacks: &mut BTreeSet<(String, String)>,
+) -> HashSet<(&'a str, &'a str)> {
+    let mut packages_by_name = HashMap::new();
+    for (recipe_id, package_ids) in packages
+        .iter()
+        .filter(|(recipe_id, _)| !skipped_recipes.contains(recipe_id.as_str()))
+    {
+        let name = recipe_id.split('/').next().unwrap_or(recipe_id);
+        for package_id in package_ids {
+            let timestamp = package_time(
+                storage_path,
+                recipe_id,
+                package_id,
+                age_source,
+                age_fallbacks,
+            );
+            packages_by_name.entry(name).or_insert_with(Vec::new).push((
+                timestamp,
+                recipe_id.as_str(),
+                package_id.as_str(),
+            ));
+        }
+    }
+
+    let mut newest_packages = HashSet::new();
+    for packages in packages_by_name.values_mut() {
+        packages.sort_by_key(|&(timestamp, recipe_id, package_id)| {
+            (std::cmp::Reverse(timestamp), recipe_id, package_id)
+        });
+        for &(_, recipe_id, package_id) in packages.iter().take(count) {
+            newest_packages.insert((recipe_id, package_id));
+        }
+    }
+    newest_packages
+}
+
+/// Returns why a package in use is kept: the usage files requiring it, or the lockfiles pinning
+/// it with `--strict-lockfiles`.
+fn in_use_reason(usage: &Usage, recipe_id: &str, package_id: &str) -> KeepReason {
+    match usage.consumers.get(&PackageKey::new(recipe_id, package_id)) {
+        Some(paths) => KeepReason::InUse(paths.clone()),
+        None if usage.strict => KeepReason::PinnedByLockfile,
+        None => KeepReason::InUse(Vec::new()),
+    }
+}
+
+/// Returns the `count` highest versions of every package name and user/channel, e.g. the two
+/// newest versions of `boost` without user/channel and the two newest of `boost@corp/stable`.
+fn latest_recipes_per_name(recipe_ids: &[String], count: usize) -> HashSet<&str> {
+    let mut recipes_by_name = HashMap::new();
+    for recipe_id in recipe_ids {
+        let parts = reference_lint::ReferenceParts::parse(recipe_id);
+        recipes_by_name
+            .entry((parts.name, parts.user, parts.channel))
+            .or_insert_with(Vec::new)
+            .push((parts.version, recipe_id.as_str()));
+    }
+
+    let mut latest_recipes = HashSet::new();
+    for recipes in recipes_by_name.values_mut() {
+        // Revisions of the same version are all kept or all removed.
+        recipes.sort_by(|(a_version, a_id), (b_version, b_id)| {
+            reference::compare_versions(b_version, a_version).then_with(|| a_id.cmp(b_id))
+        });
+        let mut versions = 0;
+        let mut previous_version = None;
+        for &(version, recipe_id) in recipes.iter() {
+            if previous_version != Some(version) {
+                versions += 1;
+                previous_version = Some(version);
+            }
+            if versions > count {
+                break;
+            }
+            latest_recipes.insert(recipe_id);
+        }
+    }
+    latest_recipes
+}
+
+/// Returns whether the package is likely header-only: it was built without any settings and,
+/// if the package folder can be found, it is not larger than `max_size`.
+fn is_header_only_package(
+    labels: &HashMap<String, HashMap<String, PackageLabel>>,
+    storage_path: Option<&Path>,
+    recipe_id: &str,
+    package_id: &str,
+    max_size: u64,
+) -> bool {
+    let has_settings = labels
+        .get(recipe_id)
+        .and_then(|recipe_labels| recipe_labels.get(package_id))
+        .is_none_or(|label| label.label != HEADER_ONLY_LABEL);
+    if has_settings {
+        return false;
+    }
+
+    match storage_path
+        .and_then(|storage_path| cache::package_dir(storage_path, recipe_id, package_id))
+    {
+        Some(package_dir) if package_dir.exists() => {
+            cache::directory_size(&package_dir) <= max_size
+        }
+        _ => true,
+    }
+}
+
+/// Returns whether the package looks like a local build put into the cache with e.g.
+/// `conan export-pkg`: it was created less than `max_age` before `now` according to its manifest
+/// and its recipe was not retrieved from any remote. Such packages are not referenced by any
+/// committed usage file yet, but removing them destroys work that cannot be downloaded again.
+/// conan 2 records the remotes in its database rather than in the recipe folders, so its local
+/// builds cannot be told apart from downloaded packages.
+fn is_recent_local_build(
+    storage_path: &Path,
+    recipe_id: &str,
+    package_id: &str,
+    max_age: Duration,
+    now: SystemTime,
+) -> bool {
+    let is_recent =
+        cache::package_manifest_time(storage_path, recipe_id, package_id).is_some_and(|created| {
+            now.duration_since(created)
+                .map_or(true, |age| age < max_age)
+        });
+    is_recent && !reference::is_conan2() && cache::recipe_remote(storage_path, recipe_id).is_none()
+}
+
+/// Returns whether the settings the package was built with contradict the profile. Packages whose
+/// settings are unknown are never considered incompatible.
+fn is_incompatible_package(
+    profile_settings: &BTreeMap<String, String>,
+    package_settings: &HashMap<(String, String), BTreeMap<String, String>>,
+    storage_path: Option<&Path>,
+    recipe_id: &str,
+    package_id: &str,
+) -> bool {
+    let settings = match package_settings.get(&(recipe_id.to_owned(), package_id.to_owned())) {
+        Some(settings) => Some(settings.clone()),
+        // Recipes reused from the previous run were not searched, so their settings have to be
+        // read from the cache.
+        None => storage_path
+            .and_then(|storage_path| cache::package_settings(storage_path, recipe_id, package_id)),
+    };
+    settings.is_some_and(|settings| !profile::is_compatible(profile_settings, &settings))
+}
+
+/// Returns the fraction of the included packages required by the usage files that exist in the
+/// cache, or `None` if the usage files don't require any such packages.
+pub fn usage_overlap(
+    usage_files: &[UsageFile],
+    packages: &BTreeMap<String, Vec<String>>,
+    options: &PlanOptions,
+) -> Option<f64> {
+    let required_packages: HashSet<_> = usage_files
+        .iter()
+        .flat_map(|usage_file| &usage_file.packages)
+        .filter(|(reference, _)| options.is_included(reference))
+        .map(|(reference, package_id)| PackageKey::new(reference, package_id))
+        .collect();
+    // Nothing to compare with, an empty cache is no sign of a different one.
+    if required_packages.is_empty() || packages.is_empty() {
+        return None;
+    }
+
+    let present_count = required_packages
+        .iter()
+        .filter(|package| {
+            packages
+                .get(&package.recipe)
+                .is_some_and(|package_ids| package_ids.contains(&package.package_id))
+        })
+        .count();
+    Some(present_count as f64 / required_packages.len() as f64)
+}
+
+/// A package left out of an approved plan, given as `<reference>:<package ID>`. Both parts support
+/// `*` and `?`, the reference is matched like the patterns of `--include`. A reference with a
+/// revision only matches that revision, one without matches any.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exception {
+    pub text: String,
+    reference: String,
+    revision: Option<String>,
+    package_id: String,
+}
+
+impl Exception {
+    pub fn parse(text: &str) -> Option<Exception> {
+        let (reference, package_id) = text.trim().split_once(':')?;
+        let (reference, revision) = match reference.split_once('#') {
+            Some((reference, revision)) => (reference, Some(revision.to_owned())),
+            None => (reference, None),
+        };
+        if reference.is_empty() || package_id.is_empty() {
+            return None;
+        }
+        Some(Exception {
+            text: text.trim().to_owned(),
+            reference: reference.to_owned(),
+            revision,
+            package_id: package_id.to_owned(),
+        })
+    }
+
+    pub fn matches(&self, recipe_id: &str, package_id: &str) -> bool {
+        let revision = recipe_id.split_once('#').map(|(_, revision)| revision);
+        reference::matches_pattern(&self.reference, recipe_id)
+            && self
+                .revision
+                .as_deref()
+                .is_none_or(|expected| revision == Some(expected))
+            && wildcard::matches(&self.package_id, package_id)
+    }
+}
+
+/// Reads the exceptions of a file with one exception per line, see `Exception`. Blank lines and
+/// lines starting with `#` are ignored.
+pub fn load_exceptions(path: &Path) -> Result<Vec<String>, TextFileError> {
+    Ok(crate::text_file::read_lines(path)?
+        .into_iter()
+        .map(|line| line.trim().to_owned())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    /// A cache in a temporary folder, removed when dropped.
+    struct TestCache {
+        storage_path: PathBuf,
+    }
+
+    impl TestCache {
+        fn new(name: &str) -> TestCache {
+            let storage_path = std::env::temp_dir().join(format!(
+                "conan_cleanup_plan_test_{}_{}",
+                std::process::id(),
+                name
+            ));
+            let _ = std::fs::remove_dir_all(&storage_path);
+            TestCache { storage_path }
+        }
+
+        /// Adds a package of a recipe without user and channel, written the given time ago.
+        fn add_package(&self, recipe_id: &str, package_id: &str, age: Duration) {
+            self.add_package_with_manifest(recipe_id, package_id, age, None);
+        }
+
+        /// Adds a package like `add_package`, with a conanmanifest.txt of the given contents.
+        fn add_package_with_manifest(
+            &self,
+            recipe_id: &str,
+            package_id: &str,
+            age: Duration,
+            manifest: Option<&str>,
+        ) {
+            let recipe_dir = self.storage_path.join(recipe_id).join("_/_");
+            std::fs::create_dir_all(recipe_dir.join("export")).unwrap();
+            std::fs::write(recipe_dir.join("export/conanfile.py"), "").unwrap();
+            let package_dir = recipe_dir.join("package").join(package_id);
+            std::fs::create_dir_all(&package_dir).unwrap();
+            std::fs::write(
+                package_dir.join("conaninfo.txt"),
+                "[settings]\n    os=Linux\n",
+            )
+            .unwrap();
+            if let Some(manifest) = manifest {
+                std::fs::write(package_dir.join("conanmanifest.txt"), manifest).unwrap();
+            }
+            std::fs::File::open(&package_dir)
+                .unwrap()
+                .set_modified(SystemTime::now() - age)
+                .unwrap();
+        }
+    }
+
+    impl Drop for TestCache {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.storage_path);
+        }
+    }
+
+    fn compute_plan(
+        storage_path: Option<&Path>,
+        packages: &BTreeMap<String, Vec<String>>,
+        usage: &Usage,
+        options: &PlanOptions,
+    ) -> RemovalPlan {
+        let recipe_ids: Vec<_> = packages.keys().cloned().collect();
+        let cache = Cache {
+            storage_path,
+            recipe_ids: &recipe_ids,
+            packages,
+            package_settings: &HashMap::new(),
+            labels: &HashMap::new(),
+            read_only: false,
+            local_index_recipes: &HashSet::new(),
+        };
+        compute(usage, &cache, options, &CancellationToken::new()).unwrap()
+    }
+
+    fn packages(entries: &[(&str, &[&str])]) -> BTreeMap<String, Vec<String>> {
+        entries
+            .iter()
+            .map(|(recipe_id, package_ids)| {
+                (
+                    recipe_id.to_string(),
+                    package_ids.iter().map(|id| id.to_string()).collect(),
+                )
+            })
+            .collect()
+    }
+
+    fn kept_reason<'a>(plan: &'a RemovalPlan, recipe_id: &str, package_id: &str) -> &'a KeepReason {
+        plan.kept[recipe_id]
+            .iter()
+            .find(|(id, _)| id == package_id)
+            .map(|(_, reason)| reason)
+            .unwrap()
+    }
+
+    #[test]
+    fn keep_latest_applies_before_preserve_newest_per_name() {
+        let cache = TestCache::new("keep_latest_first");
+        let hour = Duration::from_secs(60 * 60);
+        cache.add_package("boost/1.70.0", "a", 2 * hour);
+        cache.add_package("boost/1.75.0", "b", 3 * hour);
+        cache.add_package("boost/1.80.0", "c", hour);
+        let packages = packages(&[
+            ("boost/1.70.0", &["a"]),
+            ("boost/1.75.0", &["b"]),
+            ("boost/1.80.0", &["c"]),
+        ]);
+        let options = PlanOptions {
+            keep_latest: Some(1),
+            preserve_newest_per_name: Some(1),
+            include_recent_local: true,
+            ..PlanOptions::default()
+        };
+
+        let plan = compute_plan(
+            Some(&cache.storage_path),
+            &packages,
+            &Usage::default(),
+            &options,
+        );
+
+        assert_eq!(
+            kept_reason(&plan, "boost/1.80.0", "c"),
+            &KeepReason::Latest(1)
+        );
+        // The newest binary of the versions not kept by keep_latest is preserved, although the
+        // newest binary of all is the one of the latest version.
+        assert_eq!(
+            kept_reason(&plan, "boost/1.70.0", "a"),
+            &KeepReason::NewestOfName
+        );
+        assert_eq!(plan.packages, self::packages(&[("boost/1.75.0", &["b"])]));
+    }
+
+    /// Returns a conanmanifest.txt recording the packaging the given time ago.
+    fn manifest(age: Duration) -> String {
+        let created = SystemTime::now() - age;
+        format!(
+            "{}\nlib.a: 0123456789abcdef\n",
+            created
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+        )
+    }
+
+    #[test]
+    fn older_than_by_age_source() {
+        let cache = TestCache::new("age_source");
+        let day = Duration::from_secs(24 * 60 * 60);
+        // Restored from a backup: the folder looks new, the manifest tells the real age.
+        cache.add_package_with_manifest("zlib/1.2.11", "restored", day, Some(&manifest(90 * day)));
+        // Rebuilt after copying an old manifest along, which mtime and newest see through.
+        cache.add_package_with_manifest("zlib/1.2.11", "rebuilt", 90 * day, Some(&manifest(day)));
+        let packages = packages(&[("zlib/1.2.11", &["rebuilt", "restored"])]);
+        let plan_with = |age_source| {
+            let options = PlanOptions {
+                older_than: Some(30 * day),
+                age_source,
+                include_recent_local: true,
+                ..PlanOptions::default()
+            };
+            compute_plan(
+                Some(&cache.storage_path),
+                &packages,
+                &Usage::default(),
+                &options,
+            )
+        };
+
+        let plan = plan_with(AgeSource::Mtime);
+        assert_eq!(
+            plan.packages,
+            self::packages(&[("zlib/1.2.11", &["rebuilt"])])
+        );
+        assert!(matches!(
+            kept_reason(&plan, "zlib/1.2.11", "restored"),
+            KeepReason::TooYoung(_)
+        ));
+
+        let plan = plan_with(AgeSource::Manifest);
+        assert_eq!(
+            plan.packages,
+            self::packages(&[("zlib/1.2.11", &["restored"])])
+        );
+        let age = plan.ages[&("zlib/1.2.11".to_owned(), "restored".to_owned())];
+        assert!(age > 89 * day && age < 91 * day);
+
+        let plan = plan_with(AgeSource::Newest);
+        assert!(plan.packages.is_empty());
+        assert!(plan.age_fallbacks.is_empty());
+    }
+
+    #[test]
+    fn age_source_falls_back_to_mtime_without_readable_manifest() {
+        let cache = TestCache::new("age_source_fallback");
+        let day = Duration::from_secs(24 * 60 * 60);
+        cache.add_package("zlib/1.2.11", "missing", 90 * day);
+        cache.add_package_with_manifest("zlib/1.2.11", "garbled", day, Some("not a timestamp\n"));
+        cache.add_package_with_manifest("zlib/1.2.11", "empty", 90 * day, Some(""));
+        let packages = packages(&[("zlib/1.2.11", &["empty", "garbled", "missing"])]);
+
+        for age_source in [AgeSource::Manifest, AgeSource::Newest] {
+            let options = PlanOptions {
+                older_than: Some(30 * day),
+                age_source,
+                include_recent_local: true,
+                ..PlanOptions::default()
+            };
+            let plan = compute_plan(
+                Some(&cache.storage_path),
+                &packages,
+                &Usage::default(),
+                &options,
+            );
+
+            assert_eq!(
+                plan.packages,
+                self::packages(&[("zlib/1.2.11", &["empty", "missing"])])
+            );
+            assert!(matches!(
+                kept_reason(&plan, "zlib/1.2.11", "garbled"),
+                KeepReason::TooYoung(_)
+            ));
+            let fallbacks: Vec<_> = plan
+                .age_fallbacks
+                .iter()
+                .map(|(_, package_id)| package_id.as_str())
+                .collect();
+            assert_eq!(fallbacks, ["empty", "garbled", "missing"]);
+        }
+    }
+
+    #[test]
+    fn preserve_newest_per_name_by_manifest() {
+        let cache = TestCache::new("preserve_newest_by_manifest");
+        let day = Duration::from_secs(24 * 60 * 60);
+        cache.add_package_with_manifest("boost/1.70.0", "a", day, Some(&manifest(90 * day)));
+        cache.add_package_with_manifest("boost/1.75.0", "b", 2 * day, Some(&manifest(10 * day)));
+        let packages = packages(&[("boost/1.70.0", &["a"]), ("boost/1.75.0", &["b"])]);
+        let options = PlanOptions {
+            preserve_newest_per_name: Some(1),
+            age_source: AgeSource::Manifest,
+            include_recent_local: true,
+            ..PlanOptions::default()
+        };
+
+        let plan = compute_plan(
+            Some(&cache.storage_path),
+            &packages,
+            &Usage::default(),
+            &options,
+        );
+
+        assert_eq!(
+            kept_reason(&plan, "boost/1.75.0", "b"),
+            &KeepReason::NewestOfName
+        );
+        assert_eq!(plan.packages, self::packages(&[("boost/1.70.0", &["a"])]));
+    }
+
+    fn usage_of(entries: &[(&str, &str, &str)]) -> Usage {
+        let mut usage = Usage::default();
+        for &(recipe_id, package_id, consumer) in entries {
+            let key = PackageKey::new(recipe_id, package_id);
+            usage.packages_in_use.packages.insert(key.clone());
+            usage
+                .consumers
+                .entry(key)
+                .or_default()
+                .push(consumer.to_owned());
+        }
+        usage
+    }
+
+    fn unused_options() -> PlanOptions {
+        PlanOptions {
+            include_recent_local: true,
+            ..PlanOptions::default()
+        }
+    }
+
+    #[test]
+    fn packages_in_use_are_kept() {
+        let packages = packages(&[("zlib/1.2.11", &["a", "b"]), ("fmt/8.0.0", &["c"])]);
+        let usage = usage_of(&[("zlib/1.2.11", "a", "app/build/conanbuildinfo.txt")]);
+
+        let plan = compute_plan(None, &packages, &usage, &unused_options());
+
+        assert_eq!(
+            kept_reason(&plan, "zlib/1.2.11", "a"),
+            &KeepReason::InUse(vec!["app/build/conanbuildinfo.txt".to_owned()])
+        );
+        assert_eq!(
+            plan.packages,
+            self::packages(&[("fmt/8.0.0", &["c"]), ("zlib/1.2.11", &["b"])])
+        );
+        assert_eq!(plan.unused_count, 2);
+    }
+
+    #[test]
+    fn pinned_recipe_keeps_all_its_packages() {
+        let packages = packages(&[("zlib/1.2.11", &["a", "b"]), ("fmt/8.0.0", &["c"])]);
+        let mut usage = Usage::default();
+        usage
+            .packages_in_use
+            .recipe_ids
+            .insert("zlib/1.2.11".to_owned());
+
+        let plan = compute_plan(None, &packages, &usage, &unused_options());
+
+        assert_eq!(
+            kept_reason(&plan, "zlib/1.2.11", "a"),
+            &KeepReason::PinnedRecipe
+        );
+        assert_eq!(
+            kept_reason(&plan, "zlib/1.2.11", "b"),
+            &KeepReason::PinnedRecipe
+        );
+        assert_eq!(plan.packages, self::packages(&[("fmt/8.0.0", &["c"])]));
+    }
+
+    #[test]
+    fn exclusion_wins_over_inclusion() {
+        let options = PlanOptions {
+            include: Some(vec!["boost/*".to_owned()]),
+            exclude: vec!["boost/1.75.0".to_owned()],
+            ..PlanOptions::default()
+        };
+
+        assert!(options.is_included("boost/1.70.0"));
+        assert!(!options.is_included("boost/1.75.0"));
+        assert!(options.is_excluded("boost/1.75.0"));
+        assert!(!options.is_included("zlib/1.2.11"));
+        assert!(!options.is_excluded("zlib/1.2.11"));
+        assert!(PlanOptions::default().is_included("zlib/1.2.11"));
+    }
+
+    #[test]
+    fn keep_patterns_keep_the_recipes_and_their_packages() {
+        let packages = packages(&[
+            ("qt/5.15.2@corp/stable", &["a", "b"]),
+            ("zlib/1.2.11", &["c"]),
+        ]);
+        let options = PlanOptions {
+            keep_patterns: vec!["qt/*@corp/*".to_owned()],
+            ..unused_options()
+        };
+
+        let plan = compute_plan(None, &packages, &Usage::default(), &options);
+
+        assert_eq!(
+            kept_reason(&plan, "qt/5.15.2@corp/stable", "b"),
+            &KeepReason::KeepFile
+        );
+        assert!(plan.kept_recipes.contains("qt/5.15.2@corp/stable"));
+        assert_eq!(plan.kept_by_keep_file_count, 2);
+        assert_eq!(plan.packages, self::packages(&[("zlib/1.2.11", &["c"])]));
+    }
+
+    #[test]
+    fn exceptions_take_packages_out_of_the_plan() {
+        let mut plan = RemovalPlan {
+            packages: packages(&[("zlib/1.2.11", &["a", "b"]), ("fmt/8.0.0", &["c"])]),
+            ..RemovalPlan::default()
+        };
+        let exceptions = [
+            Exception::parse("zlib/*:a").unwrap(),
+            Exception::parse("fmt/8.0.0:c").unwrap(),
+        ];
+
+        assert_eq!(plan.apply_exceptions(&exceptions).unwrap(), 2);
+        assert_eq!(plan.packages, packages(&[("zlib/1.2.11", &["b"])]));
+    }
+
+    #[test]
+    fn exception_matching_nothing_leaves_the_plan_unchanged() {
+        let packages = packages(&[("zlib/1.2.11", &["a", "b"])]);
+        let mut plan = RemovalPlan {
+            packages: packages.clone(),
+            ..RemovalPlan::default()
+        };
+        let exceptions = [
+            Exception::parse("zlib/1.2.11:a").unwrap(),
+            Exception::parse("zlbi/1.2.11:a").unwrap(),
+        ];
+
+        assert!(matches!(
+            plan.apply_exceptions(&exceptions),
+            Err(CleanupError::InvalidArguments(ref message)) if message.ends_with("zlbi/1.2.11:a")
+        ));
+        assert_eq!(plan.packages, packages);
+        assert!(Exception::parse("zlib/1.2.11").is_none());
+        assert!(Exception::parse(":a").is_none());
+    }
+
+    #[test]
+    fn preserve_newest_per_name_without_storage_keeps_all_packages() {
+        let packages = packages(&[("boost/1.70.0", &["a"]), ("boost/1.75.0", &["b", "c"])]);
+        let options = PlanOptions {
+            preserve_newest_per_name: Some(1),
+            keep_latest: Some(1),
+            include_recent_local: true,
+            ..PlanOptions::default()
+        };
+
+        let plan = compute_plan(None, &packages, &Usage::default(), &options);
+
+        assert!(plan.packages.is_empty());
+        assert_eq!(
+            kept_reason(&plan, "boost/1.70.0", "a"),
+            &KeepReason::UnknownAge
+        );
+        assert_eq!(
+            kept_reason(&plan, "boost/1.75.0", "b"),
+            &KeepReason::Latest(1)
+        );
+    }
+
+    fn budget(max_binaries: Option<usize>, max_bytes: Option<u64>) -> Budget {
+        Budget {
+            pattern: "qt/*".to_owned(),
+            max_binaries,
+            max_bytes,
+        }
+    }
+
+    #[test]
+    fn budget_removes_the_oldest_unused_binaries() {
+        let cache = TestCache::new("budget_oldest");
+        let hour = Duration::from_secs(60 * 60);
+        cache.add_package("qt/5.15.2", "a", 3 * hour);
+        cache.add_package("qt/5.15.2", "b", hour);
+        cache.add_package("qt/6.2.0", "c", 2 * hour);
+        cache.add_package("zlib/1.2.11", "d", 4 * hour);
+        let packages = packages(&[
+            ("qt/5.15.2", &["a", "b"]),
+            ("qt/6.2.0", &["c"]),
+            ("zlib/1.2.11", &["d"]),
+        ]);
+        let options = PlanOptions {
+            older_than: Some(Duration::from_secs(24 * 60 * 60)),
+            budgets: vec![budget(Some(1), None)],
+            ..unused_options()
+        };
+
+        let plan = compute_plan(
+            Some(&cache.storage_path),
+            &packages,
+            &Usage::default(),
+            &options,
+        );
+
+        // All packages are too young, but qt exceeds its budget by two binaries.
+        assert_eq!(
+            plan.packages,
+            self::packages(&[("qt/5.15.2", &["a"]), ("qt/6.2.0", &["c"])])
+        );
+        assert_eq!(
+            plan.notes[&("qt/6.2.0".to_owned(), "c".to_owned())],
+            "over budget (qt/*)"
+        );
+        assert!(matches!(
+            kept_reason(&plan, "qt/5.15.2", "b"),
+            KeepReason::TooYoung(_)
+        ));
+        // Names without a budget are unaffected.
+        assert!(matches!(
+            kept_reason(&plan, "zlib/1.2.11", "d"),
+            KeepReason::TooYoung(_)
+        ));
+        assert!(plan.unmet_budgets.is_empty());
+    }
+
+    #[test]
+    fn budget_removes_binaries_of_the_same_age_in_order_of_reference() {
+        let cache = TestCache::new("budget_ties");
+        let manifest = manifest(Duration::from_secs(60 * 60));
+        for (recipe_id, package_id) in [("qt/6.2.0", "a"), ("qt/5.15.2", "c"), ("qt/5.15.2", "b")] {
+            cache.add_package_with_manifest(recipe_id, package_id, Duration::ZERO, Some(&manifest));
+        }
+        let packages = packages(&[("qt/5.15.2", &["b", "c"]), ("qt/6.2.0", &["a"])]);
+        let options = PlanOptions {
+            keep_latest: Some(2),
+            age_source: AgeSource::Manifest,
+            budgets: vec![budget(Some(1), None)],
+            ..unused_options()
+        };
+
+        let plan = compute_plan(
+            Some(&cache.storage_path),
+            &packages,
+            &Usage::default(),
+            &options,
+        );
+
+        assert_eq!(plan.packages, self::packages(&[("qt/5.15.2", &["b", "c"])]));
+        assert_eq!(kept_reason(&plan, "qt/6.2.0", "a"), &KeepReason::Latest(2));
+    }
+
+    #[test]
+    fn budget_by_size() {
+        let cache = TestCache::new("budget_size");
+        let hour = Duration::from_secs(60 * 60);
+        cache.add_package_with_manifest("qt/5.15.2", "a", hour, Some(&manifest(3 * hour)));
+        cache.add_package_with_manifest("qt/5.15.2", "b", hour, Some(&manifest(2 * hour)));
+        cache.add_package_with_manifest("qt/5.15.2", "c", hour, Some(&manifest(hour)));
+        std::fs::write(
+            cache.storage_path.join("qt/5.15.2/_/_/package/b/lib.a"),
+            vec![0; 64 * 1024],
+        )
+        .unwrap();
+        let packages = packages(&[("qt/5.15.2", &["a", "b", "c"])]);
+        let options = PlanOptions {
+            keep_latest: Some(1),
+            age_source: AgeSource::Manifest,
+            budgets: vec![budget(None, Some(32 * 1024))],
+            ..unused_options()
+        };
+
+        let plan = compute_plan(
+            Some(&cache.storage_path),
+            &packages,
+            &Usage::default(),
+            &options,
+        );
+
+        // Removing the oldest binary does not meet the budget, removing the large one does.
+        assert_eq!(plan.packages, self::packages(&[("qt/5.15.2", &["a", "b"])]));
+    }
+
+    #[test]
+    fn budget_that_cannot_be_met_keeps_the_packages_in_use() {
+        let cache = TestCache::new("budget_unmet");
+        let hour = Duration::from_secs(60 * 60);
+        for package_id in ["a", "b", "c"] {
+            cache.add_package("qt/5.15.2", package_id, hour);
+        }
+        let packages = packages(&[("qt/5.15.2", &["a", "b", "c"])]);
+        let usage = usage_of(&[
+            ("qt/5.15.2", "a", "app/build/conanbuildinfo.txt"),
+            ("qt/5.15.2", "b", "lib/build/conanbuildinfo.txt"),
+        ]);
+        let options = PlanOptions {
+            keep_latest: Some(1),
+            budgets: vec![budget(Some(1), None)],
+            ..unused_options()
+        };
+
+        let plan = compute_plan(Some(&cache.storage_path), &packages, &usage, &options);
+
+        assert_eq!(plan.packages, self::packages(&[("qt/5.15.2", &["c"])]));
+        assert!(matches!(
+            kept_reason(&plan, "qt/5.15.2", "a"),
+            KeepReason::InUse(_)
+        ));
+        assert!(matches!(
+            kept_reason(&plan, "qt/5.15.2", "b"),
+            KeepReason::InUse(_)
+        ));
+        assert_eq!(
+            plan.unmet_budgets,
+            BTreeSet::from([("qt/*".to_owned(), "qt".to_owned())])
+        );
+    }
+
+    #[test]
+    fn budget_without_storage_is_ignored() {
+        let packages = packages(&[("qt/5.15.2", &["a", "b"])]);
+        let options = PlanOptions {
+            keep_latest: Some(1),
+            budgets: vec![budget(Some(1), None)],
+            ..unused_options()
+        };
+
+        let plan = compute_plan(None, &packages, &Usage::default(), &options);
+
+        assert!(plan.packages.is_empty());
+    }
+}
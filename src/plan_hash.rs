@@ -0,0 +1,109 @@
+//! Content hash of a removal plan, so that the approval of a plan can be tied to exactly the
+//! packages it removes. The hash only depends on which packages of which recipes are removed, not
+//! on the order they were found in or on their sizes, which may change between measurements.
+
+use serde_json::json;
+
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Prefix of every plan hash. Changing what goes into the hash requires a new prefix, so that
+/// hashes of the old format never match instead of colliding.
+const HASH_VERSION: &str = "v1";
+
+/// Returns the hash of a plan given as package IDs to remove per recipe reference, e.g.
+/// `v1-3a7bd3e2...`.
+pub fn plan_hash<'a>(plan: impl IntoIterator<Item = (&'a str, Vec<&'a str>)>) -> String {
+    let mut packages: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for (recipe_id, package_ids) in plan {
+        packages.entry(recipe_id).or_default().extend(package_ids);
+    }
+    packages.retain(|_, package_ids| !package_ids.is_empty());
+
+    // serde_json keeps object keys sorted, so the serialization is canonical.
+    let canonical = json!({ "packages": packages }).to_string();
+    format!(
+        "{}-{}",
+        HASH_VERSION,
+        crate::sha256::sha256_hex(canonical.as_bytes())
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    #[test]
+    fn hash_is_versioned_and_stable() {
+        let hash = plan_hash(vec![
+            ("zlib/1.2.11", vec!["aaa", "bbb"]),
+            ("boost/1.75.0@user/stable", vec!["ccc"]),
+        ]);
+
+        assert!(hash.starts_with("v1-"));
+        // Changes to the canonical serialization must come with a new hash version.
+        assert_eq!(
+            hash,
+            "v1-076028c760fdc4101003518d4f971774a99bf7b6564f3703db235d66ab823a80"
+        );
+    }
+
+    #[test]
+    fn order_of_recipes_and_packages_does_not_matter() {
+        let hash = plan_hash(vec![
+            ("zlib/1.2.11", vec!["aaa", "bbb"]),
+            ("boost/1.75.0", vec!["ccc", "ddd"]),
+        ]);
+
+        assert_eq!(
+            plan_hash(vec![
+                ("boost/1.75.0", vec!["ddd", "ccc"]),
+                ("zlib/1.2.11", vec!["bbb", "aaa"]),
+            ]),
+            hash
+        );
+        // HashMaps iterate in a different order in every process and often within one.
+        for _ in 0..10 {
+            let plan: HashMap<&str, Vec<&str>> = vec![
+                ("zlib/1.2.11", vec!["aaa", "bbb"]),
+                ("boost/1.75.0", vec!["ccc", "ddd"]),
+            ]
+            .into_iter()
+            .collect();
+            assert_eq!(plan_hash(plan), hash);
+        }
+    }
+
+    #[test]
+    fn duplicates_and_empty_recipes_do_not_matter() {
+        let hash = plan_hash(vec![("zlib/1.2.11", vec!["aaa"])]);
+
+        assert_eq!(
+            plan_hash(vec![
+                ("zlib/1.2.11", vec!["aaa", "aaa"]),
+                ("boost/1.75.0", vec![]),
+            ]),
+            hash
+        );
+        assert_eq!(
+            plan_hash(vec![("zlib/1.2.11", vec![]), ("zlib/1.2.11", vec!["aaa"])]),
+            hash
+        );
+    }
+
+    #[test]
+    fn different_plans_have_different_hashes() {
+        let hash = plan_hash(vec![("zlib/1.2.11", vec!["aaa"])]);
+
+        assert_ne!(plan_hash(vec![("zlib/1.2.11", vec!["bbb"])]), hash);
+        assert_ne!(plan_hash(vec![("zlib/1.2.12", vec!["aaa"])]), hash);
+        assert_ne!(plan_hash(vec![("zlib/1.2.11", vec!["aaa", "bbb"])]), hash);
+        // The same package IDs under other recipes are other packages.
+        assert_ne!(
+            plan_hash(vec![("zlib/1.2.11", vec![]), ("bzip2/1.0.8", vec!["aaa"])]),
+            hash
+        );
+        assert_ne!(plan_hash(Vec::new()), hash);
+    }
+}
@@ -0,0 +1,171 @@
+//! Reads the settings of conan 1 profiles to compare them with the settings of cached packages.
+
+use crate::text_file::TextFileError;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Profiles including each other deeper than this are assumed to include themselves.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// Returns the path of the profile with the given name or path. Names are looked up in the
+/// `profiles` folder of the conan home, the default profile is used if no name is given.
+pub fn profile_path(conan_home: &Path, name: Option<&str>) -> PathBuf {
+    let name = match name {
+        Some(name) => name.to_owned(),
+        None => match std::env::var("CONAN_DEFAULT_PROFILE_PATH") {
+            Ok(path) => return PathBuf::from(path),
+            Err(_) => ini::Ini::load_from_file(conan_home.join("conan.conf"))
+                .ok()
+                .and_then(|conf| {
+                    conf.get_from(Some("general"), "default_profile")
+                        .map(str::to_owned)
+                })
+                .unwrap_or_else(|| "default".to_owned()),
+        },
+    };
+
+    let path = PathBuf::from(&name);
+    if path.is_absolute() || path.exists() {
+        path
+    } else {
+        conan_home.join("profiles").join(name)
+    }
+}
+
+/// Loads the `[settings]` of the given profile, following its `include()`s and substituting its
+/// variables. Package specific settings (`zlib:compiler=...`) and all other sections are ignored.
+pub fn load_settings(path: &Path) -> Result<BTreeMap<String, String>, ProfileError> {
+    let mut settings = BTreeMap::new();
+    let mut variables = BTreeMap::new();
+    load_into(path, &mut settings, &mut variables, 0)?;
+    Ok(settings)
+}
+
+fn load_into(
+    path: &Path,
+    settings: &mut BTreeMap<String, String>,
+    variables: &mut BTreeMap<String, String>,
+    depth: usize,
+) -> Result<(), ProfileError> {
+    if depth > MAX_INCLUDE_DEPTH {
+        return Err(ProfileError::TooManyIncludes(path.to_path_buf()));
+    }
+
+    let lines = crate::text_file::read_lines(path)
+        .map_err(|err| ProfileError::Read(path.to_path_buf(), err))?;
+    let mut section: Option<String> = None;
+    for line in &lines {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if line.starts_with('[') && line.ends_with(']') {
+            section = Some(line[1..line.len() - 1].trim().to_owned());
+            continue;
+        }
+
+        if section.is_none() {
+            if let Some(include) = line
+                .strip_prefix("include(")
+                .and_then(|rest| rest.strip_suffix(')'))
+            {
+                let included_path = resolve_include(path, &substitute(include.trim(), variables));
+                load_into(&included_path, settings, variables, depth + 1)?;
+            } else if let Some((name, value)) = split_assignment(line) {
+                let value = substitute(value, variables);
+                variables.insert(name.to_owned(), value);
+            }
+            continue;
+        }
+
+        if section.as_deref() == Some("settings") {
+            if let Some((name, value)) = split_assignment(line) {
+                if !name.contains(':') {
+                    settings.insert(name.to_owned(), substitute(value, variables));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn split_assignment(line: &str) -> Option<(&str, &str)> {
+    let index = line.find('=')?;
+    Some((line[..index].trim(), line[index + 1..].trim()))
+}
+
+fn substitute(value: &str, variables: &BTreeMap<String, String>) -> String {
+    let mut value = value.to_owned();
+    // Longer names first so that `$ARCH_BUILD` is not replaced as `$ARCH`.
+    let mut names: Vec<_> = variables.keys().collect();
+    names.sort_by_key(|name| std::cmp::Reverse(name.len()));
+    for name in names {
+        value = value.replace(&format!("${}", name), &variables[name]);
+    }
+    value
+}
+
+/// Includes are resolved relative to the including profile first and fall back to the folder of
+/// named profiles, like conan does.
+fn resolve_include(including_profile: &Path, include: &str) -> PathBuf {
+    let include = PathBuf::from(include);
+    if include.is_absolute() {
+        return include;
+    }
+
+    let profile_dir = including_profile.parent().unwrap_or_else(|| Path::new(""));
+    let relative_to_profile = profile_dir.join(&include);
+    if relative_to_profile.exists() {
+        return relative_to_profile;
+    }
+    match crate::cache::conan_home() {
+        Some(conan_home) => conan_home.join("profiles").join(include),
+        None => relative_to_profile,
+    }
+}
+
+/// Returns whether a package built with the given settings can be used by consumers of a profile
+/// with `profile_settings`. Settings the profile doesn't define are not compared.
+pub fn is_compatible(
+    profile_settings: &BTreeMap<String, String>,
+    package_settings: &BTreeMap<String, String>,
+) -> bool {
+    package_settings.iter().all(|(name, value)| {
+        profile_settings
+            .get(name)
+            .is_none_or(|profile_value| profile_value == value)
+    })
+}
+
+#[derive(Debug)]
+pub enum ProfileError {
+    Read(PathBuf, TextFileError),
+    TooManyIncludes(PathBuf),
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ProfileError::Read(ref path, ref err) => {
+                write!(f, "Failed to read profile '{}': {}", path.display(), err)
+            }
+            ProfileError::TooManyIncludes(ref path) => write!(
+                f,
+                "Profile '{}' is nested too deeply, the profiles probably include each other",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ProfileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            ProfileError::Read(_, ref err) => Some(err),
+            ProfileError::TooManyIncludes(_) => None,
+        }
+    }
+}
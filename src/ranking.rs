@@ -0,0 +1,170 @@
+//! Ordering and limiting of removal candidates, e.g. the recipes pruned with `--prune-sort` and
+//! `--prune-top`. The helpers work on any entries of a plan, recipes or packages, that tell their
+//! name, size and age.
+
+use std::cmp::{Ordering, Reverse};
+use std::time::Duration;
+
+/// What candidates are ordered by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SortKey {
+    /// Largest first.
+    Size,
+    /// Alphabetically.
+    Name,
+    /// Oldest first.
+    Age,
+}
+
+impl SortKey {
+    /// Parses the value of an option like `--prune-sort`.
+    pub fn parse(value: &str) -> Option<SortKey> {
+        match value {
+            "size" => Some(SortKey::Size),
+            "name" => Some(SortKey::Name),
+            "age" => Some(SortKey::Age),
+            _ => None,
+        }
+    }
+}
+
+/// What a candidate is ordered by. Sizes and ages are `None` if they are unknown, e.g. with
+/// `--no-sizes` or if the folder of the candidate cannot be found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rank<'a> {
+    pub name: &'a str,
+    pub size: Option<u64>,
+    pub age: Option<Duration>,
+}
+
+/// Compares two candidates by `key`. Candidates of unknown size or age come last, ties are broken
+/// by name.
+pub fn compare(a: &Rank, b: &Rank, key: SortKey) -> Ordering {
+    let by_key = match key {
+        SortKey::Size => {
+            (a.size.is_none(), Reverse(a.size)).cmp(&(b.size.is_none(), Reverse(b.size)))
+        }
+        SortKey::Age => (a.age.is_none(), Reverse(a.age)).cmp(&(b.age.is_none(), Reverse(b.age))),
+        SortKey::Name => Ordering::Equal,
+    };
+    by_key.then_with(|| a.name.cmp(b.name))
+}
+
+/// Sorts the entries by `key`, if any, and splits off the ones beyond the first `top`, which are
+/// deferred. Entries that compare equal keep their order.
+pub fn sort_and_limit<T>(
+    mut entries: Vec<T>,
+    key: Option<SortKey>,
+    top: Option<usize>,
+    rank: impl Fn(&T) -> Rank,
+) -> (Vec<T>, Vec<T>) {
+    if let Some(key) = key {
+        entries.sort_by(|a, b| compare(&rank(a), &rank(b), key));
+    }
+    let deferred = match top {
+        Some(top) if top < entries.len() => entries.split_off(top),
+        _ => Vec::new(),
+    };
+    (entries, deferred)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rank<'a>(entry: &'a &(&str, Option<u64>, Option<u64>)) -> Rank<'a> {
+        Rank {
+            name: entry.0,
+            size: entry.1,
+            age: entry.2.map(Duration::from_secs),
+        }
+    }
+
+    fn names<'a>(entries: &[&(&'a str, Option<u64>, Option<u64>)]) -> Vec<&'a str> {
+        entries.iter().map(|entry| entry.0).collect()
+    }
+
+    const ENTRIES: &[(&str, Option<u64>, Option<u64>)] = &[
+        ("qt/5.15.2", Some(300), Some(10)),
+        ("boost/1.75.0", None, None),
+        ("zlib/1.2.11", Some(100), Some(30)),
+        ("boost/1.70.0", Some(300), Some(20)),
+        ("fmt/8.0.0", Some(100), None),
+    ];
+
+    #[test]
+    fn sort_by_size_breaks_ties_by_name_and_puts_unknown_sizes_last() {
+        let (sorted, deferred) =
+            sort_and_limit(ENTRIES.iter().collect(), Some(SortKey::Size), None, rank);
+
+        assert_eq!(
+            names(&sorted),
+            [
+                "boost/1.70.0",
+                "qt/5.15.2",
+                "fmt/8.0.0",
+                "zlib/1.2.11",
+                "boost/1.75.0"
+            ]
+        );
+        assert!(deferred.is_empty());
+    }
+
+    #[test]
+    fn sort_by_age_puts_unknown_ages_last() {
+        let (sorted, _) = sort_and_limit(ENTRIES.iter().collect(), Some(SortKey::Age), None, rank);
+
+        assert_eq!(
+            names(&sorted),
+            [
+                "zlib/1.2.11",
+                "boost/1.70.0",
+                "qt/5.15.2",
+                "boost/1.75.0",
+                "fmt/8.0.0"
+            ]
+        );
+    }
+
+    #[test]
+    fn sort_by_name() {
+        let (sorted, _) = sort_and_limit(ENTRIES.iter().collect(), Some(SortKey::Name), None, rank);
+
+        assert_eq!(
+            names(&sorted),
+            [
+                "boost/1.70.0",
+                "boost/1.75.0",
+                "fmt/8.0.0",
+                "qt/5.15.2",
+                "zlib/1.2.11"
+            ]
+        );
+    }
+
+    #[test]
+    fn top_defers_the_rest() {
+        let (top, deferred) =
+            sort_and_limit(ENTRIES.iter().collect(), Some(SortKey::Size), Some(2), rank);
+
+        assert_eq!(names(&top), ["boost/1.70.0", "qt/5.15.2"]);
+        assert_eq!(
+            names(&deferred),
+            ["fmt/8.0.0", "zlib/1.2.11", "boost/1.75.0"]
+        );
+    }
+
+    #[test]
+    fn top_without_key_keeps_the_order() {
+        let (top, deferred) = sort_and_limit(ENTRIES.iter().collect(), None, Some(4), rank);
+
+        assert_eq!(
+            names(&top),
+            ["qt/5.15.2", "boost/1.75.0", "zlib/1.2.11", "boost/1.70.0"]
+        );
+        assert_eq!(names(&deferred), ["fmt/8.0.0"]);
+        let (all, deferred) = sort_and_limit(ENTRIES.iter().collect(), None, Some(10), rank);
+        assert_eq!(all.len(), ENTRIES.len());
+        assert!(deferred.is_empty());
+    }
+}
@@ -0,0 +1,377 @@
+//! Formats recipe references as arguments of conan commands. How a reference has to be written
+//! depends on the conan version, e.g. conan 1 takes `zlib/1.2.11` without a trailing `@` as a
+//! search pattern rather than a reference.
+
+use std::cmp::Ordering as Comparison;
+use std::sync::OnceLock;
+
+/// First conan 1 version that supports references without user and channel.
+const OPTIONAL_USER_CHANNEL_SINCE: ConanVersion = ConanVersion {
+    major: 1,
+    minor: 18,
+    patch: 0,
+};
+/// First conan 1 version that supports revisions.
+const REVISIONS_SINCE: ConanVersion = ConanVersion {
+    major: 1,
+    minor: 13,
+    patch: 0,
+};
+
+static DETECTED_VERSION: OnceLock<Option<ConanVersion>> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ConanVersion {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+}
+
+impl ConanVersion {
+    /// Parses the output of `conan --version`, e.g. `Conan version 1.40.0`.
+    pub fn parse(output: &str) -> Option<ConanVersion> {
+        let version = output.split_whitespace().last()?;
+        let mut numbers = version.split('.').map(|number| {
+            number
+                .chars()
+                .take_while(char::is_ascii_digit)
+                .collect::<String>()
+                .parse::<u64>()
+        });
+        Some(ConanVersion {
+            major: numbers.next()?.ok()?,
+            minor: numbers.next().unwrap_or(Ok(0)).ok()?,
+            patch: numbers.next().unwrap_or(Ok(0)).ok()?,
+        })
+    }
+}
+
+impl std::fmt::Display for ConanVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Returns the version of the installed conan client, asking conan only once per process. If the
+/// major version is given by `Settings::conan_major_version`, only a detected version with that
+/// major version is used.
+pub fn conan_version() -> Option<ConanVersion> {
+    match crate::session::current().settings().conan_major_version {
+        None => detected_version(),
+        Some(2) => Some(ConanVersion {
+            major: 2,
+            minor: 0,
+            patch: 0,
+        }),
+        Some(major) => detected_version().filter(|version| version.major == major),
+    }
+}
+
+/// Returns whether the installed conan client is conan 2 or later.
+pub fn is_conan2() -> bool {
+    conan_version().is_some_and(|version| version.major >= 2)
+}
+
+fn detected_version() -> Option<ConanVersion> {
+    *DETECTED_VERSION.get_or_init(|| {
+        let output = crate::subprocess::run_conan(&["--version"]).ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        ConanVersion::parse(&String::from_utf8_lossy(&output.stdout))
+    })
+}
+
+/// Returns the canonical form of a recipe reference, without revision and without placeholders
+/// for missing user and channel, e.g. `zlib/1.2.11` for `zlib/1.2.11@_/_#<revision>`.
+pub fn canonical(recipe_id: &str) -> &str {
+    let reference = recipe_id.split('#').next().unwrap_or(recipe_id);
+    reference
+        .strip_suffix("@_/_")
+        .or_else(|| reference.strip_suffix('@'))
+        .unwrap_or(reference)
+}
+
+/// Compares versions of packages like `1.2.11`, `1.75.0-rc1` or `cci.20230101`. Their parts are
+/// compared one by one, numerically if both are numbers and lexicographically otherwise, so that
+/// versions that are no semantic versions still have an order. Missing parts are smaller, e.g.
+/// `1.2` < `1.2.1`, and pre-releases after `-` are older than their release.
+pub fn compare_versions(a: &str, b: &str) -> Comparison {
+    let compare_parts = |a: &str, b: &str| {
+        let mut a_parts = a.split('.');
+        let mut b_parts = b.split('.');
+        loop {
+            let order = match (a_parts.next(), b_parts.next()) {
+                (None, None) => return Comparison::Equal,
+                (None, Some(_)) => return Comparison::Less,
+                (Some(_), None) => return Comparison::Greater,
+                (Some(a_part), Some(b_part)) => {
+                    match (a_part.parse::<u64>(), b_part.parse::<u64>()) {
+                        (Ok(a_number), Ok(b_number)) => a_number.cmp(&b_number),
+                        _ => a_part.cmp(b_part),
+                    }
+                }
+            };
+            if order != Comparison::Equal {
+                return order;
+            }
+        }
+    };
+
+    let (a_release, a_pre_release) = a.split_once('-').map_or((a, None), |(r, p)| (r, Some(p)));
+    let (b_release, b_pre_release) = b.split_once('-').map_or((b, None), |(r, p)| (r, Some(p)));
+    compare_parts(a_release, b_release).then_with(|| match (a_pre_release, b_pre_release) {
+        (None, None) => Comparison::Equal,
+        (None, Some(_)) => Comparison::Greater,
+        (Some(_), None) => Comparison::Less,
+        (Some(a_pre_release), Some(b_pre_release)) => compare_parts(a_pre_release, b_pre_release),
+    })
+}
+
+/// Returns whether the recipe matches a pattern of the form `name/version@user/channel`, e.g.
+/// `qt/*` or `boost/*@*/stable`. Every part of the pattern is matched against the same part of the
+/// reference, so `*` never spans several parts. A pattern without user and channel matches any
+/// user and channel, references without them have the user and channel `_`.
+pub fn matches_pattern(pattern: &str, recipe_id: &str) -> bool {
+    let split = |reference: &str| -> [String; 4] {
+        let (name_version, user_channel) = match reference.split_once('@') {
+            Some((name_version, user_channel)) => (name_version, Some(user_channel)),
+            None => (reference, None),
+        };
+        let (name, version) = name_version.split_once('/').unwrap_or((name_version, "*"));
+        let (user, channel) = user_channel
+            .map(|user_channel| user_channel.split_once('/').unwrap_or((user_channel, "*")))
+            .unwrap_or(("*", "*"));
+        [name, version, user, channel].map(str::to_owned)
+    };
+
+    let pattern_parts = split(pattern.split('#').next().unwrap_or(pattern));
+    let recipe_id = canonical(recipe_id);
+    let mut recipe_parts = split(recipe_id);
+    if !recipe_id.contains('@') {
+        recipe_parts[2] = "_".to_owned();
+        recipe_parts[3] = "_".to_owned();
+    }
+    pattern_parts
+        .iter()
+        .zip(&recipe_parts)
+        .all(|(pattern, part)| crate::wildcard::matches(pattern, part))
+}
+
+/// Formats a recipe reference for the installed conan client, see `format_argument`.
+pub fn argument(recipe_id: &str) -> String {
+    format_argument(recipe_id, conan_version())
+}
+
+/// Formats a recipe reference like `zlib/1.2.11@conan/stable#<revision>` or `zlib/1.2.11` as
+/// argument of conan commands for the given conan version:
+///
+/// * conan 1 needs an `@` after name and version to take a reference without user and channel as
+///   reference rather than as pattern, e.g. `zlib/1.2.11@` or `zlib/1.2.11@#<revision>`. Versions
+///   before 1.18 don't support such references at all, so they are passed unchanged.
+/// * Versions before conan 1.13 don't know revisions, so revisions are stripped for them.
+/// * conan 2 takes references as they are.
+///
+/// If the version is unknown, the latest conan 1 behavior is assumed.
+pub fn format_argument(recipe_id: &str, version: Option<ConanVersion>) -> String {
+    let revision = recipe_id.find('#').map(|index| &recipe_id[index + 1..]);
+    // Some tools write `_/_` or a bare `@` for references without user and channel.
+    let reference = canonical(recipe_id);
+    let has_user_channel = reference.contains('@');
+
+    let is_conan2 = version.is_some_and(|version| version.major >= 2);
+    let supports_revisions = version.is_none_or(|version| version >= REVISIONS_SINCE);
+    let needs_trailing_at = !is_conan2
+        && !has_user_channel
+        && version.is_none_or(|version| version >= OPTIONAL_USER_CHANNEL_SINCE);
+
+    let mut argument = reference.to_owned();
+    if needs_trailing_at {
+        argument.push('@');
+    }
+    if let Some(revision) = revision.filter(|revision| !revision.is_empty() && supports_revisions) {
+        argument.push('#');
+        argument.push_str(revision);
+    }
+    argument
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(major: u64, minor: u64) -> Option<ConanVersion> {
+        Some(ConanVersion {
+            major,
+            minor,
+            patch: 0,
+        })
+    }
+
+    #[test]
+    fn format_argument_for_every_reference_shape_and_conan_version() {
+        let versions = [
+            ("1.12", version(1, 12)),
+            ("1.17", version(1, 17)),
+            ("1.40", version(1, 40)),
+            ("2.0", version(2, 0)),
+            ("unknown", None),
+        ];
+        // The expected arguments in the order of the versions above.
+        let matrix: &[(&str, [&str; 5])] = &[
+            (
+                "zlib/1.2.11",
+                [
+                    "zlib/1.2.11",
+                    "zlib/1.2.11",
+                    "zlib/1.2.11@",
+                    "zlib/1.2.11",
+                    "zlib/1.2.11@",
+                ],
+            ),
+            (
+                "zlib/1.2.11@",
+                [
+                    "zlib/1.2.11",
+                    "zlib/1.2.11",
+                    "zlib/1.2.11@",
+                    "zlib/1.2.11",
+                    "zlib/1.2.11@",
+                ],
+            ),
+            (
+                "zlib/1.2.11@_/_",
+                [
+                    "zlib/1.2.11",
+                    "zlib/1.2.11",
+                    "zlib/1.2.11@",
+                    "zlib/1.2.11",
+                    "zlib/1.2.11@",
+                ],
+            ),
+            (
+                "zlib/1.2.11@conan/stable",
+                [
+                    "zlib/1.2.11@conan/stable",
+                    "zlib/1.2.11@conan/stable",
+                    "zlib/1.2.11@conan/stable",
+                    "zlib/1.2.11@conan/stable",
+                    "zlib/1.2.11@conan/stable",
+                ],
+            ),
+            (
+                "zlib/1.2.11#abc",
+                [
+                    "zlib/1.2.11",
+                    "zlib/1.2.11#abc",
+                    "zlib/1.2.11@#abc",
+                    "zlib/1.2.11#abc",
+                    "zlib/1.2.11@#abc",
+                ],
+            ),
+            (
+                "zlib/1.2.11@_/_#abc",
+                [
+                    "zlib/1.2.11",
+                    "zlib/1.2.11#abc",
+                    "zlib/1.2.11@#abc",
+                    "zlib/1.2.11#abc",
+                    "zlib/1.2.11@#abc",
+                ],
+            ),
+            (
+                "zlib/1.2.11@conan/stable#abc",
+                [
+                    "zlib/1.2.11@conan/stable",
+                    "zlib/1.2.11@conan/stable#abc",
+                    "zlib/1.2.11@conan/stable#abc",
+                    "zlib/1.2.11@conan/stable#abc",
+                    "zlib/1.2.11@conan/stable#abc",
+                ],
+            ),
+            (
+                "zlib/1.2.11#",
+                [
+                    "zlib/1.2.11",
+                    "zlib/1.2.11",
+                    "zlib/1.2.11@",
+                    "zlib/1.2.11",
+                    "zlib/1.2.11@",
+                ],
+            ),
+        ];
+
+        for (recipe_id, expected) in matrix {
+            for ((name, version), expected) in versions.iter().zip(expected) {
+                assert_eq!(
+                    format_argument(recipe_id, *version),
+                    *expected,
+                    "'{}' for conan {}",
+                    recipe_id,
+                    name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn parse_version() {
+        assert_eq!(ConanVersion::parse("Conan version 1.40.0"), version(1, 40));
+        assert_eq!(
+            ConanVersion::parse("Conan version 2.0.17\n").unwrap().patch,
+            17
+        );
+        assert_eq!(ConanVersion::parse("Conan version 2"), version(2, 0));
+        assert_eq!(
+            ConanVersion::parse("Conan version 1.60.0-dev"),
+            version(1, 60)
+        );
+        assert_eq!(ConanVersion::parse(""), None);
+        assert_eq!(ConanVersion::parse("Conan version unknown"), None);
+    }
+
+    #[test]
+    fn canonical_drops_revisions_and_placeholders() {
+        assert_eq!(canonical("zlib/1.2.11@_/_#abc"), "zlib/1.2.11");
+        assert_eq!(canonical("zlib/1.2.11@"), "zlib/1.2.11");
+        assert_eq!(
+            canonical("zlib/1.2.11@conan/stable#abc"),
+            "zlib/1.2.11@conan/stable"
+        );
+    }
+
+    #[test]
+    fn compare_versions_numerically_and_lexicographically() {
+        assert_eq!(compare_versions("1.10.0", "1.9.0"), Comparison::Greater);
+        assert_eq!(compare_versions("1.2", "1.2.1"), Comparison::Less);
+        assert_eq!(compare_versions("1.75.0-rc1", "1.75.0"), Comparison::Less);
+        assert_eq!(
+            compare_versions("1.75.0-rc2", "1.75.0-rc10"),
+            Comparison::Greater
+        );
+        assert_eq!(
+            compare_versions("cci.20230101", "cci.20221231"),
+            Comparison::Greater
+        );
+        assert_eq!(compare_versions("1.2.11", "1.2.11"), Comparison::Equal);
+    }
+
+    #[test]
+    fn patterns_match_part_by_part() {
+        assert!(matches_pattern("qt/*", "qt/5.15.2@corp/stable"));
+        assert!(matches_pattern(
+            "boost/*@*/stable",
+            "boost/1.75.0@user/stable"
+        ));
+        assert!(!matches_pattern(
+            "boost/*@*/stable",
+            "boost/1.75.0@user/testing"
+        ));
+        assert!(matches_pattern("zlib/*@_/_", "zlib/1.2.11"));
+        assert!(!matches_pattern("zlib/*@_/_", "zlib/1.2.11@conan/stable"));
+        assert!(matches_pattern("zlib/1.2.11", "zlib/1.2.11@_/_#abc"));
+        assert!(matches_pattern("zlib*", "zlib/1.2.11"));
+        assert!(!matches_pattern("zlib", "zlibx/1.2.11"));
+        assert!(!matches_pattern("z*/1.2.11", "zlib/1.2.12"));
+    }
+}
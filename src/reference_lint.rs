@@ -0,0 +1,124 @@
+//! Checks recipe references against the naming rules of conan. References that violate them, e.g.
+//! left behind by migrations from older conan versions, are rejected by modern conan clients and
+//! often cannot even be removed by reference.
+
+/// The parts of a recipe reference. User and channel are `None` for references without them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReferenceParts<'a> {
+    pub name: &'a str,
+    pub version: &'a str,
+    pub user: Option<&'a str>,
+    pub channel: Option<&'a str>,
+}
+
+impl<'a> ReferenceParts<'a> {
+    /// Splits a reference like `zlib/1.2.11@conan/stable#<revision>` into its parts.
+    pub fn parse(recipe_id: &'a str) -> ReferenceParts<'a> {
+        let reference = crate::reference::canonical(recipe_id);
+        let (name_version, user_channel) = match reference.split_once('@') {
+            Some((name_version, user_channel)) => (name_version, Some(user_channel)),
+            None => (reference, None),
+        };
+        let (name, version) = name_version.split_once('/').unwrap_or((name_version, ""));
+        let (user, channel) = match user_channel.map(|user_channel| user_channel.split_once('/')) {
+            Some(Some((user, channel))) => (Some(user), Some(channel)),
+            Some(None) => (user_channel, None),
+            None => (None, None),
+        };
+        ReferenceParts {
+            name,
+            version,
+            user,
+            channel,
+        }
+    }
+
+    /// Returns all parts that the reference has.
+    fn all(&self) -> Vec<&'a str> {
+        [Some(self.name), Some(self.version), self.user, self.channel]
+            .iter()
+            .flatten()
+            .copied()
+            .collect()
+    }
+}
+
+/// A naming rule of conan.
+pub struct Rule {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Whether the rule is enforced by the given major version of conan.
+    applies_to: fn(u64) -> bool,
+    /// Whether the reference violates the rule.
+    is_violated: fn(&ReferenceParts) -> bool,
+}
+
+/// Maximum length of a reference part in conan 1 and conan 2.
+const CONAN1_MAX_LENGTH: usize = 51;
+const CONAN2_MAX_LENGTH: usize = 101;
+
+pub const RULES: &[Rule] = &[
+    Rule {
+        name: "invalid-characters",
+        description: "Parts may only consist of letters, digits and '_', '+', '.', '-' and must start with a letter, digit or '_'",
+        applies_to: |_| true,
+        is_violated: |parts| parts.all().iter().any(|part| !is_valid_part(part)),
+    },
+    Rule {
+        name: "too-short",
+        description: "Name, user and channel must have at least 2 characters, the version at least 1",
+        applies_to: |_| true,
+        is_violated: |parts| {
+            parts.version.is_empty()
+                || [Some(parts.name), parts.user, parts.channel]
+                    .iter()
+                    .flatten()
+                    .any(|part| part.chars().count() < 2)
+        },
+    },
+    Rule {
+        name: "too-long",
+        description: "Parts must not be longer than 51 characters",
+        applies_to: |major| major < 2,
+        is_violated: |parts| {
+            parts
+                .all()
+                .iter()
+                .any(|part| part.chars().count() > CONAN1_MAX_LENGTH)
+        },
+    },
+    Rule {
+        name: "too-long",
+        description: "Parts must not be longer than 101 characters",
+        applies_to: |major| major >= 2,
+        is_violated: |parts| {
+            parts
+                .all()
+                .iter()
+                .any(|part| part.chars().count() > CONAN2_MAX_LENGTH)
+        },
+    },
+    Rule {
+        name: "uppercase-name",
+        description: "Package names must be lowercase",
+        applies_to: |major| major >= 2,
+        is_violated: |parts| parts.name.chars().any(|c| c.is_ascii_uppercase()),
+    },
+];
+
+fn is_valid_part(part: &str) -> bool {
+    let mut chars = part.chars();
+    let valid_start = chars
+        .next()
+        .is_none_or(|c| c.is_ascii_alphanumeric() || c == '_');
+    valid_start && chars.all(|c| c.is_ascii_alphanumeric() || "_+.-".contains(c))
+}
+
+/// Returns the rules of the given major version of conan that the reference violates.
+pub fn violations(recipe_id: &str, conan_major: u64) -> Vec<&'static Rule> {
+    let parts = ReferenceParts::parse(recipe_id);
+    RULES
+        .iter()
+        .filter(|rule| (rule.applies_to)(conan_major) && (rule.is_violated)(&parts))
+        .collect()
+}
@@ -0,0 +1,52 @@
+//! Orders the removal of recipes so that tool recipes are removed after the packages that were
+//! built with them, since conan hooks may consult the tools while packages are removed.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The recipes to remove, split into waves that are removed one after another.
+#[derive(Debug, Default)]
+pub struct RemovalOrder {
+    /// Recipes of each wave. No recipe of a wave is a build requirement of a recipe in the same or
+    /// a later wave, except for the recipes in `cycle`.
+    pub waves: Vec<Vec<String>>,
+    /// Recipes that build-require each other, directly or indirectly. They are removed in the last
+    /// wave in alphabetical order.
+    pub cycle: Vec<String>,
+}
+
+/// Splits the given recipes into removal waves. `build_requires` maps recipes to the recipes their
+/// packages were built with, build requirements that are not removed themselves are ignored. The
+/// order is deterministic: waves are as early as possible and sorted alphabetically.
+pub fn removal_waves(
+    recipes: &BTreeSet<String>,
+    build_requires: &BTreeMap<String, BTreeSet<String>>,
+) -> RemovalOrder {
+    let mut remaining = recipes.clone();
+    let mut order = RemovalOrder::default();
+    while !remaining.is_empty() {
+        let is_required = |recipe: &String| {
+            remaining.iter().any(|consumer| {
+                consumer != recipe
+                    && build_requires
+                        .get(consumer)
+                        .is_some_and(|tools| tools.contains(recipe))
+            })
+        };
+        let wave: Vec<String> = remaining
+            .iter()
+            .filter(|recipe| !is_required(recipe))
+            .cloned()
+            .collect();
+
+        if wave.is_empty() {
+            order.cycle = remaining.iter().cloned().collect();
+            order.waves.push(order.cycle.clone());
+            break;
+        }
+        for recipe in &wave {
+            remaining.remove(recipe);
+        }
+        order.waves.push(wave);
+    }
+    order
+}
@@ -0,0 +1,112 @@
+//! Measures the resources a run uses, for `--resource-report`.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+static SUBPROCESS_COUNT: AtomicU64 = AtomicU64::new(0);
+static SCANNED_BYTES: AtomicU64 = AtomicU64::new(0);
+static PARSED_JSON_BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// Counts a spawned conan process.
+pub fn count_subprocess() {
+    SUBPROCESS_COUNT.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Counts bytes read from usage files while scanning the root paths.
+pub fn count_scanned_bytes(bytes: usize) {
+    SCANNED_BYTES.fetch_add(bytes as u64, Ordering::Relaxed);
+}
+
+/// Counts bytes of JSON reported by conan and parsed by the tool.
+pub fn count_parsed_json_bytes(bytes: usize) {
+    PARSED_JSON_BYTES.fetch_add(bytes as u64, Ordering::Relaxed);
+}
+
+/// Platform specific measurements. `None` means the platform can't measure the value.
+pub trait Probe {
+    /// Peak resident set size of the tool itself in bytes.
+    fn peak_memory(&self) -> Option<u64>;
+    /// CPU time (user and system) of all child processes that have been waited for.
+    fn children_cpu_time(&self) -> Option<Duration>;
+}
+
+#[cfg(unix)]
+pub struct UnixProbe;
+
+#[cfg(unix)]
+impl UnixProbe {
+    fn usage(who: libc::c_int) -> Option<libc::rusage> {
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+        if unsafe { libc::getrusage(who, &mut usage) } != 0 {
+            return None;
+        }
+        Some(usage)
+    }
+}
+
+#[cfg(unix)]
+impl Probe for UnixProbe {
+    fn peak_memory(&self) -> Option<u64> {
+        let max_rss = UnixProbe::usage(libc::RUSAGE_SELF)?.ru_maxrss as u64;
+        // macOS reports bytes, other systems kilobytes.
+        if cfg!(target_os = "macos") {
+            Some(max_rss)
+        } else {
+            Some(max_rss * 1024)
+        }
+    }
+
+    fn children_cpu_time(&self) -> Option<Duration> {
+        let usage = UnixProbe::usage(libc::RUSAGE_CHILDREN)?;
+        let to_duration = |time: libc::timeval| {
+            Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
+        };
+        Some(to_duration(usage.ru_utime) + to_duration(usage.ru_stime))
+    }
+}
+
+/// Probe of platforms without supported measurements.
+#[cfg(not(unix))]
+pub struct NoProbe;
+
+#[cfg(not(unix))]
+impl Probe for NoProbe {
+    fn peak_memory(&self) -> Option<u64> {
+        None
+    }
+
+    fn children_cpu_time(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(unix)]
+pub fn platform_probe() -> Box<dyn Probe> {
+    Box::new(UnixProbe)
+}
+
+#[cfg(not(unix))]
+pub fn platform_probe() -> Box<dyn Probe> {
+    Box::new(NoProbe)
+}
+
+/// Resources used by the run so far.
+#[derive(Debug)]
+pub struct ResourceReport {
+    pub peak_memory: Option<u64>,
+    pub subprocess_count: u64,
+    pub children_cpu_time: Option<Duration>,
+    pub scanned_bytes: u64,
+    pub parsed_json_bytes: u64,
+}
+
+/// Combines the counters of the run with the measurements of the given probe.
+pub fn collect(probe: &dyn Probe) -> ResourceReport {
+    ResourceReport {
+        peak_memory: probe.peak_memory(),
+        subprocess_count: SUBPROCESS_COUNT.load(Ordering::Relaxed),
+        children_cpu_time: probe.children_cpu_time(),
+        scanned_bytes: SCANNED_BYTES.load(Ordering::Relaxed),
+        parsed_json_bytes: PARSED_JSON_BYTES.load(Ordering::Relaxed),
+    }
+}
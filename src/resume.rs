@@ -0,0 +1,192 @@
+//! The resume file next to the state file, which records the packages an interrupted removal
+//! already removed. The next run planning the same removal skips them and only removes the rest.
+//!
+//! The file is keyed by the hash of the plan, see `RemovalPlan::hash`. Since the removed packages
+//! are gone from the cache, the next run plans the removal of the rest only, so the plan matches if
+//! it hashes to the recorded hash together with the packages already removed.
+
+use crate::fsutil;
+use crate::plan::RemovalPlan;
+use crate::plan_hash::plan_hash;
+
+use serde_json::json;
+
+use std::collections::BTreeSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const RESUME_FILE_NAME: &str = "conan_cleanup_resume.json";
+
+/// Returns the path of the resume file belonging to the given conan home.
+pub fn resume_file_path(conan_home: &Path) -> PathBuf {
+    conan_home.join(RESUME_FILE_NAME)
+}
+
+/// The packages removed so far by the removal of a plan.
+#[derive(Debug)]
+pub struct Progress {
+    path: PathBuf,
+    plan_hash: String,
+    /// Recipe and package ID of the removed packages. Removals of different recipes run
+    /// concurrently and record their progress as soon as they are done.
+    removed: Mutex<BTreeSet<(String, String)>>,
+}
+
+impl Progress {
+    /// Starts recording the removal of the given plan to the resume file at `path`, continuing the
+    /// given progress of an interrupted removal. Nothing is written before the first removal.
+    pub fn start(path: PathBuf, plan: &RemovalPlan, resumed: Option<Progress>) -> Progress {
+        match resumed {
+            Some(resumed) => Progress { path, ..resumed },
+            None => Progress {
+                path,
+                plan_hash: plan.hash(),
+                removed: Mutex::new(BTreeSet::new()),
+            },
+        }
+    }
+
+    /// Loads the resume file at `path`, if there is one.
+    pub fn load(path: &Path) -> io::Result<Option<Progress>> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "not a resume file");
+        let json: serde_json::Value = serde_json::from_str(&content)?;
+        let plan_hash = json
+            .get("plan_hash")
+            .and_then(serde_json::Value::as_str)
+            .ok_or_else(invalid)?;
+        let removed = json
+            .get("removed")
+            .and_then(serde_json::Value::as_array)
+            .ok_or_else(invalid)?
+            .iter()
+            .map(|package| {
+                let package = package.as_array().ok_or_else(invalid)?;
+                match package
+                    .iter()
+                    .map(serde_json::Value::as_str)
+                    .collect::<Vec<_>>()[..]
+                {
+                    [Some(recipe_id), Some(package_id)] => {
+                        Ok((recipe_id.to_owned(), package_id.to_owned()))
+                    }
+                    _ => Err(invalid()),
+                }
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(Some(Progress {
+            path: path.to_owned(),
+            plan_hash: plan_hash.to_owned(),
+            removed: Mutex::new(removed),
+        }))
+    }
+
+    /// Returns whether the progress belongs to the removal of the given plan, which lacks the
+    /// packages removed so far.
+    pub fn matches(&self, plan: &RemovalPlan) -> bool {
+        let removed = self.removed.lock().unwrap();
+        let hash = plan_hash(
+            plan.packages
+                .iter()
+                .map(|(recipe_id, package_ids)| {
+                    (
+                        recipe_id.as_str(),
+                        package_ids.iter().map(String::as_str).collect(),
+                    )
+                })
+                .chain(removed.iter().map(|(recipe_id, package_id)| {
+                    (recipe_id.as_str(), vec![package_id.as_str()])
+                })),
+        );
+        hash == self.plan_hash
+    }
+
+    /// Returns whether the given package was removed already.
+    pub fn is_removed(&self, recipe_id: &str, package_id: &str) -> bool {
+        self.removed
+            .lock()
+            .unwrap()
+            .contains(&(recipe_id.to_owned(), package_id.to_owned()))
+    }
+
+    /// Returns the number of packages removed so far.
+    pub fn removed_count(&self) -> usize {
+        self.removed.lock().unwrap().len()
+    }
+
+    /// Records the removal of a package and writes the resume file, replacing it atomically so
+    /// that an interruption at any time leaves the previous or the new progress behind.
+    pub fn record(&self, recipe_id: &str, package_id: &str) -> io::Result<()> {
+        let mut removed = self.removed.lock().unwrap();
+        removed.insert((recipe_id.to_owned(), package_id.to_owned()));
+        let json = json!({
+            "plan_hash": self.plan_hash,
+            "removed": *removed,
+        });
+        fsutil::atomic_write(&self.path, json.to_string().as_bytes())
+    }
+
+    /// Deletes the resume file once the removal is complete.
+    pub fn finish(self) -> io::Result<()> {
+        remove(&self.path)
+    }
+}
+
+/// Deletes the resume file at `path`, if there is one.
+pub fn remove(path: &Path) -> io::Result<()> {
+    match fsutil::remove_file(path) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    fn plan(entries: &[(&str, &[&str])]) -> RemovalPlan {
+        let packages: BTreeMap<String, Vec<String>> = entries
+            .iter()
+            .map(|(recipe_id, package_ids)| {
+                (
+                    recipe_id.to_string(),
+                    package_ids.iter().map(|id| id.to_string()).collect(),
+                )
+            })
+            .collect();
+        RemovalPlan {
+            packages,
+            ..RemovalPlan::default()
+        }
+    }
+
+    #[test]
+    fn progress_matches_the_rest_of_its_plan() {
+        let path = std::env::temp_dir().join(format!(
+            "conan_cleanup_resume_test_{}.json",
+            std::process::id()
+        ));
+        let original = plan(&[("zlib/1.2.11", &["a", "b"]), ("fmt/8.0.0", &["c"])]);
+        let progress = Progress::start(path.clone(), &original, None);
+        assert!(progress.matches(&original));
+        progress.record("zlib/1.2.11", "a").unwrap();
+        progress.record("fmt/8.0.0", "c").unwrap();
+
+        let loaded = Progress::load(&path).unwrap().unwrap();
+        assert_eq!(loaded.removed_count(), 2);
+        assert!(loaded.is_removed("fmt/8.0.0", "c"));
+        assert!(loaded.matches(&plan(&[("zlib/1.2.11", &["b"])])));
+        assert!(!loaded.matches(&plan(&[("zlib/1.2.11", &["b"]), ("fmt/9.0.0", &["d"])])));
+
+        loaded.finish().unwrap();
+        assert!(!path.exists());
+        assert!(Progress::load(&path).unwrap().is_none());
+    }
+}
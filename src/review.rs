@@ -0,0 +1,117 @@
+//! Review of large removal plans in chunks for `--review-chunk`. Each chunk is confirmed and
+//! removed before the next one is shown, so that quitting halfway still keeps the progress made.
+
+use crate::plan_hash::plan_hash;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+const DEFERRED_PLAN_FILE_NAME: &str = "conan_cleanup_deferred_plan.txt";
+
+/// Answer to the question whether to remove the packages of a chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkAnswer {
+    Yes,
+    No,
+    /// Stop reviewing, keeping the packages of this and all further chunks.
+    Quit,
+}
+
+/// Packages of a chunk as package IDs per recipe reference, in the order of the plan.
+pub type Chunk<'a> = Vec<(&'a str, Vec<&'a str>)>;
+
+/// Splits a plan, given as package IDs per recipe reference, into chunks of at most `chunk_size`
+/// packages. Recipes are never split, so a recipe with more packages than that forms a chunk of its
+/// own. The chunks are balanced by the size of their packages, or by their number if no sizes are
+/// known, and only depend on the plan and the sizes.
+pub fn chunks<'a>(
+    plan: &[(&'a str, Vec<&'a str>)],
+    sizes: &HashMap<(&str, &str), u64>,
+    chunk_size: usize,
+) -> Vec<Chunk<'a>> {
+    let chunk_size = chunk_size.max(1);
+    let weight = |(recipe_id, package_ids): &(&str, Vec<&str>)| -> u64 {
+        if sizes.is_empty() {
+            return package_ids.len() as u64;
+        }
+        package_ids
+            .iter()
+            .filter_map(|package_id| sizes.get(&(*recipe_id, *package_id)))
+            .sum()
+    };
+
+    // The heaviest recipes are placed first, each into the lightest chunk with room for it.
+    let mut order: Vec<usize> = (0..plan.len()).collect();
+    order.sort_by_key(|&index| {
+        (
+            std::cmp::Reverse(weight(&plan[index])),
+            std::cmp::Reverse(plan[index].1.len()),
+            index,
+        )
+    });
+    let package_count: usize = plan.iter().map(|(_, package_ids)| package_ids.len()).sum();
+    // Weight, package count and indices of the recipes of each chunk.
+    let mut bins: Vec<(u64, usize, Vec<usize>)> =
+        vec![(0, 0, Vec::new()); package_count.div_ceil(chunk_size)];
+    for index in order {
+        let count = plan[index].1.len();
+        let lightest = bins
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, bin_count, _))| bin_count + count <= chunk_size)
+            .min_by_key(|(bin_index, (bin_weight, bin_count, _))| {
+                (*bin_weight, *bin_count, *bin_index)
+            })
+            .map(|(bin_index, _)| bin_index);
+        let bin_index = lightest.unwrap_or_else(|| {
+            bins.push((0, 0, Vec::new()));
+            bins.len() - 1
+        });
+        let bin = &mut bins[bin_index];
+        bin.0 += weight(&plan[index]);
+        bin.1 += count;
+        bin.2.push(index);
+    }
+
+    // Chunks list their recipes in the order of the plan and are reviewed in that order, too.
+    let mut bins: Vec<_> = bins
+        .into_iter()
+        .map(|(_, _, mut indices)| {
+            indices.sort_unstable();
+            indices
+        })
+        .filter(|indices| !indices.is_empty())
+        .collect();
+    bins.sort();
+    bins.into_iter()
+        .map(|indices| {
+            indices
+                .into_iter()
+                .map(|index| plan[index].clone())
+                .collect()
+        })
+        .collect()
+}
+
+/// Returns the path of the file the chunks that were not reviewed are written to, kept in the given
+/// folder.
+pub fn deferred_plan_path(dir: &Path) -> PathBuf {
+    dir.join(DEFERRED_PLAN_FILE_NAME)
+}
+
+/// Writes the chunks that were not reviewed to a file with one `<reference>:<package ID>` per
+/// line, preceded by comments with the plan hash of those packages.
+pub fn write_deferred_plan(path: &Path, chunks: &[Chunk]) -> std::io::Result<()> {
+    let packages = || chunks.iter().flatten().cloned();
+    let mut content = format!(
+        "# Packages of the removal plan that were not reviewed since --review-chunk was quit.\n\
+         # Plan hash: {}\n",
+        plan_hash(packages())
+    );
+    for (recipe_id, package_ids) in packages() {
+        for package_id in package_ids {
+            content.push_str(&format!("{}:{}\n", recipe_id, package_id));
+        }
+    }
+    crate::fsutil::atomic_write(path, content.as_bytes())
+}
@@ -0,0 +1,81 @@
+//! Risk of removing a package, derived from the rules that put it into the plan despite a reason
+//! to keep it. Packages at or above a configurable risk level are confirmed one by one.
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RiskLevel {
+    Low,
+    Medium,
+    High,
+}
+
+impl RiskLevel {
+    pub const NAMES: &'static [&'static str] = &["low", "medium", "high"];
+
+    pub fn from_name(name: &str) -> Option<RiskLevel> {
+        match name {
+            "low" => Some(RiskLevel::Low),
+            "medium" => Some(RiskLevel::Medium),
+            "high" => Some(RiskLevel::High),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for RiskLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            RiskLevel::Low => "low",
+            RiskLevel::Medium => "medium",
+            RiskLevel::High => "high",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A rule that plans a package for removal although something speaks for keeping it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RiskReason {
+    /// The package is in use, but only by projects selected with `--only-consumed-by`.
+    InUseBySelectedProjects,
+    /// The package is only used by projects whose last build failed.
+    WeaklyProtected,
+    /// The package would be kept as header-only or newest of its name, but is incompatible with
+    /// the profile.
+    IncompatibleDespiteKeepRule,
+    /// The package looks like a local build that cannot be downloaded again.
+    RecentLocalBuild,
+}
+
+impl RiskReason {
+    pub fn level(self) -> RiskLevel {
+        match self {
+            RiskReason::InUseBySelectedProjects | RiskReason::RecentLocalBuild => RiskLevel::High,
+            RiskReason::WeaklyProtected | RiskReason::IncompatibleDespiteKeepRule => {
+                RiskLevel::Medium
+            }
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            RiskReason::InUseBySelectedProjects => "in use by the selected projects",
+            RiskReason::WeaklyProtected => "in use by projects whose build failed",
+            RiskReason::IncompatibleDespiteKeepRule => {
+                "would be kept if it was compatible with the profile"
+            }
+            RiskReason::RecentLocalBuild => "recent local build that cannot be downloaded again",
+        }
+    }
+}
+
+/// Returns the risk of removing a package for the given reasons. Packages without any reason are
+/// of low risk.
+pub fn risk_level(reasons: &[RiskReason]) -> RiskLevel {
+    reasons
+        .iter()
+        .map(|reason| reason.level())
+        .max()
+        .unwrap_or(RiskLevel::Low)
+}
@@ -0,0 +1,849 @@
+//! Scanning of the project directories for usage files, which tell which packages are in use.
+
+use crate::cancel::CancellationToken;
+use crate::fsutil::{self, FileIdentity};
+use crate::size::format_size;
+use crate::{lockfile, resources, sha256, usage_pattern, wildcard};
+
+use walkdir::WalkDir;
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::sync::atomic::Ordering;
+
+/// Directories of version control systems, which never contain usage files.
+pub const DEFAULT_EXCLUDED_DIRS: &[&str] = &[".git", ".svn", ".hg"];
+
+/// A file found while scanning the projects that tells which packages are in use.
+pub struct UsageFile {
+    pub path: std::path::PathBuf,
+    /// The pattern the file was found by, which also determines its format.
+    pub pattern: usage_pattern::UsagePattern,
+    /// SHA-256 of the file content.
+    pub content_hash: String,
+    /// Recipe references and package IDs of the packages required by the file.
+    pub packages: Vec<(String, String)>,
+    /// References of the recipes pinned without package ID by a conan 2 lockfile, all of whose
+    /// packages are in use.
+    pub pinned_recipes: Vec<String>,
+    /// Why the build the file belongs to is believed to have failed, if it is.
+    pub failed_build: Option<String>,
+}
+
+/// A root path left out since another root path contains it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RootOverlap {
+    pub root_path: String,
+    /// The root path containing it, or the same folder if `duplicate` is set.
+    pub ancestor: String,
+    /// Whether both root paths lead to the same folder, e.g. given twice or through a symlink.
+    pub duplicate: bool,
+}
+
+/// Leaves out the root paths contained in other root paths, which would only be scanned twice.
+/// Paths are compared by the identity of their folders, so symlinked aliases of a folder or of one
+/// of its parents are detected as well. Of root paths leading to the same folder, the first one is
+/// kept. Returns the remaining root paths in their order and the ones left out.
+pub fn collapse_roots<'a>(root_paths: &[&'a str]) -> (Vec<&'a str>, Vec<RootOverlap>) {
+    let identities: Vec<Option<FileIdentity>> = root_paths
+        .iter()
+        .map(|root_path| fsutil::file_identity(std::path::Path::new(root_path)).ok())
+        .collect();
+    let mut kept = Vec::new();
+    let mut overlaps = Vec::new();
+    for (index, root_path) in root_paths.iter().enumerate() {
+        let canonical_path = std::fs::canonicalize(root_path)
+            .unwrap_or_else(|_| std::path::PathBuf::from(root_path));
+        // The root itself first, so that a duplicate is reported as such.
+        let overlap = canonical_path
+            .ancestors()
+            .enumerate()
+            .find_map(|(depth, dir)| {
+                let identity = fsutil::file_identity(dir).ok()?;
+                let other = identities
+                    .iter()
+                    .enumerate()
+                    .position(|(other, other_identity)| {
+                        let same_folder = other_identity.as_ref() == Some(&identity);
+                        // Of roots leading to the same folder, only the later ones are left out.
+                        same_folder && other != index && (depth > 0 || other < index)
+                    })?;
+                Some(RootOverlap {
+                    root_path: root_path.to_string(),
+                    ancestor: root_paths[other].to_owned(),
+                    duplicate: depth == 0,
+                })
+            });
+        match overlap {
+            Some(overlap) => overlaps.push(overlap),
+            None => kept.push(*root_path),
+        }
+    }
+    (kept, overlaps)
+}
+
+/// Finds the usage files matching the patterns below the root paths, leaving out the excluded
+/// directories and the conan caches. Every file is read once, even if several paths lead to it,
+/// e.g. through symlinks. Stops walking once `token` is cancelled.
+pub fn find_usage_files(
+    root_paths: &[&str],
+    patterns: &[usage_pattern::UsagePattern],
+    excluded_dirs: &[&str],
+    cache_dirs: &[std::path::PathBuf],
+    max_file_size: u64,
+    token: &CancellationToken,
+) -> Vec<UsageFile> {
+    let mut usage_files = Vec::new();
+    let mut conan2_files = Vec::new();
+    let mut files_per_pattern = vec![0; patterns.len()];
+    // Files matching several patterns, by the indices of the matching patterns.
+    let mut ambiguous_files: BTreeMap<Vec<usize>, Vec<std::path::PathBuf>> = BTreeMap::new();
+    let mut pruned_dir_count = 0;
+    let mut seen_files = HashSet::new();
+    let session = crate::session::current();
+    let failed_files = &session.failed_usage_files;
+    'roots: for root_path in root_paths {
+        let walker = WalkDir::new(root_path)
+            .follow_links(true)
+            .into_iter()
+            .filter_entry(|entry| {
+                let excluded = entry.depth() > 0
+                    && entry.file_type().is_dir()
+                    && is_excluded_dir(root_path, entry.path(), excluded_dirs);
+                if excluded {
+                    pruned_dir_count += 1;
+                }
+                !excluded
+            });
+        for entry in walker.filter_map(|e| e.ok()) {
+            if token.is_cancelled() {
+                break 'roots;
+            }
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let matching_patterns = wildcard::matching_patterns(
+                patterns.iter().map(|pattern| pattern.pattern.as_str()),
+                &entry.file_name().to_string_lossy(),
+            );
+            let pattern = match matching_patterns.first() {
+                Some(&index) => &patterns[index],
+                None => continue,
+            };
+            if let Ok(identity) = fsutil::file_identity(entry.path()) {
+                if !seen_files.insert(identity) {
+                    trace!(
+                        "Skipped '{}', which was reached by another path already.",
+                        entry.path().display()
+                    );
+                    continue;
+                }
+            }
+            if matching_patterns.len() > 1 {
+                ambiguous_files
+                    .entry(matching_patterns.clone())
+                    .or_default()
+                    .push(entry.path().to_path_buf());
+            }
+
+            // Misnamed binary files can be huge, reading them would only waste memory.
+            let file_size = entry.metadata().map_or(0, |metadata| metadata.len());
+            if file_size > max_file_size {
+                warning!(
+                    "Skipped usage file '{}' of {}, which is larger than the limit of {}.",
+                    entry.path().display(),
+                    format_size(file_size),
+                    format_size(max_file_size)
+                );
+                continue;
+            }
+
+            let mut pinned_recipes = Vec::new();
+            let packages = match pattern.format {
+                usage_pattern::UsageFormat::Conaninfo => {
+                    match parse_required_packages(entry.path()) {
+                        Ok(packages) => packages,
+                        Err(ConanIniError::Conan2Layout) => {
+                            // conan 2 writes these files into its own cache as well, which is expected.
+                            if !cache_dirs.iter().any(|dir| entry.path().starts_with(dir)) {
+                                conan2_files.push(entry.into_path());
+                            }
+                            continue;
+                        }
+                        Err(ref err) => {
+                            eprintln!("Failed to parse '{}': {}", entry.path().display(), err);
+                            failed_files.fetch_add(1, Ordering::Relaxed);
+                            continue;
+                        }
+                    }
+                }
+                usage_pattern::UsageFormat::Conanbuildinfo => {
+                    match parse_build_info_packages(entry.path()) {
+                        Ok(packages) => packages,
+                        Err(ref err) => {
+                            eprintln!("Failed to parse '{}': {}", entry.path().display(), err);
+                            failed_files.fetch_add(1, Ordering::Relaxed);
+                            continue;
+                        }
+                    }
+                }
+                usage_pattern::UsageFormat::Lockfile => {
+                    match lockfile::parse_lockfile(entry.path()) {
+                        Ok(locked_packages) => {
+                            let mut packages = Vec::new();
+                            for locked in locked_packages {
+                                match locked.package_id {
+                                    Some(package_id) => {
+                                        packages.push((locked.reference, package_id))
+                                    }
+                                    None => pinned_recipes.push(locked.reference),
+                                }
+                            }
+                            packages
+                        }
+                        Err(ref err) => {
+                            eprintln!("Failed to parse '{}': {}", entry.path().display(), err);
+                            failed_files.fetch_add(1, Ordering::Relaxed);
+                            continue;
+                        }
+                    }
+                }
+            };
+
+            let content_hash = match std::fs::read(entry.path()) {
+                Ok(content) => {
+                    resources::count_scanned_bytes(content.len());
+                    sha256::sha256_hex(&content)
+                }
+                Err(ref err) => {
+                    eprintln!("Failed to read '{}': {}", entry.path().display(), err);
+                    failed_files.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                }
+            };
+            files_per_pattern[matching_patterns[0]] += 1;
+            debug!("Found usage file '{}'", entry.path().display());
+            trace!(
+                "'{}' requires: {}",
+                entry.path().display(),
+                packages
+                    .iter()
+                    .map(|(recipe_id, package_id)| format!("{}:{}", recipe_id, package_id))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            usage_files.push(UsageFile {
+                path: entry.into_path(),
+                pattern: pattern.clone(),
+                content_hash,
+                packages,
+                pinned_recipes,
+                failed_build: None,
+            });
+        }
+    }
+
+    debug!(
+        "Skipped {} excluded directories while searching for usage files.",
+        pruned_dir_count
+    );
+    for (matching_patterns, files) in &ambiguous_files {
+        let names: Vec<_> = matching_patterns
+            .iter()
+            .map(|&index| format!("'{}'", patterns[index]))
+            .collect();
+        warning!(
+            "{} files match the usage file patterns {} (e.g. '{}'), they are read by the first one.",
+            files.len(),
+            names.join(", "),
+            files[0].display()
+        );
+    }
+    if patterns.len() > 1 {
+        let counts: Vec<_> = patterns
+            .iter()
+            .zip(&files_per_pattern)
+            .map(|(pattern, count)| format!("{} matching '{}'", count, pattern))
+            .collect();
+        debug!("Found usage files: {}", counts.join(", "));
+    }
+
+    if !conan2_files.is_empty() {
+        eprintln!(
+            "Skipped {} conaninfo.txt files written by conan 2 (e.g. '{}').",
+            conan2_files.len(),
+            conan2_files[0].display()
+        );
+        eprintln!("These files contain no dependency information, so packages used by these projects are not detected as in use.");
+    }
+
+    usage_files.sort_by(|a, b| a.path.cmp(&b.path));
+    usage_files
+}
+
+/// Returns the number of usage files that could not be read or parsed by `find_usage_files`.
+pub fn failed_file_count() -> usize {
+    crate::session::current()
+        .failed_usage_files
+        .load(Ordering::Relaxed)
+}
+
+/// Identifies a package of the cache. Package IDs alone are ambiguous, since packages of different
+/// recipes share them, e.g. all header-only packages have the same ID.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PackageKey {
+    /// Recipe reference without revision.
+    pub recipe: String,
+    pub package_id: String,
+}
+
+impl PackageKey {
+    /// Returns the key of a package, ignoring the revisions of the recipe reference and the package
+    /// ID. Usage files may carry revisions the cache doesn't know, see `revision_mismatch`.
+    pub fn new(recipe_id: &str, package_id: &str) -> PackageKey {
+        PackageKey {
+            recipe: recipe_id.split('#').next().unwrap_or(recipe_id).to_owned(),
+            package_id: package_id
+                .split('#')
+                .next()
+                .unwrap_or(package_id)
+                .to_owned(),
+        }
+    }
+}
+
+/// Packages in use according to the usage files and lockfiles.
+#[derive(Debug, Default)]
+pub struct PackagesInUse {
+    pub packages: HashSet<PackageKey>,
+    /// References without revision of recipes whose packages are all in use. conan 2 lockfiles
+    /// don't pin package IDs, only recipes.
+    pub recipe_ids: HashSet<String>,
+}
+
+impl PackagesInUse {
+    /// Returns whether the package of the recipe is in use, ignoring the revision of the reference.
+    pub fn contains_package(&self, recipe_id: &str, package_id: &str) -> bool {
+        self.packages
+            .contains(&PackageKey::new(recipe_id, package_id))
+    }
+
+    /// Returns whether all packages of the recipe are in use, ignoring the revision of the
+    /// reference.
+    pub fn contains_recipe(&self, recipe_id: &str) -> bool {
+        self.recipe_ids
+            .contains(recipe_id.split('#').next().unwrap_or(recipe_id))
+    }
+}
+
+/// Returns the usage files that reference revisions although the cache has them disabled, which
+/// happens when they were generated on machines with revisions enabled. Such references still
+/// match, since `PackageKey` ignores revisions. Usage files without revisions are no mismatch for a
+/// cache with revisions enabled, since conan only records them for the package ID modes based on
+/// revisions.
+pub fn revision_mismatch(
+    usage_files: &[UsageFile],
+    cache_revisions: bool,
+) -> Vec<&std::path::Path> {
+    if cache_revisions {
+        return Vec::new();
+    }
+    usage_files
+        .iter()
+        .filter(|usage_file| {
+            usage_file
+                .packages
+                .iter()
+                .any(|(recipe_id, package_id)| recipe_id.contains('#') || package_id.contains('#'))
+        })
+        .map(|usage_file| usage_file.path.as_path())
+        .collect()
+}
+
+/// Collects the packages required by the usage files and pinned by the lockfiles.
+pub fn find_packages_in_use(
+    usage_files: &[UsageFile],
+    locked_packages: &[lockfile::LockedPackage],
+) -> PackagesInUse {
+    let mut packages_in_use = PackagesInUse::default();
+    packages_in_use.packages.extend(
+        usage_files
+            .iter()
+            .flat_map(|usage_file| &usage_file.packages)
+            .map(|(recipe_id, package_id)| PackageKey::new(recipe_id, package_id)),
+    );
+    packages_in_use.recipe_ids.extend(
+        usage_files
+            .iter()
+            .flat_map(|usage_file| usage_file.pinned_recipes.iter().cloned()),
+    );
+    for locked_package in locked_packages {
+        match locked_package.package_id {
+            Some(ref package_id) => {
+                packages_in_use
+                    .packages
+                    .insert(PackageKey::new(&locked_package.reference, package_id));
+            }
+            None => {
+                packages_in_use
+                    .recipe_ids
+                    .insert(locked_package.reference.clone());
+            }
+        }
+    }
+    packages_in_use
+}
+
+/// Returns whether the walk for usage files must not descend into the given directory. Patterns
+/// are matched against the directory name and the path relative to the root path, a leading `**/`
+/// also matches directories directly below the root path.
+fn is_excluded_dir(root_path: &str, dir: &std::path::Path, patterns: &[&str]) -> bool {
+    let name = dir
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let relative_path = dir
+        .strip_prefix(root_path)
+        .unwrap_or(dir)
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    patterns.iter().any(|pattern| {
+        wildcard::matches(pattern, &name)
+            || wildcard::matches(pattern, &relative_path)
+            || pattern
+                .strip_prefix("**/")
+                .is_some_and(|pattern| wildcard::matches(pattern, &relative_path))
+    })
+}
+
+#[derive(Debug)]
+pub enum ConanIniError {
+    Io(std::io::Error),
+    MissingSection(String),
+    /// The file contains NUL bytes, so it is no text file at all.
+    NotText,
+    /// The file was written by conan 2, which uses a different layout without `[full_requires]`.
+    Conan2Layout,
+}
+
+impl fmt::Display for ConanIniError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConanIniError::Io(ref err) => err.fmt(f),
+            ConanIniError::MissingSection(ref section) => {
+                write!(f, "Section '{}' is missing", section)
+            }
+            ConanIniError::NotText => write!(f, "File contains binary data"),
+            ConanIniError::Conan2Layout => write!(f, "File was written by conan 2"),
+        }
+    }
+}
+
+impl std::error::Error for ConanIniError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            ConanIniError::Io(ref err) => Some(err),
+            ConanIniError::MissingSection(_)
+            | ConanIniError::NotText
+            | ConanIniError::Conan2Layout => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ConanIniError {
+    fn from(err: std::io::Error) -> ConanIniError {
+        ConanIniError::Io(err)
+    }
+}
+
+/// Reads the packages of the `[full_requires]` section of a conaninfo.txt. The file is read line by
+/// line, so memory use is bounded by the longest line rather than the file size.
+pub fn parse_required_packages<P: AsRef<std::path::Path>>(
+    file_path: P,
+) -> Result<Vec<(String, String)>, ConanIniError> {
+    use std::io::BufRead;
+
+    let reader = std::io::BufReader::new(std::fs::File::open(file_path)?);
+    let mut sections = HashSet::new();
+    let mut section = String::new();
+    let mut required_packages = Vec::new();
+    for line in reader.split(b'\n') {
+        let line = line?;
+        if line.contains(&0) {
+            return Err(ConanIniError::NotText);
+        }
+        let line = String::from_utf8_lossy(&line);
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            section = name.trim().to_owned();
+            sections.insert(section.clone());
+            continue;
+        }
+        if section == "full_requires" {
+            // Entries look like `zlib/1.2.11@conan/stable:<package ID>`.
+            let (reference, package_id) = line.split_once([':', '=']).unwrap_or((line, ""));
+            required_packages.push((reference.trim().to_owned(), package_id.trim().to_owned()));
+        }
+    }
+
+    if !sections.contains("full_requires") {
+        return Err(if is_conan2_layout(&sections) {
+            ConanIniError::Conan2Layout
+        } else {
+            ConanIniError::MissingSection("full_requires".to_owned())
+        });
+    }
+    required_packages.sort();
+    Ok(required_packages)
+}
+
+/// conan 2 still writes conaninfo.txt files, but with settings and options only.
+fn is_conan2_layout(sections: &HashSet<String>) -> bool {
+    let has_section = |name: &str| sections.contains(name);
+    (has_section("settings") || has_section("options"))
+        && !has_section("full_requires")
+        && !has_section("requires")
+}
+
+/// Reads the packages of a conanbuildinfo.txt from the paths of its `[rootpath_<name>]` sections,
+/// which point to the package folders in the cache, like
+/// `<storage>/zlib/1.2.11/conan/stable/package/<package ID>`. Paths of other layouts, like
+/// short_paths or editable packages, tell no package ID and are skipped.
+pub fn parse_build_info_packages<P: AsRef<std::path::Path>>(
+    file_path: P,
+) -> Result<Vec<(String, String)>, ConanIniError> {
+    use std::io::BufRead;
+
+    let file_path = file_path.as_ref();
+    let reader = std::io::BufReader::new(std::fs::File::open(file_path)?);
+    let mut in_root_path = false;
+    let mut has_root_paths = false;
+    let mut required_packages = Vec::new();
+    for line in reader.split(b'\n') {
+        let line = line?;
+        if line.contains(&0) {
+            return Err(ConanIniError::NotText);
+        }
+        let line = String::from_utf8_lossy(&line);
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            in_root_path = name.trim().starts_with("rootpath_");
+            has_root_paths |= in_root_path;
+            continue;
+        }
+        if in_root_path {
+            match package_of_root_path(line) {
+                Some(package) => required_packages.push(package),
+                None => debug!(
+                    "'{}' names no package folder in '{}'",
+                    line,
+                    file_path.display()
+                ),
+            }
+        }
+    }
+
+    if !has_root_paths {
+        return Err(ConanIniError::MissingSection("rootpath_*".to_owned()));
+    }
+    required_packages.sort();
+    required_packages.dedup();
+    Ok(required_packages)
+}
+
+/// Returns the recipe reference and package ID of a package folder in the cache. Packages without
+/// user and channel are stored below `_/_`.
+fn package_of_root_path(path: &str) -> Option<(String, String)> {
+    let components: Vec<_> = path.split(['/', '\\']).filter(|c| !c.is_empty()).collect();
+    let [name, version, user, channel, "package", package_id] =
+        components.get(components.len().checked_sub(6)?..)?
+    else {
+        return None;
+    };
+    let reference = match (*user, *channel) {
+        ("_", "_") => format!("{}/{}", name, version),
+        (user, channel) => format!("{}/{}@{}/{}", name, version, user, channel),
+    };
+    Some((reference, package_id.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::{Path, PathBuf};
+
+    /// A folder for the test `name` with the given subfolders, removed on drop.
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        fn new(name: &str, dirs: &[&str]) -> TestDir {
+            let root = std::env::temp_dir().join(format!(
+                "conan_cleanup_scan_{}_{}",
+                std::process::id(),
+                name
+            ));
+            let _ = std::fs::remove_dir_all(&root);
+            for dir in dirs {
+                std::fs::create_dir_all(root.join(dir)).unwrap();
+            }
+            TestDir(root)
+        }
+
+        fn path(&self, dir: &str) -> String {
+            self.0.join(dir).to_string_lossy().into_owned()
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn nested_roots_collapse_into_their_ancestor() {
+        let dir = TestDir::new("nested", &["work/teamA/app", "other"]);
+        let (team, work, other) = (dir.path("work/teamA"), dir.path("work"), dir.path("other"));
+
+        let (kept, overlaps) = collapse_roots(&[&team, &work, &other]);
+
+        assert_eq!(kept, [work.as_str(), other.as_str()]);
+        assert_eq!(
+            overlaps,
+            [RootOverlap {
+                root_path: team.clone(),
+                ancestor: work.clone(),
+                duplicate: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn identical_roots_are_scanned_once() {
+        let dir = TestDir::new("identical", &["work"]);
+        let work = dir.path("work");
+        let dotted = dir.path("work/../work");
+
+        let (kept, overlaps) = collapse_roots(&[&work, &work, &dotted]);
+
+        assert_eq!(kept, [work.as_str()]);
+        assert_eq!(overlaps.len(), 2);
+        assert!(overlaps.iter().all(|overlap| overlap.duplicate));
+        assert!(overlaps.iter().all(|overlap| overlap.ancestor == work));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_aliases_are_detected() {
+        let dir = TestDir::new("symlinks", &["work/teamA"]);
+        std::os::unix::fs::symlink(dir.0.join("work"), dir.0.join("alias")).unwrap();
+        std::os::unix::fs::symlink(dir.0.join("work/teamA"), dir.0.join("team_link")).unwrap();
+        let (work, alias, team_link) = (dir.path("work"), dir.path("alias"), dir.path("team_link"));
+
+        let (kept, overlaps) = collapse_roots(&[&work, &alias, &team_link]);
+
+        assert_eq!(kept, [work.as_str()]);
+        assert_eq!(
+            overlaps,
+            [
+                RootOverlap {
+                    root_path: alias,
+                    ancestor: work.clone(),
+                    duplicate: true,
+                },
+                RootOverlap {
+                    root_path: team_link,
+                    ancestor: work,
+                    duplicate: false,
+                },
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn usage_files_reached_through_symlinks_are_read_once() {
+        let dir = TestDir::new("usage_symlinks", &["work/app"]);
+        std::fs::write(
+            dir.0.join("work/app/conaninfo.txt"),
+            "[full_requires]\n    zlib/1.2.11:aaa\n",
+        )
+        .unwrap();
+        std::os::unix::fs::symlink(dir.0.join("work/app"), dir.0.join("work/app_link")).unwrap();
+        let work = dir.path("work");
+
+        let usage_files = find_usage_files(
+            &[&work, &work],
+            &[usage_pattern::UsagePattern::conaninfo(
+                usage_pattern::DEFAULT_CONANINFO_PATTERN,
+            )],
+            DEFAULT_EXCLUDED_DIRS,
+            &[],
+            u64::MAX,
+            &CancellationToken::new(),
+        );
+
+        assert_eq!(usage_files.len(), 1);
+        assert!(Path::new(&usage_files[0].path).starts_with(&work));
+        assert_eq!(
+            usage_files[0].packages,
+            [("zlib/1.2.11".to_owned(), "aaa".to_owned())]
+        );
+    }
+
+    fn usage_file(packages: &[(&str, &str)]) -> UsageFile {
+        UsageFile {
+            path: PathBuf::from("conaninfo.txt"),
+            pattern: usage_pattern::UsagePattern::conaninfo(
+                usage_pattern::DEFAULT_CONANINFO_PATTERN,
+            ),
+            content_hash: String::new(),
+            packages: packages
+                .iter()
+                .map(|&(recipe_id, package_id)| (recipe_id.to_owned(), package_id.to_owned()))
+                .collect(),
+            pinned_recipes: Vec::new(),
+            failed_build: None,
+        }
+    }
+
+    #[test]
+    fn identical_package_ids_under_different_recipes_are_different_packages() {
+        // Header-only packages of all recipes share the same package ID.
+        let header_only = "5ab84d6acfe1f23c4fae0ab88f26e3a396351ac9";
+        let usage = find_packages_in_use(
+            &[usage_file(&[("fmt/8.0.0@corp/stable", header_only)])],
+            &[],
+        );
+
+        assert!(usage.contains_package("fmt/8.0.0@corp/stable", header_only));
+        assert!(!usage.contains_package("spdlog/1.9.0@corp/stable", header_only));
+        assert!(!usage.contains_package("fmt/8.0.0", header_only));
+        assert!(!usage.contains_package("fmt/8.0.1@corp/stable", header_only));
+    }
+
+    #[test]
+    fn revisions_are_ignored_when_matching_packages() {
+        let usage = find_packages_in_use(
+            &[usage_file(&[("zlib/1.2.11#rrev", "aaa#prev")])],
+            &[lockfile::LockedPackage {
+                reference: "boost/1.75.0".to_owned(),
+                package_id: None,
+            }],
+        );
+
+        assert!(usage.contains_package("zlib/1.2.11", "aaa"));
+        assert!(usage.contains_package("zlib/1.2.11#other", "aaa"));
+        assert!(!usage.contains_package("zlib/1.2.11", "bbb"));
+        assert!(usage.contains_recipe("boost/1.75.0#rrev"));
+        assert!(!usage.contains_package("boost/1.75.0", "aaa"));
+    }
+
+    #[test]
+    fn full_requires_are_split_into_recipe_and_package_id() {
+        let dir = TestDir::new("full_requires", &["app"]);
+        let conaninfo = dir.0.join("app/conaninfo.txt");
+        std::fs::write(
+            &conaninfo,
+            "[settings]\n    os=Linux\n[full_requires]\n    zlib/1.2.11@conan/stable:aaa\n    fmt/8.0.0:5ab84d6acfe1f23c4fae0ab88f26e3a396351ac9\n\n[full_options]\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            parse_required_packages(&conaninfo).unwrap(),
+            [
+                (
+                    "fmt/8.0.0".to_owned(),
+                    "5ab84d6acfe1f23c4fae0ab88f26e3a396351ac9".to_owned()
+                ),
+                ("zlib/1.2.11@conan/stable".to_owned(), "aaa".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn conaninfo_without_full_requires() {
+        let dir = TestDir::new("conaninfo_errors", &["app"]);
+        let write = |name: &str, content: &[u8]| {
+            let path = dir.0.join("app").join(name);
+            std::fs::write(&path, content).unwrap();
+            path
+        };
+
+        assert!(matches!(
+            parse_required_packages(write("conan2.txt", b"[settings]\nos=Linux\n[options]\n")),
+            Err(ConanIniError::Conan2Layout)
+        ));
+        assert!(matches!(
+            parse_required_packages(write("other.txt", b"[requires]\nzlib/1.2.11\n")),
+            Err(ConanIniError::MissingSection(ref section)) if section == "full_requires"
+        ));
+        assert!(matches!(
+            parse_required_packages(write("binary.txt", b"[full_requires]\n\0\0")),
+            Err(ConanIniError::NotText)
+        ));
+    }
+
+    #[test]
+    fn build_info_packages_are_read_from_the_root_paths() {
+        let dir = TestDir::new("build_info", &["app"]);
+        let build_info = dir.0.join("app/conanbuildinfo.txt");
+        std::fs::write(
+            &build_info,
+            "[includedirs]\n/home/user/.conan/data/zlib/1.2.11/_/_/package/aaa/include\n\
+             [rootpath_zlib]\n/home/user/.conan/data/zlib/1.2.11/_/_/package/aaa\n\
+             [rootpath_boost]\nC:\\Users\\user\\.conan\\data\\boost\\1.75.0\\corp\\stable\\package\\bbb\n\
+             [rootpath_short]\nC:\\.conan\\3a4b5c\\1\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            parse_build_info_packages(&build_info).unwrap(),
+            [
+                ("boost/1.75.0@corp/stable".to_owned(), "bbb".to_owned()),
+                ("zlib/1.2.11".to_owned(), "aaa".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn excluded_dirs_match_names_and_relative_paths() {
+        let root = "/work";
+
+        assert!(is_excluded_dir(
+            root,
+            Path::new("/work/app/.git"),
+            &[".git"]
+        ));
+        assert!(is_excluded_dir(
+            root,
+            Path::new("/work/app/build"),
+            &["app/build"]
+        ));
+        assert!(is_excluded_dir(
+            root,
+            Path::new("/work/node_modules"),
+            &["**/node_modules"]
+        ));
+        assert!(!is_excluded_dir(
+            root,
+            Path::new("/work/app/src"),
+            &[".git", "app/build"]
+        ));
+    }
+}
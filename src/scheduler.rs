@@ -0,0 +1,47 @@
+//! Runs work items on a pool of threads while serializing the items of each group.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// Calls `work` for every item of every group on up to `jobs` threads and returns the results
+/// grouped the same way as the input.
+///
+/// The items of one group are processed one after another in their given order, so at most one
+/// item per group is in flight at any time, while different groups are processed concurrently.
+pub fn run_grouped<G, T, R, F>(groups: Vec<(G, Vec<T>)>, jobs: usize, work: F) -> Vec<(G, Vec<R>)>
+where
+    G: Send,
+    T: Send,
+    R: Send,
+    F: Fn(&G, &T) -> R + Sync,
+{
+    let group_count = groups.len();
+    let queue = Mutex::new(groups.into_iter().enumerate().collect::<VecDeque<_>>());
+    let finished = Mutex::new(Vec::with_capacity(group_count));
+
+    // The workers run in the session of the calling thread, see `Session::run`.
+    let session = crate::session::current();
+    let worker = || {
+        session.run(|| loop {
+            let next_group = queue.lock().unwrap().pop_front();
+            let (index, (group, items)) = match next_group {
+                Some(next_group) => next_group,
+                None => break,
+            };
+
+            let results = items.iter().map(|item| work(&group, item)).collect();
+            finished.lock().unwrap().push((index, (group, results)));
+        })
+    };
+
+    let thread_count = jobs.max(1).min(group_count);
+    std::thread::scope(|scope| {
+        for _ in 0..thread_count {
+            scope.spawn(worker);
+        }
+    });
+
+    let mut finished = finished.into_inner().unwrap();
+    finished.sort_by_key(|(index, _)| *index);
+    finished.into_iter().map(|(_, group)| group).collect()
+}
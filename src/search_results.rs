@@ -0,0 +1,162 @@
+//! Decides where conan writes the JSON results of its searches. By default every search writes to a
+//! temporary file in a folder of the current run that is removed once the result was read,
+//! optionally every result is kept in a file of its own for later analysis.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub enum SearchResults {
+    /// Temporary files in a folder of the current run, numbered so that concurrent searches don't
+    /// overwrite each other's results.
+    Temporary { dir: PathBuf, count: AtomicUsize },
+    /// A folder of the current run, receiving one numbered file per search.
+    Kept { dir: PathBuf, count: AtomicUsize },
+}
+
+impl SearchResults {
+    /// Writes the search results of this run to temporary files in a new subfolder of `base_dir`
+    /// with a random name, so that neither concurrent runs nor files left behind by crashed runs
+    /// get mixed up with the results of this run. The folder is removed when the search results are
+    /// dropped.
+    pub fn temporary(base_dir: &Path) -> std::io::Result<SearchResults> {
+        let dir = loop {
+            let suffix = RandomState::new().build_hasher().finish();
+            let dir = base_dir.join(format!(
+                "conan_cleanup_{}_{:016x}",
+                std::process::id(),
+                suffix
+            ));
+            match std::fs::create_dir(&dir) {
+                Ok(()) => break dir,
+                Err(ref err) if err.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            }
+        };
+        Ok(SearchResults::Temporary {
+            dir,
+            count: AtomicUsize::new(0),
+        })
+    }
+
+    /// Keeps the search results of this run in a new subfolder of `base_dir`, named after the
+    /// start of the run in seconds since the UNIX epoch.
+    pub fn kept(base_dir: &Path) -> std::io::Result<SearchResults> {
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since_epoch| since_epoch.as_secs());
+        let mut dir = base_dir.join(started_at.to_string());
+        // Concurrent runs starting in the same second get folders of their own.
+        if dir.exists() {
+            dir = base_dir.join(format!("{}-{}", started_at, std::process::id()));
+        }
+        std::fs::create_dir_all(&dir)?;
+        Ok(SearchResults::Kept {
+            dir,
+            count: AtomicUsize::new(0),
+        })
+    }
+
+    /// Returns the file the next search, described by `label` (e.g. the searched reference),
+    /// writes its result to.
+    pub fn path_for(&self, label: &str) -> PathBuf {
+        match *self {
+            SearchResults::Temporary { ref dir, ref count } => {
+                let index = count.fetch_add(1, Ordering::SeqCst) + 1;
+                dir.join(format!("conan_search_result_{}.json", index))
+            }
+            SearchResults::Kept { ref dir, ref count } => {
+                let index = count.fetch_add(1, Ordering::SeqCst) + 1;
+                dir.join(format!("{:04}_{}.json", index, sanitize(label)))
+            }
+        }
+    }
+
+    /// Removes a result file returned by `path_for` once its result was read, unless results are
+    /// kept.
+    pub fn discard(&self, path: &Path) {
+        if let SearchResults::Temporary { .. } = *self {
+            match std::fs::remove_file(path) {
+                Ok(()) => {}
+                // conan may have reported its result on stdout without ever creating the file.
+                Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => {
+                    eprintln!(
+                        "Failed to remove temporary file '{}': {}",
+                        path.display(),
+                        err
+                    );
+                    eprintln!("Please remove the file manually.");
+                }
+            }
+        }
+    }
+}
+
+impl Drop for SearchResults {
+    /// Removes the folder of temporary search results including any results that were not
+    /// discarded, e.g. since the run failed while reading them.
+    fn drop(&mut self) {
+        if let SearchResults::Temporary { ref dir, .. } = *self {
+            if let Err(err) = std::fs::remove_dir_all(dir) {
+                eprintln!(
+                    "Failed to remove temporary folder '{}': {}",
+                    dir.display(),
+                    err
+                );
+                eprintln!("Please remove the folder manually.");
+            }
+        }
+    }
+}
+
+/// Replaces all characters that are not safe in file names on every platform.
+fn sanitize(label: &str) -> String {
+    label
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Removes the run folders in `base_dir` that were created more than `max_age` ago. Only folders
+/// named like the ones created by `SearchResults::kept` are considered. Returns the folders that
+/// were due for removal together with the result of removing them.
+pub fn prune(base_dir: &Path, max_age: Duration) -> Vec<(PathBuf, std::io::Result<()>)> {
+    let oldest_kept = match SystemTime::now()
+        .checked_sub(max_age)
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+    {
+        Some(oldest_kept) => oldest_kept.as_secs(),
+        None => return Vec::new(),
+    };
+    let entries = match std::fs::read_dir(base_dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut pruned = Vec::new();
+    for entry in entries.filter_map(|e| e.ok()) {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let started_at = name
+            .split('-')
+            .next()
+            .and_then(|secs| secs.parse::<u64>().ok());
+        match started_at {
+            Some(started_at) if started_at < oldest_kept && entry.path().is_dir() => {
+                let result = std::fs::remove_dir_all(entry.path());
+                pruned.push((entry.path(), result));
+            }
+            _ => {}
+        }
+    }
+    pruned.sort_by(|a, b| a.0.cmp(&b.0));
+    pruned
+}
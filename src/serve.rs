@@ -0,0 +1,700 @@
+//! `conan_cleanup serve --stdio`: a line-delimited JSON-RPC 2.0 server for tools that inspect the
+//! cache repeatedly, like editor extensions. The scan of the projects and the search of the cache
+//! are held in the process between requests, until `rescan` or a `scan` of other roots.
+//!
+//! Every line read is one request and is answered by exactly one line, also if it cannot be parsed.
+//! The session starts with `initialize`, which checks the `protocol_version` of the client against
+//! `PROTOCOL_VERSION`, and ends after answering `shutdown` or at the end of the input. Methods:
+//!
+//! - `initialize {protocol_version}`
+//! - `scan {roots}`: finds the usage files below the roots and searches the cache
+//! - `rescan {roots?}`: drops the held scan and plan and scans again
+//! - `plan {include?, exclude?, keep_latest?, preserve_newest_per_name?, older_than?}`
+//! - `execute {selection, plan_hash?}`: removes the selected packages of the held plan
+//! - `why {ref}`: tells why the packages of a recipe, or a single package, are kept or removed
+//! - `shutdown`
+
+use crate::cancel::CancellationToken;
+use crate::error::CleanupError;
+use crate::json_output::{self, PackageReference, RecipeCandidates};
+use crate::plan::{self, PlanOptions, RemovalPlan, Usage};
+use crate::scan::{self, PackageKey, UsageFile};
+use crate::state::PackageLabel;
+use crate::{cache, conan, duration, execute, search_results, usage_pattern};
+
+use serde_json::{json, Value};
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// Version of the protocol, raised with every incompatible change of the methods.
+pub const PROTOCOL_VERSION: u64 = 1;
+
+/// Usage files larger than this are skipped, like with the default of `--max-usage-file-size`.
+const MAX_USAGE_FILE_SIZE: u64 = 5 * 1024 * 1024;
+
+const METHODS: &[&str] = &[
+    "initialize",
+    "scan",
+    "rescan",
+    "plan",
+    "execute",
+    "why",
+    "shutdown",
+];
+
+// Error codes of JSON-RPC 2.0, and of the server for requests out of order and failed runs.
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const NOT_INITIALIZED: i64 = -32002;
+const OUT_OF_ORDER: i64 = -32001;
+const FAILED: i64 = -32000;
+
+/// Error of a request, answered instead of a result.
+struct RpcError {
+    code: i64,
+    message: String,
+    /// The JSON error of `json_output::error_to_json` for failed runs.
+    data: Option<Value>,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> RpcError {
+        RpcError {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+}
+
+impl From<CleanupError> for RpcError {
+    fn from(err: CleanupError) -> RpcError {
+        RpcError {
+            code: FAILED,
+            message: err.to_string(),
+            data: Some(json_output::error_to_json(&err)),
+        }
+    }
+}
+
+/// The usage of the projects and the packages of the cache, as found by `scan`.
+struct Scan {
+    root_paths: Vec<String>,
+    usage_files: Vec<UsageFile>,
+    usage: Usage,
+    storage_path: Option<PathBuf>,
+    recipe_ids: Vec<String>,
+    packages: BTreeMap<String, Vec<String>>,
+    package_settings: HashMap<(String, String), BTreeMap<String, String>>,
+    labels: HashMap<String, HashMap<String, PackageLabel>>,
+}
+
+/// State of a session.
+struct Server<'a> {
+    token: &'a CancellationToken,
+    initialized: bool,
+    scan: Option<Scan>,
+    plan: Option<RemovalPlan>,
+}
+
+/// Serves the requests read line by line from `input` until `shutdown` or the end of the input,
+/// writing one response line per request to `output`. Fails if the input cannot be read. A closed
+/// output ends the session, since nobody reads the responses any more.
+pub fn serve(
+    input: impl BufRead,
+    mut output: impl Write,
+    token: &CancellationToken,
+) -> std::io::Result<()> {
+    let mut server = Server {
+        token,
+        initialized: false,
+        scan: None,
+        plan: None,
+    };
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (response, shutdown) = server.handle_line(&line);
+        if writeln!(output, "{}", response)
+            .and_then(|_| output.flush())
+            .is_err()
+        {
+            break;
+        }
+        if shutdown || token.is_cancelled() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+impl Server<'_> {
+    /// Answers a request line. Returns the response and whether the session ends.
+    fn handle_line(&mut self, line: &str) -> (Value, bool) {
+        let request: Value = match serde_json::from_str(line) {
+            Ok(request) => request,
+            Err(err) => {
+                let error = RpcError::new(PARSE_ERROR, format!("Invalid JSON: {}", err));
+                return (response(&Value::Null, Err(error)), false);
+            }
+        };
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+        let method = match (request["jsonrpc"].as_str(), request["method"].as_str()) {
+            (Some("2.0"), Some(method)) if request.get("id").is_some() => method,
+            _ => {
+                let error = RpcError::new(
+                    INVALID_REQUEST,
+                    "Expected a JSON-RPC 2.0 request with 'id' and 'method'",
+                );
+                return (response(&id, Err(error)), false);
+            }
+        };
+        let params = match request.get("params") {
+            None | Some(Value::Null) => json!({}),
+            Some(params) if params.is_object() => params.clone(),
+            Some(_) => {
+                let error = RpcError::new(INVALID_PARAMS, "'params' must be an object");
+                return (response(&id, Err(error)), false);
+            }
+        };
+
+        let result = self.handle(method, &params);
+        (response(&id, result), method == "shutdown")
+    }
+
+    fn handle(&mut self, method: &str, params: &Value) -> Result<Value, RpcError> {
+        if !METHODS.contains(&method) {
+            return Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("Unknown method '{}'", method),
+            ));
+        }
+        match method {
+            "initialize" => return self.initialize(params),
+            "shutdown" => return Ok(Value::Null),
+            _ if !self.initialized => {
+                return Err(RpcError::new(
+                    NOT_INITIALIZED,
+                    "The session was not initialized, send 'initialize' first",
+                ))
+            }
+            _ => {}
+        }
+        match method {
+            "scan" => self.scan(params, false),
+            "rescan" => self.scan(params, true),
+            "plan" => self.plan(params),
+            "execute" => self.execute(params),
+            "why" => self.why(params),
+            _ => unreachable!(),
+        }
+    }
+
+    fn initialize(&mut self, params: &Value) -> Result<Value, RpcError> {
+        if self.initialized {
+            return Err(RpcError::new(
+                OUT_OF_ORDER,
+                "The session is already initialized",
+            ));
+        }
+        match params["protocol_version"].as_u64() {
+            Some(PROTOCOL_VERSION) => {}
+            version => {
+                return Err(RpcError::new(
+                    INVALID_PARAMS,
+                    format!(
+                        "Unsupported protocol version {}, the server speaks version {}",
+                        version.map_or_else(|| "none".to_owned(), |version| version.to_string()),
+                        PROTOCOL_VERSION
+                    ),
+                ))
+            }
+        }
+        self.initialized = true;
+        Ok(json!({
+            "protocol_version": PROTOCOL_VERSION,
+            "server": {"name": "conan_cleanup", "version": env!("CARGO_PKG_VERSION")},
+            "methods": METHODS,
+        }))
+    }
+
+    /// Scans the roots, or returns the held scan of the same roots unless `rescan` is set.
+    fn scan(&mut self, params: &Value, rescan: bool) -> Result<Value, RpcError> {
+        let root_paths = match params.get("roots") {
+            Some(roots) => string_list(roots, "roots")?,
+            None if rescan => match self.scan {
+                Some(ref scan) => scan.root_paths.clone(),
+                None => return Err(RpcError::new(OUT_OF_ORDER, "Nothing scanned yet")),
+            },
+            None => return Err(RpcError::new(INVALID_PARAMS, "Missing 'roots'")),
+        };
+        if root_paths.is_empty() {
+            return Err(RpcError::new(INVALID_PARAMS, "'roots' must not be empty"));
+        }
+        let cached = !rescan
+            && self
+                .scan
+                .as_ref()
+                .is_some_and(|scan| scan.root_paths == root_paths);
+        if !cached {
+            self.scan = None;
+            self.plan = None;
+            self.scan = Some(scan_projects(root_paths, self.token)?);
+        }
+
+        let scan = self.scan.as_ref().unwrap();
+        let mut packages_in_use: Vec<_> = scan
+            .usage_files
+            .iter()
+            .flat_map(|usage_file| &usage_file.packages)
+            .map(|(recipe, package)| PackageReference {
+                recipe: recipe.clone(),
+                package: package.clone(),
+            })
+            .collect();
+        packages_in_use.sort();
+        packages_in_use.dedup();
+        Ok(json!({
+            "cached": cached,
+            "roots": scan.root_paths,
+            "usage_files": scan.usage_files.len(),
+            "packages_in_use": packages_in_use,
+            "recipes": scan.recipe_ids.len(),
+            "packages": scan.packages.values().map(Vec::len).sum::<usize>(),
+        }))
+    }
+
+    fn plan(&mut self, params: &Value) -> Result<Value, RpcError> {
+        let scan = self
+            .scan
+            .as_ref()
+            .ok_or_else(|| RpcError::new(OUT_OF_ORDER, "Nothing scanned yet, send 'scan' first"))?;
+        let options = plan_options(params)?;
+        let included_packages: BTreeMap<String, Vec<String>> = scan
+            .packages
+            .iter()
+            .filter(|(recipe_id, _)| options.is_included(recipe_id))
+            .map(|(recipe_id, package_ids)| (recipe_id.clone(), package_ids.clone()))
+            .collect();
+        let removal_plan = plan::compute(
+            &scan.usage,
+            &plan::Cache {
+                storage_path: scan.storage_path.as_deref(),
+                recipe_ids: &scan.recipe_ids,
+                packages: &included_packages,
+                package_settings: &scan.package_settings,
+                labels: &scan.labels,
+                read_only: false,
+                local_index_recipes: &Default::default(),
+            },
+            &options,
+            self.token,
+        )?;
+
+        let candidates: Vec<_> = removal_plan
+            .packages
+            .iter()
+            .map(|(recipe_id, package_ids)| RecipeCandidates {
+                recipe: recipe_id.clone(),
+                packages: package_ids.clone(),
+            })
+            .collect();
+        let kept: Vec<_> = removal_plan
+            .kept
+            .iter()
+            .flat_map(|(recipe_id, packages)| {
+                packages.iter().map(move |(package_id, reason)| {
+                    json!({"recipe": recipe_id, "package": package_id, "reason": reason.to_string()})
+                })
+            })
+            .collect();
+        let result = json!({
+            "plan_hash": removal_plan.hash(),
+            "candidates": candidates,
+            "kept": kept,
+        });
+        self.plan = Some(removal_plan);
+        Ok(result)
+    }
+
+    /// Removes the selected packages, which must all be planned for removal by the held plan.
+    fn execute(&mut self, params: &Value) -> Result<Value, RpcError> {
+        let (scan, removal_plan) = match (self.scan.as_mut(), self.plan.as_ref()) {
+            (Some(scan), Some(removal_plan)) => (scan, removal_plan),
+            _ => {
+                return Err(RpcError::new(
+                    OUT_OF_ORDER,
+                    "Nothing planned yet, send 'plan' first",
+                ))
+            }
+        };
+        if let Some(plan_hash) = params.get("plan_hash") {
+            if plan_hash.as_str() != Some(removal_plan.hash().as_str()) {
+                return Err(RpcError::new(
+                    INVALID_PARAMS,
+                    "'plan_hash' does not match the held plan, send 'plan' again",
+                ));
+            }
+        }
+        let selection = params["selection"]
+            .as_array()
+            .ok_or_else(|| RpcError::new(INVALID_PARAMS, "Missing 'selection'"))?;
+        let mut packages: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for entry in selection {
+            let (recipe_id, package_id) =
+                match (entry["recipe"].as_str(), entry["package"].as_str()) {
+                    (Some(recipe_id), Some(package_id)) => (recipe_id, package_id),
+                    _ => {
+                        return Err(RpcError::new(
+                            INVALID_PARAMS,
+                            "Every entry of 'selection' needs 'recipe' and 'package'",
+                        ))
+                    }
+                };
+            let planned = removal_plan
+                .packages
+                .get(recipe_id)
+                .is_some_and(|package_ids| package_ids.iter().any(|id| id == package_id));
+            if !planned {
+                return Err(RpcError::new(
+                    INVALID_PARAMS,
+                    format!("'{}:{}' is not planned for removal", recipe_id, package_id),
+                ));
+            }
+            let package_ids = packages.entry(recipe_id.to_owned()).or_default();
+            if !package_ids.iter().any(|id| id == package_id) {
+                package_ids.push(package_id.to_owned());
+            }
+        }
+
+        let order = execute::removal_order(&packages, scan.storage_path.as_deref());
+        let execution = execute::execute(
+            &packages,
+            &order,
+            &execute::ExecuteOptions {
+                storage_path: scan.storage_path.as_deref(),
+                jobs: 1,
+                progress: None,
+            },
+            self.token,
+        )?;
+        // The held scan forgets the removed packages, the plan has to be made again.
+        for removal in execution
+            .removals
+            .iter()
+            .filter(|removal| removal.error.is_none())
+        {
+            if let (Some(package_ids), Some(package_id)) =
+                (scan.packages.get_mut(&removal.recipe), &removal.package)
+            {
+                package_ids.retain(|id| id != package_id);
+            }
+        }
+        self.plan = None;
+        Ok(json!({
+            "removals": execution.removals,
+            "freed_bytes": execution.freed_bytes,
+        }))
+    }
+
+    /// Tells for every package of a recipe, or for a single package given as
+    /// `<recipe>:<package ID>`, which usage files require it and what the held plan does with it.
+    fn why(&self, params: &Value) -> Result<Value, RpcError> {
+        let scan = self
+            .scan
+            .as_ref()
+            .ok_or_else(|| RpcError::new(OUT_OF_ORDER, "Nothing scanned yet, send 'scan' first"))?;
+        let reference = params["ref"]
+            .as_str()
+            .ok_or_else(|| RpcError::new(INVALID_PARAMS, "Missing 'ref'"))?;
+        let (recipe_id, package_id) = match reference.split_once(':') {
+            Some((recipe_id, package_id)) => (recipe_id, Some(package_id)),
+            None => (reference, None),
+        };
+        let package_ids: Vec<&String> = scan
+            .packages
+            .get(recipe_id)
+            .into_iter()
+            .flatten()
+            .filter(|id| package_id.is_none_or(|package_id| *id == package_id))
+            .collect();
+
+        let packages: Vec<_> = package_ids
+            .into_iter()
+            .map(|package_id| {
+                let consumers = scan
+                    .usage
+                    .consumers
+                    .get(&PackageKey::new(recipe_id, package_id))
+                    .cloned()
+                    .unwrap_or_default();
+                let (status, reason) = match self.plan {
+                    None => ("unplanned", None),
+                    Some(ref removal_plan) => {
+                        let kept = removal_plan
+                            .kept
+                            .get(recipe_id)
+                            .and_then(|packages| packages.iter().find(|(id, _)| id == package_id));
+                        let removed = removal_plan
+                            .packages
+                            .get(recipe_id)
+                            .is_some_and(|package_ids| package_ids.contains(package_id));
+                        match kept {
+                            Some((_, reason)) => ("kept", Some(reason.to_string())),
+                            None if removed => ("remove", None),
+                            None => ("ignored", None),
+                        }
+                    }
+                };
+                json!({
+                    "package": package_id,
+                    "status": status,
+                    "reason": reason,
+                    "consumers": consumers,
+                })
+            })
+            .collect();
+        Ok(json!({
+            "recipe": recipe_id,
+            "in_cache": scan.packages.contains_key(recipe_id),
+            "packages": packages,
+        }))
+    }
+}
+
+/// Finds the usage files below the roots with the default patterns and searches the whole cache.
+fn scan_projects(root_paths: Vec<String>, token: &CancellationToken) -> Result<Scan, CleanupError> {
+    for root_path in &root_paths {
+        if !Path::new(root_path).is_dir() {
+            return Err(CleanupError::InvalidArguments(format!(
+                "Root path '{}' is not an existing directory.",
+                root_path
+            )));
+        }
+    }
+    let conan_home = cache::conan_home();
+    let cache_dirs: Vec<_> = conan_home
+        .iter()
+        .cloned()
+        .chain(cache::conan2_home())
+        .collect();
+    let storage_path = conan_home.map(|conan_home| cache::storage_path(&conan_home));
+    let roots: Vec<&str> = root_paths.iter().map(String::as_str).collect();
+    let usage_patterns = [
+        usage_pattern::UsagePattern::conaninfo(usage_pattern::DEFAULT_CONANINFO_PATTERN),
+        usage_pattern::UsagePattern::conanbuildinfo(usage_pattern::DEFAULT_CONANBUILDINFO_PATTERN),
+        usage_pattern::UsagePattern::lockfile(usage_pattern::DEFAULT_LOCKFILE_PATTERN),
+    ];
+    let usage_files = scan::find_usage_files(
+        &roots,
+        &usage_patterns,
+        scan::DEFAULT_EXCLUDED_DIRS,
+        &cache_dirs,
+        MAX_USAGE_FILE_SIZE,
+        token,
+    );
+    if token.is_cancelled() {
+        return Err(CleanupError::Cancelled(Box::default()));
+    }
+    let usage = Usage::new(&usage_files, &roots, None);
+
+    let temp_dir = std::env::temp_dir();
+    let search_results = search_results::SearchResults::temporary(&temp_dir).map_err(|err| {
+        CleanupError::File("create a temporary folder in", temp_dir.clone(), err.into())
+    })?;
+    let recipe_ids = conan::dedup_recipe_ids(conan::conan_search(
+        &search_results,
+        None,
+        conan::parse_recipe_ids,
+        token,
+    )?);
+    let jobs = std::thread::available_parallelism().map_or(1, |jobs| jobs.get());
+    let search = conan::search_cache(
+        &search_results,
+        &recipe_ids,
+        storage_path.as_deref(),
+        None,
+        None,
+        jobs,
+        token,
+    )?;
+    let mut labels = HashMap::new();
+    for (recipe_id, packages) in &search.searched {
+        crate::state::update_package_labels(&mut labels, recipe_id, packages);
+    }
+    Ok(Scan {
+        root_paths,
+        usage_files,
+        usage,
+        storage_path,
+        recipe_ids,
+        package_settings: search.package_settings(),
+        packages: search.packages,
+        labels,
+    })
+}
+
+/// Reads the rules of `plan` from its parameters, using the defaults of the binary otherwise.
+fn plan_options(params: &Value) -> Result<PlanOptions, RpcError> {
+    let count = |name: &str| -> Result<Option<usize>, RpcError> {
+        match params.get(name) {
+            None | Some(Value::Null) => Ok(None),
+            Some(value) => value
+                .as_u64()
+                .map(|count| Some(count as usize))
+                .ok_or_else(|| {
+                    RpcError::new(INVALID_PARAMS, format!("'{}' must be a count", name))
+                }),
+        }
+    };
+    let mut options = PlanOptions {
+        keep_latest: count("keep_latest")?,
+        preserve_newest_per_name: count("preserve_newest_per_name")?,
+        ..PlanOptions::default()
+    };
+    if let Some(include) = params.get("include").filter(|value| !value.is_null()) {
+        options.include = Some(string_list(include, "include")?);
+    }
+    if let Some(exclude) = params.get("exclude").filter(|value| !value.is_null()) {
+        options.exclude = string_list(exclude, "exclude")?;
+    }
+    if let Some(older_than) = params.get("older_than").filter(|value| !value.is_null()) {
+        options.older_than = Some(
+            older_than
+                .as_str()
+                .and_then(duration::parse_duration)
+                .ok_or_else(|| {
+                    RpcError::new(INVALID_PARAMS, "'older_than' must be a duration like '30d'")
+                })?,
+        );
+    }
+    Ok(options)
+}
+
+fn string_list(value: &Value, name: &str) -> Result<Vec<String>, RpcError> {
+    value
+        .as_array()
+        .and_then(|values| {
+            values
+                .iter()
+                .map(|value| value.as_str().map(str::to_owned))
+                .collect()
+        })
+        .ok_or_else(|| {
+            RpcError::new(
+                INVALID_PARAMS,
+                format!("'{}' must be a list of strings", name),
+            )
+        })
+}
+
+/// Returns the response line to the request with the given ID.
+fn response(id: &Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+        Err(error) => {
+            let mut error_json = json!({"code": error.code, "message": error.message});
+            if let Some(data) = error.data {
+                error_json["data"] = data;
+            }
+            json!({"jsonrpc": "2.0", "id": id, "error": error_json})
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Serves the given request lines and returns the parsed response lines.
+    fn session(requests: &[&str]) -> Vec<Value> {
+        let mut output = Vec::new();
+        serve(
+            requests.join("\n").as_bytes(),
+            &mut output,
+            &CancellationToken::new(),
+        )
+        .unwrap();
+        String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    const INITIALIZE: &str =
+        r#"{"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {"protocol_version": 1}}"#;
+
+    #[test]
+    fn handshake() {
+        let responses = session(&[INITIALIZE]);
+
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0]["id"], 1);
+        assert_eq!(responses[0]["result"]["protocol_version"], PROTOCOL_VERSION);
+    }
+
+    #[test]
+    fn requests_before_the_handshake_are_refused() {
+        let responses = session(&[
+            r#"{"jsonrpc": "2.0", "id": 1, "method": "scan", "params": {"roots": ["."]}}"#,
+            r#"{"jsonrpc": "2.0", "id": 2, "method": "initialize", "params": {"protocol_version": 2}}"#,
+            r#"{"jsonrpc": "2.0", "id": 3, "method": "why", "params": {"ref": "zlib/1.2.11"}}"#,
+        ]);
+
+        assert_eq!(responses[0]["error"]["code"], NOT_INITIALIZED);
+        assert_eq!(responses[1]["error"]["code"], INVALID_PARAMS);
+        assert_eq!(responses[2]["error"]["code"], NOT_INITIALIZED);
+    }
+
+    #[test]
+    fn one_response_per_request_line() {
+        let responses = session(&[
+            INITIALIZE,
+            "not json",
+            "",
+            r#"{"jsonrpc": "2.0", "method": "why"}"#,
+            r#"{"jsonrpc": "2.0", "id": "a", "method": "frobnicate"}"#,
+            r#"{"jsonrpc": "2.0", "id": "b", "method": "why", "params": [1]}"#,
+            r#"{"jsonrpc": "2.0", "id": "c", "method": "plan"}"#,
+            r#"{"jsonrpc": "2.0", "id": "d", "method": "initialize", "params": {"protocol_version": 1}}"#,
+        ]);
+
+        let codes: Vec<_> = responses[1..]
+            .iter()
+            .map(|response| response["error"]["code"].as_i64().unwrap())
+            .collect();
+        assert_eq!(
+            codes,
+            [
+                PARSE_ERROR,
+                INVALID_REQUEST,
+                METHOD_NOT_FOUND,
+                INVALID_PARAMS,
+                OUT_OF_ORDER,
+                OUT_OF_ORDER
+            ]
+        );
+        assert_eq!(responses[1]["id"], Value::Null);
+        assert_eq!(responses[4]["id"], "b");
+    }
+
+    #[test]
+    fn shutdown_ends_the_session() {
+        let responses = session(&[
+            INITIALIZE,
+            r#"{"jsonrpc": "2.0", "id": 2, "method": "shutdown"}"#,
+            r#"{"jsonrpc": "2.0", "id": 3, "method": "why", "params": {"ref": "zlib/1.2.11"}}"#,
+        ]);
+
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[1]["id"], 2);
+        assert_eq!(responses[1]["result"], Value::Null);
+    }
+}
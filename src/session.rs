@@ -0,0 +1,151 @@
+//! The settings of a run, like the conan home to clean and `--read-only`, together with the state
+//! that belongs to the run, like the conan processes running for it and its transcript. Library
+//! functions take them from the session the calling thread runs in, see `Session::run`, so that an
+//! embedder can run cleanups with different settings side by side, each in a session of its own.
+//! Threads started by the library run in the session of the thread that started them.
+
+use crate::io_budget::{SystemClock, Throttle};
+use crate::logging::Level;
+use crate::transcript;
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicUsize;
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::time::Instant;
+
+thread_local! {
+    static CURRENT: RefCell<Option<Arc<Session>>> = const { RefCell::new(None) };
+}
+
+/// Settings that apply to everything a run does.
+#[derive(Debug, Clone, Default)]
+pub struct Settings {
+    /// The conan home to clean instead of the one of the environment, see `--conan-home`.
+    pub conan_home: Option<PathBuf>,
+    /// The major version of conan to assume instead of the detected one, see `--conan-version`.
+    pub conan_major_version: Option<u64>,
+    /// Refuses to run conan commands that may modify the cache, see `--read-only`.
+    pub read_only: bool,
+    /// How many conan processes may run at the same time. At least one always may.
+    pub conan_jobs: usize,
+    /// Patterns of the output lines of successful conan commands that are worth reporting, see
+    /// `subprocess::DEFAULT_IMPORTANT_PATTERNS`.
+    pub conan_warning_patterns: Vec<String>,
+    /// Rejects hand-edited input files that need normalization, see `text_file`.
+    pub strict_input_files: bool,
+    /// Bytes per second the filesystem operations are limited to, see `io_budget`.
+    pub io_budget: Option<u64>,
+    /// Records the conan commands of the run, see `transcript`.
+    pub transcript: bool,
+    /// Shows desktop notifications, see `notify`.
+    pub notify: bool,
+    /// Copies the warnings, the error and the summary to the logs of the system, see `system_log`.
+    pub system_log: bool,
+    /// Level of the diagnostics printed to stderr, see `logging`.
+    pub log_level: Option<Level>,
+    /// The effective configuration embedded in the files the run writes, see `run_info`.
+    pub configuration: Option<serde_json::Value>,
+}
+
+/// A run with its settings and the state collected while it runs.
+pub struct Session {
+    settings: Settings,
+    /// Number of conan processes currently running for the session.
+    pub(crate) conan_processes: Mutex<usize>,
+    pub(crate) conan_process_finished: Condvar,
+    /// Lines of conan output matching the warning patterns, with how often conan printed them.
+    pub(crate) conan_warnings: Mutex<BTreeMap<String, usize>>,
+    pub(crate) throttle: Option<Mutex<Throttle<SystemClock>>>,
+    pub(crate) transcript: Mutex<Vec<transcript::Entry>>,
+    /// When the user last answered a prompt, see `notify`.
+    pub(crate) last_interaction: Mutex<Option<Instant>>,
+    /// Folders below which all writes and removals fail, see `fsutil::protect`.
+    pub(crate) protected_dirs: Mutex<Vec<PathBuf>>,
+    /// Number of usage files that could not be read or parsed.
+    pub(crate) failed_usage_files: AtomicUsize,
+}
+
+impl Session {
+    pub fn new(settings: Settings) -> Arc<Session> {
+        let throttle = settings
+            .io_budget
+            .map(|bytes_per_second| Mutex::new(Throttle::new(SystemClock, bytes_per_second)));
+        Arc::new(Session {
+            settings,
+            conan_processes: Mutex::new(0),
+            conan_process_finished: Condvar::new(),
+            conan_warnings: Mutex::new(BTreeMap::new()),
+            throttle,
+            transcript: Mutex::new(Vec::new()),
+            // Starting the run counts as interaction, nobody needs to be notified right away.
+            last_interaction: Mutex::new(Some(Instant::now())),
+            protected_dirs: Mutex::new(Vec::new()),
+            failed_usage_files: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// Calls `f` in this session, so that the library functions it calls on this thread use the
+    /// settings and the state of this session. The thread returns to its previous session after.
+    pub fn run<R>(self: &Arc<Session>, f: impl FnOnce() -> R) -> R {
+        struct Restore(Option<Arc<Session>>);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                CURRENT.with(|current| *current.borrow_mut() = self.0.take());
+            }
+        }
+
+        let previous = CURRENT.with(|current| current.replace(Some(Arc::clone(self))));
+        let _restore = Restore(previous);
+        f()
+    }
+}
+
+/// Returns the session the calling thread runs in, or a session with the default settings if it
+/// runs in none.
+pub fn current() -> Arc<Session> {
+    static DEFAULT: OnceLock<Arc<Session>> = OnceLock::new();
+
+    CURRENT
+        .with(|current| current.borrow().clone())
+        .unwrap_or_else(|| Arc::clone(DEFAULT.get_or_init(|| Session::new(Settings::default()))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reading_only() -> Settings {
+        Settings {
+            read_only: true,
+            ..Settings::default()
+        }
+    }
+
+    #[test]
+    fn sessions_apply_to_their_threads_only() {
+        let session = Session::new(reading_only());
+        session.run(|| {
+            assert!(current().settings().read_only);
+            let other_thread = std::thread::spawn(|| current().settings().read_only);
+            assert!(!other_thread.join().unwrap());
+        });
+        assert!(!current().settings().read_only);
+    }
+
+    #[test]
+    fn nested_sessions_restore_the_outer_one() {
+        let outer = Session::new(reading_only());
+        let inner = Session::new(Settings::default());
+        outer.run(|| {
+            inner.run(|| assert!(!current().settings().read_only));
+            assert!(current().settings().read_only);
+        });
+    }
+}
@@ -0,0 +1,605 @@
+use crate::cache::RecipeFingerprint;
+use crate::conan::PackageInfo;
+use crate::fsutil;
+use crate::timing::{self, RemovalTiming};
+
+use serde_json::json;
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const STATE_FILE_NAME: &str = "conan_cleanup_state.json";
+pub const STATE_VERSION: u64 = 1;
+/// Number of runs kept in the history.
+const MAX_HISTORY_LENGTH: usize = 20;
+/// Label of packages built without any settings.
+pub const HEADER_ONLY_LABEL: &str = "headeronly";
+
+/// Returns the path of the state file belonging to the given conan home.
+pub fn state_file_path(conan_home: &Path) -> PathBuf {
+    conan_home.join(STATE_FILE_NAME)
+}
+
+/// Information that is kept between runs of the tool.
+#[derive(Debug, Default)]
+pub struct State {
+    /// Cache contents as seen by the previous run.
+    pub snapshot: Option<Snapshot>,
+    /// Human-friendly labels of the packages seen so far, by recipe and package ID.
+    pub labels: HashMap<String, HashMap<String, PackageLabel>>,
+    /// Successful cleanups of the conan home, oldest first.
+    pub history: Vec<CleanupRecord>,
+    /// When the last run that could remove packages started, in seconds since the UNIX epoch.
+    /// Used by `--cooldown`.
+    pub last_run_at: Option<u64>,
+    /// Recorded removal times by package name, used to estimate how long removals take.
+    pub removal_timings: BTreeMap<String, RemovalTiming>,
+}
+
+/// A successful cleanup of the conan home.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CleanupRecord {
+    /// When the cleanup finished, in seconds since the UNIX epoch.
+    pub finished_at: u64,
+    /// Total size of the removed packages.
+    pub freed_bytes: u64,
+}
+
+/// Per-recipe cache contents recorded by a run, used to skip unchanged recipes in incremental runs.
+#[derive(Debug)]
+pub struct Snapshot {
+    /// Hash of the effective configuration the snapshot was taken with.
+    pub config_hash: u64,
+    pub recipes: HashMap<String, RecipeSnapshot>,
+}
+
+#[derive(Debug)]
+pub struct RecipeSnapshot {
+    pub fingerprint: RecipeFingerprint,
+    pub package_ids: Vec<String>,
+}
+
+/// Label derived from the settings of a package. The hash of the settings the label was derived
+/// from is kept to detect package IDs that reappear with different settings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageLabel {
+    pub settings_hash: u64,
+    pub label: String,
+}
+
+/// Derives a short label like `win-x64-msvc16-Release-shared` from the settings and options of a
+/// package. Packages built without any settings are labeled `headeronly`, packages whose settings
+/// are not known `unknown`.
+fn package_label(package: &PackageInfo) -> String {
+    let settings = match package.settings {
+        Some(ref settings) => settings,
+        None => return "unknown".to_owned(),
+    };
+    if settings.is_empty() {
+        return HEADER_ONLY_LABEL.to_owned();
+    }
+
+    let mut parts = Vec::new();
+    if let Some(os) = settings.get("os") {
+        parts.push(match os.as_str() {
+            "Windows" => "win".to_owned(),
+            "Macos" => "mac".to_owned(),
+            os => os.to_lowercase(),
+        });
+    }
+    if let Some(arch) = settings.get("arch") {
+        parts.push(match arch.as_str() {
+            "x86_64" => "x64".to_owned(),
+            "armv8" => "arm64".to_owned(),
+            arch => arch.to_owned(),
+        });
+    }
+    if let Some(compiler) = settings.get("compiler") {
+        let compiler = match compiler.as_str() {
+            "Visual Studio" | "msvc" => "msvc".to_owned(),
+            "apple-clang" => "appleclang".to_owned(),
+            compiler => compiler.to_lowercase().replace(' ', ""),
+        };
+        let version = settings
+            .get("compiler.version")
+            .map(String::as_str)
+            .unwrap_or("");
+        parts.push(format!("{}{}", compiler, version));
+    }
+    if let Some(build_type) = settings.get("build_type") {
+        parts.push(build_type.clone());
+    }
+    match package.options.get("shared").map(String::as_str) {
+        Some("True") => parts.push("shared".to_owned()),
+        Some("False") => parts.push("static".to_owned()),
+        _ => {}
+    }
+
+    if parts.is_empty() {
+        "unknown".to_owned()
+    } else {
+        parts.join("-")
+    }
+}
+
+/// Records the labels of the given packages of a recipe, replacing labels of package IDs whose
+/// settings changed and forgetting package IDs that are no longer present.
+pub fn update_package_labels(
+    labels: &mut HashMap<String, HashMap<String, PackageLabel>>,
+    recipe_id: &str,
+    packages: &[PackageInfo],
+) {
+    let recipe_labels = labels.entry(recipe_id.to_owned()).or_default();
+    recipe_labels.retain(|package_id, _| packages.iter().any(|package| package.id == *package_id));
+
+    for package in packages {
+        let mut hasher = DefaultHasher::new();
+        package.settings.hash(&mut hasher);
+        package.options.hash(&mut hasher);
+        let settings_hash = hasher.finish();
+
+        let is_current = recipe_labels
+            .get(&package.id)
+            .is_some_and(|label| label.settings_hash == settings_hash);
+        if !is_current {
+            recipe_labels.insert(
+                package.id.clone(),
+                PackageLabel {
+                    settings_hash,
+                    label: package_label(package),
+                },
+            );
+        }
+    }
+}
+
+/// Loads the state from the given file, or returns an empty state if there is no file to load it
+/// from or it cannot be loaded. A corrupt file is moved aside, so that the run can write a new one.
+pub fn load_or_default(state_path: Option<&Path>) -> State {
+    let state_path = match state_path {
+        Some(path) => path,
+        None => return State::default(),
+    };
+
+    State::load(state_path).unwrap_or_else(|err| {
+        eprintln!(
+            "Failed to load state file '{}': {}",
+            state_path.display(),
+            err
+        );
+        // Moving a corrupt file aside lets this run write a fresh one.
+        if !matches!(err, StateError::Io(_)) {
+            match fsutil::quarantine(state_path) {
+                Ok(quarantine_path) => eprintln!(
+                    "Moved the corrupt state file to '{}'.",
+                    quarantine_path.display()
+                ),
+                Err(err) => eprintln!("Failed to move the corrupt state file aside: {}", err),
+            }
+        }
+        eprintln!("Continuing without information from previous runs.");
+        State::default()
+    })
+}
+
+/// Saves the state to the given file, unless the file is protected, see `fsutil::protect`, or its
+/// folder does not exist. Failures to save are reported but not fatal.
+pub fn save_unless_protected(state: &State, state_path: Option<&Path>) {
+    // Nothing is recorded with --read-only, or without a conan home to record it in.
+    let state_path = state_path
+        .filter(|path| !fsutil::is_protected(path) && path.parent().is_some_and(Path::is_dir));
+    if let Some(state_path) = state_path {
+        if let Err(err) = state.save(state_path) {
+            eprintln!(
+                "Failed to save state file '{}': {}",
+                state_path.display(),
+                err
+            );
+        }
+    }
+}
+
+impl State {
+    /// Loads the state from the given file. A missing file results in an empty state.
+    pub fn load(path: &Path) -> Result<State, StateError> {
+        let file_content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(State::default())
+            }
+            Err(err) => return Err(StateError::Io(err)),
+        };
+        let json: serde_json::Value = serde_json::from_str(&file_content)?;
+        crate::run_info::print_embedded(path, &json);
+
+        let version = json["version"]
+            .as_u64()
+            .ok_or_else(|| StateError::FormatError("Missing 'version' number".to_owned()))?;
+        if version != STATE_VERSION {
+            return Err(StateError::FormatError(format!(
+                "Unsupported version {}",
+                version
+            )));
+        }
+
+        let snapshot = match json.get("snapshot") {
+            Some(snapshot) if !snapshot.is_null() => Some(parse_snapshot(snapshot)?),
+            _ => None,
+        };
+        let labels = match json.get("labels") {
+            Some(labels) => parse_labels(labels)?,
+            None => HashMap::new(),
+        };
+        let history = match json.get("history") {
+            Some(history) => parse_history(history)?,
+            None => Vec::new(),
+        };
+        let removal_timings = match json.get("removal_timings") {
+            Some(removal_timings) => parse_removal_timings(removal_timings)?,
+            None => BTreeMap::new(),
+        };
+        Ok(State {
+            snapshot,
+            labels,
+            history,
+            last_run_at: json.get("last_run_at").and_then(serde_json::Value::as_u64),
+            removal_timings,
+        })
+    }
+
+    /// Returns the most recent successful cleanup, if any was recorded.
+    pub fn last_cleanup(&self) -> Option<&CleanupRecord> {
+        self.history.last()
+    }
+
+    /// Records a successful cleanup, dropping the oldest records if the history gets too long.
+    pub fn record_cleanup(&mut self, record: CleanupRecord) {
+        self.history.push(record);
+        if self.history.len() > MAX_HISTORY_LENGTH {
+            let excess = self.history.len() - MAX_HISTORY_LENGTH;
+            self.history.drain(..excess);
+        }
+    }
+
+    /// Records how long measuring and removing a package of the given recipe took.
+    pub fn record_removal(&mut self, recipe_id: &str, sizing: Duration, removal: Duration) {
+        self.removal_timings
+            .entry(timing::timing_key(recipe_id).to_owned())
+            .or_default()
+            .add(sizing, removal);
+    }
+
+    /// Writes the state to the given file, replacing any previous content.
+    pub fn save(&self, path: &Path) -> Result<(), StateError> {
+        let snapshot = self.snapshot.as_ref().map(|snapshot| {
+            let recipes: serde_json::Map<String, serde_json::Value> = snapshot
+                .recipes
+                .iter()
+                .map(|(recipe_id, recipe)| {
+                    (
+                        recipe_id.clone(),
+                        json!({
+                            "export_mtime": recipe.fingerprint.export_mtime,
+                            "package_count": recipe.fingerprint.package_count,
+                            "packages": recipe.package_ids,
+                        }),
+                    )
+                })
+                .collect();
+            json!({
+                "config_hash": format!("{:016x}", snapshot.config_hash),
+                "recipes": recipes,
+            })
+        });
+        let labels: serde_json::Map<String, serde_json::Value> = self
+            .labels
+            .iter()
+            .map(|(recipe_id, packages)| {
+                let packages: serde_json::Map<String, serde_json::Value> = packages
+                    .iter()
+                    .map(|(package_id, label)| {
+                        (
+                            package_id.clone(),
+                            json!({
+                                "settings_hash": format!("{:016x}", label.settings_hash),
+                                "label": label.label,
+                            }),
+                        )
+                    })
+                    .collect();
+                (recipe_id.clone(), serde_json::Value::Object(packages))
+            })
+            .collect();
+        let history: Vec<_> = self
+            .history
+            .iter()
+            .map(|record| {
+                json!({
+                    "finished_at": record.finished_at,
+                    "freed_bytes": record.freed_bytes,
+                })
+            })
+            .collect();
+        let removal_timings: serde_json::Map<String, serde_json::Value> = self
+            .removal_timings
+            .iter()
+            .map(|(name, timing)| {
+                (
+                    name.clone(),
+                    json!({
+                        "removals": timing.removals,
+                        "removal_seconds": timing.removal_seconds,
+                        "sizing_seconds": timing.sizing_seconds,
+                    }),
+                )
+            })
+            .collect();
+        let json = json!({
+            "version": STATE_VERSION,
+            "run_info": crate::run_info::to_json(),
+            "snapshot": snapshot,
+            "labels": labels,
+            "history": history,
+            "last_run_at": self.last_run_at,
+            "removal_timings": removal_timings,
+        });
+
+        crate::fsutil::atomic_write(path, serde_json::to_string_pretty(&json)?.as_bytes())?;
+        Ok(())
+    }
+}
+
+fn parse_snapshot(snapshot: &serde_json::Value) -> Result<Snapshot, StateError> {
+    let config_hash = snapshot["config_hash"]
+        .as_str()
+        .and_then(|hash| u64::from_str_radix(hash, 16).ok())
+        .ok_or_else(|| StateError::FormatError("Snapshot has no valid 'config_hash'".to_owned()))?;
+    let recipe_objects = snapshot["recipes"]
+        .as_object()
+        .ok_or_else(|| StateError::FormatError("Snapshot has no 'recipes' object".to_owned()))?;
+
+    let mut recipes = HashMap::new();
+    for (recipe_id, recipe) in recipe_objects {
+        let export_mtime = recipe["export_mtime"].as_u64();
+        let package_count = recipe["package_count"].as_u64();
+        let package_ids: Option<Vec<String>> = recipe["packages"].as_array().and_then(|ids| {
+            ids.iter()
+                .map(|id| id.as_str().map(str::to_owned))
+                .collect()
+        });
+        match (export_mtime, package_count, package_ids) {
+            (Some(export_mtime), Some(package_count), Some(package_ids)) => {
+                recipes.insert(
+                    recipe_id.clone(),
+                    RecipeSnapshot {
+                        fingerprint: RecipeFingerprint {
+                            export_mtime,
+                            package_count: package_count as usize,
+                        },
+                        package_ids,
+                    },
+                );
+            }
+            _ => {
+                return Err(StateError::FormatError(format!(
+                    "Snapshot of recipe '{}' is incomplete",
+                    recipe_id
+                )))
+            }
+        }
+    }
+
+    Ok(Snapshot {
+        config_hash,
+        recipes,
+    })
+}
+
+fn parse_labels(
+    labels: &serde_json::Value,
+) -> Result<HashMap<String, HashMap<String, PackageLabel>>, StateError> {
+    let recipe_objects = labels
+        .as_object()
+        .ok_or_else(|| StateError::FormatError("'labels' is not an object".to_owned()))?;
+
+    let mut recipes = HashMap::new();
+    for (recipe_id, packages) in recipe_objects {
+        let package_objects = packages.as_object().ok_or_else(|| {
+            StateError::FormatError(format!(
+                "Labels of recipe '{}' are not an object",
+                recipe_id
+            ))
+        })?;
+
+        let mut labels = HashMap::new();
+        for (package_id, label) in package_objects {
+            let settings_hash = label["settings_hash"]
+                .as_str()
+                .and_then(|hash| u64::from_str_radix(hash, 16).ok());
+            match (settings_hash, label["label"].as_str()) {
+                (Some(settings_hash), Some(label)) => {
+                    labels.insert(
+                        package_id.clone(),
+                        PackageLabel {
+                            settings_hash,
+                            label: label.to_owned(),
+                        },
+                    );
+                }
+                _ => {
+                    return Err(StateError::FormatError(format!(
+                        "Label of package '{}:{}' is incomplete",
+                        recipe_id, package_id
+                    )))
+                }
+            }
+        }
+        recipes.insert(recipe_id.clone(), labels);
+    }
+    Ok(recipes)
+}
+
+fn parse_history(history: &serde_json::Value) -> Result<Vec<CleanupRecord>, StateError> {
+    let records = history
+        .as_array()
+        .ok_or_else(|| StateError::FormatError("'history' is not an array".to_owned()))?;
+
+    records
+        .iter()
+        .map(|record| {
+            match (
+                record["finished_at"].as_u64(),
+                record["freed_bytes"].as_u64(),
+            ) {
+                (Some(finished_at), Some(freed_bytes)) => Ok(CleanupRecord {
+                    finished_at,
+                    freed_bytes,
+                }),
+                _ => Err(StateError::FormatError(
+                    "Cleanup record in 'history' is incomplete".to_owned(),
+                )),
+            }
+        })
+        .collect()
+}
+
+fn parse_removal_timings(
+    removal_timings: &serde_json::Value,
+) -> Result<BTreeMap<String, RemovalTiming>, StateError> {
+    let timing_objects = removal_timings
+        .as_object()
+        .ok_or_else(|| StateError::FormatError("'removal_timings' is not an object".to_owned()))?;
+
+    timing_objects
+        .iter()
+        .map(|(name, timing)| {
+            match (
+                timing["removals"].as_f64(),
+                timing["removal_seconds"].as_f64(),
+                timing["sizing_seconds"].as_f64(),
+            ) {
+                (Some(removals), Some(removal_seconds), Some(sizing_seconds)) => Ok((
+                    name.clone(),
+                    RemovalTiming {
+                        removals,
+                        removal_seconds,
+                        sizing_seconds,
+                    },
+                )),
+                _ => Err(StateError::FormatError(format!(
+                    "Removal timing of '{}' is incomplete",
+                    name
+                ))),
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+pub enum StateError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    FormatError(String),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StateError::Io(ref err) => err.fmt(f),
+            StateError::Json(ref err) => err.fmt(f),
+            StateError::FormatError(ref err) => write!(f, "Unexpected state file format: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for StateError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            StateError::Io(ref err) => Some(err),
+            StateError::Json(ref err) => Some(err),
+            StateError::FormatError(_) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for StateError {
+    fn from(err: std::io::Error) -> StateError {
+        StateError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for StateError {
+    fn from(err: serde_json::Error) -> StateError {
+        StateError::Json(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(settings: Option<&[(&str, &str)]>, options: &[(&str, &str)]) -> PackageInfo {
+        let map = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|&(key, value)| (key.to_owned(), value.to_owned()))
+                .collect()
+        };
+        PackageInfo {
+            id: "0123".to_owned(),
+            settings: settings.map(map),
+            options: map(options),
+        }
+    }
+
+    #[test]
+    fn package_labels() {
+        let windows = package(
+            Some(&[
+                ("os", "Windows"),
+                ("arch", "x86_64"),
+                ("compiler", "Visual Studio"),
+                ("compiler.version", "16"),
+                ("build_type", "Release"),
+            ]),
+            &[("shared", "True")],
+        );
+        let linux = package(
+            Some(&[
+                ("os", "Linux"),
+                ("compiler", "gcc"),
+                ("build_type", "Debug"),
+            ]),
+            &[("shared", "False"), ("fPIC", "True")],
+        );
+
+        assert_eq!(package_label(&windows), "win-x64-msvc16-Release-shared");
+        assert_eq!(package_label(&linux), "linux-gcc-Debug-static");
+        assert_eq!(package_label(&package(Some(&[]), &[])), "headeronly");
+        assert_eq!(package_label(&package(None, &[])), "unknown");
+    }
+
+    #[test]
+    fn package_labels_follow_changed_settings() {
+        let mut labels = HashMap::new();
+        let label = |labels: &HashMap<String, HashMap<String, PackageLabel>>| {
+            labels["zlib/1.2.11"]
+                .get("0123")
+                .map(|label| label.label.clone())
+        };
+        let debug = package(Some(&[("build_type", "Debug")]), &[]);
+        update_package_labels(&mut labels, "zlib/1.2.11", &[debug]);
+        assert_eq!(label(&labels).as_deref(), Some("Debug"));
+
+        let release = package(Some(&[("build_type", "Release")]), &[]);
+        update_package_labels(&mut labels, "zlib/1.2.11", &[release]);
+        assert_eq!(label(&labels).as_deref(), Some("Release"));
+
+        update_package_labels(&mut labels, "zlib/1.2.11", &[]);
+        assert_eq!(label(&labels), None);
+    }
+}
@@ -0,0 +1,280 @@
+//! Runs conan processes while limiting how many of them run at the same time across all phases of
+//! a session, and keeps track of the running ones so they can be shown on request. The output of
+//! successful conan commands is only relayed in verbose mode, otherwise just the lines matching
+//! important patterns are collected to be reported once at the end.
+
+use crate::cancel::CancellationToken;
+use crate::logging::{self, Level};
+use crate::session::{self, Session};
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+static RUNNING: Mutex<BTreeMap<u64, (String, Instant)>> = Mutex::new(BTreeMap::new());
+
+static STATUS_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Patterns of conan output lines that are worth reporting even if the command succeeded. They are
+/// matched case-insensitively against whole lines, `*` matches any number of characters and `?` a
+/// single one.
+pub const DEFAULT_IMPORTANT_PATTERNS: &[&str] =
+    &["*deprecat*", "*not found*", "*migrat*", "*is disabled*"];
+
+/// conan commands that only read the cache. All others are refused with `--read-only`.
+const READ_ONLY_COMMANDS: &[&str] = &["--version", "search", "list", "info"];
+
+/// Subcommands of conan 2 that only read the cache, while their siblings may modify it.
+const READ_ONLY_SUBCOMMANDS: &[[&str; 2]] = &[["cache", "path"], ["remote", "list"]];
+
+/// Returns whether the conan command only reads the cache, which `--read-only` allows.
+fn reads_only(args: &[&str]) -> bool {
+    // Showing the help of a command doesn't run it.
+    args.first()
+        .is_some_and(|command| READ_ONLY_COMMANDS.contains(command))
+        || READ_ONLY_SUBCOMMANDS
+            .iter()
+            .any(|subcommand| args.starts_with(subcommand))
+        || args.contains(&"--help")
+}
+
+/// Returns whether the complete output of conan commands is relayed to stderr instead of only
+/// collecting its important lines, i.e. whether `--verbose` was given.
+pub fn is_verbose() -> bool {
+    logging::is_enabled(Level::Debug)
+}
+
+/// Runs conan with the given arguments and waits for it to finish. Blocks while the maximum number
+/// of conan processes of the session is already running, see `Settings::conan_jobs`.
+pub fn run_conan(args: &[&str]) -> std::io::Result<Output> {
+    run_conan_unless_cancelled(args, &CancellationToken::new())
+}
+
+/// Like `run_conan`, but fails with `ErrorKind::Interrupted` instead of starting conan once `token`
+/// is cancelled.
+pub fn run_conan_unless_cancelled(
+    args: &[&str],
+    token: &CancellationToken,
+) -> std::io::Result<Output> {
+    let session = session::current();
+    if session.settings().read_only && !reads_only(args) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            format!(
+                "'{}' may modify the cache, which --read-only forbids",
+                crate::conan::command_line(args)
+            ),
+        ));
+    }
+    let output = {
+        let _slot = Slot::acquire(Arc::clone(&session));
+        // Checked after waiting for the slot, since the run may have been cancelled meanwhile.
+        if token.is_cancelled() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Interrupted,
+                "the run was cancelled",
+            ));
+        }
+        crate::transcript::record_executed(args);
+        let command_line = crate::conan::command_line(args);
+        debug!("Running '{}'", command_line);
+        let _registration = Registration::new(command_line);
+        crate::resources::count_subprocess();
+        let start = Instant::now();
+        let mut command = Command::new("conan");
+        command.args(args);
+        if let Some(ref conan_home) = session.settings().conan_home {
+            command.envs(crate::cache::conan_home_env(conan_home));
+        }
+        let output = command.output()?;
+        trace!(
+            "'{}' finished with {} after {:.2?}",
+            crate::conan::command_line(args),
+            output.status,
+            start.elapsed()
+        );
+        output
+    };
+
+    if is_verbose() {
+        relay_output(args, &output);
+    } else if output.status.success() {
+        // Failed commands are reported by the callers, including their output.
+        collect_important_lines(&session, &output);
+    }
+    Ok(output)
+}
+
+fn relay_output(args: &[&str], output: &Output) {
+    let command_line = crate::conan::command_line(args);
+    for stream in &[&output.stdout, &output.stderr] {
+        for line in String::from_utf8_lossy(stream).lines() {
+            if !line.trim().is_empty() {
+                eprintln!("[{}] {}", command_line, line);
+            }
+        }
+    }
+}
+
+fn collect_important_lines(session: &Session, output: &Output) {
+    let patterns: Vec<_> = session
+        .settings()
+        .conan_warning_patterns
+        .iter()
+        .map(|pattern| pattern.to_lowercase())
+        .collect();
+    let mut warnings = session.conan_warnings.lock().unwrap();
+    for stream in &[&output.stdout, &output.stderr] {
+        for line in String::from_utf8_lossy(stream).lines() {
+            let line = line.trim();
+            let lowercase_line = line.to_lowercase();
+            if patterns
+                .iter()
+                .any(|pattern| crate::wildcard::matches(pattern, &lowercase_line))
+            {
+                *warnings.entry(line.to_owned()).or_insert(0) += 1;
+            }
+        }
+    }
+}
+
+/// Prints the important lines collected from the output of successful conan commands to stderr,
+/// each one only once together with how often conan printed it.
+pub fn print_warning_summary() {
+    let session = session::current();
+    let warnings = session.conan_warnings.lock().unwrap();
+    for (line, count) in warnings.iter() {
+        if *count == 1 {
+            eprintln!("conan warned: '{}'", line);
+        } else {
+            eprintln!("conan warned {} times: '{}'", count, line);
+        }
+    }
+}
+
+/// Permission of a session to run a conan process, see `Settings::conan_jobs`.
+struct Slot(Arc<Session>);
+
+impl Slot {
+    fn acquire(session: Arc<Session>) -> Slot {
+        let limit = session.settings().conan_jobs.max(1);
+        let mut active_count = session.conan_processes.lock().unwrap();
+        while *active_count >= limit {
+            active_count = session.conan_process_finished.wait(active_count).unwrap();
+        }
+        *active_count += 1;
+        drop(active_count);
+        Slot(session)
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        *self.0.conan_processes.lock().unwrap() -= 1;
+        self.0.conan_process_finished.notify_one();
+    }
+}
+
+struct Registration(u64);
+
+impl Registration {
+    fn new(command_line: String) -> Registration {
+        let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+        RUNNING
+            .lock()
+            .unwrap()
+            .insert(id, (command_line, Instant::now()));
+        Registration(id)
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        RUNNING.lock().unwrap().remove(&self.0);
+    }
+}
+
+/// Prints the currently running conan command lines together with how long they have been running
+/// to stderr.
+pub fn print_status() {
+    let running = RUNNING.lock().unwrap();
+    if running.is_empty() {
+        eprintln!("No conan commands running.");
+        return;
+    }
+
+    eprintln!("Running conan commands:");
+    for (command_line, started) in running.values() {
+        eprintln!(
+            "  {} (running for {}s)",
+            command_line,
+            started.elapsed().as_secs()
+        );
+    }
+}
+
+/// Prints the running conan commands whenever the process receives SIGQUIT (on unix) or the given
+/// trigger file is created. The trigger file is removed after each status dump.
+pub fn watch_status_requests(trigger_file: PathBuf) {
+    install_signal_handler();
+    std::thread::spawn(move || loop {
+        std::thread::sleep(Duration::from_millis(500));
+        let triggered_by_file = std::fs::remove_file(&trigger_file).is_ok();
+        if STATUS_REQUESTED.swap(false, Ordering::SeqCst) || triggered_by_file {
+            print_status();
+        }
+    });
+}
+
+#[cfg(unix)]
+fn install_signal_handler() {
+    extern "C" fn request_status(_: libc::c_int) {
+        STATUS_REQUESTED.store(true, Ordering::SeqCst);
+    }
+
+    unsafe {
+        libc::signal(
+            libc::SIGQUIT,
+            request_status as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    }
+}
+
+#[cfg(not(unix))]
+fn install_signal_handler() {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn searches_read_only() {
+        assert!(reads_only(&["--version"]));
+        assert!(reads_only(&["search", "-j", "/tmp/result.json"]));
+        assert!(reads_only(&["search", "zlib/1.2.11@", "-r", "all"]));
+        assert!(reads_only(&["list", "*", "--format=json"]));
+        assert!(reads_only(&["info", "zlib/1.2.11@", "--paths"]));
+        assert!(reads_only(&["cache", "path", "zlib/1.2.11:bbb"]));
+        assert!(reads_only(&["remote", "list"]));
+    }
+
+    #[test]
+    fn removals_and_cleaning_modify_the_cache() {
+        assert!(!reads_only(&["remove", "zlib/1.2.11@", "-p", "bbb", "-f"]));
+        assert!(!reads_only(&["remove", "zlib/1.2.11#*:bbb", "-c"]));
+        assert!(!reads_only(&["cache", "clean"]));
+        assert!(!reads_only(&["remote", "remove", "conancenter"]));
+        assert!(!reads_only(&["install", "."]));
+        assert!(!reads_only(&[]));
+    }
+
+    #[test]
+    fn help_reads_only() {
+        assert!(reads_only(&["cache", "clean", "--help"]));
+        assert!(reads_only(&["remove", "--help"]));
+    }
+}
@@ -0,0 +1,231 @@
+//! The folder of `--support-bundle`: everything needed to reproduce a dry run for a bug report, i.e.
+//! the transcript of the conan commands, the conan search results the plan was made from, the
+//! effective configuration and the environment the tool found. The name of the current user is
+//! redacted from paths unless `--no-redact` is given, since bundles get attached to public issues.
+
+use crate::transcript;
+
+use serde_json::json;
+
+use std::path::Path;
+
+/// The transcript, for humans, in the bundle. The JSON form is next to it.
+pub const TRANSCRIPT_FILE: &str = "transcript.txt";
+/// The folder of the bundle receiving the conan search results.
+pub const SEARCH_RESULTS_DIR: &str = "search_results";
+const CONFIG_FILE: &str = "config.json";
+const ENVIRONMENT_FILE: &str = "environment.json";
+/// Replaces the name of the user in paths.
+const REDACTED_USER: &str = "<user>";
+
+/// Replaces the name of a user where it is a component of a path, e.g. in `/home/<name>/.conan`,
+/// but not in other words containing it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redaction {
+    username: Option<String>,
+}
+
+impl Redaction {
+    /// Redacts the name of the user running the tool.
+    pub fn of_current_user() -> Redaction {
+        Redaction {
+            username: std::env::var("USER")
+                .or_else(|_| std::env::var("USERNAME"))
+                .ok()
+                .filter(|username| !username.is_empty()),
+        }
+    }
+
+    /// Redacts nothing.
+    pub fn none() -> Redaction {
+        Redaction { username: None }
+    }
+
+    pub fn of_user(username: &str) -> Redaction {
+        Redaction {
+            username: Some(username.to_owned()).filter(|username| !username.is_empty()),
+        }
+    }
+
+    pub fn apply(&self, text: &str) -> String {
+        let username = match self.username {
+            Some(ref username) => username.as_str(),
+            None => return text.to_owned(),
+        };
+        let is_separator = |c: char| c == '/' || c == '\\';
+        let mut redacted = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find(username) {
+            let end = start + username.len();
+            let is_component = rest[..start].chars().next_back().is_some_and(is_separator)
+                && rest[end..].chars().next().is_none_or(|c| !is_name_char(c));
+            redacted.push_str(&rest[..start]);
+            redacted.push_str(if is_component {
+                REDACTED_USER
+            } else {
+                username
+            });
+            rest = &rest[end..];
+        }
+        redacted.push_str(rest);
+        redacted
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-' || c == '.'
+}
+
+/// Returns what the tool found about its environment, like a doctor command would report it: the
+/// conan version and folders, and the platform.
+pub fn environment() -> serde_json::Value {
+    let conan_home = crate::cache::conan_home();
+    let storage_path = conan_home
+        .as_ref()
+        .map(|conan_home| crate::cache::storage_path(conan_home).display().to_string());
+    json!({
+        "conan_version": crate::reference::conan_version().map(|version| version.to_string()),
+        "conan_home": conan_home.as_ref().map(|conan_home| conan_home.display().to_string()),
+        "conan2_home": crate::cache::conan2_home().map(|conan_home| conan_home.display().to_string()),
+        "storage_path": storage_path,
+        "revisions_enabled": conan_home.as_ref().map(|conan_home| crate::cache::revisions_enabled(conan_home)),
+        "os": std::env::consts::OS,
+        "arch": std::env::consts::ARCH,
+        "temp_dir": std::env::temp_dir().display().to_string(),
+    })
+}
+
+/// Writes the bundle to `dir`, which already holds the search results in `SEARCH_RESULTS_DIR`, and
+/// redacts all of its files.
+pub fn write(
+    dir: &Path,
+    entries: &[transcript::Entry],
+    environment: &serde_json::Value,
+    redaction: &Redaction,
+) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let transcript_path = dir.join(TRANSCRIPT_FILE);
+    transcript::write(&transcript_path, entries)?;
+    std::fs::write(
+        dir.join(CONFIG_FILE),
+        serde_json::to_string_pretty(&crate::run_info::to_json())?,
+    )?;
+    std::fs::write(
+        dir.join(ENVIRONMENT_FILE),
+        serde_json::to_string_pretty(environment)?,
+    )?;
+    redact_files(dir, redaction)
+}
+
+/// Redacts every file below `dir` in place.
+fn redact_files(dir: &Path, redaction: &Redaction) -> std::io::Result<()> {
+    if redaction.username.is_none() {
+        return Ok(());
+    }
+    for entry in walkdir::WalkDir::new(dir) {
+        let entry = entry.map_err(std::io::Error::from)?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let content = std::fs::read_to_string(entry.path())?;
+        let redacted = redaction.apply(&content);
+        if redacted != content {
+            std::fs::write(entry.path(), redacted)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        fn new(name: &str) -> TestDir {
+            let dir = std::env::temp_dir().join(format!(
+                "conan_cleanup_support_bundle_{}_{}",
+                std::process::id(),
+                name
+            ));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            TestDir(dir)
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn username_is_redacted_in_paths() {
+        let redaction = Redaction::of_user("jdoe");
+
+        assert_eq!(
+            redaction.apply("/home/jdoe/.conan/data"),
+            "/home/<user>/.conan/data"
+        );
+        assert_eq!(
+            redaction.apply(r#"{"path": "C:\\Users\\jdoe\\.conan"}"#),
+            r#"{"path": "C:\\Users\\<user>\\.conan"}"#
+        );
+        assert_eq!(redaction.apply("/home/jdoe"), "/home/<user>");
+        assert_eq!(
+            redaction.apply("/home/jdoe/a and /tmp/jdoe/b"),
+            "/home/<user>/a and /tmp/<user>/b"
+        );
+    }
+
+    #[test]
+    fn username_is_kept_outside_of_paths() {
+        let redaction = Redaction::of_user("jdoe");
+
+        assert_eq!(redaction.apply("jdoe/stable"), "jdoe/stable");
+        assert_eq!(redaction.apply("/home/jdoe2/x"), "/home/jdoe2/x");
+        assert_eq!(redaction.apply("/home/xjdoe/x"), "/home/xjdoe/x");
+        assert_eq!(Redaction::of_user("").apply("/home/jdoe"), "/home/jdoe");
+    }
+
+    #[test]
+    fn redaction_can_be_turned_off() {
+        assert_eq!(Redaction::none().apply("/home/jdoe"), "/home/jdoe");
+    }
+
+    #[test]
+    fn bundle_holds_the_transcript_configuration_and_environment() {
+        let dir = TestDir::new("bundle");
+        let search_results = dir.0.join(SEARCH_RESULTS_DIR).join("1");
+        std::fs::create_dir_all(&search_results).unwrap();
+        std::fs::write(
+            search_results.join("0001_recipes.json"),
+            r#"{"results": [], "path": "/home/jdoe/.conan/data"}"#,
+        )
+        .unwrap();
+        let entries = vec![transcript::Entry {
+            args: vec!["--version".to_owned()],
+            phase: "detecting conan",
+            target: None,
+            reason: "detect the conan version".to_owned(),
+            executed: true,
+        }];
+        let environment = json!({"conan_home": "/home/jdoe/.conan"});
+
+        write(&dir.0, &entries, &environment, &Redaction::of_user("jdoe")).unwrap();
+
+        let read = |path: &Path| std::fs::read_to_string(path).unwrap();
+        assert!(read(&dir.0.join(TRANSCRIPT_FILE)).contains("conan --version"));
+        assert!(read(&dir.0.join("transcript.txt.json")).contains("detecting conan"));
+        assert!(read(&dir.0.join(CONFIG_FILE)).contains("tool_version"));
+        assert_eq!(
+            read(&dir.0.join(ENVIRONMENT_FILE)),
+            "{\n  \"conan_home\": \"/home/<user>/.conan\"\n}"
+        );
+        assert!(read(&search_results.join("0001_recipes.json")).contains("/home/<user>/.conan"));
+    }
+}
@@ -0,0 +1,150 @@
+//! Copies of the warnings, the fatal error and the summary of a run for the logs of the system,
+//! which ops tooling collects: syslog with `--syslog` on Unix and the Windows Event Log with
+//! `--eventlog`. Entries are written with the APIs of the platform if the `system-log` feature is
+//! enabled. Failing to write an entry never affects the run, it is only reported with `--verbose`.
+
+use crate::error::ExitCode;
+
+/// Name of the application the entries are logged for.
+#[cfg(all(feature = "system-log", windows))]
+const APPLICATION: &str = "conan_cleanup";
+/// Event ID of the summary of a run.
+const SUMMARY_EVENT_ID: u32 = 100;
+/// Event ID of warnings, which have no codes of their own.
+const WARNING_EVENT_ID: u32 = 200;
+/// Event IDs of fatal errors start here, offset by the exit code.
+const ERROR_EVENT_ID_BASE: u32 = 300;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+/// An entry for the logs of the system.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub severity: Severity,
+    pub event_id: u32,
+    pub message: String,
+}
+
+/// Logs a warning that was printed, see `warning!`.
+pub fn warning(message: &str) {
+    log(&Entry {
+        severity: Severity::Warning,
+        event_id: WARNING_EVENT_ID,
+        message: message.to_owned(),
+    });
+}
+
+/// Logs the error that aborted the run. Findings like an overdue cleanup are logged as warnings.
+pub fn run_failed(exit_code: ExitCode, message: &str) {
+    let severity = match exit_code {
+        ExitCode::CandidatesFound | ExitCode::CleanupOverdue => Severity::Warning,
+        _ => Severity::Error,
+    };
+    log(&Entry {
+        severity,
+        event_id: ERROR_EVENT_ID_BASE + exit_code as u32,
+        message: message.to_owned(),
+    });
+}
+
+/// Logs the summary of the run, see `notify::finished_message`.
+pub fn run_finished(freed_bytes: u64, failure_count: usize, dry_run: bool) {
+    let severity = match failure_count {
+        0 => Severity::Info,
+        _ => Severity::Warning,
+    };
+    log(&Entry {
+        severity,
+        event_id: SUMMARY_EVENT_ID,
+        message: crate::notify::finished_message(freed_bytes, failure_count, dry_run),
+    });
+}
+
+/// Writes an entry to the logs of the system if the session copies its output there, see
+/// `Settings::system_log`.
+fn log(entry: &Entry) {
+    if !crate::session::current().settings().system_log {
+        return;
+    }
+    if let Err(err) = deliver(entry) {
+        if crate::subprocess::is_verbose() {
+            eprintln!("Failed to write to the system log: {}", err);
+        }
+    }
+}
+
+#[cfg(not(feature = "system-log"))]
+fn deliver(_entry: &Entry) -> Result<(), String> {
+    Err("not supported by this build".to_owned())
+}
+
+#[cfg(all(feature = "system-log", unix))]
+fn deliver(entry: &Entry) -> Result<(), String> {
+    use std::ffi::CString;
+    use std::sync::Once;
+
+    static OPEN: Once = Once::new();
+    // syslog keeps the identifier, so it must live for the rest of the process.
+    static IDENTIFIER: &[u8] = b"conan_cleanup\0";
+    OPEN.call_once(|| unsafe {
+        libc::openlog(
+            IDENTIFIER.as_ptr() as *const libc::c_char,
+            libc::LOG_PID,
+            libc::LOG_USER,
+        )
+    });
+
+    let priority = match entry.severity {
+        Severity::Info => libc::LOG_INFO,
+        Severity::Warning => libc::LOG_WARNING,
+        Severity::Error => libc::LOG_ERR,
+    };
+    // syslog has no event IDs, so they are part of the message.
+    let message = CString::new(format!("[{}] {}", entry.event_id, entry.message))
+        .map_err(|err| err.to_string())?;
+    unsafe {
+        libc::syslog(
+            priority,
+            b"%s\0".as_ptr() as *const libc::c_char,
+            message.as_ptr(),
+        )
+    };
+    Ok(())
+}
+
+#[cfg(all(feature = "system-log", windows))]
+fn deliver(entry: &Entry) -> Result<(), String> {
+    let severity = match entry.severity {
+        Severity::Info => "INFORMATION",
+        Severity::Warning => "WARNING",
+        Severity::Error => "ERROR",
+    };
+    // eventcreate registers the application as event source of the Application log on first use.
+    let status = std::process::Command::new("eventcreate")
+        .args([
+            "/L",
+            "APPLICATION",
+            "/SO",
+            APPLICATION,
+            "/T",
+            severity,
+            "/ID",
+        ])
+        .arg(entry.event_id.to_string())
+        .arg("/D")
+        .arg(&entry.message)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status();
+    match status {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(format!("eventcreate failed with {}", status)),
+        Err(err) => Err(err.to_string()),
+    }
+}
@@ -0,0 +1,163 @@
+//! Reads text files that users edit by hand, like keep files and profiles. Files edited on Windows
+//! often start with a byte order mark, mix line endings or have trailing whitespace, which would
+//! make the first line or lines ending with `\r` silently fail to match. Such files are normalized
+//! with a warning, or rejected with `--strict-input-files`.
+
+use std::fmt;
+use std::path::Path;
+
+const BYTE_ORDER_MARK: char = '\u{feff}';
+/// Maximum number of line numbers listed per kind of normalization.
+const MAX_LISTED_LINES: usize = 10;
+
+/// What had to be normalized in a text file. Line numbers start at 1.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Normalization {
+    pub byte_order_mark: bool,
+    /// Lines whose line ending differs from the one most lines of the file end with.
+    pub mixed_line_endings: Vec<usize>,
+    /// Lines ending with whitespace, including carriage returns that are not part of a `\r\n`.
+    pub trailing_whitespace: Vec<usize>,
+}
+
+impl Normalization {
+    pub fn is_empty(&self) -> bool {
+        *self == Normalization::default()
+    }
+}
+
+/// Reads the lines of a text file without byte order mark, line endings and trailing whitespace.
+/// Empty lines are kept, so that the position of a line is its line number. Prints a warning if
+/// the file had to be normalized, or fails with `--strict-input-files`, see
+/// `Settings::strict_input_files`.
+pub fn read_lines(path: &Path) -> Result<Vec<String>, TextFileError> {
+    let content = std::fs::read_to_string(path)?;
+    let (lines, normalization) = normalize(&content);
+    if !normalization.is_empty() {
+        if crate::session::current().settings().strict_input_files {
+            return Err(TextFileError::NotNormalized(normalization));
+        }
+        warning!(
+            "'{}' has {}, which were ignored. Pass --strict-input-files to reject such files.",
+            path.display(),
+            normalization
+        );
+    }
+    Ok(lines)
+}
+
+/// Splits `content` into normalized lines, see `read_lines`.
+pub fn normalize(content: &str) -> (Vec<String>, Normalization) {
+    let mut normalization = Normalization::default();
+    let content = match content.strip_prefix(BYTE_ORDER_MARK) {
+        Some(content) => {
+            normalization.byte_order_mark = true;
+            content
+        }
+        None => content,
+    };
+
+    let mut lines = Vec::new();
+    let mut crlf_lines = Vec::new();
+    let mut lf_lines = Vec::new();
+    for (index, line) in content.split_inclusive('\n').enumerate() {
+        let number = index + 1;
+        let line = match line.strip_suffix('\n') {
+            Some(line) => match line.strip_suffix('\r') {
+                Some(line) => {
+                    crlf_lines.push(number);
+                    line
+                }
+                None => {
+                    lf_lines.push(number);
+                    line
+                }
+            },
+            None => line,
+        };
+        let trimmed = line.trim_end();
+        if trimmed.len() != line.len() {
+            normalization.trailing_whitespace.push(number);
+        }
+        lines.push(trimmed.to_owned());
+    }
+    if !crlf_lines.is_empty() && !lf_lines.is_empty() {
+        normalization.mixed_line_endings = if crlf_lines.len() <= lf_lines.len() {
+            crlf_lines
+        } else {
+            lf_lines
+        };
+    }
+    (lines, normalization)
+}
+
+impl fmt::Display for Normalization {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = Vec::new();
+        if self.byte_order_mark {
+            parts.push("a byte order mark".to_owned());
+        }
+        if !self.mixed_line_endings.is_empty() {
+            parts.push(format!(
+                "mixed line endings (differing in {})",
+                describe_lines(&self.mixed_line_endings)
+            ));
+        }
+        if !self.trailing_whitespace.is_empty() {
+            parts.push(format!(
+                "trailing whitespace ({})",
+                describe_lines(&self.trailing_whitespace)
+            ));
+        }
+        parts.join(", ").fmt(f)
+    }
+}
+
+/// Describes line numbers like `lines 1, 4 and 3 more`.
+fn describe_lines(numbers: &[usize]) -> String {
+    let listed: Vec<_> = numbers
+        .iter()
+        .take(MAX_LISTED_LINES)
+        .map(usize::to_string)
+        .collect();
+    let noun = if numbers.len() == 1 { "line" } else { "lines" };
+    match numbers.len().checked_sub(MAX_LISTED_LINES) {
+        Some(more) if more > 0 => format!("{} {} and {} more", noun, listed.join(", "), more),
+        _ => format!("{} {}", noun, listed.join(", ")),
+    }
+}
+
+#[derive(Debug)]
+pub enum TextFileError {
+    Io(std::io::Error),
+    /// The file had to be normalized and `--strict-input-files` was given.
+    NotNormalized(Normalization),
+}
+
+impl fmt::Display for TextFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TextFileError::Io(ref err) => err.fmt(f),
+            TextFileError::NotNormalized(ref normalization) => write!(
+                f,
+                "File has {}, which --strict-input-files rejects",
+                normalization
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TextFileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            TextFileError::Io(ref err) => Some(err),
+            TextFileError::NotNormalized(_) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for TextFileError {
+    fn from(err: std::io::Error) -> TextFileError {
+        TextFileError::Io(err)
+    }
+}
@@ -0,0 +1,305 @@
+//! Estimates of how long removing packages takes, for the confirmation prompt and
+//! `--simulate-timing`. They are based on the removal times of previous runs, recorded per package
+//! name in the state file, and on the time it took to measure the packages of the current plan.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Seconds a package removal is assumed to take when no removal has been recorded yet: starting
+/// conan, which takes about a second, plus deleting a package of a few hundred MB.
+pub const DEFAULT_SECONDS_PER_PACKAGE: f64 = 2.0;
+/// Seconds measuring a package before its removal is assumed to take when it was not measured.
+pub const DEFAULT_SIZING_SECONDS_PER_PACKAGE: f64 = 0.2;
+/// Number of removals the averages are made of at most, so that they follow slower or faster
+/// machines and caches instead of being dominated by old runs.
+const MAX_SAMPLES: f64 = 100.0;
+
+/// Recorded removal times of the packages of a package name.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RemovalTiming {
+    /// Number of removals the totals are made of, at most `MAX_SAMPLES`.
+    pub removals: f64,
+    /// Total seconds conan took to remove the packages.
+    pub removal_seconds: f64,
+    /// Total seconds it took to measure the packages before removing them.
+    pub sizing_seconds: f64,
+}
+
+impl RemovalTiming {
+    /// Adds a removal, scaling the older ones down once there are more than `MAX_SAMPLES`.
+    pub fn add(&mut self, sizing: Duration, removal: Duration) {
+        self.removals += 1.0;
+        self.removal_seconds += removal.as_secs_f64();
+        self.sizing_seconds += sizing.as_secs_f64();
+        if self.removals > MAX_SAMPLES {
+            let scale = MAX_SAMPLES / self.removals;
+            self.removals = MAX_SAMPLES;
+            self.removal_seconds *= scale;
+            self.sizing_seconds *= scale;
+        }
+    }
+
+    fn average_removal_seconds(&self) -> f64 {
+        self.removal_seconds / self.removals
+    }
+
+    fn average_sizing_seconds(&self) -> f64 {
+        self.sizing_seconds / self.removals
+    }
+}
+
+/// Returns the key removal times are recorded by: the package name of the recipe reference.
+pub fn timing_key(recipe_id: &str) -> &str {
+    recipe_id.split('/').next().unwrap_or(recipe_id)
+}
+
+/// How far an estimate can be trusted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Confidence {
+    /// No removal was recorded, the estimate assumes `DEFAULT_SECONDS_PER_PACKAGE`.
+    Low,
+    /// Some packages are of names without recorded removals and assume the average of all names.
+    Medium,
+    /// Removals of every package name were recorded.
+    High,
+}
+
+impl Confidence {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Confidence::Low => "low",
+            Confidence::Medium => "medium",
+            Confidence::High => "high",
+        }
+    }
+}
+
+/// Estimated duration of removing the packages of a recipe.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecipeEstimate {
+    pub recipe: String,
+    pub packages: usize,
+    pub removal: Duration,
+    pub sizing: Duration,
+}
+
+/// Estimated duration of removing the packages of a plan.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Estimate {
+    /// Time spent removing through conan, with the removals spread over the concurrent jobs.
+    pub removal: Duration,
+    /// Time spent measuring the packages before removing them, spread the same way.
+    pub sizing: Duration,
+    pub recipes: Vec<RecipeEstimate>,
+    pub confidence: Confidence,
+    /// Number of packages of names without recorded removals.
+    pub unrecorded_packages: usize,
+}
+
+impl Estimate {
+    pub fn total(&self) -> Duration {
+        self.removal + self.sizing
+    }
+
+    /// Formats the estimate for humans, e.g. `~11 minutes removal + 3 minutes sizing`, labeled as
+    /// low confidence unless removals were recorded.
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "~{} removal + {} sizing",
+            crate::duration::format_duration(self.removal),
+            crate::duration::format_duration(self.sizing)
+        );
+        if self.confidence == Confidence::Low {
+            summary.push_str(&format!(
+                " (low confidence: no removals recorded yet, assuming {}s per package)",
+                DEFAULT_SECONDS_PER_PACKAGE
+            ));
+        }
+        summary
+    }
+}
+
+/// Estimates how long removing the packages, given as package count per recipe, takes with `jobs`
+/// concurrent removals. `measured_sizing` holds the time it took to measure the packages of a
+/// recipe in this run, which stands in for the recorded sizing times.
+pub fn estimate<'a>(
+    packages: impl IntoIterator<Item = (&'a str, usize)>,
+    timings: &BTreeMap<String, RemovalTiming>,
+    measured_sizing: &BTreeMap<String, Duration>,
+    jobs: usize,
+) -> Estimate {
+    let overall = timings
+        .values()
+        .fold(RemovalTiming::default(), |mut overall, timing| {
+            overall.removals += timing.removals;
+            overall.removal_seconds += timing.removal_seconds;
+            overall.sizing_seconds += timing.sizing_seconds;
+            overall
+        });
+    let overall = Some(overall).filter(|overall| overall.removals > 0.0);
+
+    let mut unrecorded_packages = 0;
+    let recipes: Vec<_> = packages
+        .into_iter()
+        .map(|(recipe_id, package_count)| {
+            let recorded = timings
+                .get(timing_key(recipe_id))
+                .filter(|timing| timing.removals > 0.0);
+            if recorded.is_none() {
+                unrecorded_packages += package_count;
+            }
+            let (removal_seconds, sizing_seconds) = match recorded.or(overall.as_ref()) {
+                Some(timing) => (
+                    timing.average_removal_seconds(),
+                    timing.average_sizing_seconds(),
+                ),
+                None => (
+                    DEFAULT_SECONDS_PER_PACKAGE,
+                    DEFAULT_SIZING_SECONDS_PER_PACKAGE,
+                ),
+            };
+            let count = package_count as f64;
+            RecipeEstimate {
+                recipe: recipe_id.to_owned(),
+                packages: package_count,
+                removal: Duration::from_secs_f64(removal_seconds * count),
+                sizing: measured_sizing
+                    .get(recipe_id)
+                    .copied()
+                    .unwrap_or_else(|| Duration::from_secs_f64(sizing_seconds * count)),
+            }
+        })
+        .collect();
+
+    let confidence = if overall.is_none() {
+        Confidence::Low
+    } else if unrecorded_packages > 0 {
+        Confidence::Medium
+    } else {
+        Confidence::High
+    };
+    Estimate {
+        removal: spread(recipes.iter().map(|recipe| recipe.removal), jobs),
+        sizing: spread(recipes.iter().map(|recipe| recipe.sizing), jobs),
+        recipes,
+        confidence,
+        unrecorded_packages,
+    }
+}
+
+/// Returns how long work items take when spread over `jobs` workers, each item on a single worker
+/// like the packages of a recipe, longest items first.
+fn spread(durations: impl Iterator<Item = Duration>, jobs: usize) -> Duration {
+    let mut durations: Vec<_> = durations.collect();
+    durations.sort_unstable_by(|a, b| b.cmp(a));
+    let mut workers = vec![Duration::ZERO; jobs.max(1)];
+    for duration in durations {
+        let least_busy = workers.iter_mut().min().unwrap();
+        *least_busy += duration;
+    }
+    workers.into_iter().max().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(entries: &[(&str, u32, f64)]) -> BTreeMap<String, RemovalTiming> {
+        entries
+            .iter()
+            .map(|&(name, removals, seconds)| {
+                let mut timing = RemovalTiming::default();
+                for _ in 0..removals {
+                    timing.add(
+                        Duration::from_secs_f64(seconds / 10.0),
+                        Duration::from_secs_f64(seconds),
+                    );
+                }
+                (name.to_owned(), timing)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn without_history_the_default_is_low_confidence() {
+        let estimate = estimate(
+            vec![("zlib/1.2.11", 3), ("boost/1.75.0@user/stable", 2)],
+            &BTreeMap::new(),
+            &BTreeMap::new(),
+            1,
+        );
+
+        assert_eq!(estimate.confidence, Confidence::Low);
+        assert_eq!(estimate.unrecorded_packages, 5);
+        assert_eq!(
+            estimate.removal,
+            Duration::from_secs_f64(5.0 * DEFAULT_SECONDS_PER_PACKAGE)
+        );
+        assert!(estimate.summary().contains("low confidence"));
+    }
+
+    #[test]
+    fn recorded_names_use_their_average() {
+        let timings = history(&[("zlib", 4, 1.0), ("boost", 2, 10.0)]);
+        let estimate = estimate(
+            vec![("zlib/1.2.11", 3), ("boost/1.75.0@user/stable", 2)],
+            &timings,
+            &BTreeMap::new(),
+            1,
+        );
+
+        assert_eq!(estimate.confidence, Confidence::High);
+        assert_eq!(estimate.recipes[0].removal, Duration::from_secs(3));
+        assert_eq!(estimate.recipes[1].removal, Duration::from_secs(20));
+        assert_eq!(estimate.removal, Duration::from_secs(23));
+        assert_eq!(estimate.sizing, Duration::from_millis(2300));
+        assert!(!estimate.summary().contains("low confidence"));
+    }
+
+    #[test]
+    fn unrecorded_names_use_the_average_of_all_names() {
+        let timings = history(&[("zlib", 1, 2.0), ("boost", 3, 6.0)]);
+        let estimate = estimate(vec![("qt/5.15.2", 2)], &timings, &BTreeMap::new(), 1);
+
+        assert_eq!(estimate.confidence, Confidence::Medium);
+        assert_eq!(estimate.unrecorded_packages, 2);
+        assert_eq!(estimate.removal, Duration::from_secs(10));
+    }
+
+    #[test]
+    fn measured_sizing_replaces_the_recorded_one() {
+        let timings = history(&[("zlib", 1, 1.0)]);
+        let measured = vec![("zlib/1.2.11".to_owned(), Duration::from_secs(7))]
+            .into_iter()
+            .collect();
+        let estimate = estimate(vec![("zlib/1.2.11", 2)], &timings, &measured, 1);
+
+        assert_eq!(estimate.sizing, Duration::from_secs(7));
+    }
+
+    #[test]
+    fn recipes_are_spread_over_the_jobs() {
+        let timings = history(&[("a", 1, 1.0)]);
+        let recipes = vec![("a/1", 6), ("a/2", 3), ("a/3", 3), ("a/4", 2)];
+
+        let serial = estimate(recipes.clone(), &timings, &BTreeMap::new(), 1);
+        let parallel = estimate(recipes, &timings, &BTreeMap::new(), 2);
+
+        assert_eq!(serial.removal, Duration::from_secs(14));
+        assert_eq!(parallel.removal, Duration::from_secs(8));
+    }
+
+    #[test]
+    fn averages_follow_recent_removals() {
+        let mut timing = RemovalTiming::default();
+        for _ in 0..1000 {
+            timing.add(Duration::ZERO, Duration::from_secs(1));
+        }
+        for _ in 0..100 {
+            timing.add(Duration::ZERO, Duration::from_secs(3));
+        }
+
+        assert_eq!(timing.removals, MAX_SAMPLES);
+        assert!(timing.average_removal_seconds() > 2.0);
+    }
+}
@@ -0,0 +1,329 @@
+//! The transcript of `--transcript`: every conan command of a dry run in the order of the run, the
+//! ones that were executed, like the searches, and the removals that would have been executed, each
+//! with its phase, target reference and the reason it was planned. It shows exactly what a real
+//! run would do to the cache, which makes it part of the support bundle.
+
+use serde_json::json;
+
+use std::path::{Path, PathBuf};
+
+/// A conan command of the run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub args: Vec<String>,
+    /// What the run was doing, e.g. `searching packages`.
+    pub phase: &'static str,
+    /// The recipe or package reference the command works on, if any.
+    pub target: Option<String>,
+    pub reason: String,
+    /// Whether the command was executed or only planned, i.e. left out by `--dry-run`.
+    pub executed: bool,
+}
+
+impl Entry {
+    pub fn command_line(&self) -> String {
+        let args: Vec<&str> = self.args.iter().map(String::as_str).collect();
+        crate::conan::command_line(&args)
+    }
+}
+
+/// Records a conan command that is about to be executed, if the session records its commands, see
+/// `Settings::transcript`.
+pub fn record_executed(args: &[&str]) {
+    if !crate::session::current().settings().transcript {
+        return;
+    }
+    let (phase, target, reason) = classify(args);
+    push(Entry {
+        args: args.iter().map(|arg| arg.to_string()).collect(),
+        phase,
+        target,
+        reason: reason.to_owned(),
+        executed: true,
+    });
+}
+
+/// Records a conan command a run without `--dry-run` would have executed, for the given reason.
+pub fn record_planned(args: Vec<String>, reason: &str) {
+    if !crate::session::current().settings().transcript {
+        return;
+    }
+    let (phase, target, _) = classify(&args.iter().map(String::as_str).collect::<Vec<_>>());
+    push(Entry {
+        args,
+        phase,
+        target,
+        reason: reason.to_owned(),
+        executed: false,
+    });
+}
+
+fn push(entry: Entry) {
+    crate::session::current()
+        .transcript
+        .lock()
+        .unwrap()
+        .push(entry);
+}
+
+/// Returns the commands recorded in the session in the order they were recorded.
+pub fn entries() -> Vec<Entry> {
+    crate::session::current().transcript.lock().unwrap().clone()
+}
+
+/// Returns the phase, the target reference and the reason of a conan command by its arguments.
+pub fn classify(args: &[&str]) -> (&'static str, Option<String>, &'static str) {
+    let positional: Vec<&str> = {
+        // Skips options and their values, e.g. `-j <file>` and `-r <remote>`.
+        let mut positional = Vec::new();
+        let mut args = args.iter().skip(1);
+        while let Some(&arg) = args.next() {
+            match arg {
+                "-j" | "--json" | "-r" | "--remote" | "-p" | "--package" => {
+                    args.next();
+                }
+                _ if arg.starts_with('-') => {}
+                _ => positional.push(arg),
+            }
+        }
+        positional
+    };
+    let target = positional
+        .first()
+        .filter(|&&target| target != "*")
+        .map(|target| target.to_string());
+    let remote = args.iter().any(|&arg| arg == "-r" || arg == "--remote");
+    match args.first().copied() {
+        Some("--version") => ("detecting conan", None, "detect the conan version"),
+        Some("search") | Some("list") if remote => (
+            "checking remotes",
+            target,
+            "check whether the recipe can be downloaded again",
+        ),
+        Some("search") | Some("list") => match target {
+            Some(pattern) => (
+                "searching packages",
+                Some(
+                    pattern
+                        .trim_end_matches(":*")
+                        .trim_end_matches("#*")
+                        .to_owned(),
+                ),
+                "list the packages of the recipe",
+            ),
+            None => ("searching recipes", None, "list the recipes of the cache"),
+        },
+        Some("remote") => ("checking remotes", None, "list the configured remotes"),
+        Some("info") => ("locating folders", target, "find the folders of the recipe"),
+        Some("cache") if positional.first() == Some(&"path") => (
+            "locating folders",
+            positional.get(1).map(|target| target.to_string()),
+            "find the folder of the package",
+        ),
+        Some("cache") if args.contains(&"--help") => (
+            "cleaning the cache",
+            None,
+            "check whether the cache can be cleaned",
+        ),
+        Some("cache") => (
+            "cleaning the cache",
+            None,
+            "clean the temporary folders of the cache",
+        ),
+        Some("remove") => {
+            let package_id = args
+                .windows(2)
+                .find(|pair| pair[0] == "-p" || pair[0] == "--package")
+                .map(|pair| pair[1]);
+            match (target, package_id) {
+                (Some(recipe), Some(package_id)) => (
+                    "removing packages",
+                    Some(format!("{}:{}", recipe, package_id)),
+                    "remove the package",
+                ),
+                (Some(ref reference), None) if reference.contains(':') => (
+                    "removing packages",
+                    Some(reference.clone()),
+                    "remove the package",
+                ),
+                (target, _) => ("removing recipes", target, "remove the recipe"),
+            }
+        }
+        _ => ("other", target, "run conan"),
+    }
+}
+
+/// Formats the transcript for humans, one command per line followed by its details.
+pub fn to_text(entries: &[Entry]) -> String {
+    let mut text = String::from(
+        "# conan commands of the run in order. 'planned' ones were left out by --dry-run.\n",
+    );
+    for (index, entry) in entries.iter().enumerate() {
+        text.push_str(&format!(
+            "{:>4}. {} {}\n      phase: {}, target: {}, reason: {}\n",
+            index + 1,
+            if entry.executed {
+                "executed"
+            } else {
+                "planned "
+            },
+            entry.command_line(),
+            entry.phase,
+            entry.target.as_deref().unwrap_or("-"),
+            entry.reason
+        ));
+    }
+    text
+}
+
+/// Returns the transcript as JSON, with the run info.
+pub fn to_json(entries: &[Entry]) -> serde_json::Value {
+    json!({
+        "run_info": crate::run_info::to_json(),
+        "commands": entries
+            .iter()
+            .map(|entry| json!({
+                "command_line": entry.command_line(),
+                "args": entry.args,
+                "phase": entry.phase,
+                "target": entry.target,
+                "reason": entry.reason,
+                "executed": entry.executed,
+            }))
+            .collect::<Vec<_>>(),
+    })
+}
+
+/// Returns the file the JSON form of the transcript at `path` is written to, `<path>.json`.
+pub fn json_path(path: &Path) -> PathBuf {
+    let mut json_path = path.as_os_str().to_owned();
+    json_path.push(".json");
+    PathBuf::from(json_path)
+}
+
+/// Writes the transcript to `path` for humans and to `json_path(path)` as JSON.
+pub fn write(path: &Path, entries: &[Entry]) -> std::io::Result<()> {
+    std::fs::write(path, to_text(entries))?;
+    std::fs::write(
+        json_path(path),
+        serde_json::to_string_pretty(&to_json(entries))?,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classified(args: &[&str]) -> (&'static str, Option<String>) {
+        let (phase, target, _) = classify(args);
+        (phase, target)
+    }
+
+    #[test]
+    fn searches_of_conan_1() {
+        assert_eq!(
+            classified(&["search", "-j", "/tmp/result.json"]),
+            ("searching recipes", None)
+        );
+        assert_eq!(
+            classified(&["search", "-j", "/tmp/result.json", "zlib/1.2.11@"]),
+            ("searching packages", Some("zlib/1.2.11@".to_owned()))
+        );
+        assert_eq!(
+            classified(&[
+                "search",
+                "zlib/1.2.11@",
+                "-r",
+                "all",
+                "-j",
+                "/tmp/remote.json"
+            ]),
+            ("checking remotes", Some("zlib/1.2.11@".to_owned()))
+        );
+    }
+
+    #[test]
+    fn searches_of_conan_2() {
+        assert_eq!(
+            classified(&["list", "*", "--format=json"]),
+            ("searching recipes", None)
+        );
+        assert_eq!(
+            classified(&["list", "zlib/1.2.11#*:*", "--format=json"]),
+            ("searching packages", Some("zlib/1.2.11".to_owned()))
+        );
+        assert_eq!(
+            classified(&["list", "zlib/1.2.11#abc:*", "--format=json"]),
+            ("searching packages", Some("zlib/1.2.11#abc".to_owned()))
+        );
+        assert_eq!(
+            classified(&["cache", "path", "zlib/1.2.11:bbb"]),
+            ("locating folders", Some("zlib/1.2.11:bbb".to_owned()))
+        );
+    }
+
+    #[test]
+    fn removals() {
+        assert_eq!(
+            classified(&["remove", "zlib/1.2.11@", "-p", "bbb", "-f"]),
+            ("removing packages", Some("zlib/1.2.11@:bbb".to_owned()))
+        );
+        assert_eq!(
+            classified(&["remove", "zlib/1.2.11#*:bbb", "-c"]),
+            ("removing packages", Some("zlib/1.2.11#*:bbb".to_owned()))
+        );
+        assert_eq!(
+            classified(&["remove", "zlib/1.2.11@", "-f"]),
+            ("removing recipes", Some("zlib/1.2.11@".to_owned()))
+        );
+    }
+
+    #[test]
+    fn text_and_json_keep_the_order() {
+        let entries = vec![
+            Entry {
+                args: vec!["--version".to_owned()],
+                phase: "detecting conan",
+                target: None,
+                reason: "detect the conan version".to_owned(),
+                executed: true,
+            },
+            Entry {
+                args: vec![
+                    "remove".to_owned(),
+                    "zlib/1.2.11@".to_owned(),
+                    "-f".to_owned(),
+                ],
+                phase: "removing recipes",
+                target: Some("zlib/1.2.11@".to_owned()),
+                reason: "no packages left".to_owned(),
+                executed: false,
+            },
+        ];
+
+        let text = to_text(&entries);
+        let version = text.find("1. executed conan --version").unwrap();
+        let removal = text
+            .find("2. planned  conan remove zlib/1.2.11@ -f")
+            .unwrap();
+        assert!(version < removal);
+        assert!(text.contains("target: zlib/1.2.11@, reason: no packages left"));
+
+        let json = to_json(&entries);
+        assert_eq!(json["commands"][0]["executed"], true);
+        assert_eq!(
+            json["commands"][1]["command_line"],
+            "conan remove zlib/1.2.11@ -f"
+        );
+        assert_eq!(json["commands"][1]["phase"], "removing recipes");
+        assert_eq!(json["commands"][1]["executed"], false);
+    }
+
+    #[test]
+    fn json_is_written_next_to_the_text() {
+        assert_eq!(
+            json_path(Path::new("/tmp/transcript.txt")),
+            Path::new("/tmp/transcript.txt.json")
+        );
+    }
+}
@@ -0,0 +1,107 @@
+//! File name patterns deciding which files below the root paths are usage files, and in which
+//! format they are read.
+
+use std::fmt;
+
+/// Default pattern of usage files, in the conaninfo format.
+pub const DEFAULT_CONANINFO_PATTERN: &str = "conaninfo.txt";
+/// Default pattern of usage files in the conanbuildinfo format, which older build systems leave
+/// behind when they clean up conaninfo.txt.
+pub const DEFAULT_CONANBUILDINFO_PATTERN: &str = "conanbuildinfo.txt";
+/// Default pattern of usage files in the lockfile format, which conan writes next to the build
+/// trees of projects that don't leave conaninfo.txt files behind.
+pub const DEFAULT_LOCKFILE_PATTERN: &str = "conan.lock";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UsageFormat {
+    /// conaninfo.txt files written by `conan install`, whose `[full_requires]` name the packages.
+    Conaninfo,
+    /// conanbuildinfo.txt files written by the `txt` generator, whose `[rootpath_*]` sections point
+    /// to the folders of the packages.
+    Conanbuildinfo,
+    /// conan 1 or conan 2 lockfiles. conan 2 lockfiles don't pin package IDs, so all packages of
+    /// the recipes they pin are in use.
+    Lockfile,
+}
+
+impl UsageFormat {
+    pub fn from_name(name: &str) -> Option<UsageFormat> {
+        match name {
+            "conaninfo" => Some(UsageFormat::Conaninfo),
+            "conanbuildinfo" => Some(UsageFormat::Conanbuildinfo),
+            "lock" | "lockfile" => Some(UsageFormat::Lockfile),
+            _ => None,
+        }
+    }
+
+    /// Name of the format as written to the provenance document.
+    pub fn name(self) -> &'static str {
+        match self {
+            UsageFormat::Conaninfo => "conaninfo",
+            UsageFormat::Conanbuildinfo => "conanbuildinfo",
+            UsageFormat::Lockfile => "lockfile",
+        }
+    }
+}
+
+/// A file name pattern of usage files together with the format of the matching files.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct UsagePattern {
+    pub format: UsageFormat,
+    /// Pattern matched against file names, supporting `*` and `?`.
+    pub pattern: String,
+}
+
+impl UsagePattern {
+    pub fn conaninfo(pattern: &str) -> UsagePattern {
+        UsagePattern {
+            format: UsageFormat::Conaninfo,
+            pattern: pattern.to_owned(),
+        }
+    }
+
+    pub fn conanbuildinfo(pattern: &str) -> UsagePattern {
+        UsagePattern {
+            format: UsageFormat::Conanbuildinfo,
+            pattern: pattern.to_owned(),
+        }
+    }
+
+    pub fn lockfile(pattern: &str) -> UsagePattern {
+        UsagePattern {
+            format: UsageFormat::Lockfile,
+            pattern: pattern.to_owned(),
+        }
+    }
+
+    /// Parses a pattern with format hint like `lock:*.conan.lock`, `conaninfo:deps.conaninfo` or
+    /// `conanbuildinfo:conanbuildinfo.txt`.
+    pub fn parse(value: &str) -> Result<UsagePattern, String> {
+        let (format, pattern) = value
+            .split_once(':')
+            .ok_or_else(|| format!("'{}' is not of the form FORMAT:PATTERN", value))?;
+        let format = UsageFormat::from_name(format).ok_or_else(|| {
+            format!(
+                "Unknown usage file format '{}', expected 'conaninfo', 'conanbuildinfo' or 'lock'",
+                format
+            )
+        })?;
+        if pattern.is_empty() {
+            return Err(format!("'{}' has an empty pattern", value));
+        }
+        Ok(UsagePattern {
+            format,
+            pattern: pattern.to_owned(),
+        })
+    }
+}
+
+impl fmt::Display for UsagePattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.format {
+            UsageFormat::Conaninfo => write!(f, "{}", self.pattern),
+            UsageFormat::Conanbuildinfo => write!(f, "conanbuildinfo:{}", self.pattern),
+            UsageFormat::Lockfile => write!(f, "lock:{}", self.pattern),
+        }
+    }
+}
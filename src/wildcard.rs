@@ -0,0 +1,139 @@
+//! Matching of simple wildcard patterns, where `*` matches any number of characters (including
+//! path separators) and `?` matches a single one.
+
+use std::path::{Path, PathBuf};
+
+use walkdir::WalkDir;
+
+/// Returns whether `text` matches `pattern` as a whole.
+pub fn matches(pattern: &str, text: &str) -> bool {
+    matches_bytes(pattern.as_bytes(), text.as_bytes())
+}
+
+/// Returns the indices of all patterns matching `text` as a whole, in the order of the patterns.
+pub fn matching_patterns<'a, I>(patterns: I, text: &str) -> Vec<usize>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    patterns
+        .into_iter()
+        .enumerate()
+        .filter(|(_, pattern)| matches(pattern, text))
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// Matches by backtracking to the most recent `*` only: a later `*` can take over whatever an
+/// earlier one would have matched, so earlier ones never need to be retried. This keeps matching
+/// linear in practice, even for patterns with several `*`.
+fn matches_bytes(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` in the pattern and of the text it was tried to match up to.
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                // Let the last `*` match one more character and retry the rest of the pattern.
+                Some((star_p, star_t)) => {
+                    star = Some((star_p, star_t + 1));
+                    p = star_p + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// Returns the files matching the given path pattern, sorted by path. Only the folder named by the
+/// pattern's components up to the first wildcard is searched, recursively.
+pub fn find_files(pattern: &str) -> Vec<PathBuf> {
+    let pattern = pattern.replace('\\', "/");
+    let base_end = match pattern.find(['*', '?']) {
+        Some(wildcard) => pattern[..wildcard].rfind('/').map_or(0, |slash| slash + 1),
+        None => {
+            let path = PathBuf::from(pattern);
+            return if path.is_file() {
+                vec![path]
+            } else {
+                Vec::new()
+            };
+        }
+    };
+    let base_dir = if base_end == 0 {
+        Path::new(".")
+    } else {
+        Path::new(&pattern[..base_end])
+    };
+
+    let mut files: Vec<_> = WalkDir::new(base_dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter(|e| {
+            let relative_path = e
+                .path()
+                .strip_prefix(base_dir)
+                .unwrap_or_else(|_| e.path())
+                .to_string_lossy()
+                .replace('\\', "/");
+            matches(&pattern[base_end..], &relative_path)
+        })
+        .map(|e| e.into_path())
+        .collect();
+    files.sort();
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_literals_and_wildcards() {
+        assert!(matches("zlib", "zlib"));
+        assert!(!matches("zlib", "zlib1"));
+        assert!(matches("z?ib", "zlib"));
+        assert!(!matches("z?ib", "zib"));
+        assert!(matches("*", ""));
+        assert!(matches("*", "any/thing@at/all"));
+        assert!(matches("boost/*", "boost/1.75.0"));
+        assert!(!matches("boost/*", "boostx/1.75.0"));
+        assert!(matches("*.lock", "metadata.json.lock"));
+        assert!(!matches("*.lock", "metadata.json.lock.bak"));
+    }
+
+    #[test]
+    fn backtracks_to_the_last_star() {
+        assert!(matches("*a*b", "xaxxab"));
+        assert!(matches("a*b*c", "abbbcbc"));
+        assert!(!matches("a*b*c", "abbbcb"));
+        assert!(matches("**x", "aaax"));
+        assert!(matches("*?", "a"));
+        assert!(!matches("*?", ""));
+    }
+
+    #[test]
+    fn patterns_with_many_stars_are_fast() {
+        let text = "a".repeat(10_000);
+        let pattern = format!("{}b", "a*".repeat(50));
+        assert!(!matches(&pattern, &text));
+    }
+
+    #[test]
+    fn lists_matching_patterns_in_order() {
+        assert_eq!(
+            matching_patterns(vec!["*.txt", "conan*", "*.lock"], "conaninfo.txt"),
+            vec![0, 1]
+        );
+    }
+}
@@ -0,0 +1,167 @@
+//! Ctrl+C during a run against the fake conan: the run stops at the next conan call instead of
+//! being killed halfway through a removal. A run killed outright is resumed by the next one.
+
+#![cfg(unix)]
+
+mod common;
+
+use common::FakeConan;
+
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Stdio};
+
+fn interrupt(child: &Child) {
+    assert_eq!(
+        unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGINT) },
+        0
+    );
+}
+
+#[test]
+fn cancel_mid_scan() {
+    let mut conan = FakeConan::new("cancel_mid_scan");
+    conan
+        .add_package("boost/1.75.0", "a")
+        .add_package("fmt/8.0.0", "b")
+        .add_package("zlib/1.2.11", "c")
+        .add_project("app", &[]);
+    conan.env("FAKE_CONAN_SEARCH_SLEEP", "1");
+    let mut child = conan
+        .command(&["projects", "-f", "--jobs", "1"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    // The search of the recipes and the one of the packages of the first recipe, which the run
+    // lets finish.
+    conan.wait_for_calls("search -j", 2);
+    interrupt(&child);
+    let status = child.wait().unwrap();
+
+    assert_eq!(status.code(), Some(130));
+    assert_eq!(conan.count_calls("search -j"), 2, "{:?}", conan.calls());
+    assert_eq!(conan.count_calls("remove"), 0);
+    assert!(conan.has_package("boost/1.75.0", "a"));
+    assert!(conan.has_package("fmt/8.0.0", "b"));
+    assert!(conan.has_package("zlib/1.2.11", "c"));
+}
+
+#[test]
+fn cancel_mid_removal() {
+    let mut conan = FakeConan::new("cancel_mid_removal");
+    conan
+        .add_package("boost/1.75.0", "a")
+        .add_package("fmt/8.0.0", "b")
+        .add_package("zlib/1.2.11", "c")
+        .add_project("app", &[]);
+    conan.env("FAKE_CONAN_REMOVE_SLEEP", "1");
+    let mut child = conan
+        .command(&["projects", "-f", "--jobs-remove", "1"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    conan.wait_for_calls("remove", 1);
+    interrupt(&child);
+    let status = child.wait().unwrap();
+
+    assert_eq!(status.code(), Some(130));
+    // The removal running when interrupted completes, no other one starts.
+    assert_eq!(conan.count_calls("remove"), 1, "{:?}", conan.calls());
+    assert!(!conan.has_package("boost/1.75.0", "a"));
+    assert!(conan.has_package("fmt/8.0.0", "b"));
+    assert!(conan.has_package("zlib/1.2.11", "c"));
+}
+
+#[test]
+fn killed_removal_is_resumed() {
+    let mut conan = FakeConan::new("killed_removal_is_resumed");
+    conan
+        .add_package("boost/1.75.0", "a")
+        .add_package("fmt/8.0.0", "b")
+        .add_package("zlib/1.2.11", "c")
+        .add_project("app", &[]);
+    conan.env("FAKE_CONAN_REMOVE_SLEEP", "1");
+    // In a process group of its own, so that the conan call running is killed with it.
+    let mut child = conan
+        .command(&["projects", "-f", "--jobs-remove", "1"])
+        .process_group(0)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    conan.wait_for_calls("remove", 2);
+    assert_eq!(
+        unsafe { libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL) },
+        0
+    );
+    child.wait().unwrap();
+    assert!(!conan.has_package("boost/1.75.0", "a"));
+    assert!(conan.has_package("fmt/8.0.0", "b"));
+    let resume_file = conan.dir.join("home/.conan/conan_cleanup_resume.json");
+    assert!(resume_file.is_file());
+
+    conan.env("FAKE_CONAN_REMOVE_SLEEP", "0");
+    let output = conan.run(&["projects", "-f", "--resume", "--jobs-remove", "1"]);
+
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains(
+            "Resuming the interrupted removal of this plan, which removed 1 packages already."
+        ),
+        "{}",
+        stdout
+    );
+    // The killed removal of fmt is started again, the completed one of boost is not.
+    assert_eq!(
+        conan.count_calls("remove boost/1.75.0@ -p"),
+        1,
+        "{:?}",
+        conan.calls()
+    );
+    assert_eq!(
+        conan.count_calls("remove fmt/8.0.0@ -p"),
+        2,
+        "{:?}",
+        conan.calls()
+    );
+    assert_eq!(
+        conan.count_calls("remove zlib/1.2.11@ -p"),
+        1,
+        "{:?}",
+        conan.calls()
+    );
+    assert!(!conan.has_package("fmt/8.0.0", "b"));
+    assert!(!conan.has_package("zlib/1.2.11", "c"));
+    assert!(!resume_file.exists());
+}
+
+#[test]
+fn resume_file_of_another_plan_is_dropped() {
+    let conan = FakeConan::new("resume_file_of_another_plan");
+    conan
+        .add_package("boost/1.75.0", "a")
+        .add_package("fmt/8.0.0", "b")
+        .add_project("app", &[]);
+    let resume_file = conan.dir.join("home/.conan/conan_cleanup_resume.json");
+    std::fs::write(
+        &resume_file,
+        r#"{"plan_hash":"v1-0000","removed":[["zlib/1.2.11","c"]]}"#,
+    )
+    .unwrap();
+
+    let output = conan.run(&["projects", "-f", "--resume"]);
+    assert_eq!(output.status.code(), Some(4));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("belongs to a different plan"));
+    assert!(!resume_file.exists());
+    assert_eq!(conan.count_calls("remove"), 0);
+
+    let output = conan.run(&["projects", "-f"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(!conan.has_package("boost/1.75.0", "a"));
+    assert!(!conan.has_package("fmt/8.0.0", "b"));
+}
@@ -0,0 +1,59 @@
+//! Runs of the cleanup against the fake conan, checking the conan commands it issues and what is
+//! left of the cache.
+
+#![cfg(unix)]
+
+mod common;
+
+use common::FakeConan;
+
+#[test]
+fn empty_cache_is_nothing_to_do() {
+    let conan = FakeConan::new("empty_cache");
+    conan.add_project("app", &[]);
+    let output = conan.run(&["projects", "-f"]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(
+        String::from_utf8_lossy(&output.stdout).contains("Local cache is empty, nothing to do.")
+    );
+    assert!(!conan.calls().iter().any(|call| call.starts_with("remove")));
+}
+
+#[test]
+fn identical_package_ids_under_different_recipes() {
+    let conan = FakeConan::new("identical_package_ids");
+    conan
+        .add_package("fmt/8.0.0", "header_only")
+        .add_package("spdlog/1.9.0@corp/stable", "header_only")
+        .add_project("app", &["fmt/8.0.0:header_only"]);
+    let output = conan.run(&["projects", "-f"]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(conan.has_package("fmt/8.0.0", "header_only"));
+    assert!(!conan.has_package("spdlog/1.9.0@corp/stable", "header_only"));
+}
+
+#[test]
+fn read_only_run_only_reads_the_cache() {
+    let conan = FakeConan::new("read_only");
+    conan
+        .add_package("zlib/1.2.11", "used")
+        .add_package("zlib/1.2.11", "unused")
+        .add_package("fmt/8.0.0", "unused")
+        .add_project("app", &["zlib/1.2.11:used"]);
+    let output = conan.run(&["projects", "--read-only"]);
+
+    assert_eq!(output.status.code(), Some(0));
+    let calls = conan.calls();
+    assert!(calls.iter().any(|call| call.starts_with("search")));
+    assert!(
+        calls
+            .iter()
+            .all(|call| call == "--version" || call.starts_with("search ")),
+        "{:?}",
+        calls
+    );
+    assert!(conan.has_package("zlib/1.2.11", "unused"));
+    assert!(conan.has_package("fmt/8.0.0", "unused"));
+}
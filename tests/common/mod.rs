@@ -0,0 +1,222 @@
+//! Runs conan_cleanup against a fake conan 1, a shell script answering `--version`, `search` and
+//! `remove` from a cache in a temporary directory and logging every call.
+
+#![allow(dead_code)]
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+use std::time::{Duration, Instant};
+
+const FAKE_CONAN: &str = r#"#!/bin/sh
+echo "$*" >> "$FAKE_CONAN_DIR/calls.log"
+storage="$FAKE_CONAN_DIR/home/.conan/data"
+
+ref_dir() {
+    ref=${1%@}
+    case "$ref" in
+        *@*) echo "$storage/${ref%@*}/${ref#*@}" ;;
+        *) echo "$storage/$ref/_/_" ;;
+    esac
+}
+
+case "$1" in
+--version)
+    echo "Conan version 1.40.0"
+    ;;
+search)
+    if [ -n "$FAKE_CONAN_SEARCH_ERROR" ]; then
+        echo "ERROR: $FAKE_CONAN_SEARCH_ERROR" >&2
+        exit 1
+    fi
+    json=$3
+    if [ -z "$4" ]; then
+        items=""
+        for dir in "$storage"/*/*/*/*; do
+            [ -d "$dir" ] || continue
+            channel=${dir##*/}; dir=${dir%/*}
+            user=${dir##*/}; dir=${dir%/*}
+            version=${dir##*/}; dir=${dir%/*}
+            name=${dir##*/}
+            if [ "$user" = "_" ]; then ref="$name/$version"; else ref="$name/$version@$user/$channel"; fi
+            items="$items${items:+,}{\"recipe\":{\"id\":\"$ref\"}}"
+        done
+        echo "{\"error\":false,\"results\":[{\"remote\":null,\"items\":[$items]}]}" > "$json"
+    elif [ -n "$FAKE_CONAN_LOCKED" ]; then
+        echo "{\"error\":true,\"results\":[]}" > "$json"
+        echo "ERROR: Lock on recipe could not be acquired" >&2
+        exit 1
+    else
+        sleep "${FAKE_CONAN_SEARCH_SLEEP:-0}"
+        packages=""
+        for dir in "$(ref_dir "$4")"/package/*; do
+            [ -d "$dir" ] || continue
+            packages="$packages${packages:+,}{\"id\":\"${dir##*/}\",\"settings\":{}}"
+        done
+        echo "{\"error\":false,\"results\":[{\"remote\":null,\"items\":[{\"recipe\":{\"id\":\"${4%@}\"},\"packages\":[$packages]}]}]}" > "$json"
+    fi
+    ;;
+remove)
+    sleep "${FAKE_CONAN_REMOVE_SLEEP:-0}"
+    if [ -n "$FAKE_CONAN_REMOVE_ERROR" ]; then
+        echo "ERROR: $FAKE_CONAN_REMOVE_ERROR" >&2
+        exit 1
+    fi
+    if [ "$3" = "-p" ]; then
+        rm -rf "$(ref_dir "$2")/package/$4"
+    else
+        rm -rf "$(ref_dir "$2")"
+    fi
+    ;;
+*)
+    echo "ERROR: Unknown command $1" >&2
+    exit 1
+    ;;
+esac
+"#;
+
+/// A conan home with a fake conan and a project folder with the usage files of the projects.
+pub struct FakeConan {
+    pub dir: PathBuf,
+    envs: Vec<(String, String)>,
+}
+
+impl FakeConan {
+    /// Creates an empty cache in a directory unique to the test `name`.
+    pub fn new(name: &str) -> FakeConan {
+        let dir = std::env::temp_dir().join(format!(
+            "conan_cleanup_test_{}_{}",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("bin")).unwrap();
+        fs::create_dir_all(dir.join("home/.conan/data")).unwrap();
+        fs::create_dir_all(dir.join("projects")).unwrap();
+        let conan = dir.join("bin/conan");
+        fs::write(&conan, FAKE_CONAN).unwrap();
+        fs::set_permissions(&conan, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::write(dir.join("calls.log"), "").unwrap();
+        FakeConan {
+            dir,
+            envs: Vec::new(),
+        }
+    }
+
+    /// Adds a package, and its recipe if missing, to the cache. `recipe` is e.g. `zlib/1.2.11` or
+    /// `zlib/1.2.11@user/channel`.
+    pub fn add_package(&self, recipe: &str, package_id: &str) -> &FakeConan {
+        let recipe_dir = self.recipe_dir(recipe);
+        fs::create_dir_all(recipe_dir.join("export")).unwrap();
+        fs::write(recipe_dir.join("export/conanfile.py"), "").unwrap();
+        let package_dir = recipe_dir.join("package").join(package_id);
+        fs::create_dir_all(&package_dir).unwrap();
+        fs::write(package_dir.join("conaninfo.txt"), "[settings]\n").unwrap();
+        fs::write(package_dir.join("lib.a"), "data").unwrap();
+        self
+    }
+
+    /// Adds a project using the given packages, given as `recipe:package_id`.
+    pub fn add_project(&self, name: &str, packages: &[&str]) -> &FakeConan {
+        let project = self.dir.join("projects").join(name);
+        fs::create_dir_all(&project).unwrap();
+        let mut conaninfo = String::from("[settings]\n[full_requires]\n");
+        for package in packages {
+            conaninfo.push_str(&format!("    {}\n", package));
+        }
+        fs::write(project.join("conaninfo.txt"), conaninfo).unwrap();
+        self
+    }
+
+    /// Sets an environment variable of the fake conan, e.g. `FAKE_CONAN_LOCKED`.
+    pub fn env(&mut self, key: &str, value: &str) -> &mut FakeConan {
+        self.envs.push((key.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Returns whether the cache holds the given package.
+    pub fn has_package(&self, recipe: &str, package_id: &str) -> bool {
+        self.recipe_dir(recipe)
+            .join("package")
+            .join(package_id)
+            .is_dir()
+    }
+
+    /// Returns the command running conan_cleanup on the projects with the given arguments.
+    pub fn command(&self, args: &[&str]) -> Command {
+        let path = std::env::var_os("PATH").unwrap_or_default();
+        let mut paths = vec![self.dir.join("bin")];
+        paths.extend(std::env::split_paths(&path));
+        let mut command = Command::new(env!("CARGO_BIN_EXE_conan_cleanup"));
+        command
+            .current_dir(&self.dir)
+            .args(args)
+            .env("PATH", std::env::join_paths(paths).unwrap())
+            .env("HOME", self.dir.join("home"))
+            .env("CONAN_USER_HOME", self.dir.join("home"))
+            .env("FAKE_CONAN_DIR", &self.dir)
+            .env_remove("CONAN_HOME")
+            .env_remove("CONAN_READ_ONLY_CACHE")
+            .envs(self.envs.iter().map(|(key, value)| (key, value)));
+        command
+    }
+
+    /// Runs conan_cleanup with the given arguments and waits for it.
+    pub fn run(&self, args: &[&str]) -> Output {
+        self.command(args).output().unwrap()
+    }
+
+    /// Returns the arguments of the conan calls so far, one entry per call.
+    pub fn calls(&self) -> Vec<String> {
+        fs::read_to_string(self.dir.join("calls.log"))
+            .unwrap()
+            .lines()
+            .map(str::to_owned)
+            .collect()
+    }
+
+    /// Waits until conan was called `count` times with arguments starting with `prefix`, for at
+    /// most 10 seconds.
+    pub fn wait_for_calls(&self, prefix: &str, count: usize) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while self.count_calls(prefix) < count {
+            assert!(
+                Instant::now() < deadline,
+                "conan was not called {} times with '{}'",
+                count,
+                prefix
+            );
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    /// Returns the number of conan calls so far with arguments starting with `prefix`.
+    pub fn count_calls(&self, prefix: &str) -> usize {
+        self.calls()
+            .iter()
+            .filter(|call| call.starts_with(prefix))
+            .count()
+    }
+
+    fn recipe_dir(&self, recipe: &str) -> PathBuf {
+        let path = match recipe.split_once('@') {
+            Some((name_version, user_channel)) => format!("{}/{}", name_version, user_channel),
+            None => format!("{}/_/_", recipe),
+        };
+        self.dir.join("home/.conan/data").join(path)
+    }
+}
+
+impl Drop for FakeConan {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Returns the parsed last line of stdout, which holds the JSON error of `--errors json`.
+pub fn json_error(output: &Output) -> serde_json::Value {
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let line = stdout.lines().last().unwrap_or_default();
+    serde_json::from_str(line).unwrap_or_else(|err| panic!("invalid JSON '{}': {}", stdout, err))
+}
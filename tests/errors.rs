@@ -0,0 +1,139 @@
+//! The JSON errors of `--errors json` and `--output json`, forced with the fake conan.
+
+#![cfg(unix)]
+
+mod common;
+
+use common::{json_error, FakeConan};
+
+#[test]
+fn invalid_arguments() {
+    let conan = FakeConan::new("invalid_arguments");
+    let output = conan.run(&["projects", "--errors", "json", "--older-than", "soon"]);
+
+    assert_eq!(output.status.code(), Some(4));
+    let json = json_error(&output);
+    assert_eq!(json["error"]["code"], "invalid_arguments");
+    assert_eq!(json["error"]["exit_code"], 4);
+    assert!(json["error"]["message"]
+        .as_str()
+        .unwrap()
+        .contains("--older-than"));
+}
+
+#[test]
+fn invalid_arguments_rejected_by_clap_with_output_json() {
+    let conan = FakeConan::new("invalid_arguments_output_json");
+    let output = conan.run(&["projects", "--output=json", "--no-such-flag"]);
+
+    assert_eq!(output.status.code(), Some(4));
+    assert_eq!(json_error(&output)["error"]["code"], "invalid_arguments");
+}
+
+#[test]
+fn conan_not_found() {
+    let conan = FakeConan::new("conan_not_found");
+    std::fs::remove_file(conan.dir.join("bin/conan")).unwrap();
+    let output = conan
+        .command(&["projects", "--dry-run", "--errors", "json"])
+        .env("PATH", conan.dir.join("bin"))
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    let json = json_error(&output);
+    assert_eq!(json["error"]["code"], "conan_not_found");
+    assert!(json["error"]["context"]["command_line"]
+        .as_str()
+        .unwrap()
+        .starts_with("conan "));
+}
+
+#[test]
+fn conan_failed() {
+    let mut conan = FakeConan::new("conan_failed");
+    conan.env("FAKE_CONAN_SEARCH_ERROR", "Profile not found: default");
+    let output = conan.run(&["projects", "--dry-run", "--errors", "json"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    let json = json_error(&output);
+    assert_eq!(json["error"]["code"], "conan_failed");
+    assert_eq!(json["error"]["context"]["exit_code"], 1);
+    assert!(json["error"]["context"]["output"]
+        .as_str()
+        .unwrap()
+        .contains("Profile not found"));
+}
+
+#[test]
+fn cache_locked() {
+    let mut conan = FakeConan::new("cache_locked");
+    conan.add_package("zlib/1.2.11", "aaa");
+    conan.env("FAKE_CONAN_LOCKED", "1");
+    let output = conan.run(&["projects", "--dry-run", "--errors", "json"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    let json = json_error(&output);
+    assert_eq!(json["error"]["code"], "cache_locked");
+    let failures = json["error"]["context"]["failures"].as_array().unwrap();
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0]["code"], "cache_locked");
+}
+
+#[test]
+fn removal_failed_is_part_of_the_report() {
+    let mut conan = FakeConan::new("removal_failed");
+    conan
+        .add_package("zlib/1.2.11", "aaa")
+        .add_project("app", &[]);
+    conan.env("FAKE_CONAN_REMOVE_ERROR", "Permission denied");
+    let output = conan.run(&["projects", "-f", "--output", "json"]);
+
+    assert_eq!(output.status.code(), Some(3));
+    // A single document, the report with the error.
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["error"]["code"], "removal_failed");
+    assert_eq!(report["error"]["context"]["failed_packages"], 1);
+    assert_eq!(report["removals"][0]["succeeded"], false);
+    assert!(conan.has_package("zlib/1.2.11", "aaa"));
+}
+
+#[test]
+fn human_errors_by_default() {
+    let mut conan = FakeConan::new("human_errors");
+    conan.env("FAKE_CONAN_SEARCH_ERROR", "Profile not found: default");
+    let output = conan.run(&["projects", "--dry-run"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("\"error\""));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Profile not found"));
+}
+
+#[test]
+fn strict_input_files_apply_to_subcommands() {
+    let conan = FakeConan::new("strict_aggregate");
+    std::fs::write(conan.dir.join("report.json"), "{}").unwrap();
+    std::fs::write(
+        conan.dir.join("hosts.txt"),
+        "\u{feff}build-01\r\nbuild-02\n",
+    )
+    .unwrap();
+    let args = [
+        "--errors",
+        "json",
+        "aggregate",
+        "report.json",
+        "--expect-hosts",
+        "hosts.txt",
+    ];
+
+    assert_eq!(conan.run(&args).status.code(), Some(0));
+    let output = conan.run(&[&["--strict-input-files"], &args[..]].concat());
+    assert_eq!(output.status.code(), Some(2));
+    let json = json_error(&output);
+    assert_eq!(json["error"]["code"], "invalid_file");
+    assert!(json["error"]["message"]
+        .as_str()
+        .unwrap()
+        .contains("hosts.txt"));
+}
@@ -0,0 +1,148 @@
+//! Profiles of the configuration file selected with `--profile-name`, and the precedence of the
+//! options given on the command line over them.
+
+#![cfg(unix)]
+
+mod common;
+
+use common::{json_error, FakeConan};
+
+const CONFIG: &str = "[profile.ci]
+exclude = zlib/*
+older-than = 30d
+jobs-remove = 2
+
+[profile.audit]
+read-only = true
+";
+
+fn fake_conan(name: &str) -> FakeConan {
+    let conan = FakeConan::new(name);
+    std::fs::write(conan.dir.join("home/.conan/conan_cleanup.conf"), CONFIG).unwrap();
+    conan
+}
+
+fn sources(output: &std::process::Output) -> Vec<(String, String)> {
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    json["options"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|option| {
+            (
+                option["name"].as_str().unwrap().to_owned(),
+                option["source"].as_str().unwrap().to_owned(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn command_line_takes_precedence_over_the_profile() {
+    let conan = fake_conan("profile_precedence");
+    let output = conan.run(&[
+        "--profile-name",
+        "ci",
+        "--older-than",
+        "1d",
+        "--print-config",
+        "--output",
+        "json",
+    ]);
+
+    assert_eq!(output.status.code(), Some(0));
+    let sources = sources(&output);
+    let source = |name: &str| {
+        sources
+            .iter()
+            .find(|(option, _)| option == name)
+            .map(|(_, source)| source.as_str())
+    };
+    assert_eq!(source("older-than"), Some("command line"));
+    assert_eq!(source("jobs-remove"), Some("profile 'ci'"));
+    assert_eq!(source("exclude"), Some("profile 'ci'"));
+    assert_eq!(source("age-source"), Some("default"));
+    assert_eq!(source("read-only"), None);
+}
+
+#[test]
+fn profile_options_apply_to_the_run() {
+    let conan = fake_conan("profile_run");
+    conan
+        .add_package("zlib/1.2.11", "a")
+        .add_package("fmt/8.0.0", "b")
+        .add_project("app", &[]);
+
+    let output = conan.run(&[
+        "projects",
+        "-f",
+        "--profile-name",
+        "ci",
+        "--older-than",
+        "0s",
+    ]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(conan.has_package("zlib/1.2.11", "a"));
+    assert!(!conan.has_package("fmt/8.0.0", "b"));
+
+    conan.add_package("fmt/8.0.0", "b");
+    let output = conan.run(&[
+        "projects",
+        "-f",
+        "--profile-name",
+        "ci",
+        "--older-than",
+        "0s",
+        "--exclude",
+        "fmt/*",
+    ]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(!conan.has_package("zlib/1.2.11", "a"));
+    assert!(conan.has_package("fmt/8.0.0", "b"));
+}
+
+#[test]
+fn read_only_profile() {
+    let conan = fake_conan("profile_read_only");
+    conan.add_package("fmt/8.0.0", "b").add_project("app", &[]);
+    let output = conan.run(&["projects", "--profile-name", "audit"]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(conan.has_package("fmt/8.0.0", "b"));
+    assert!(!conan.calls().iter().any(|call| call.starts_with("remove")));
+}
+
+#[test]
+fn unknown_profile_lists_the_defined_ones() {
+    let conan = fake_conan("profile_unknown");
+    let output = conan.run(&["projects", "--profile-name", "nightly", "--errors", "json"]);
+
+    assert_eq!(output.status.code(), Some(4));
+    let message = json_error(&output)["error"]["message"]
+        .as_str()
+        .unwrap()
+        .to_owned();
+    assert!(
+        message.starts_with("Unknown profile 'nightly'"),
+        "{}",
+        message
+    );
+    assert!(message.ends_with("defines: audit, ci."), "{}", message);
+}
+
+#[test]
+fn profiles_subcommand_summarizes_the_changes() {
+    let conan = fake_conan("profiles_subcommand");
+    let output = conan.run(&["profiles"]);
+
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().skip(1).collect();
+    assert_eq!(
+        lines,
+        [
+            "  audit  --read-only",
+            "  ci     --exclude zlib/*; --jobs-remove 2 (default 1); --older-than 30d",
+        ]
+    );
+}
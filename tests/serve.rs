@@ -0,0 +1,60 @@
+//! A session of `serve --stdio` against the fake conan.
+
+#![cfg(unix)]
+
+mod common;
+
+use common::FakeConan;
+
+use std::io::Write;
+use std::process::Stdio;
+
+#[test]
+fn scan_plan_execute_and_why() {
+    let conan = FakeConan::new("serve");
+    conan
+        .add_package("zlib/1.2.11", "aaa")
+        .add_package("zlib/1.2.11", "bbb")
+        .add_project("app", &["zlib/1.2.11:aaa"]);
+    let mut child = conan
+        .command(&["serve", "--stdio"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let requests = [
+        r#"{"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {"protocol_version": 1}}"#,
+        r#"{"jsonrpc": "2.0", "id": 2, "method": "scan", "params": {"roots": ["projects"]}}"#,
+        r#"{"jsonrpc": "2.0", "id": 3, "method": "plan"}"#,
+        r#"{"jsonrpc": "2.0", "id": 4, "method": "why", "params": {"ref": "zlib/1.2.11:aaa"}}"#,
+        r#"{"jsonrpc": "2.0", "id": 5, "method": "execute", "params": {"selection": [{"recipe": "zlib/1.2.11", "package": "bbb"}]}}"#,
+        r#"{"jsonrpc": "2.0", "id": 6, "method": "scan", "params": {"roots": ["projects"]}}"#,
+        r#"{"jsonrpc": "2.0", "id": 7, "method": "shutdown"}"#,
+    ];
+    writeln!(child.stdin.take().unwrap(), "{}", requests.join("\n")).unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success());
+    let responses: Vec<serde_json::Value> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(responses.len(), requests.len());
+    for (id, response) in (1..).zip(&responses) {
+        assert_eq!(response["id"], id, "{}", response);
+        assert!(response.get("error").is_none(), "{}", response);
+    }
+    assert_eq!(responses[1]["result"]["packages"], 2);
+    assert_eq!(
+        responses[2]["result"]["candidates"][0]["packages"],
+        serde_json::json!(["bbb"])
+    );
+    let why = &responses[3]["result"]["packages"][0];
+    assert_eq!(why["status"], "kept");
+    assert_eq!(why["consumers"][0], "app/conaninfo.txt");
+    assert_eq!(responses[4]["result"]["removals"][0]["succeeded"], true);
+    assert_eq!(responses[5]["result"]["cached"], true);
+    assert_eq!(responses[5]["result"]["packages"], 1);
+    assert!(conan.has_package("zlib/1.2.11", "aaa"));
+    assert!(!conan.has_package("zlib/1.2.11", "bbb"));
+}